@@ -1,18 +1,342 @@
-extern crate proc_macro2;
+//! Derive macro for the `VertexLayout` trait of `shared_lib`.
+//!
+//! The macro is re-exported as `shared_lib::opengl::vertex_layout::VertexLayout`,
+//! next to the trait, so deriving the layout only needs a single import:
+//!
+//! ```ignore
+//! use shared_lib::opengl::vertex_layout::VertexLayout;
+//!
+//! #[repr(C)]
+//! #[derive(VertexLayout)]
+//! pub struct MyVertex {
+//!     #[vertex(name = "aPos")]
+//!     pub position: cgmath::Vector3<f32>,
+//!     #[vertex(location = 3)]
+//!     pub tex_coords: [f32; 2],
+//!     pub color: [u8; 4],
+//! }
+//! ```
+//!
+//! # Field types
+//! - Scalars: `f32`, `f64`, `i8`, `u8`, `i16`, `u16`, `i32` and `u32`.
+//! - Arrays of 1 to 4 scalars, e.g. `[f32; 3]`.
+//! - cgmath `Vector1` to `Vector4` and `Point1` to `Point3` of a scalar.
+//! - Matrices as arrays of columns, e.g. `[[f32; 4]; 4]`, or cgmath `Matrix2` to
+//!   `Matrix4`. Every column takes its own attribute location.
+//!
+//! Byte sized scalars (`u8`, `i8`) are normalized by default, so that a `[u8; 4]`
+//! color arrives as `vec4` in the range 0 to 1. Integers which aren't normalized
+//! are read by `int`/`uint` inputs (e.g. `uvec4` bone indices), and `f64` fields
+//! by `double` inputs.
+//!
+//! # Field attributes
+//! - `#[vertex(name = "...")]`: Name of the attribute, defaults to the field name.
+//! - `#[vertex(location = N)]`: Attribute location, defaults to the location
+//!   following the previous attribute.
+//! - `#[vertex(normalized)]` / `#[vertex(normalized = false)]`: Overrides whether
+//!   integer values are normalized.
 
-use proc_macro2::TokenStream;
-use syn::{DeriveInput, parse_macro_input};
+use proc_macro::TokenStream;
+use proc_macro2::{Ident, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, Data, DeriveInput, Expr, ExprLit, Field, Fields, GenericArgument, Lit,
+    LitBool, LitInt, LitStr, PathArguments, Type,
+};
 
-#[proc_macro_attribute]
-pub fn vertex_layout(_: TokenStream, TokenStream) -> TokenStream {
-    let input = parse_macro_input!(item as DeriveInput);
+#[proc_macro_derive(VertexLayout, attributes(vertex))]
+pub fn derive_vertex_layout(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_vertex_layout(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
 }
 
+fn expand_vertex_layout(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    check_repr_c(input)?;
 
-#[vertex_layout]
-pub struct MyVertex{
-    [vertex_position]
-    pub position: [f32; 3],
-    [vertex_color]
-    pub color: [f32; 4],
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new(
+                    input.ident.span(),
+                    "VertexLayout can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new(
+                input.ident.span(),
+                "VertexLayout can only be derived for structs",
+            ))
+        }
+    };
+
+    let attributes = fields.iter().map(expand_attribute).collect::<syn::Result<Vec<_>>>()?;
+
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::shared_lib::opengl::vertex_layout::VertexLayout
+            for #name #type_generics #where_clause
+        {
+            fn attributes() -> ::std::vec::Vec<::shared_lib::opengl::vertex_attribute::VertexAttribute> {
+                let stride = ::core::mem::size_of::<Self>() as i32;
+                ::std::vec![#(#attributes),*]
+            }
+        }
+    })
+}
+
+/// Without `#[repr(C)]` the compiler may reorder the fields, which the shaders
+/// wouldn't know about.
+fn check_repr_c(input: &DeriveInput) -> syn::Result<()> {
+    let mut has_repr_c = false;
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("repr")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("C") {
+                has_repr_c = true;
+            }
+            Ok(())
+        })?;
+    }
+
+    if has_repr_c {
+        Ok(())
+    } else {
+        Err(syn::Error::new(
+            input.ident.span(),
+            "VertexLayout requires #[repr(C)] to keep the field order",
+        ))
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - Attributes -
+//////////////////////////////////////////////////////////////////////////////
+
+/// The options of a `#[vertex(...)]` field attribute.
+#[derive(Default)]
+struct FieldOptions {
+    name: Option<LitStr>,
+    location: Option<LitInt>,
+    normalized: Option<bool>,
+}
+
+impl FieldOptions {
+    fn parse(field: &Field) -> syn::Result<Self> {
+        let mut options = FieldOptions::default();
+        for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("vertex")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("name") {
+                    options.name = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("location") {
+                    options.location = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("normalized") {
+                    options.normalized = match meta.value() {
+                        Ok(value) => Some(value.parse::<LitBool>()?.value),
+                        Err(_) => Some(true),
+                    };
+                } else {
+                    return Err(meta.error(
+                        "unknown vertex attribute option, expected `name`, `location` or `normalized`",
+                    ));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(options)
+    }
+}
+
+fn expand_attribute(field: &Field) -> syn::Result<TokenStream2> {
+    let ident = field.ident.as_ref().expect("named field");
+    let options = FieldOptions::parse(field)?;
+    let format = FieldFormat::infer(&field.ty)?;
+
+    let components = format.components;
+    let data_type = format_ident!("{}", format.scalar.data_type());
+    let normalized = options.normalized.unwrap_or(format.scalar.normalized_by_default());
+    let name = options.name.unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+
+    let mut attribute = quote! {
+        ::shared_lib::opengl::vertex_attribute::VertexAttribute::new(
+            #components,
+            ::shared_lib::gl_types::VertexDataType::#data_type,
+        )
+        .name(::std::string::String::from(#name))
+        .normalized(#normalized)
+        .stride(stride)
+        .offset(::std::option::Option::Some(::core::mem::offset_of!(Self, #ident) as u32))
+    };
+    if let Some(columns) = format.columns {
+        attribute.extend(quote! { .columns(#columns) });
+    }
+    if let Some(location) = options.location {
+        attribute.extend(quote! { .location(#location) });
+    }
+    Ok(attribute)
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - FieldFormat -
+//////////////////////////////////////////////////////////////////////////////
+
+/// The scalar types a vertex attribute can be made of.
+#[derive(Clone, Copy)]
+enum Scalar {
+    F32,
+    F64,
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+}
+
+impl Scalar {
+    fn from_ident(ident: &Ident) -> Option<Self> {
+        let scalar = match ident.to_string().as_str() {
+            "f32" => Scalar::F32,
+            "f64" => Scalar::F64,
+            "i8" => Scalar::I8,
+            "u8" => Scalar::U8,
+            "i16" => Scalar::I16,
+            "u16" => Scalar::U16,
+            "i32" => Scalar::I32,
+            "u32" => Scalar::U32,
+            _ => return None,
+        };
+        Some(scalar)
+    }
+
+    /// Returns the name of the matching `VertexDataType` variant.
+    fn data_type(&self) -> &'static str {
+        match self {
+            Scalar::F32 => "Float",
+            Scalar::F64 => "Double",
+            Scalar::I8 => "Byte",
+            Scalar::U8 => "UnsignedByte",
+            Scalar::I16 => "Short",
+            Scalar::U16 => "UnsignedShort",
+            Scalar::I32 => "Int",
+            Scalar::U32 => "UnsignedInt",
+        }
+    }
+
+    fn normalized_by_default(&self) -> bool {
+        matches!(self, Scalar::I8 | Scalar::U8)
+    }
+}
+
+/// The format of a vertex attribute as inferred from the type of a field.
+struct FieldFormat {
+    scalar: Scalar,
+    components: u8,
+    /// Number of columns for matrices.
+    columns: Option<u8>,
+}
+
+impl FieldFormat {
+    fn infer(ty: &Type) -> syn::Result<Self> {
+        match ty {
+            Type::Group(group) => Self::infer(&group.elem),
+            Type::Paren(paren) => Self::infer(&paren.elem),
+            Type::Array(array) => {
+                let length = array_length(&array.len)?;
+                let inner = Self::infer(&array.elem)?;
+                if inner.columns.is_some() || inner.components > 1 {
+                    // An array of vectors describes the columns of a matrix
+                    if inner.columns.is_some() || !(1..=4).contains(&length) {
+                        return Err(unsupported_type(ty));
+                    }
+                    return Ok(Self {
+                        columns: Some(length as u8),
+                        ..inner
+                    });
+                }
+                Self::vector(inner.scalar, length, ty)
+            }
+            Type::Path(path) if path.qself.is_none() => {
+                let segment = path.path.segments.last().ok_or_else(|| unsupported_type(ty))?;
+                if let Some(scalar) = Scalar::from_ident(&segment.ident) {
+                    if matches!(segment.arguments, PathArguments::None) {
+                        return Ok(Self {
+                            scalar,
+                            components: 1,
+                            columns: None,
+                        });
+                    }
+                }
+
+                let name = segment.ident.to_string();
+                let (kind, size) = name.split_at(name.len().saturating_sub(1));
+                let size: usize = size.parse().map_err(|_| unsupported_type(ty))?;
+                let scalar =
+                    generic_scalar(&segment.arguments).ok_or_else(|| unsupported_type(ty))?;
+                match kind {
+                    "Vector" | "Point" => Self::vector(scalar, size, ty),
+                    "Matrix" if (2..=4).contains(&size) => Ok(Self {
+                        scalar,
+                        components: size as u8,
+                        columns: Some(size as u8),
+                    }),
+                    _ => Err(unsupported_type(ty)),
+                }
+            }
+            _ => Err(unsupported_type(ty)),
+        }
+    }
+
+    fn vector(scalar: Scalar, components: usize, ty: &Type) -> syn::Result<Self> {
+        if !(1..=4).contains(&components) {
+            return Err(syn::Error::new(
+                ty.span(),
+                format!("vertex attributes have between 1 and 4 components, found {}", components),
+            ));
+        }
+        Ok(Self {
+            scalar,
+            components: components as u8,
+            columns: None,
+        })
+    }
+}
+
+/// Reads the length of an array type, which has to be an integer literal.
+fn array_length(len: &Expr) -> syn::Result<usize> {
+    match len {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(length),
+            ..
+        }) => length.base10_parse(),
+        _ => Err(syn::Error::new(
+            len.span(),
+            "the array length of a vertex attribute has to be an integer literal",
+        )),
+    }
+}
+
+/// Returns the scalar of a single generic argument, e.g. `f32` for `Vector3<f32>`.
+fn generic_scalar(arguments: &PathArguments) -> Option<Scalar> {
+    let PathArguments::AngleBracketed(arguments) = arguments else {
+        return None;
+    };
+    match arguments.args.first() {
+        Some(GenericArgument::Type(Type::Path(path))) if arguments.args.len() == 1 => {
+            path.path.get_ident().and_then(Scalar::from_ident)
+        }
+        _ => None,
+    }
+}
+
+fn unsupported_type(ty: &Type) -> syn::Error {
+    syn::Error::new(
+        ty.span(),
+        "unsupported vertex attribute type, expected a scalar, an array of 1 to 4 scalars, \
+         a cgmath vector or point, or a matrix",
+    )
 }
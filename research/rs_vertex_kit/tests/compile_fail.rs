@@ -0,0 +1,5 @@
+#[test]
+fn unsupported_layouts_fail_to_compile() {
+    let tests = trybuild::TestCases::new();
+    tests.compile_fail("tests/compile_fail/*.rs");
+}
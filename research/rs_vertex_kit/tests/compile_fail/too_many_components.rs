@@ -0,0 +1,9 @@
+use shared_lib::opengl::vertex_layout::VertexLayout;
+
+#[repr(C)]
+#[derive(VertexLayout)]
+struct Vertex {
+    position: [f32; 5],
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use shared_lib::opengl::vertex_layout::VertexLayout;
+
+#[repr(C)]
+#[derive(VertexLayout)]
+struct Vertex {
+    position: [f32; 3],
+    label: String,
+}
+
+fn main() {}
@@ -0,0 +1,91 @@
+use cgmath::{Matrix4, Vector3};
+use shared_lib::gl_types::VertexDataType;
+use shared_lib::opengl::vertex_layout::VertexLayout;
+use std::mem::{offset_of, size_of};
+
+#[repr(C)]
+#[derive(VertexLayout)]
+struct MeshVertex {
+    position: Vector3<f32>,
+    #[vertex(name = "aTexCoords")]
+    tex_coords: [f32; 2],
+    color: [u8; 4],
+    #[vertex(location = 5, normalized = false)]
+    bone_ids: [u8; 4],
+    weight: f32,
+}
+
+#[repr(C)]
+#[derive(VertexLayout)]
+struct InstanceData {
+    model: Matrix4<f32>,
+    #[vertex(normalized)]
+    tint: [u16; 3],
+}
+
+#[test]
+fn infers_components_and_data_types() {
+    let attributes = MeshVertex::attributes();
+    let formats: Vec<_> = attributes
+        .iter()
+        .map(|attribute| (attribute.components, attribute.data_type, attribute.normalized))
+        .collect();
+
+    assert_eq!(
+        formats,
+        vec![
+            (3, VertexDataType::Float, false),
+            (2, VertexDataType::Float, false),
+            (4, VertexDataType::UnsignedByte, true),
+            (4, VertexDataType::UnsignedByte, false),
+            (1, VertexDataType::Float, false),
+        ]
+    );
+}
+
+#[test]
+fn uses_field_offsets_and_struct_size() {
+    let attributes = MeshVertex::attributes();
+    let offsets: Vec<_> = attributes.iter().map(|attribute| attribute.offset).collect();
+
+    assert_eq!(
+        offsets,
+        vec![
+            Some(offset_of!(MeshVertex, position) as u32),
+            Some(offset_of!(MeshVertex, tex_coords) as u32),
+            Some(offset_of!(MeshVertex, color) as u32),
+            Some(offset_of!(MeshVertex, bone_ids) as u32),
+            Some(offset_of!(MeshVertex, weight) as u32),
+        ]
+    );
+    assert!(attributes
+        .iter()
+        .all(|attribute| attribute.stride == size_of::<MeshVertex>() as i32));
+}
+
+#[test]
+fn applies_field_attributes() {
+    let attributes = MeshVertex::attributes();
+    let names: Vec<_> = attributes.iter().map(|attribute| attribute.name.as_deref()).collect();
+
+    assert_eq!(
+        names,
+        vec![Some("position"), Some("aTexCoords"), Some("color"), Some("bone_ids"), Some("weight"),]
+    );
+    assert_eq!(attributes[3].location, Some(5));
+    assert!(attributes
+        .iter()
+        .enumerate()
+        .all(|(index, attribute)| index == 3 || attribute.location.is_none()));
+}
+
+#[test]
+fn splits_matrices_into_columns() {
+    let attributes = InstanceData::attributes();
+
+    assert_eq!(attributes[0].components, 4);
+    assert_eq!(attributes[0].location_count(), 4);
+    assert_eq!(attributes[0].calculate_size(), size_of::<Matrix4<f32>>());
+    assert_eq!(attributes[1].data_type, VertexDataType::UnsignedShort);
+    assert!(attributes[1].normalized);
+}
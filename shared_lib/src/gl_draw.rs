@@ -1,6 +1,11 @@
+use crate::gl_traits::Bindable;
 use crate::gl_types::{IndicesValueType, PrimitiveType};
+use crate::opengl::gl_backend::with_backend;
+use crate::opengl::indirect_buffer::{
+    supports_base_instance, supports_multi_draw_indirect, IndirectBuffer,
+};
+use anyhow::{anyhow, Result};
 use gl::types::{GLint, GLsizei, GLuint};
-use std::ptr;
 
 /// Draws geometric primitives from array data.
 ///
@@ -15,8 +20,7 @@ use std::ptr;
 ///   to a `GLsizei` to match OpenGL's expected parameter type.
 ///
 /// # Safety
-/// This function contains an `unsafe` block due to the direct call to the OpenGL function `gl::DrawArrays`.
-/// It is the caller's responsibility to ensure that all OpenGL state required for the draw call (e.g., the
+/// The draw call goes through the current `GlBackend`. It is the caller's responsibility to ensure that all OpenGL state required for the draw call (e.g., the
 /// current shader program, bound vertex array object, and vertex buffer objects) has been correctly set up
 /// before calling this function. Failure to do so may lead to undefined behavior or a crash.
 ///
@@ -33,9 +37,7 @@ use std::ptr;
 /// This function does not support specifying an offset into the vertex array. If such functionality is
 /// needed, consider using `glDrawArraysInstanced` or `glDrawElements` with an index buffer.
 pub fn draw_primitive(primitive_type: PrimitiveType, vertex_count: u32) {
-    unsafe {
-        gl::DrawArrays(primitive_type.to_gl_enum(), 0, vertex_count as GLsizei);
-    }
+    DrawCommand::arrays(primitive_type, vertex_count as usize).draw();
 }
 
 /// Draws elements using OpenGL.
@@ -74,22 +76,532 @@ pub fn draw_elements(
     elements_count: u32, //<< TODO usize
     indices_type: IndicesValueType,
 ) {
-    unsafe {
-        gl::DrawElements(
-            primitive_type.to_gl_enum(),
-            elements_count as GLsizei,
-            indices_type.to_gl_enum(),
-            ptr::null(),
-        );
-    }
+    DrawCommand::elements(primitive_type, elements_count as usize)
+        .indices_type(indices_type)
+        .draw();
 }
 
 pub fn draw_arrays(primitive_type: PrimitiveType, first: usize, count: usize) {
-    unsafe {
-        gl::DrawArrays(
-            primitive_type.to_gl_enum(),
-            first as GLint,
-            count as GLsizei,
-        )
+    DrawCommand::arrays(primitive_type, count).first(first).draw();
+}
+
+/// Draws `instance_count` instances of a range of array data, see `draw_arrays`.
+pub fn draw_arrays_instanced(
+    primitive_type: PrimitiveType,
+    first: usize,
+    count: usize,
+    instance_count: usize,
+) {
+    DrawCommand::arrays(primitive_type, count)
+        .first(first)
+        .instances(instance_count)
+        .draw();
+}
+
+/// Draws `instance_count` instances of the indexed elements of the bound element
+/// buffer, see `draw_elements`.
+pub fn draw_elements_instanced(
+    primitive_type: PrimitiveType,
+    elements_count: u32,
+    indices_type: IndicesValueType,
+    instance_count: usize,
+) {
+    DrawCommand::elements(primitive_type, elements_count as usize)
+        .indices_type(indices_type)
+        .instances(instance_count)
+        .draw();
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - DrawCommand -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Describes a single draw call, either of array data or of the indices of the
+/// bound element buffer.
+///
+/// # Fields
+/// - `primitive_type`: The type of primitives to render.
+/// - `indices_type`: The type of the indices, `None` for array draws.
+/// - `first`: The first vertex for array draws, the first index for element draws.
+/// - `count`: The number of vertices or indices to render.
+/// - `base_vertex`: A constant added to every index before the vertex is fetched.
+///   Only used by element draws.
+/// - `instance_count`: The number of instances to render.
+/// - `base_instance`: The instance from which per-instance attributes are read first.
+/// - `primitive_restart`: Whether the maximum value of `indices_type` restarts the
+///   primitive. Only used by element draws.
+///
+/// # Example
+/// ```ignore
+/// // Two quads stored as triangle strips, separated by the restart index
+/// DrawCommand::elements(PrimitiveType::TriangleStrip, 9)
+///     .indices_type(IndicesValueType::Short)
+///     .primitive_restart(true)
+///     .draw();
+///
+/// // Second mesh of a shared vertex and element buffer
+/// DrawCommand::elements(PrimitiveType::Triangles, 36)
+///     .first(36)
+///     .base_vertex(24)
+///     .draw();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrawCommand {
+    pub primitive_type: PrimitiveType,
+    pub indices_type: Option<IndicesValueType>,
+    pub first: usize,
+    pub count: usize,
+    pub base_vertex: i32,
+    pub instance_count: usize,
+    pub base_instance: u32,
+    pub primitive_restart: bool,
+}
+
+impl DrawCommand {
+    /// Creates a command which draws `count` vertices of array data.
+    pub fn arrays(primitive_type: PrimitiveType, count: usize) -> Self {
+        Self {
+            primitive_type,
+            indices_type: None,
+            first: 0,
+            count,
+            base_vertex: 0,
+            instance_count: 1,
+            base_instance: 0,
+            primitive_restart: false,
+        }
+    }
+
+    /// Creates a command which draws `count` indices of the bound element buffer.
+    /// The indices are expected to be `u32` unless `indices_type` is set.
+    pub fn elements(primitive_type: PrimitiveType, count: usize) -> Self {
+        Self {
+            indices_type: Some(IndicesValueType::Int),
+            ..Self::arrays(primitive_type, count)
+        }
+    }
+
+    pub fn indices_type(mut self, indices_type: IndicesValueType) -> Self {
+        self.indices_type = Some(indices_type);
+        self
+    }
+
+    pub fn first(mut self, first: usize) -> Self {
+        self.first = first;
+        self
+    }
+
+    pub fn base_vertex(mut self, base_vertex: i32) -> Self {
+        self.base_vertex = base_vertex;
+        self
+    }
+
+    pub fn instances(mut self, instance_count: usize) -> Self {
+        self.instance_count = instance_count;
+        self
+    }
+
+    pub fn base_instance(mut self, base_instance: u32) -> Self {
+        self.base_instance = base_instance;
+        self
+    }
+
+    pub fn primitive_restart(mut self, primitive_restart: bool) -> Self {
+        self.primitive_restart = primitive_restart;
+        self
+    }
+
+    /// Returns `true` if the command draws indices of an element buffer.
+    pub fn is_indexed(&self) -> bool {
+        self.indices_type.is_some()
+    }
+
+    /// Returns the byte offset of the first index into the element buffer.
+    pub fn index_offset(&self) -> usize {
+        self.indices_type.map_or(0, |indices_type| self.first * indices_type.size())
+    }
+
+    /// Issues the draw call.
+    ///
+    /// Commands without vertices or instances are skipped. It is the caller's
+    /// responsibility to bind the shader program, vertex array and buffers first.
+    /// Primitive restart is only changed for the draw call itself.
+    pub fn draw(&self) {
+        if self.count == 0 || self.instance_count == 0 {
+            return;
+        }
+
+        let mode = self.primitive_type.to_gl_enum();
+        let count = self.count as GLsizei;
+        let instance_count = self.instance_count as GLsizei;
+
+        let Some(indices_type) = self.indices_type else {
+            let first = self.first as GLint;
+            with_backend(|backend| match (self.instance_count, self.base_instance) {
+                (1, 0) => backend.draw_arrays(mode, first, count),
+                (_, 0) => backend.draw_arrays_instanced(mode, first, count, instance_count),
+                (_, base_instance) => backend.draw_arrays_instanced_base_instance(
+                    mode,
+                    first,
+                    count,
+                    instance_count,
+                    base_instance,
+                ),
+            });
+            return;
+        };
+
+        let gl_type = indices_type.to_gl_enum();
+        let offset = self.index_offset();
+        with_backend(|backend| {
+            // The primitive restart state of the caller is restored after the draw
+            let previous_restart = self.primitive_restart.then(|| {
+                let previous = (
+                    backend.is_enabled(gl::PRIMITIVE_RESTART),
+                    backend.get_integer(gl::PRIMITIVE_RESTART_INDEX) as GLuint,
+                );
+                backend.enable(gl::PRIMITIVE_RESTART);
+                backend.primitive_restart_index(indices_type.restart_index());
+                previous
+            });
+
+            match (self.instance_count, self.base_vertex, self.base_instance) {
+                (1, 0, 0) => backend.draw_elements(mode, count, gl_type, offset),
+                (1, base_vertex, 0) => {
+                    backend.draw_elements_base_vertex(mode, count, gl_type, offset, base_vertex)
+                }
+                (_, 0, 0) => {
+                    backend.draw_elements_instanced(mode, count, gl_type, offset, instance_count)
+                }
+                (_, base_vertex, 0) => backend.draw_elements_instanced_base_vertex(
+                    mode,
+                    count,
+                    gl_type,
+                    offset,
+                    instance_count,
+                    base_vertex,
+                ),
+                (_, base_vertex, base_instance) => backend
+                    .draw_elements_instanced_base_vertex_base_instance(
+                        mode,
+                        count,
+                        gl_type,
+                        offset,
+                        instance_count,
+                        base_vertex,
+                        base_instance,
+                    ),
+            }
+
+            if let Some((was_enabled, previous_index)) = previous_restart {
+                if !was_enabled {
+                    backend.disable(gl::PRIMITIVE_RESTART);
+                }
+                backend.primitive_restart_index(previous_index);
+            }
+        });
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - Indirect Commands -
+//////////////////////////////////////////////////////////////////////////////
+
+/// A draw command which is read by the GPU from an `IndirectBuffer`.
+///
+/// The implementing types match the memory layout OpenGL expects for the
+/// commands, so that they can be uploaded as they are.
+pub trait IndirectCommand: Copy {
+    /// `true` if the command draws indices of an element buffer.
+    const INDEXED: bool;
+
+    /// Converts the command into a `DrawCommand`, which is used to draw the command
+    /// on the CPU when multi-draw indirect isn't supported. Indexed commands expect
+    /// `u32` indices unless the indices type is changed.
+    fn to_draw_command(&self, primitive_type: PrimitiveType) -> DrawCommand;
+}
+
+/// The command read by `glMultiDrawElementsIndirect`.
+///
+/// # Example
+/// ```ignore
+/// // One command per mesh, all meshes share one vertex and element buffer
+/// let command = DrawElementsIndirectCommand::new(mesh.index_count)
+///     .first_index(mesh.first_index)
+///     .base_vertex(mesh.first_vertex as i32)
+///     .base_instance(mesh_index);
+/// ```
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DrawElementsIndirectCommand {
+    pub count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub base_vertex: i32,
+    pub base_instance: u32,
+}
+
+impl DrawElementsIndirectCommand {
+    /// Creates a command which draws a single instance of `count` indices.
+    pub fn new(count: u32) -> Self {
+        Self {
+            count,
+            instance_count: 1,
+            ..Default::default()
+        }
+    }
+
+    pub fn instances(mut self, instance_count: u32) -> Self {
+        self.instance_count = instance_count;
+        self
+    }
+
+    pub fn first_index(mut self, first_index: u32) -> Self {
+        self.first_index = first_index;
+        self
+    }
+
+    pub fn base_vertex(mut self, base_vertex: i32) -> Self {
+        self.base_vertex = base_vertex;
+        self
+    }
+
+    pub fn base_instance(mut self, base_instance: u32) -> Self {
+        self.base_instance = base_instance;
+        self
+    }
+}
+
+impl IndirectCommand for DrawElementsIndirectCommand {
+    const INDEXED: bool = true;
+
+    fn to_draw_command(&self, primitive_type: PrimitiveType) -> DrawCommand {
+        DrawCommand::elements(primitive_type, self.count as usize)
+            .first(self.first_index as usize)
+            .base_vertex(self.base_vertex)
+            .instances(self.instance_count as usize)
+            .base_instance(self.base_instance)
+    }
+}
+
+/// The command read by `glMultiDrawArraysIndirect`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DrawArraysIndirectCommand {
+    pub count: u32,
+    pub instance_count: u32,
+    pub first: u32,
+    pub base_instance: u32,
+}
+
+impl DrawArraysIndirectCommand {
+    /// Creates a command which draws a single instance of `count` vertices.
+    pub fn new(count: u32) -> Self {
+        Self {
+            count,
+            instance_count: 1,
+            ..Default::default()
+        }
+    }
+
+    pub fn instances(mut self, instance_count: u32) -> Self {
+        self.instance_count = instance_count;
+        self
+    }
+
+    pub fn first(mut self, first: u32) -> Self {
+        self.first = first;
+        self
+    }
+
+    pub fn base_instance(mut self, base_instance: u32) -> Self {
+        self.base_instance = base_instance;
+        self
+    }
+}
+
+impl IndirectCommand for DrawArraysIndirectCommand {
+    const INDEXED: bool = false;
+
+    fn to_draw_command(&self, primitive_type: PrimitiveType) -> DrawCommand {
+        DrawCommand::arrays(primitive_type, self.count as usize)
+            .first(self.first as usize)
+            .instances(self.instance_count as usize)
+            .base_instance(self.base_instance)
+    }
+}
+
+/// Draws all commands of `commands` with a single `glMultiDrawElementsIndirect`
+/// call, using the indices of the bound element buffer.
+///
+/// If the context doesn't support multi-draw indirect (OpenGL 4.3 or
+/// `GL_ARB_multi_draw_indirect`), the commands are drawn one by one from the CPU
+/// copy of the buffer instead.
+///
+/// # Errors
+/// Returns an error if the indirect buffer can't be bound, or if commands with a
+/// base instance have to be drawn one by one on a context without base instances.
+///
+/// # Example
+/// ```ignore
+/// let commands = IndirectBuffer::with_commands(BufferUsage::StaticDraw, mesh_commands);
+/// vao.bind();
+/// multi_draw_elements_indirect(PrimitiveType::Triangles, IndicesValueType::Int, &commands)?;
+/// ```
+pub fn multi_draw_elements_indirect(
+    primitive_type: PrimitiveType,
+    indices_type: IndicesValueType,
+    commands: &IndirectBuffer<DrawElementsIndirectCommand>,
+) -> Result<()> {
+    multi_draw_indirect(primitive_type, Some(indices_type), commands)
+}
+
+/// Draws all commands of `commands` with a single `glMultiDrawArraysIndirect` call,
+/// see `multi_draw_elements_indirect`.
+pub fn multi_draw_arrays_indirect(
+    primitive_type: PrimitiveType,
+    commands: &IndirectBuffer<DrawArraysIndirectCommand>,
+) -> Result<()> {
+    multi_draw_indirect(primitive_type, None, commands)
+}
+
+/// Draws indirect commands of either kind. `indices_type` has to be set for
+/// indexed commands.
+pub(crate) fn multi_draw_indirect<C: IndirectCommand>(
+    primitive_type: PrimitiveType,
+    indices_type: Option<IndicesValueType>,
+    commands: &IndirectBuffer<C>,
+) -> Result<()> {
+    if commands.is_empty() {
+        return Ok(());
+    }
+
+    if !supports_multi_draw_indirect() {
+        let draw_commands: Vec<DrawCommand> = commands
+            .commands()
+            .iter()
+            .map(|command| {
+                let draw_command = command.to_draw_command(primitive_type);
+                match indices_type {
+                    Some(indices_type) => draw_command.indices_type(indices_type),
+                    None => draw_command,
+                }
+            })
+            .collect();
+        check_base_instances(&draw_commands, supports_base_instance())?;
+        for draw_command in &draw_commands {
+            draw_command.draw();
+        }
+        return Ok(());
+    }
+
+    let mode = primitive_type.to_gl_enum();
+    let draw_count = commands.len() as GLsizei;
+    commands.bind()?;
+    with_backend(|backend| match indices_type {
+        Some(indices_type) => {
+            backend.multi_draw_elements_indirect(mode, indices_type.to_gl_enum(), 0, draw_count, 0)
+        }
+        None => backend.multi_draw_arrays_indirect(mode, 0, draw_count, 0),
+    });
+    commands.unbind()
+}
+
+/// Checks that the commands can be drawn one by one. Base instances need the
+/// `*BaseInstance` draw calls of OpenGL 4.2, which aren't available on every context
+/// without multi-draw indirect.
+fn check_base_instances(commands: &[DrawCommand], supports_base_instance: bool) -> Result<()> {
+    if supports_base_instance {
+        return Ok(());
+    }
+    match commands.iter().position(|command| command.base_instance != 0) {
+        Some(index) => Err(anyhow!(
+            "Indirect command {} has the base instance {}, which needs OpenGL 4.2 or \
+             GL_ARB_base_instance",
+            index,
+            commands[index].base_instance
+        )),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opengl::gl_backend::{install_backend, GlCall, RecordingGlBackend};
+
+    #[test]
+    fn test_primitive_restart_is_restored() {
+        let backend = RecordingGlBackend::new();
+        let _guard = install_backend(backend.clone());
+        let strip = DrawCommand::elements(PrimitiveType::TriangleStrip, 8)
+            .indices_type(IndicesValueType::Short)
+            .primitive_restart(true);
+
+        strip.draw();
+        assert!(!backend.is_enabled(gl::PRIMITIVE_RESTART));
+        assert!(backend.take_calls().contains(&GlCall::PrimitiveRestartIndex(0xFFFF)));
+
+        // Restart enabled by the caller stays enabled, with its index
+        with_backend(|backend| {
+            backend.enable(gl::PRIMITIVE_RESTART);
+            backend.primitive_restart_index(7);
+        });
+        strip.draw();
+        assert!(backend.is_enabled(gl::PRIMITIVE_RESTART));
+        assert_eq!(backend.state(gl::PRIMITIVE_RESTART_INDEX)[0], 7);
+
+        // Commands without primitive restart don't touch it
+        backend.take_calls();
+        strip.primitive_restart(false).draw();
+        assert!(matches!(backend.calls()[..], [GlCall::DrawElements { .. }]));
+        assert!(backend.is_enabled(gl::PRIMITIVE_RESTART));
+    }
+
+    #[test]
+    fn test_index_offset_depends_on_indices_type() {
+        let command = DrawCommand::elements(PrimitiveType::Triangles, 6).first(3);
+        assert_eq!(command.index_offset(), 12);
+        assert_eq!(command.indices_type(IndicesValueType::Short).index_offset(), 6);
+        assert_eq!(command.indices_type(IndicesValueType::Byte).index_offset(), 3);
+
+        let command = DrawCommand::arrays(PrimitiveType::Triangles, 6).first(3);
+        assert!(!command.is_indexed());
+        assert_eq!(command.index_offset(), 0);
+    }
+
+    #[test]
+    fn test_indirect_commands_match_gl_layout() {
+        assert_eq!(size_of::<DrawElementsIndirectCommand>(), 20);
+        assert_eq!(size_of::<DrawArraysIndirectCommand>(), 16);
+    }
+
+    #[test]
+    fn test_indirect_command_converts_to_draw_command() {
+        let command = DrawElementsIndirectCommand::new(36)
+            .first_index(72)
+            .base_vertex(48)
+            .instances(4)
+            .base_instance(8)
+            .to_draw_command(PrimitiveType::Triangles);
+
+        assert_eq!(command.count, 36);
+        assert_eq!(command.first, 72);
+        assert_eq!(command.base_vertex, 48);
+        assert_eq!(command.instance_count, 4);
+        assert_eq!(command.base_instance, 8);
+        assert_eq!(command.indices_type, Some(IndicesValueType::Int));
+    }
+
+    #[test]
+    fn test_base_instances_need_support() {
+        let commands = [
+            DrawCommand::arrays(PrimitiveType::Triangles, 3),
+            DrawCommand::arrays(PrimitiveType::Triangles, 3).base_instance(1),
+        ];
+        assert!(check_base_instances(&commands[..1], false).is_ok());
+        assert!(check_base_instances(&commands, true).is_ok());
+        let error = check_base_instances(&commands, false).unwrap_err();
+        assert!(error.to_string().starts_with("Indirect command 1 has the base instance 1"));
     }
 }
@@ -10,12 +10,14 @@ use gl_utils::*;
 
 use crate::gl_traits::ToOpenGL;
 use crate::gl_utils;
+use crate::opengl::gl_backend::with_backend;
 use crate::opengl::vertex_attribute::VertexAttribute;
 
 //////////////////////////////////////////////////////////////////////////////
 // - BufferType -
 //////////////////////////////////////////////////////////////////////////////
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BufferType {
     /// Stores vertex attributes like vertex coordinates, normals, texture coordinates, etc.
     ArrayBuffer,
@@ -143,11 +145,75 @@ impl BufferUsage {
     }
 }
 
+//////////////////////////////////////////////////////////////////////////////
+// - BufferMapFlags -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Optional flags for mapping a range of a buffer object for writing
+/// (see `BufferObject::map_range`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BufferMapFlags {
+    /// The previous contents of the mapped range may be discarded.
+    pub invalidate_range: bool,
+    /// The previous contents of the whole buffer may be discarded.
+    pub invalidate_buffer: bool,
+    /// OpenGL doesn't wait for pending operations on the buffer before mapping it.
+    /// The caller is responsible for not overwriting data which is still in use.
+    pub unsynchronized: bool,
+    /// Modified sub-ranges have to be flushed explicitly before unmapping.
+    pub flush_explicit: bool,
+}
+
+impl BufferMapFlags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn invalidate_range(mut self) -> Self {
+        self.invalidate_range = true;
+        self
+    }
+
+    pub fn invalidate_buffer(mut self) -> Self {
+        self.invalidate_buffer = true;
+        self
+    }
+
+    pub fn unsynchronized(mut self) -> Self {
+        self.unsynchronized = true;
+        self
+    }
+
+    pub fn flush_explicit(mut self) -> Self {
+        self.flush_explicit = true;
+        self
+    }
+}
+
+impl ToOpenGL for BufferMapFlags {
+    fn to_opengl(&self) -> u32 {
+        let mut access = gl::MAP_WRITE_BIT;
+        if self.invalidate_range {
+            access |= gl::MAP_INVALIDATE_RANGE_BIT;
+        }
+        if self.invalidate_buffer {
+            access |= gl::MAP_INVALIDATE_BUFFER_BIT;
+        }
+        if self.unsynchronized {
+            access |= gl::MAP_UNSYNCHRONIZED_BIT;
+        }
+        if self.flush_explicit {
+            access |= gl::MAP_FLUSH_EXPLICIT_BIT;
+        }
+        access
+    }
+}
+
 //////////////////////////////////////////////////////////////////////////////
 // - VertexDataType -
 //////////////////////////////////////////////////////////////////////////////
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VertexDataType {
     Byte,
     UnsignedByte,
@@ -232,6 +298,54 @@ impl VertexDataType {
             VertexDataType::UnsignedInt_10F_11F_11F_Rev => 4,
         }
     }
+
+    /// Returns `true` for the plain integer types, which can be read as integers by
+    /// `int`/`uint` shader attributes. Packed types aren't included.
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            VertexDataType::Byte
+                | VertexDataType::UnsignedByte
+                | VertexDataType::Short
+                | VertexDataType::UnsignedShort
+                | VertexDataType::Int
+                | VertexDataType::UnsignedInt
+        )
+    }
+
+    /// Returns `true` for the types which pack all components of an attribute into a
+    /// single 32-bit value, so that `size` is the size of the whole attribute.
+    pub fn is_packed(&self) -> bool {
+        matches!(
+            self,
+            VertexDataType::Int_2_10_10_10_Rev
+                | VertexDataType::UnsignedInt_2_10_10_10_Rev
+                | VertexDataType::UnsignedInt_10F_11F_11F_Rev
+        )
+    }
+
+    /// Returns the data type with the given name, as written by `Display`. The
+    /// comparison ignores case, so `"float"` matches `VertexDataType::Float`.
+    pub fn from_name(name: &str) -> Option<VertexDataType> {
+        const ALL_TYPES: [VertexDataType; 13] = [
+            VertexDataType::Byte,
+            VertexDataType::UnsignedByte,
+            VertexDataType::Short,
+            VertexDataType::UnsignedShort,
+            VertexDataType::Int,
+            VertexDataType::UnsignedInt,
+            VertexDataType::HalfFloat,
+            VertexDataType::Float,
+            VertexDataType::Double,
+            VertexDataType::Fixed,
+            VertexDataType::Int_2_10_10_10_Rev,
+            VertexDataType::UnsignedInt_2_10_10_10_Rev,
+            VertexDataType::UnsignedInt_10F_11F_11F_Rev,
+        ];
+        ALL_TYPES
+            .into_iter()
+            .find(|data_type| data_type.to_string().eq_ignore_ascii_case(name))
+    }
 }
 
 impl Display for VertexDataType {
@@ -291,25 +405,20 @@ impl VertexAttributeType {
     ///
     /// # Errors
     /// Returns an error if an OpenGL error occurs during attribute setup.
-    ///
-    /// # Safety
-    /// This method contains unsafe code that calls into the OpenGL API. It is the caller's
-    /// responsibility to ensure that the provided parameters are valid and that the OpenGL
-    /// context is correctly set up.
     pub fn setup(&self, index: GLuint, stride: GLsizei, offset: *const c_void) -> Result<()> {
         let (size, r#type, normalized) = self.to_gl_data();
-        unsafe {
-            gl::EnableVertexAttribArray(index);
-            gl::VertexAttribPointer(
+        with_backend(|backend| {
+            backend.enable_vertex_attrib_array(index);
+            backend.vertex_attrib_pointer(
                 index,
                 size,
                 r#type,
-                normalized,
+                normalized == gl::TRUE,
                 stride,
-                offset as *const c_void,
+                offset as usize,
             );
-            check_gl_error().context(format!("Failed to set up attribute {}", index))?;
-        }
+        });
+        check_gl_error().context(format!("Failed to set up attribute {}", index))?;
 
         Ok(())
     }
@@ -496,6 +605,51 @@ impl IndicesValueType {
             IndicesValueType::Int => gl::UNSIGNED_INT,
         }
     }
+
+    /// Returns the size of a single index in bytes.
+    pub fn size(&self) -> usize {
+        match self {
+            IndicesValueType::Byte => size_of::<u8>(),
+            IndicesValueType::Short => size_of::<u16>(),
+            IndicesValueType::Int => size_of::<u32>(),
+        }
+    }
+
+    /// Returns the index value which restarts the primitive, the maximum value of the type.
+    pub fn restart_index(&self) -> u32 {
+        match self {
+            IndicesValueType::Byte => u8::MAX as u32,
+            IndicesValueType::Short => u16::MAX as u32,
+            IndicesValueType::Int => u32::MAX,
+        }
+    }
+
+    /// Returns the type for indices which take `size` bytes, if there is one.
+    pub fn from_size(size: usize) -> Option<Self> {
+        match size {
+            1 => Some(IndicesValueType::Byte),
+            2 => Some(IndicesValueType::Short),
+            4 => Some(IndicesValueType::Int),
+            _ => None,
+        }
+    }
+}
+
+/// Implemented by the value types which can be stored in an element buffer.
+pub trait IndexValue: Copy {
+    const INDICES_VALUE_TYPE: IndicesValueType;
+}
+
+impl IndexValue for u8 {
+    const INDICES_VALUE_TYPE: IndicesValueType = IndicesValueType::Byte;
+}
+
+impl IndexValue for u16 {
+    const INDICES_VALUE_TYPE: IndicesValueType = IndicesValueType::Short;
+}
+
+impl IndexValue for u32 {
+    const INDICES_VALUE_TYPE: IndicesValueType = IndicesValueType::Int;
 }
 
 //////////////////////////////////////////////////////////////////////////////
@@ -623,6 +777,7 @@ impl TextureTarget {
 //////////////////////////////////////////////////////////////////////////////
 
 /// Represents OpenGL capabilities that can be enabled or disabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Capability {
     /// Capability to perform alpha testing.
     //AlphaTest = gl::ALPHA_TEST as isize,
@@ -654,17 +809,17 @@ impl Capability {
 
     /// Enables this OpenGL capability.
     pub fn enable(self) {
-        unsafe { gl::Enable(self.to_gl_enum()) }
+        with_backend(|backend| backend.enable(self.to_gl_enum()))
     }
 
     /// Disables this OpenGL capability.
     pub fn disable(self) {
-        unsafe { gl::Disable(self.to_gl_enum()) }
+        with_backend(|backend| backend.disable(self.to_gl_enum()))
     }
 
     /// Returns true if the OpenGL capability is currently enabled.
     pub fn check_enabled(self) -> bool {
-        unsafe { gl::IsEnabled(self.to_gl_enum()) > 0 }
+        with_backend(|backend| backend.is_enabled(self.to_gl_enum()))
     }
 }
 
@@ -1,10 +1,17 @@
 #![allow(dead_code)]
 
+use crate::opengl::gl_backend::with_backend;
 use crate::string_utils::convert_glubyte_to_string;
 use anyhow::Result;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::os::raw::c_void;
 use std::ptr;
 
+thread_local! {
+    static CAPABILITY_CACHE: RefCell<HashMap<&'static str, bool>> = RefCell::new(HashMap::new());
+}
+
 /// Converts an `usize` offset to a raw pointer of type `*const c_void`.
 ///
 /// This function is designed to convert numerical offsets to equivalent raw pointers.
@@ -51,7 +58,7 @@ pub fn as_c_void(offset: usize) -> *const c_void {
 }
 
 pub(crate) fn check_gl_error2() -> Result<()> {
-    let error = unsafe { gl::GetError() };
+    let error = with_backend(|backend| backend.get_error());
     if error != gl::NO_ERROR {
         return Err(anyhow::anyhow!("OpenGL error: {}", error));
     }
@@ -62,7 +69,7 @@ pub(crate) fn check_gl_error() -> Result<()> {
     let mut errors = Vec::new();
 
     loop {
-        let error_code = unsafe { gl::GetError() };
+        let error_code = with_backend(|backend| backend.get_error());
         if error_code == gl::NO_ERROR {
             break;
         }
@@ -154,3 +161,59 @@ pub fn gl_get_version() -> String {
     };
     version.to_string()
 }
+
+/// Returns the major and minor version of the current OpenGL context.
+pub fn gl_get_version_number() -> (i32, i32) {
+    with_backend(|backend| {
+        (backend.get_integer(gl::MAJOR_VERSION), backend.get_integer(gl::MINOR_VERSION))
+    })
+}
+
+/// Returns true if the version of the current OpenGL context is at least
+/// `major.minor`.
+pub fn gl_version_at_least(major: i32, minor: i32) -> bool {
+    gl_get_version_number() >= (major, minor)
+}
+
+/// Returns whether the current context has the capability `name`, running `check`
+/// only on the first call. Capability checks which run every frame should use this,
+/// as `gl_has_extension` enumerates all extensions. The cache is cleared with
+/// `clear_capability_cache` when the context is destroyed.
+pub fn cached_capability(name: &'static str, check: impl FnOnce() -> bool) -> bool {
+    if let Some(supported) = CAPABILITY_CACHE.with(|cache| cache.borrow().get(name).copied()) {
+        return supported;
+    }
+    let supported = check();
+    CAPABILITY_CACHE.with(|cache| cache.borrow_mut().insert(name, supported));
+    supported
+}
+
+/// Forgets the results of `cached_capability`. Called when `SdlWindow` is dropped,
+/// as the next context may support other capabilities.
+pub fn clear_capability_cache() {
+    let _ = CAPABILITY_CACHE.try_with(|cache| cache.borrow_mut().clear());
+}
+
+/// Checks if the current OpenGL context supports the extension with the given
+/// name, e.g. `"GL_ARB_buffer_storage"`.
+pub fn gl_has_extension(name: &str) -> bool {
+    with_backend(|backend| {
+        let extension_count = backend.get_integer(gl::NUM_EXTENSIONS);
+        (0..extension_count.max(0) as u32)
+            .any(|index| backend.get_string_i(gl::EXTENSIONS, index).as_deref() == Some(name))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cached_capability() {
+        assert!(cached_capability("test capability", || true));
+        // The first result is kept until the cache is cleared
+        assert!(cached_capability("test capability", || false));
+        clear_capability_cache();
+        assert!(!cached_capability("test capability", || false));
+    }
+}
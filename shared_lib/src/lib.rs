@@ -1,5 +1,7 @@
 #![allow(unused)]
 extern crate gl;
+// Lets the code generated by `#[derive(VertexLayout)]` refer to `::shared_lib` inside this crate
+extern crate self as shared_lib;
 
 use crate::color::Color;
 use anyhow::Result;
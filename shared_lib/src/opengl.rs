@@ -1,16 +1,34 @@
 pub mod blend_guard;
 pub mod buffer_object;
+pub mod clip_mask;
+pub mod debug_output;
 pub mod font;
+pub mod framebuffer;
+pub mod gl_backend;
+pub mod golden_image;
+pub mod gpu_timer;
+pub mod indirect_buffer;
+pub mod occlusion;
+pub mod pipeline_state;
+pub mod post_process;
+pub mod query;
+pub mod resource_registry;
 pub mod shader;
 mod shader_compile;
 pub mod shader_manager;
 pub mod shader_program;
 pub mod shader_uniform_matrix;
 pub mod shader_uniform_value;
+pub mod state_guard;
+pub mod stencil;
+pub mod stencil_outline;
+pub mod stream_buffer;
 pub mod texture;
 pub mod texture_builder;
 pub mod texture_manager;
+pub mod texture_manifest;
 pub mod texture_utils;
+pub mod transform_feedback;
 pub mod vertex_array_object;
 pub mod vertex_attribute;
 pub mod vertex_layout;
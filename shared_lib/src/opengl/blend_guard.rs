@@ -3,6 +3,7 @@ use gl::types::GLenum;
 
 use crate::check_gl_panic;
 use crate::gl_utils::check_gl_error;
+use crate::opengl::gl_backend::with_backend;
 
 pub struct BlendGuard {
     original_blend: bool,
@@ -15,14 +16,14 @@ pub struct BlendGuard {
 
 impl BlendGuard {
     pub fn new(blend_src: GLenum, blend_dest: GLenum) -> Result<Self> {
-        let mut original_blend = false;
-        unsafe {
-            original_blend = gl::IsEnabled(gl::BLEND) == gl::TRUE;
-            gl::Enable(gl::BLEND);
-            check_gl_panic!("Error enabling GL blend");
-            gl::BlendFunc(blend_src, blend_dest);
-            check_gl_panic!("Error calling blend function");
-        }
+        let original_blend = with_backend(|backend| {
+            let original_blend = backend.is_enabled(gl::BLEND);
+            backend.enable(gl::BLEND);
+            original_blend
+        });
+        check_gl_panic!("Error enabling GL blend");
+        with_backend(|backend| backend.blend_func(blend_src, blend_dest));
+        check_gl_panic!("Error calling blend function");
         check_gl_error()?;
         Ok(BlendGuard {
             original_blend,
@@ -36,12 +37,12 @@ impl BlendGuard {
 
     pub fn enable(&mut self) -> Result<()> {
         if self.enabled && self.call_callback(true) {
-            unsafe {
-                gl::Enable(gl::BLEND);
-                gl::BlendFunc(self.blend_src, self.blend_dest);
-                if let Some(separate_blend) = self.separate_blend {
-                    enable_separate_blend(&separate_blend)?;
-                }
+            with_backend(|backend| {
+                backend.enable(gl::BLEND);
+                backend.blend_func(self.blend_src, self.blend_dest);
+            });
+            if let Some(separate_blend) = self.separate_blend {
+                enable_separate_blend(&separate_blend)?;
             }
             check_gl_error()?;
         }
@@ -50,9 +51,7 @@ impl BlendGuard {
 
     pub fn disable(&mut self) -> Result<()> {
         if self.enabled && self.call_callback(false) {
-            unsafe {
-                gl::Disable(gl::BLEND);
-            }
+            with_backend(|backend| backend.disable(gl::BLEND));
             check_gl_error()?;
         }
         Ok(())
@@ -67,11 +66,9 @@ impl BlendGuard {
 
     pub fn set_blend_func_immediate(&mut self, src: GLenum, dest: GLenum) -> Result<()> {
         self.set_blend_func(src, dest)?;
-        unsafe {
-            gl::BlendFunc(self.blend_src, self.blend_dest);
-            if let Some(separate_blend) = self.separate_blend {
-                enable_separate_blend(&separate_blend)?;
-            }
+        with_backend(|backend| backend.blend_func(self.blend_src, self.blend_dest));
+        if let Some(separate_blend) = self.separate_blend {
+            enable_separate_blend(&separate_blend)?;
         }
         check_gl_error()?;
         Ok(())
@@ -97,7 +94,7 @@ impl BlendGuard {
     }
 
     pub fn is_blend_enabled(&self) -> bool {
-        unsafe { gl::IsEnabled(gl::BLEND) == gl::TRUE }
+        with_backend(|backend| backend.is_enabled(gl::BLEND))
     }
 
     pub fn set_separate_blend_func(&mut self, separate_blend: Option<SeparateBlend>) -> Result<()> {
@@ -126,7 +123,7 @@ impl Drop for BlendGuard {
 unsafe impl Send for BlendGuard {}
 unsafe impl Sync for BlendGuard {}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct SeparateBlend {
     pub src_rgb: GLenum,
     pub dest_rgb: GLenum,
@@ -146,13 +143,10 @@ impl SeparateBlend {
 }
 
 fn enable_separate_blend(separate_blend: &SeparateBlend) -> Result<()> {
-    unsafe {
-        let src_rgb = separate_blend.src_rgb;
-        let dest_rgb = separate_blend.dest_rgb;
-        let src_alpha = separate_blend.src_alpha;
-        let dest_alpha = separate_blend.dest_alpha;
-        gl::BlendFuncSeparate(src_rgb, dest_rgb, src_alpha, dest_alpha);
-        check_gl_error()?;
-    }
-    Ok(())
+    let src_rgb = separate_blend.src_rgb;
+    let dest_rgb = separate_blend.dest_rgb;
+    let src_alpha = separate_blend.src_alpha;
+    let dest_alpha = separate_blend.dest_alpha;
+    with_backend(|backend| backend.blend_func_separate(src_rgb, dest_rgb, src_alpha, dest_alpha));
+    check_gl_error()
 }
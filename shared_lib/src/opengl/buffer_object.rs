@@ -1,22 +1,38 @@
 use crate::gl_prelude::check_gl_error;
-use crate::gl_traits::{Bindable, Deletable};
-use crate::gl_types::{BufferType, BufferUsage};
+use crate::gl_traits::{Bindable, Deletable, ToOpenGL};
+use crate::gl_types::{BufferMapFlags, BufferType, BufferUsage, IndicesValueType};
+use crate::opengl::gl_backend::with_backend;
+use crate::opengl::resource_registry::{self, ResourceKind};
 use crate::opengl::vertex_array_object::VertexArrayObject;
-use anyhow::Result;
-use gl::types::{GLint, GLsizeiptr};
+use anyhow::{anyhow, Context, Result};
+use gl::types::{GLbitfield, GLint, GLintptr, GLsizeiptr};
+use std::any::type_name;
 use std::ffi::c_void;
-use std::mem::size_of;
+use std::mem::{size_of, size_of_val};
 use std::ptr;
 
 //////////////////////////////////////////////////////////////////////////////
 // - BufferObject -
 //////////////////////////////////////////////////////////////////////////////
 
+/// A typed OpenGL buffer object.
+///
+/// The buffer distinguishes between its length (the number of elements which
+/// have been uploaded) and its capacity (the number of elements the GPU storage
+/// can hold). Growing the buffer beyond its capacity re-allocates (orphans) the
+/// GPU storage with at least twice the previous capacity.
+///
+/// By default a CPU shadow copy of the uploaded elements is kept, which can be
+/// read with `data()`. Large streaming buffers can opt out with
+/// `without_shadow_copy`.
 pub struct BufferObject<T> {
     id: u32,
     buffer_type: BufferType,
     buffer_usage: BufferUsage,
     data: Vec<T>,
+    data_len: usize,
+    capacity: usize,
+    keep_shadow_copy: bool,
 }
 
 impl<T> BufferObject<T> {
@@ -89,30 +105,73 @@ impl<T> BufferObject<T> {
     /// available and current. Failing to do so could result in OpenGL errors or undefined behavior.
 
     pub fn new(type_: BufferType, usage: BufferUsage, data: Vec<T>) -> BufferObject<T> {
-        let mut id = 0;
         let buffer_type = type_.to_gl_enum();
-        unsafe {
-            gl::GenBuffers(1, &mut id);
-            gl::BindBuffer(buffer_type, id);
+        let id = with_backend(|backend| {
+            let id = backend.gen_buffer();
+            backend.bind_buffer(buffer_type, id);
 
             if !data.is_empty() {
-                gl::BufferData(
-                    buffer_type,
-                    (data.len() * size_of::<T>()) as GLsizeiptr,
-                    data.as_ptr() as *const c_void,
-                    usage.to_gl_enum(),
-                );
+                unsafe {
+                    backend.buffer_data(
+                        buffer_type,
+                        (data.len() * size_of::<T>()) as GLsizeiptr,
+                        data.as_ptr() as *const c_void,
+                        usage.to_gl_enum(),
+                    );
+                }
             }
-        }
+            id
+        });
+
+        let label = format!("{:?} of {}", type_, type_name::<T>());
+        resource_registry::register(ResourceKind::Buffer, id, label, data.len() * size_of::<T>());
 
         BufferObject {
             id,
             buffer_type: type_,
             buffer_usage: usage,
+            data_len: data.len(),
+            capacity: data.len(),
             data,
+            keep_shadow_copy: true,
+        }
+    }
+
+    /// Creates a buffer object with uninitialized GPU storage for `capacity`
+    /// elements. The buffer is empty until data is written with `update_range` or
+    /// `map_range`.
+    pub fn with_capacity(type_: BufferType, usage: BufferUsage, capacity: usize) -> Self {
+        let mut buffer_object = Self::new(type_, usage, Vec::new());
+        if capacity > 0 {
+            with_backend(|backend| unsafe {
+                backend.buffer_data(
+                    type_.to_gl_enum(),
+                    (capacity * size_of::<T>()) as GLsizeiptr,
+                    ptr::null(),
+                    usage.to_gl_enum(),
+                );
+            });
+            buffer_object.capacity = capacity;
+            buffer_object.update_registered_size();
         }
+        buffer_object
+    }
+
+    /// Stops keeping a CPU copy of the buffer contents and releases the current
+    /// one. Afterwards `data()` returns an empty vector, while `data_len()` still
+    /// reports the number of elements stored on the GPU.
+    pub fn without_shadow_copy(mut self) -> Self {
+        self.keep_shadow_copy = false;
+        self.data = Vec::new();
+        self
     }
 
+    pub fn keeps_shadow_copy(&self) -> bool {
+        self.keep_shadow_copy
+    }
+
+    /// Creates a buffer while `vao` is bound. Element buffers are attached to the
+    /// vertex array object this way, which remembers the type of their indices.
     pub fn new_with_vao(
         vao: &VertexArrayObject,
         type_: BufferType,
@@ -122,6 +181,11 @@ impl<T> BufferObject<T> {
         vao.bind();
         let vbo = Self::new(type_, usage, data);
         VertexArrayObject::unbind();
+        if type_ == BufferType::ElementArrayBuffer {
+            if let Some(indices_type) = IndicesValueType::from_size(size_of::<T>()) {
+                vao.remember_element_buffer(vbo.id, indices_type);
+            }
+        }
         vbo
     }
 
@@ -133,23 +197,47 @@ impl<T> BufferObject<T> {
         self.buffer_usage
     }
 
+    pub fn buffer_type(&self) -> BufferType {
+        self.buffer_type
+    }
+
+    /// Returns the CPU shadow copy of the buffer contents. The vector is empty if
+    /// the buffer was created `without_shadow_copy`.
     pub fn data(&self) -> &Vec<T> {
         &self.data
     }
 
+    /// Returns the number of elements stored in the buffer.
     pub fn data_len(&self) -> usize {
-        self.data.len()
+        self.data_len
     }
 
+    /// Returns the size of the stored elements in bytes.
     pub fn data_size(&self) -> usize {
-        self.data.len() + size_of::<T>()
+        self.data_len * size_of::<T>()
+    }
+
+    /// Returns the number of elements the GPU storage can hold without growing.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the size of the GPU storage in bytes.
+    pub fn capacity_size(&self) -> usize {
+        self.capacity * size_of::<T>()
+    }
+
+    /// Keeps the size of the buffer in the resource registry up to date.
+    fn update_registered_size(&self) {
+        resource_registry::set_byte_size(ResourceKind::Buffer, self.id, self.capacity_size());
     }
 
     /// Updates the data of the buffer object.
     ///
-    /// This function updates the internal data of the buffer object with the provided vertices.
+    /// This function replaces the contents of the buffer object with the provided vertices.
     /// Optionally, it can also update the buffer's usage pattern if a new one is provided.
-    /// It then binds the buffer and updates its data in the OpenGL context.
+    /// The GPU storage is orphaned and only re-allocated with a larger size if the vertices
+    /// exceed the current capacity, otherwise the capacity is kept.
     ///
     /// # Parameters
     /// - `vertices`: A vector of type `T` containing the new vertex data to be stored
@@ -157,39 +245,204 @@ impl<T> BufferObject<T> {
     /// - `usage`: An optional parameter of type `BufferUsage` that specifies the new usage
     ///   pattern of the buffer. If `None`, the current usage pattern remains unchanged.
     ///
-    /// # Returns
-    /// - `Result<()>`: Returns `Ok(())` if the operation is successful, or an error if
-    ///   an OpenGL error is encountered.
-    ///
     /// # Safety
     /// This function involves unsafe operations to interact with the OpenGL API. It assumes
     /// that the OpenGL context is properly initialized and that the buffer ID is valid.
     ///
     /// # Example
     /// ```no-run
-    /// buffer.update_data(new_vertices, Some(BufferUsage::DynamicDraw))?;
+    /// buffer.update_data(new_vertices, Some(BufferUsage::DynamicDraw));
     /// ```
     pub fn update_data(&mut self, vertices: Vec<T>, usage: Option<BufferUsage>) {
-        self.data = vertices;
-
         if let Some(new_usage) = usage {
             self.buffer_usage = new_usage;
         }
 
         let buffer_type = self.buffer_type.to_gl_enum();
+        let capacity = self.grown_capacity(vertices.len());
 
-        let data_len = self.data.len();
-        let data_size = data_len * size_of::<T>();
-
-        unsafe {
-            gl::BindBuffer(buffer_type, self.id);
-            gl::BufferData(
+        with_backend(|backend| unsafe {
+            backend.bind_buffer(buffer_type, self.id);
+            backend.buffer_data(
                 buffer_type,
-                data_size as GLsizeiptr,
-                self.data.as_ptr() as *const c_void,
+                (capacity * size_of::<T>()) as GLsizeiptr,
+                ptr::null(),
+                self.buffer_usage.to_gl_enum(),
+            );
+            if !vertices.is_empty() {
+                backend.buffer_sub_data(
+                    buffer_type,
+                    0,
+                    (vertices.len() * size_of::<T>()) as GLsizeiptr,
+                    vertices.as_ptr() as *const c_void,
+                );
+            }
+        });
+
+        self.capacity = capacity;
+        self.update_registered_size();
+        self.data_len = vertices.len();
+        if self.keep_shadow_copy {
+            self.data = vertices;
+        }
+    }
+
+    /// Makes sure that the GPU storage can hold at least `capacity` elements.
+    /// The current contents of the buffer are preserved.
+    pub fn reserve(&mut self, capacity: usize) -> Result<()> {
+        if capacity <= self.capacity {
+            return Ok(());
+        }
+        self.resize_storage(capacity)
+    }
+
+    /// Returns the capacity needed to hold `required` elements, growing by at least
+    /// a factor of two to keep the number of re-allocations low.
+    fn grown_capacity(&self, required: usize) -> usize {
+        if required <= self.capacity {
+            self.capacity
+        } else {
+            required.max(self.capacity * 2)
+        }
+    }
+
+    /// Re-allocates the GPU storage with `capacity` elements and restores the
+    /// stored elements, either from the shadow copy or through a temporary buffer.
+    ///
+    /// The buffer is bound to the copy targets only, so that the element buffer
+    /// binding of the current vertex array object isn't affected.
+    fn resize_storage(&mut self, capacity: usize) -> Result<()> {
+        let used_size = (self.data_len * size_of::<T>()) as GLsizeiptr;
+        let copy_through_gpu = used_size > 0 && !self.keep_shadow_copy;
+
+        with_backend(|backend| unsafe {
+            // Save the contents which would be lost by orphaning
+            let mut temp_buffer = 0;
+            if copy_through_gpu {
+                temp_buffer = backend.gen_buffer();
+                backend.bind_buffer(gl::COPY_WRITE_BUFFER, temp_buffer);
+                backend.buffer_data(gl::COPY_WRITE_BUFFER, used_size, ptr::null(), gl::STREAM_COPY);
+                backend.bind_buffer(gl::COPY_READ_BUFFER, self.id);
+                backend.copy_buffer_sub_data(
+                    gl::COPY_READ_BUFFER,
+                    gl::COPY_WRITE_BUFFER,
+                    0,
+                    0,
+                    used_size,
+                );
+            }
+
+            backend.bind_buffer(gl::COPY_WRITE_BUFFER, self.id);
+            backend.buffer_data(
+                gl::COPY_WRITE_BUFFER,
+                (capacity * size_of::<T>()) as GLsizeiptr,
+                ptr::null(),
                 self.buffer_usage.to_gl_enum(),
             );
+
+            if copy_through_gpu {
+                backend.bind_buffer(gl::COPY_READ_BUFFER, temp_buffer);
+                backend.copy_buffer_sub_data(
+                    gl::COPY_READ_BUFFER,
+                    gl::COPY_WRITE_BUFFER,
+                    0,
+                    0,
+                    used_size,
+                );
+                backend.delete_buffer(temp_buffer);
+            } else if used_size > 0 {
+                backend.buffer_sub_data(
+                    gl::COPY_WRITE_BUFFER,
+                    0,
+                    used_size,
+                    self.data.as_ptr() as *const c_void,
+                );
+            }
+
+            backend.bind_buffer(gl::COPY_READ_BUFFER, 0);
+            backend.bind_buffer(gl::COPY_WRITE_BUFFER, 0);
+        });
+        check_gl_error().with_context(|| {
+            format!("Failed to resize buffer {} to {} elements", self.id, capacity)
+        })?;
+
+        self.capacity = capacity;
+        self.update_registered_size();
+        Ok(())
+    }
+
+    /// Checks that a write at `offset` doesn't leave a gap behind the stored
+    /// elements and grows the storage if the write exceeds the capacity.
+    ///
+    /// Gaps are allowed for buffers without shadow copy, as their contents are
+    /// only known to the GPU anyway.
+    fn prepare_write(&mut self, offset: usize, count: usize) -> Result<()> {
+        if offset > self.data_len && self.keep_shadow_copy {
+            return Err(anyhow!(
+                "Offset {} would leave a gap, the buffer contains {} elements",
+                offset,
+                self.data_len
+            ));
+        }
+
+        let end = offset + count;
+        if end > self.capacity {
+            self.resize_storage(self.grown_capacity(end))?;
         }
+        Ok(())
+    }
+
+    /// Orphans the GPU storage of the buffer.
+    ///
+    /// The storage is re-specified with the same capacity and undefined contents,
+    /// which lets the driver hand out fresh memory instead of waiting for pending
+    /// draw calls that still read the old contents. The buffer is empty afterwards.
+    pub fn orphan(&mut self) -> Result<()> {
+        with_backend(|backend| unsafe {
+            backend.bind_buffer(gl::COPY_WRITE_BUFFER, self.id);
+            backend.buffer_data(
+                gl::COPY_WRITE_BUFFER,
+                (self.capacity * size_of::<T>()) as GLsizeiptr,
+                ptr::null(),
+                self.buffer_usage.to_gl_enum(),
+            );
+            backend.bind_buffer(gl::COPY_WRITE_BUFFER, 0);
+        });
+        check_gl_error().with_context(|| format!("Failed to orphan buffer {}", self.id))?;
+
+        self.data.clear();
+        self.data_len = 0;
+        Ok(())
+    }
+
+    /// Allocates immutable storage for `capacity` elements with `glBufferStorage`
+    /// (OpenGL 4.4 or `GL_ARB_buffer_storage`).
+    ///
+    /// Immutable storage can't be re-specified, so the buffer must not be written
+    /// with `update_data`, grown or orphaned afterwards.
+    pub(crate) fn allocate_immutable_storage(
+        &mut self,
+        capacity: usize,
+        flags: GLbitfield,
+    ) -> Result<()> {
+        with_backend(|backend| unsafe {
+            backend.bind_buffer(gl::COPY_WRITE_BUFFER, self.id);
+            backend.buffer_storage(
+                gl::COPY_WRITE_BUFFER,
+                (capacity * size_of::<T>()) as GLsizeiptr,
+                ptr::null(),
+                flags,
+            );
+            backend.bind_buffer(gl::COPY_WRITE_BUFFER, 0);
+        });
+        check_gl_error()
+            .with_context(|| format!("Failed to allocate storage for buffer {}", self.id))?;
+
+        self.data.clear();
+        self.data_len = 0;
+        self.capacity = capacity;
+        self.update_registered_size();
+        Ok(())
     }
 
     /// Clears the data from the buffer object.
@@ -209,41 +462,176 @@ impl<T> BufferObject<T> {
     pub fn clear_data(&mut self, unbind: bool) {
         // Reset the BufferContent to an empty state
         self.data = Vec::new();
+        self.data_len = 0;
+        self.capacity = 0;
+        self.update_registered_size();
 
         let buffer_type = self.buffer_type.to_gl_enum();
         let buffer_usage = self.buffer_usage.to_gl_enum();
 
-        unsafe {
-            gl::BindBuffer(buffer_type, self.id);
+        with_backend(|backend| unsafe {
+            backend.bind_buffer(buffer_type, self.id);
             // Update the buffer with zero size to clear its data on the GPU
-            gl::BufferData(buffer_type, 0, ptr::null(), buffer_usage);
+            backend.buffer_data(buffer_type, 0, ptr::null(), buffer_usage);
 
             if unbind {
-                gl::BindBuffer(buffer_type, 0);
+                backend.bind_buffer(buffer_type, 0);
             }
+        });
+    }
+}
+
+impl<T: Copy> BufferObject<T> {
+    /// Overwrites a range of the buffer, starting at the element `offset`, with the
+    /// given values using `glBufferSubData`.
+    ///
+    /// The range may extend the stored elements, in which case the buffer grows as
+    /// needed, but it has to start within or directly behind them.
+    ///
+    /// # Example
+    /// ```no-run
+    /// // Replace the second and third vertex
+    /// buffer.update_range(1, &[vertex_b, vertex_c])?;
+    /// ```
+    pub fn update_range(&mut self, offset: usize, values: &[T]) -> Result<()> {
+        if values.is_empty() {
+            return Ok(());
+        }
+        self.prepare_write(offset, values.len())?;
+
+        with_backend(|backend| unsafe {
+            backend.bind_buffer(gl::COPY_WRITE_BUFFER, self.id);
+            backend.buffer_sub_data(
+                gl::COPY_WRITE_BUFFER,
+                (offset * size_of::<T>()) as GLintptr,
+                size_of_val(values) as GLsizeiptr,
+                values.as_ptr() as *const c_void,
+            );
+            backend.bind_buffer(gl::COPY_WRITE_BUFFER, 0);
+        });
+        check_gl_error().with_context(|| format!("Failed to update buffer {}", self.id))?;
+
+        self.write_shadow_copy(offset, values);
+        self.data_len = self.data_len.max(offset + values.len());
+        Ok(())
+    }
+
+    /// Maps `count` elements of the buffer, starting at the element `offset`, for
+    /// writing.
+    ///
+    /// The mapping is write only, values are stored with `MappedRange::write` and
+    /// the buffer is unmapped when the range is dropped. Like with `update_range`,
+    /// the range may extend the stored elements and the buffer grows as needed. The
+    /// written values are also kept for the shadow copy, if there is one, and copied
+    /// into it on unmapping. The contents of mapped elements which aren't written
+    /// are undefined if the range is mapped with `invalidate_range`.
+    ///
+    /// # Example
+    /// ```no-run
+    /// let flags = BufferMapFlags::new().invalidate_range();
+    /// let mut range = buffer.map_range(0, vertices.len(), flags)?;
+    /// range.write(0, &vertices)?;
+    /// ```
+    pub fn map_range(
+        &mut self,
+        offset: usize,
+        count: usize,
+        flags: BufferMapFlags,
+    ) -> Result<MappedRange<'_, T>> {
+        if count == 0 {
+            return Err(anyhow!("Can't map an empty range of buffer {}", self.id));
         }
+        self.prepare_write(offset, count)?;
+
+        let mapped_ptr = with_backend(|backend| {
+            backend.bind_buffer(gl::COPY_WRITE_BUFFER, self.id);
+            let mapped_ptr = backend.map_buffer_range(
+                gl::COPY_WRITE_BUFFER,
+                (offset * size_of::<T>()) as GLintptr,
+                (count * size_of::<T>()) as GLsizeiptr,
+                flags.to_opengl(),
+            );
+            backend.bind_buffer(gl::COPY_WRITE_BUFFER, 0);
+            mapped_ptr
+        });
+        check_gl_error().with_context(|| format!("Failed to map buffer {}", self.id))?;
+        if mapped_ptr.is_null() {
+            return Err(anyhow!("Failed to map buffer {}", self.id));
+        }
+
+        Ok(MappedRange {
+            buffer: self,
+            mapped_ptr: mapped_ptr as *mut T,
+            offset,
+            count,
+            staging: Vec::new(),
+            written: 0,
+        })
+    }
+
+    /// Reads `count` elements, starting at the element `offset`, back from the GPU
+    /// storage. This bypasses the shadow copy, so it also returns data which was
+    /// written by the GPU, e.g. with transform feedback. Reading stalls until the GPU
+    /// has finished writing.
+    ///
+    /// # Errors
+    /// Returns an error if the range exceeds the GPU storage.
+    pub fn read_gpu_data(&self, offset: usize, count: usize) -> Result<Vec<T>> {
+        if offset + count > self.capacity {
+            return Err(anyhow!(
+                "Can't read elements {}..{} of buffer {} with a capacity of {}",
+                offset,
+                offset + count,
+                self.id,
+                self.capacity
+            ));
+        }
+
+        let mut values = Vec::with_capacity(count);
+        with_backend(|backend| unsafe {
+            backend.bind_buffer(gl::COPY_READ_BUFFER, self.id);
+            backend.get_buffer_sub_data(
+                gl::COPY_READ_BUFFER,
+                (offset * size_of::<T>()) as GLintptr,
+                (count * size_of::<T>()) as GLsizeiptr,
+                values.as_mut_ptr() as *mut c_void,
+            );
+            backend.bind_buffer(gl::COPY_READ_BUFFER, 0);
+        });
+        check_gl_error().with_context(|| format!("Failed to read buffer {}", self.id))?;
+        // The elements were initialized by OpenGL
+        unsafe {
+            values.set_len(count);
+        }
+        Ok(values)
+    }
+
+    /// Writes the values into the shadow copy, extending it where necessary.
+    fn write_shadow_copy(&mut self, offset: usize, values: &[T]) {
+        if !self.keep_shadow_copy {
+            return;
+        }
+        let overlap = (self.data.len().min(offset + values.len())).saturating_sub(offset);
+        self.data[offset..offset + overlap].copy_from_slice(&values[..overlap]);
+        self.data.extend_from_slice(&values[overlap..]);
     }
 }
 
 impl<T> Bindable for BufferObject<T> {
     fn bind(&self) -> Result<()> {
-        unsafe { gl::BindBuffer(self.buffer_type.to_gl_enum(), self.id) }
+        with_backend(|backend| backend.bind_buffer(self.buffer_type.to_gl_enum(), self.id));
         check_gl_error()
     }
 
     fn unbind(&self) -> Result<()> {
-        unsafe {
-            gl::BindBuffer(self.buffer_type.to_gl_enum(), 0);
-        }
+        with_backend(|backend| backend.bind_buffer(self.buffer_type.to_gl_enum(), 0));
         check_gl_error()
     }
 
     fn is_bound(&self) -> Result<bool> {
-        let mut current_buffer_id = 0;
-        unsafe {
-            gl::GetIntegerv(self.buffer_type.to_gl_enum(), &mut current_buffer_id);
-            check_gl_error()?;
-        }
+        let current_buffer_id =
+            with_backend(|backend| backend.get_integer(self.buffer_type.to_gl_enum()));
+        check_gl_error()?;
         Ok(current_buffer_id == self.id as GLint)
     }
 }
@@ -251,9 +639,8 @@ impl<T> Bindable for BufferObject<T> {
 impl<T> Deletable for BufferObject<T> {
     fn delete(&mut self) -> Result<()> {
         if self.id != 0 {
-            unsafe {
-                gl::DeleteBuffers(1, &self.id);
-            }
+            resource_registry::unregister(ResourceKind::Buffer, self.id)?;
+            with_backend(|backend| backend.delete_buffer(self.id));
             self.id = 0;
         }
         Ok(())
@@ -269,6 +656,120 @@ impl<T> Drop for BufferObject<T> {
     }
 }
 
+//////////////////////////////////////////////////////////////////////////////
+// - MappedRange -
+//////////////////////////////////////////////////////////////////////////////
+
+/// A range of a `BufferObject` which is mapped into client memory for writing.
+/// The buffer is unmapped when the range is dropped.
+///
+/// The mapped memory is never read, as reading a write only or invalidated
+/// mapping is undefined. Values written for the shadow copy are staged instead.
+pub struct MappedRange<'a, T: Copy> {
+    buffer: &'a mut BufferObject<T>,
+    mapped_ptr: *mut T,
+    offset: usize,
+    count: usize,
+    /// The written values, only kept if the buffer has a shadow copy.
+    staging: Vec<T>,
+    /// Number of elements from the start of the range which have been written.
+    written: usize,
+}
+
+impl<T: Copy> MappedRange<'_, T> {
+    /// Returns the offset of the mapped range in elements.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the number of mapped elements.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Writes `values` to the mapping, starting at `offset` (relative to the mapped
+    /// range).
+    ///
+    /// # Errors
+    /// Returns an error if the values exceed the mapped range, or if they would leave
+    /// a gap of unwritten elements in a buffer with shadow copy.
+    pub fn write(&mut self, offset: usize, values: &[T]) -> Result<()> {
+        let end = offset + values.len();
+        if end > self.count {
+            return Err(anyhow!(
+                "Write range {}..{} exceeds the mapped range of {} elements",
+                offset,
+                end,
+                self.count
+            ));
+        }
+        let keep_shadow_copy = self.buffer.keep_shadow_copy;
+        if keep_shadow_copy && offset > self.written {
+            return Err(anyhow!(
+                "Offset {} would leave a gap, {} elements of the mapped range are written",
+                offset,
+                self.written
+            ));
+        }
+
+        unsafe {
+            ptr::copy_nonoverlapping(values.as_ptr(), self.mapped_ptr.add(offset), values.len());
+        }
+        if keep_shadow_copy {
+            let overlap = self.staging.len().min(end).saturating_sub(offset);
+            self.staging[offset..offset + overlap].copy_from_slice(&values[..overlap]);
+            self.staging.extend_from_slice(&values[overlap..]);
+        }
+        self.written = self.written.max(end);
+        Ok(())
+    }
+
+    /// Flushes `count` elements starting at `offset` (relative to the mapped range).
+    /// Only needed if the range was mapped with `BufferMapFlags::flush_explicit`.
+    pub fn flush(&self, offset: usize, count: usize) -> Result<()> {
+        if offset + count > self.count {
+            return Err(anyhow!(
+                "Flush range {}..{} exceeds the mapped range of {} elements",
+                offset,
+                offset + count,
+                self.count
+            ));
+        }
+        with_backend(|backend| {
+            backend.bind_buffer(gl::COPY_WRITE_BUFFER, self.buffer.id);
+            backend.flush_mapped_buffer_range(
+                gl::COPY_WRITE_BUFFER,
+                (offset * size_of::<T>()) as GLintptr,
+                (count * size_of::<T>()) as GLsizeiptr,
+            );
+            backend.bind_buffer(gl::COPY_WRITE_BUFFER, 0);
+        });
+        check_gl_error()
+    }
+}
+
+impl<T: Copy> Drop for MappedRange<'_, T> {
+    fn drop(&mut self) {
+        let staging = std::mem::take(&mut self.staging);
+        self.buffer.write_shadow_copy(self.offset, &staging);
+        self.buffer.data_len = self.buffer.data_len.max(self.offset + self.written);
+
+        let unmapped = with_backend(|backend| {
+            backend.bind_buffer(gl::COPY_WRITE_BUFFER, self.buffer.id);
+            let unmapped = backend.unmap_buffer(gl::COPY_WRITE_BUFFER);
+            backend.bind_buffer(gl::COPY_WRITE_BUFFER, 0);
+            unmapped
+        });
+        if !unmapped {
+            eprintln!("Buffer {} got corrupted while being mapped", self.buffer.id);
+        }
+    }
+}
+
 /// A macro to unbind multiple buffer objects and handle any potential errors.
 ///
 /// This macro takes a variadic list of buffer objects and attempts to unbind each one by calling
@@ -322,3 +823,79 @@ macro_rules! unbind_buffers {
         )*
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opengl::gl_backend::{install_backend, GlCall, RecordingGlBackend};
+
+    fn bytes(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|value| value.to_ne_bytes()).collect()
+    }
+
+    #[test]
+    fn test_growing_keeps_contents() {
+        let backend = RecordingGlBackend::new();
+        let _guard = install_backend(backend.clone());
+
+        let mut buffer =
+            BufferObject::new(BufferType::ArrayBuffer, BufferUsage::DynamicDraw, vec![1u32, 2]);
+        assert_eq!(backend.bound_buffer(gl::ARRAY_BUFFER), buffer.buffer_id());
+        assert_eq!(backend.buffer_contents(buffer.buffer_id()), Some(bytes(&[1, 2])));
+
+        // Growing re-uploads the shadow copy
+        buffer.update_range(2, &[3, 4, 5]).unwrap();
+        assert_eq!(buffer.capacity(), 5);
+        assert_eq!(backend.buffer_contents(buffer.buffer_id()), Some(bytes(&[1, 2, 3, 4, 5])));
+
+        // Without a shadow copy the contents are copied through a temporary buffer
+        let mut buffer = buffer.without_shadow_copy();
+        backend.take_calls();
+        buffer.reserve(12).unwrap();
+        let calls = backend.take_calls();
+        let temp_buffer = match calls[0] {
+            GlCall::GenBuffer(temp_buffer) => temp_buffer,
+            ref call => panic!("Expected a temporary buffer, got {:?}", call),
+        };
+        assert!(calls.contains(&GlCall::DeleteBuffer(temp_buffer)));
+        assert_eq!(
+            backend.buffer_contents(buffer.buffer_id()).unwrap()[..20],
+            bytes(&[1, 2, 3, 4, 5])
+        );
+        assert_eq!(buffer.read_gpu_data(1, 2).unwrap(), vec![2, 3]);
+
+        // The element buffer binding of vertex arrays isn't touched
+        assert_eq!(backend.bound_buffer(gl::COPY_WRITE_BUFFER), 0);
+        assert_eq!(backend.bound_buffer(gl::ELEMENT_ARRAY_BUFFER), 0);
+    }
+
+    #[test]
+    fn test_mapped_range_writes_shadow_copy() {
+        let backend = RecordingGlBackend::new();
+        let _guard = install_backend(backend.clone());
+
+        let mut buffer =
+            BufferObject::with_capacity(BufferType::ArrayBuffer, BufferUsage::StreamDraw, 4);
+        {
+            let mut range = buffer.map_range(0, 3, BufferMapFlags::new()).unwrap();
+            range.write(0, &[7u32, 8]).unwrap();
+            // Gaps and writes beyond the range are rejected
+            assert!(range.write(3, &[1]).is_err());
+            assert!(range.write(2, &[9, 10]).is_err());
+            range.write(1, &[5, 9]).unwrap();
+        }
+        assert_eq!(buffer.data(), &vec![7, 5, 9]);
+        assert_eq!(buffer.data_len(), 3);
+        assert_eq!(backend.buffer_contents(buffer.buffer_id()), Some(bytes(&[7, 5, 9, 0])));
+        assert!(backend.calls().contains(&GlCall::UnmapBuffer(gl::COPY_WRITE_BUFFER)));
+
+        // Errors of the backend are reported
+        backend.push_error(gl::OUT_OF_MEMORY);
+        assert!(buffer.update_range(0, &[1]).is_err());
+
+        let id = buffer.buffer_id();
+        drop(buffer);
+        assert_eq!(backend.buffer_contents(id), None);
+        assert_eq!(backend.live_objects(), 0);
+    }
+}
@@ -0,0 +1,132 @@
+use crate::opengl::debug_output::DebugGroup;
+use crate::opengl::pipeline_state::ColorMask;
+use crate::opengl::state_guard::{ColorMaskGuard, DepthGuard, StencilGuard};
+use crate::opengl::stencil::{StencilFunc, StencilOp, StencilState};
+use anyhow::Result;
+use std::cell::Cell;
+
+thread_local! {
+    /// Number of `ClipMask`s currently pushed, which is also the stencil value of
+    /// the innermost clip region.
+    static CLIP_LEVEL: Cell<i32> = const { Cell::new(0) };
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - ClipMask -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Restricts drawing to an arbitrarily shaped 2D region while it is alive, e.g. to
+/// clip the content of a rounded UI panel.
+///
+/// The mask is drawn into the stencil buffer only, every pixel it covers becomes
+/// part of the clip region. Masks nest: a pushed mask is intersected with the
+/// regions of the masks pushed before it. Dropping the mask draws it a second time
+/// to remove it from the stencil buffer, so the outer region is usable again.
+///
+/// The window needs a stencil buffer, see `SdlWindowOptions::stencil_bits`. With
+/// 8 stencil bits up to 255 masks can be nested.
+///
+/// # Example
+/// ```ignore
+/// let panel = ClipMask::push(|| panel_shape.draw())?;
+/// text_renderer.render_text("Long text which is cut off at the panel", position, 1.0)?;
+/// {
+///     let _icon = ClipMask::push(|| icon_circle.draw())?;
+///     icon.draw()?;
+/// }
+/// drop(panel);
+/// ```
+pub struct ClipMask<F: Fn() -> Result<()>> {
+    draw_mask: F,
+    /// The clip level outside of this mask.
+    outer_level: i32,
+    content_guard: Option<StencilGuard>,
+}
+
+impl<F: Fn() -> Result<()>> ClipMask<F> {
+    /// Draws the mask into the stencil buffer and restricts drawing to the pixels
+    /// covered by it and all outer masks.
+    ///
+    /// `draw_mask` is called again when the mask is dropped and has to draw the same
+    /// shape. Color, depth and stencil state are handled by the mask.
+    ///
+    /// # Errors
+    /// Returns an error if `draw_mask` fails or OpenGL reports an error.
+    pub fn push(draw_mask: F) -> Result<Self> {
+        let outer_level = CLIP_LEVEL.with(Cell::get);
+        draw_mask_pass(&draw_mask, outer_level, StencilOp::Increment)?;
+        CLIP_LEVEL.with(|level| level.set(outer_level + 1));
+
+        let mut clip_mask = Self {
+            draw_mask,
+            outer_level,
+            content_guard: None,
+        };
+        clip_mask.content_guard = Some(StencilGuard::new(inside_state(outer_level + 1))?);
+        Ok(clip_mask)
+    }
+
+    /// Returns the nesting level of this mask, starting at 1.
+    pub fn level(&self) -> i32 {
+        self.outer_level + 1
+    }
+}
+
+impl<F: Fn() -> Result<()>> Drop for ClipMask<F> {
+    fn drop(&mut self) {
+        // Restore the caller's stencil state before removing the mask
+        self.content_guard.take();
+        let result = draw_mask_pass(&self.draw_mask, self.outer_level + 1, StencilOp::Decrement);
+        CLIP_LEVEL.with(|level| level.set(self.outer_level));
+        if let Err(err) = result {
+            eprintln!("Error while dropping ClipMask: {}", err);
+        }
+    }
+}
+
+/// Draws the mask into the stencil buffer only, changing the pixels which are
+/// inside the clip region at `level`.
+fn draw_mask_pass<F: Fn() -> Result<()>>(draw_mask: &F, level: i32, op: StencilOp) -> Result<()> {
+    let _debug_group = DebugGroup::push("Clip mask");
+    let _stencil_guard = StencilGuard::new(mask_pass_state(level, op))?;
+    let _color_mask_guard = ColorMaskGuard::new(ColorMask::NONE)?;
+    let _depth_guard = DepthGuard::disabled()?;
+    draw_mask()
+}
+
+/// The stencil state of a mask pass, which applies `op` to the pixels of the clip
+/// region at `level`.
+fn mask_pass_state(level: i32, op: StencilOp) -> StencilState {
+    StencilState::new().func(StencilFunc::Equal, level, u32::MAX).ops(
+        StencilOp::Keep,
+        StencilOp::Keep,
+        op,
+    )
+}
+
+/// The stencil state while drawing the content of the clip region at `level`.
+fn inside_state(level: i32) -> StencilState {
+    StencilState::new().func(StencilFunc::Equal, level, u32::MAX).write_mask(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nested_mask_states() {
+        // Pushing the second mask increments the pixels inside the first one
+        let push = mask_pass_state(1, StencilOp::Increment);
+        assert_eq!((push.func, push.reference), (StencilFunc::Equal, 1));
+        assert_eq!(push.pass, StencilOp::Increment);
+        assert_eq!((push.stencil_fail, push.depth_fail), (StencilOp::Keep, StencilOp::Keep));
+
+        // Its content is only drawn where both masks overlap, without changing them
+        let inside = inside_state(2);
+        assert_eq!((inside.func, inside.reference, inside.write_mask), (StencilFunc::Equal, 2, 0));
+
+        // Popping it decrements exactly the pixels it incremented
+        let pop = mask_pass_state(2, StencilOp::Decrement);
+        assert_eq!((pop.reference, pop.pass), (2, StencilOp::Decrement));
+    }
+}
@@ -0,0 +1,465 @@
+use crate::gl_utils::{cached_capability, check_gl_error, gl_has_extension, gl_version_at_least};
+use crate::opengl::gl_backend::with_backend;
+use crate::opengl::resource_registry::{self, ResourceKind};
+use anyhow::{anyhow, Context, Result};
+use gl::types::{GLchar, GLenum, GLsizei, GLuint};
+use once_cell::sync::Lazy;
+use std::ffi::{c_void, CStr};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+
+//////////////////////////////////////////////////////////////////////////////
+// - DebugSource -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Where a debug message comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DebugSource {
+    Api,
+    WindowSystem,
+    ShaderCompiler,
+    ThirdParty,
+    Application,
+    Other,
+}
+
+impl DebugSource {
+    pub fn from_gl_enum(value: GLenum) -> Self {
+        match value {
+            gl::DEBUG_SOURCE_API => DebugSource::Api,
+            gl::DEBUG_SOURCE_WINDOW_SYSTEM => DebugSource::WindowSystem,
+            gl::DEBUG_SOURCE_SHADER_COMPILER => DebugSource::ShaderCompiler,
+            gl::DEBUG_SOURCE_THIRD_PARTY => DebugSource::ThirdParty,
+            gl::DEBUG_SOURCE_APPLICATION => DebugSource::Application,
+            _ => DebugSource::Other,
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - DebugType -
+//////////////////////////////////////////////////////////////////////////////
+
+/// What a debug message is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DebugType {
+    Error,
+    DeprecatedBehavior,
+    UndefinedBehavior,
+    Portability,
+    Performance,
+    Marker,
+    PushGroup,
+    PopGroup,
+    Other,
+}
+
+impl DebugType {
+    pub fn from_gl_enum(value: GLenum) -> Self {
+        match value {
+            gl::DEBUG_TYPE_ERROR => DebugType::Error,
+            gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR => DebugType::DeprecatedBehavior,
+            gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR => DebugType::UndefinedBehavior,
+            gl::DEBUG_TYPE_PORTABILITY => DebugType::Portability,
+            gl::DEBUG_TYPE_PERFORMANCE => DebugType::Performance,
+            gl::DEBUG_TYPE_MARKER => DebugType::Marker,
+            gl::DEBUG_TYPE_PUSH_GROUP => DebugType::PushGroup,
+            gl::DEBUG_TYPE_POP_GROUP => DebugType::PopGroup,
+            _ => DebugType::Other,
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - DebugSeverity -
+//////////////////////////////////////////////////////////////////////////////
+
+/// The severity of a debug message, ordered from least to most severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DebugSeverity {
+    Notification,
+    Low,
+    Medium,
+    High,
+}
+
+impl DebugSeverity {
+    pub fn from_gl_enum(value: GLenum) -> Self {
+        match value {
+            gl::DEBUG_SEVERITY_HIGH => DebugSeverity::High,
+            gl::DEBUG_SEVERITY_MEDIUM => DebugSeverity::Medium,
+            gl::DEBUG_SEVERITY_LOW => DebugSeverity::Low,
+            _ => DebugSeverity::Notification,
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - DebugMessage -
+//////////////////////////////////////////////////////////////////////////////
+
+/// A message reported by the OpenGL driver through the debug output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugMessage {
+    pub source: DebugSource,
+    pub message_type: DebugType,
+    /// Implementation specific id, which can be used to silence single messages.
+    pub id: u32,
+    pub severity: DebugSeverity,
+    pub message: String,
+}
+
+impl Display for DebugMessage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[GL {:?} {:?} {:?} {}] {}",
+            self.severity, self.source, self.message_type, self.id, self.message
+        )
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - DebugMessageFilter -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Decides which debug messages are passed on to the handler.
+///
+/// The default filter passes everything from `Low` severity up and drops the
+/// push and pop group messages caused by `DebugGroup`.
+///
+/// # Example
+/// ```ignore
+/// // Only errors and warnings of the driver, without a noisy buffer usage hint
+/// let filter = DebugMessageFilter::default()
+///     .min_severity(DebugSeverity::Medium)
+///     .ignore_source(DebugSource::ShaderCompiler)
+///     .ignore_id(131185);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugMessageFilter {
+    min_severity: DebugSeverity,
+    ignored_sources: Vec<DebugSource>,
+    ignored_types: Vec<DebugType>,
+    ignored_ids: Vec<u32>,
+}
+
+impl Default for DebugMessageFilter {
+    fn default() -> Self {
+        Self {
+            min_severity: DebugSeverity::Low,
+            ignored_sources: Vec::new(),
+            ignored_types: vec![DebugType::PushGroup, DebugType::PopGroup],
+            ignored_ids: Vec::new(),
+        }
+    }
+}
+
+impl DebugMessageFilter {
+    /// Creates a filter which passes every message.
+    pub fn all() -> Self {
+        Self {
+            min_severity: DebugSeverity::Notification,
+            ignored_sources: Vec::new(),
+            ignored_types: Vec::new(),
+            ignored_ids: Vec::new(),
+        }
+    }
+
+    pub fn min_severity(mut self, min_severity: DebugSeverity) -> Self {
+        self.min_severity = min_severity;
+        self
+    }
+
+    pub fn ignore_source(mut self, source: DebugSource) -> Self {
+        self.ignored_sources.push(source);
+        self
+    }
+
+    pub fn ignore_type(mut self, message_type: DebugType) -> Self {
+        self.ignored_types.push(message_type);
+        self
+    }
+
+    pub fn ignore_id(mut self, id: u32) -> Self {
+        self.ignored_ids.push(id);
+        self
+    }
+
+    /// Returns `true` if the message should be passed to the handler.
+    pub fn accepts(&self, message: &DebugMessage) -> bool {
+        message.severity >= self.min_severity
+            && !self.ignored_sources.contains(&message.source)
+            && !self.ignored_types.contains(&message.message_type)
+            && !self.ignored_ids.contains(&message.id)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - Debug callback -
+//////////////////////////////////////////////////////////////////////////////
+
+type DebugHandler = Box<dyn Fn(&DebugMessage) + Send + 'static>;
+
+struct DebugOutput {
+    filter: DebugMessageFilter,
+    handler: DebugHandler,
+}
+
+static DEBUG_OUTPUT: Lazy<Mutex<Option<DebugOutput>>> = Lazy::new(|| Mutex::new(None));
+
+/// Checks if the driver supports the debug output, object labels and debug groups,
+/// either through OpenGL 4.3 or the KHR_debug extension. Loaded function pointers
+/// alone don't tell, some platforms return them for unsupported functions too.
+///
+/// The result is cached, as labels and debug groups are used for every resource
+/// and every frame.
+pub fn is_debug_output_supported() -> bool {
+    cached_capability("khr_debug", || {
+        with_backend(|backend| backend.is_function_loaded("glDebugMessageCallback"))
+            && (gl_version_at_least(4, 3) || gl_has_extension("GL_KHR_debug"))
+    })
+}
+
+/// Routes the debug messages of the current context, which should be a debug
+/// context, through `filter` into `handler`. Replaces a previously installed handler.
+///
+/// Messages are reported synchronously, so the handler runs on the thread and
+/// inside the GL call which caused the message. A panic in the handler is caught
+/// and printed, as it can't unwind through the driver.
+///
+/// # Errors
+/// Returns an error if the driver doesn't support the debug output.
+///
+/// # Example
+/// ```ignore
+/// install_debug_callback(DebugMessageFilter::default(), |message| {
+///     if message.message_type == DebugType::Error {
+///         eprintln!("{}\n{}", message, std::backtrace::Backtrace::force_capture());
+///     }
+/// })?;
+/// ```
+pub fn install_debug_callback<F>(filter: DebugMessageFilter, handler: F) -> Result<()>
+where
+    F: Fn(&DebugMessage) + Send + 'static,
+{
+    if !is_debug_output_supported() {
+        return Err(anyhow!("The OpenGL driver doesn't support KHR_debug"));
+    }
+    *debug_output() = Some(DebugOutput {
+        filter,
+        handler: Box::new(handler),
+    });
+    with_backend(|backend| {
+        backend.enable(gl::DEBUG_OUTPUT);
+        backend.enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+        backend.debug_message_callback(Some(debug_callback));
+    });
+    check_gl_error().context("Failed to install the debug callback")
+}
+
+/// Removes the handler installed by `install_debug_callback` and disables the
+/// debug output.
+pub fn remove_debug_callback() {
+    if is_debug_output_supported() {
+        with_backend(|backend| {
+            backend.debug_message_callback(None);
+            backend.disable(gl::DEBUG_OUTPUT);
+        });
+    }
+    *debug_output() = None;
+}
+
+/// The handler installed by `SdlWindow` for debug contexts, which prints the
+/// messages to stderr.
+pub fn print_debug_message(message: &DebugMessage) {
+    eprintln!("{}", message);
+}
+
+fn debug_output() -> MutexGuard<'static, Option<DebugOutput>> {
+    DEBUG_OUTPUT.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+extern "system" fn debug_callback(
+    source: GLenum,
+    message_type: GLenum,
+    id: GLuint,
+    severity: GLenum,
+    length: GLsizei,
+    message: *const GLchar,
+    _user_param: *mut c_void,
+) {
+    let text = if message.is_null() {
+        String::new()
+    } else if length >= 0 {
+        let bytes = unsafe { std::slice::from_raw_parts(message as *const u8, length as usize) };
+        String::from_utf8_lossy(bytes).into_owned()
+    } else {
+        unsafe { CStr::from_ptr(message) }.to_string_lossy().into_owned()
+    };
+    let message = DebugMessage {
+        source: DebugSource::from_gl_enum(source),
+        message_type: DebugType::from_gl_enum(message_type),
+        id,
+        severity: DebugSeverity::from_gl_enum(severity),
+        message: text.trim_end().to_string(),
+    };
+
+    let output = match DEBUG_OUTPUT.try_lock() {
+        Ok(output) => output,
+        Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+        Err(TryLockError::WouldBlock) => {
+            // The handler itself caused this message, calling it again would deadlock
+            eprintln!("{}", message);
+            return;
+        }
+    };
+    if let Some(output) = output.as_ref() {
+        if output.filter.accepts(&message) {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| (output.handler)(&message)));
+            if result.is_err() {
+                eprintln!("The GL debug handler panicked on: {}", message);
+            }
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - Object labels -
+//////////////////////////////////////////////////////////////////////////////
+
+fn object_identifier(kind: ResourceKind) -> GLenum {
+    match kind {
+        ResourceKind::Buffer => gl::BUFFER,
+        ResourceKind::Texture => gl::TEXTURE,
+        ResourceKind::VertexArray => gl::VERTEX_ARRAY,
+        ResourceKind::Shader => gl::SHADER,
+        ResourceKind::Program => gl::PROGRAM,
+        ResourceKind::Query => gl::QUERY,
+        ResourceKind::TransformFeedback => gl::TRANSFORM_FEEDBACK,
+        ResourceKind::Framebuffer => gl::FRAMEBUFFER,
+        ResourceKind::Renderbuffer => gl::RENDERBUFFER,
+    }
+}
+
+/// Labels a GL object, so debug messages and graphics debuggers show `label`
+/// instead of its number. The label is also used by the resource registry, e.g.
+/// in leak reports. Does nothing but updating the registry if the driver doesn't
+/// support KHR_debug.
+///
+/// # Example
+/// ```ignore
+/// label_object(ResourceKind::Texture, texture.get_texture_id(), "crate_diffuse");
+/// ```
+pub fn label_object(kind: ResourceKind, gl_name: u32, label: &str) {
+    resource_registry::set_label(kind, gl_name, label);
+    if gl_name == 0 || !is_debug_output_supported() {
+        return;
+    }
+    with_backend(|backend| backend.object_label(object_identifier(kind), gl_name, label));
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - DebugGroup -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Pushes a named debug group, which is popped again when the guard is dropped.
+///
+/// Groups nest and show up as a tree of scopes in graphics debuggers like
+/// RenderDoc, which makes captures much easier to read. Without KHR_debug support
+/// the guard does nothing.
+///
+/// # Example
+/// ```ignore
+/// let _group = DebugGroup::push("Shadow pass");
+/// for model in &models {
+///     model.render()?;
+/// }
+/// ```
+#[derive(Debug)]
+pub struct DebugGroup {
+    pushed: bool,
+}
+
+impl DebugGroup {
+    pub fn push(name: &str) -> Self {
+        let pushed = is_debug_output_supported();
+        if pushed {
+            with_backend(|backend| backend.push_debug_group(gl::DEBUG_SOURCE_APPLICATION, 0, name));
+        }
+        Self {
+            pushed,
+        }
+    }
+}
+
+impl Drop for DebugGroup {
+    fn drop(&mut self) {
+        if self.pushed {
+            with_backend(|backend| backend.pop_debug_group());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(severity: DebugSeverity, message_type: DebugType) -> DebugMessage {
+        DebugMessage {
+            source: DebugSource::Api,
+            message_type,
+            id: 1281,
+            severity,
+            message: "GL_INVALID_VALUE in glTexImage2D".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_gl_enum_conversion() {
+        assert_eq!(DebugSeverity::from_gl_enum(gl::DEBUG_SEVERITY_HIGH), DebugSeverity::High);
+        assert_eq!(
+            DebugSeverity::from_gl_enum(gl::DEBUG_SEVERITY_NOTIFICATION),
+            DebugSeverity::Notification
+        );
+        assert_eq!(
+            DebugSource::from_gl_enum(gl::DEBUG_SOURCE_SHADER_COMPILER),
+            DebugSource::ShaderCompiler
+        );
+        assert_eq!(DebugType::from_gl_enum(gl::DEBUG_TYPE_PERFORMANCE), DebugType::Performance);
+        assert!(DebugSeverity::Medium > DebugSeverity::Low);
+    }
+
+    #[test]
+    fn test_default_filter() {
+        let filter = DebugMessageFilter::default();
+        assert!(filter.accepts(&message(DebugSeverity::High, DebugType::Error)));
+        assert!(filter.accepts(&message(DebugSeverity::Low, DebugType::Performance)));
+        assert!(!filter.accepts(&message(DebugSeverity::Notification, DebugType::Other)));
+        assert!(!filter.accepts(&message(DebugSeverity::High, DebugType::PushGroup)));
+        assert!(DebugMessageFilter::all()
+            .accepts(&message(DebugSeverity::Notification, DebugType::PushGroup)));
+    }
+
+    #[test]
+    fn test_custom_filter() {
+        let filter = DebugMessageFilter::default()
+            .min_severity(DebugSeverity::Medium)
+            .ignore_source(DebugSource::ShaderCompiler)
+            .ignore_id(131185);
+        let error = message(DebugSeverity::High, DebugType::Error);
+        assert!(filter.accepts(&error));
+        assert!(!filter.accepts(&message(DebugSeverity::Low, DebugType::Error)));
+        assert!(!filter.accepts(&DebugMessage {
+            source: DebugSource::ShaderCompiler,
+            ..error.clone()
+        }));
+        assert!(!filter.accepts(&DebugMessage {
+            id: 131185,
+            ..error.clone()
+        }));
+        assert_eq!(error.to_string(), "[GL High Api Error 1281] GL_INVALID_VALUE in glTexImage2D");
+    }
+}
@@ -0,0 +1,1353 @@
+use crate::color::Color;
+use crate::gl_traits::{Deletable, ToOpenGL};
+use crate::gl_types::RenderMask;
+use crate::gl_utils::check_gl_error;
+use crate::opengl::debug_output::label_object;
+use crate::opengl::gl_backend::with_backend;
+use crate::opengl::resource_registry::{self, ResourceKind};
+use crate::sdl_window::SdlWindow;
+use crate::RenderTarget;
+use anyhow::{anyhow, Context, Result};
+use gl::types::{GLenum, GLint, GLsizei, GLuint};
+use std::borrow::Cow;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::ptr;
+use thiserror::Error;
+
+//////////////////////////////////////////////////////////////////////////////
+// - AttachmentKind -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Which buffer of a framebuffer an attachment format can be used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AttachmentKind {
+    Color,
+    Depth,
+    Stencil,
+    DepthStencil,
+}
+
+impl Display for AttachmentKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AttachmentKind::Color => write!(f, "color"),
+            AttachmentKind::Depth => write!(f, "depth"),
+            AttachmentKind::Stencil => write!(f, "stencil"),
+            AttachmentKind::DepthStencil => write!(f, "depth/stencil"),
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - AttachmentFormat -
+//////////////////////////////////////////////////////////////////////////////
+
+/// The internal format of a framebuffer attachment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AttachmentFormat {
+    R8,
+    Rg8,
+    Rgba8,
+    /// Stores sRGB encoded colors, writes are converted if `GL_FRAMEBUFFER_SRGB` is on.
+    Srgb8Alpha8,
+    Rgb10A2,
+    R11fG11fB10f,
+    R16F,
+    Rg16F,
+    Rgba16F,
+    R32F,
+    Rg32F,
+    Rgba32F,
+    Depth16,
+    Depth24,
+    Depth32F,
+    Depth24Stencil8,
+    Depth32FStencil8,
+    /// Only usable as renderbuffer with OpenGL 3.3.
+    Stencil8,
+    /// Any other sized internal format. `format` and `data_type` are the pixel
+    /// transfer parameters matching it, e.g. `gl::RED_INTEGER` and `gl::UNSIGNED_INT`
+    /// for `gl::R32UI`. `bytes_per_pixel` is only used for memory statistics.
+    Custom {
+        internal_format: GLenum,
+        format: GLenum,
+        data_type: GLenum,
+        bytes_per_pixel: usize,
+    },
+}
+
+impl AttachmentFormat {
+    pub fn internal_format(&self) -> GLenum {
+        match self {
+            AttachmentFormat::R8 => gl::R8,
+            AttachmentFormat::Rg8 => gl::RG8,
+            AttachmentFormat::Rgba8 => gl::RGBA8,
+            AttachmentFormat::Srgb8Alpha8 => gl::SRGB8_ALPHA8,
+            AttachmentFormat::Rgb10A2 => gl::RGB10_A2,
+            AttachmentFormat::R11fG11fB10f => gl::R11F_G11F_B10F,
+            AttachmentFormat::R16F => gl::R16F,
+            AttachmentFormat::Rg16F => gl::RG16F,
+            AttachmentFormat::Rgba16F => gl::RGBA16F,
+            AttachmentFormat::R32F => gl::R32F,
+            AttachmentFormat::Rg32F => gl::RG32F,
+            AttachmentFormat::Rgba32F => gl::RGBA32F,
+            AttachmentFormat::Depth16 => gl::DEPTH_COMPONENT16,
+            AttachmentFormat::Depth24 => gl::DEPTH_COMPONENT24,
+            AttachmentFormat::Depth32F => gl::DEPTH_COMPONENT32F,
+            AttachmentFormat::Depth24Stencil8 => gl::DEPTH24_STENCIL8,
+            AttachmentFormat::Depth32FStencil8 => gl::DEPTH32F_STENCIL8,
+            AttachmentFormat::Stencil8 => gl::STENCIL_INDEX8,
+            AttachmentFormat::Custom {
+                internal_format,
+                ..
+            } => *internal_format,
+        }
+    }
+
+    /// The pixel format and data type used to allocate texture storage.
+    pub fn pixel_format(&self) -> (GLenum, GLenum) {
+        match self {
+            AttachmentFormat::R8 => (gl::RED, gl::UNSIGNED_BYTE),
+            AttachmentFormat::Rg8 => (gl::RG, gl::UNSIGNED_BYTE),
+            AttachmentFormat::Rgba8 | AttachmentFormat::Srgb8Alpha8 => {
+                (gl::RGBA, gl::UNSIGNED_BYTE)
+            }
+            AttachmentFormat::Rgb10A2 => (gl::RGBA, gl::UNSIGNED_INT_2_10_10_10_REV),
+            AttachmentFormat::R11fG11fB10f => (gl::RGB, gl::FLOAT),
+            AttachmentFormat::R16F | AttachmentFormat::R32F => (gl::RED, gl::FLOAT),
+            AttachmentFormat::Rg16F | AttachmentFormat::Rg32F => (gl::RG, gl::FLOAT),
+            AttachmentFormat::Rgba16F | AttachmentFormat::Rgba32F => (gl::RGBA, gl::FLOAT),
+            AttachmentFormat::Depth16 => (gl::DEPTH_COMPONENT, gl::UNSIGNED_SHORT),
+            AttachmentFormat::Depth24 => (gl::DEPTH_COMPONENT, gl::UNSIGNED_INT),
+            AttachmentFormat::Depth32F => (gl::DEPTH_COMPONENT, gl::FLOAT),
+            AttachmentFormat::Depth24Stencil8 => (gl::DEPTH_STENCIL, gl::UNSIGNED_INT_24_8),
+            AttachmentFormat::Depth32FStencil8 => {
+                (gl::DEPTH_STENCIL, gl::FLOAT_32_UNSIGNED_INT_24_8_REV)
+            }
+            AttachmentFormat::Stencil8 => (gl::STENCIL_INDEX, gl::UNSIGNED_BYTE),
+            AttachmentFormat::Custom {
+                format,
+                data_type,
+                ..
+            } => (*format, *data_type),
+        }
+    }
+
+    pub fn kind(&self) -> AttachmentKind {
+        match self.pixel_format().0 {
+            gl::DEPTH_COMPONENT => AttachmentKind::Depth,
+            gl::DEPTH_STENCIL => AttachmentKind::DepthStencil,
+            gl::STENCIL_INDEX => AttachmentKind::Stencil,
+            _ => AttachmentKind::Color,
+        }
+    }
+
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            AttachmentFormat::R8 | AttachmentFormat::Stencil8 => 1,
+            AttachmentFormat::Rg8 | AttachmentFormat::R16F | AttachmentFormat::Depth16 => 2,
+            AttachmentFormat::Depth24 => 3,
+            AttachmentFormat::Rgba8
+            | AttachmentFormat::Srgb8Alpha8
+            | AttachmentFormat::Rgb10A2
+            | AttachmentFormat::R11fG11fB10f
+            | AttachmentFormat::Rg16F
+            | AttachmentFormat::R32F
+            | AttachmentFormat::Depth32F
+            | AttachmentFormat::Depth24Stencil8 => 4,
+            AttachmentFormat::Rgba16F
+            | AttachmentFormat::Rg32F
+            | AttachmentFormat::Depth32FStencil8 => 8,
+            AttachmentFormat::Rgba32F => 16,
+            AttachmentFormat::Custom {
+                bytes_per_pixel,
+                ..
+            } => *bytes_per_pixel,
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - AttachmentStorage -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Where the pixels of an attachment live.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum AttachmentStorage {
+    /// A texture, which can be sampled by later passes.
+    #[default]
+    Texture,
+    /// A renderbuffer, for buffers which are only rendered to, blitted or resolved.
+    Renderbuffer,
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - BlitFilter -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Filter used by blits between framebuffers of different sizes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum BlitFilter {
+    #[default]
+    Nearest,
+    /// Only allowed for color blits.
+    Linear,
+}
+
+impl BlitFilter {
+    pub fn to_gl_enum(&self) -> GLenum {
+        match self {
+            BlitFilter::Nearest => gl::NEAREST,
+            BlitFilter::Linear => gl::LINEAR,
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - FramebufferStatus -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Why a framebuffer is incomplete, from `glCheckFramebufferStatus`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FramebufferStatus {
+    Undefined,
+    IncompleteAttachment,
+    MissingAttachment,
+    IncompleteDrawBuffer,
+    IncompleteReadBuffer,
+    Unsupported,
+    IncompleteMultisample,
+    IncompleteLayerTargets,
+    Unknown(GLenum),
+}
+
+impl FramebufferStatus {
+    /// Returns `None` for `GL_FRAMEBUFFER_COMPLETE`.
+    pub fn from_gl(status: GLenum) -> Option<Self> {
+        match status {
+            gl::FRAMEBUFFER_COMPLETE => None,
+            gl::FRAMEBUFFER_UNDEFINED => Some(FramebufferStatus::Undefined),
+            gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT => Some(FramebufferStatus::IncompleteAttachment),
+            gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => {
+                Some(FramebufferStatus::MissingAttachment)
+            }
+            gl::FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER => Some(FramebufferStatus::IncompleteDrawBuffer),
+            gl::FRAMEBUFFER_INCOMPLETE_READ_BUFFER => Some(FramebufferStatus::IncompleteReadBuffer),
+            gl::FRAMEBUFFER_UNSUPPORTED => Some(FramebufferStatus::Unsupported),
+            gl::FRAMEBUFFER_INCOMPLETE_MULTISAMPLE => {
+                Some(FramebufferStatus::IncompleteMultisample)
+            }
+            gl::FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS => {
+                Some(FramebufferStatus::IncompleteLayerTargets)
+            }
+            other => Some(FramebufferStatus::Unknown(other)),
+        }
+    }
+}
+
+impl Display for FramebufferStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FramebufferStatus::Undefined => {
+                write!(f, "the default framebuffer doesn't exist (GL_FRAMEBUFFER_UNDEFINED)")
+            }
+            FramebufferStatus::IncompleteAttachment => write!(
+                f,
+                "an attachment has no storage or a format that can't be rendered to \
+                 (GL_FRAMEBUFFER_INCOMPLETE_ATTACHMENT)"
+            ),
+            FramebufferStatus::MissingAttachment => {
+                write!(f, "there are no attachments (GL_FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT)")
+            }
+            FramebufferStatus::IncompleteDrawBuffer => write!(
+                f,
+                "a draw buffer refers to a missing color attachment \
+                 (GL_FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER)"
+            ),
+            FramebufferStatus::IncompleteReadBuffer => write!(
+                f,
+                "the read buffer refers to a missing color attachment \
+                 (GL_FRAMEBUFFER_INCOMPLETE_READ_BUFFER)"
+            ),
+            FramebufferStatus::Unsupported => write!(
+                f,
+                "the driver doesn't support this combination of formats \
+                 (GL_FRAMEBUFFER_UNSUPPORTED)"
+            ),
+            FramebufferStatus::IncompleteMultisample => write!(
+                f,
+                "the attachments have different sample counts, or textures and \
+                 renderbuffers are mixed without fixed sample locations \
+                 (GL_FRAMEBUFFER_INCOMPLETE_MULTISAMPLE)"
+            ),
+            FramebufferStatus::IncompleteLayerTargets => write!(
+                f,
+                "layered and non-layered attachments are mixed \
+                 (GL_FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS)"
+            ),
+            FramebufferStatus::Unknown(status) => write!(f, "unknown status 0x{:04X}", status),
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - FramebufferError -
+//////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Error)]
+pub enum FramebufferError {
+    #[error("Framebuffer {label:?} is incomplete: {status}")]
+    Incomplete {
+        label: String,
+        status: FramebufferStatus,
+    },
+    #[error("{format:?} can't be used as {expected} attachment, it is a {actual} format")]
+    InvalidFormat {
+        format: AttachmentFormat,
+        expected: AttachmentKind,
+        actual: AttachmentKind,
+    },
+    #[error("Framebuffer size {width}x{height} is invalid")]
+    InvalidSize {
+        width: u32,
+        height: u32,
+    },
+    #[error("Combined depth/stencil attachments can't be used with a separate stencil attachment")]
+    ConflictingStencil,
+    #[error("Stencil attachments have to be renderbuffers, stencil textures need OpenGL 4.4")]
+    StencilTexture,
+    #[error("{requested} color attachments requested, the driver supports {max}")]
+    TooManyColorAttachments {
+        requested: usize,
+        max: usize,
+    },
+    #[error("Draw buffer {index} doesn't exist, there are {count} color attachments")]
+    InvalidDrawBuffer {
+        index: usize,
+        count: usize,
+    },
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - Attachment -
+//////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct AttachmentSpec {
+    format: AttachmentFormat,
+    storage: AttachmentStorage,
+}
+
+/// A texture or renderbuffer owned by a framebuffer, deleted when dropped.
+#[derive(Debug)]
+struct Attachment {
+    spec: AttachmentSpec,
+    id: GLuint,
+    multisample: bool,
+}
+
+impl Attachment {
+    fn create(
+        spec: AttachmentSpec,
+        width: u32,
+        height: u32,
+        samples: u32,
+        label: &str,
+    ) -> Result<Self> {
+        let byte_size = width as usize * height as usize * spec.format.bytes_per_pixel();
+        let byte_size = byte_size * samples.max(1) as usize;
+        let internal_format = spec.format.internal_format();
+        let id = with_backend(|backend| match spec.storage {
+            AttachmentStorage::Texture => {
+                let id = backend.gen_texture();
+                if samples > 0 {
+                    backend.bind_texture(gl::TEXTURE_2D_MULTISAMPLE, id);
+                    backend.tex_image_2d_multisample(
+                        gl::TEXTURE_2D_MULTISAMPLE,
+                        samples as GLsizei,
+                        internal_format,
+                        width as GLsizei,
+                        height as GLsizei,
+                        true,
+                    );
+                    backend.bind_texture(gl::TEXTURE_2D_MULTISAMPLE, 0);
+                } else {
+                    let (format, data_type) = spec.format.pixel_format();
+                    // Depth and integer formats can't be filtered
+                    let filter = match spec.format {
+                        AttachmentFormat::Custom {
+                            ..
+                        } => gl::NEAREST,
+                        _ if spec.format.kind() != AttachmentKind::Color => gl::NEAREST,
+                        _ => gl::LINEAR,
+                    };
+                    backend.bind_texture(gl::TEXTURE_2D, id);
+                    unsafe {
+                        backend.tex_image_2d(
+                            gl::TEXTURE_2D,
+                            0,
+                            internal_format as GLint,
+                            width as GLsizei,
+                            height as GLsizei,
+                            format,
+                            data_type,
+                            ptr::null(),
+                        );
+                    }
+                    backend.tex_parameter_i(
+                        gl::TEXTURE_2D,
+                        gl::TEXTURE_MIN_FILTER,
+                        filter as GLint,
+                    );
+                    backend.tex_parameter_i(
+                        gl::TEXTURE_2D,
+                        gl::TEXTURE_MAG_FILTER,
+                        filter as GLint,
+                    );
+                    backend.tex_parameter_i(
+                        gl::TEXTURE_2D,
+                        gl::TEXTURE_WRAP_S,
+                        gl::CLAMP_TO_EDGE as GLint,
+                    );
+                    backend.tex_parameter_i(
+                        gl::TEXTURE_2D,
+                        gl::TEXTURE_WRAP_T,
+                        gl::CLAMP_TO_EDGE as GLint,
+                    );
+                    backend.bind_texture(gl::TEXTURE_2D, 0);
+                }
+                id
+            }
+            AttachmentStorage::Renderbuffer => {
+                let id = backend.gen_renderbuffer();
+                backend.bind_renderbuffer(gl::RENDERBUFFER, id);
+                backend.renderbuffer_storage_multisample(
+                    gl::RENDERBUFFER,
+                    samples as GLsizei,
+                    internal_format,
+                    width as GLsizei,
+                    height as GLsizei,
+                );
+                backend.bind_renderbuffer(gl::RENDERBUFFER, 0);
+                id
+            }
+        });
+        let attachment = Self {
+            spec,
+            id,
+            multisample: samples > 0,
+        };
+        check_gl_error().with_context(|| {
+            format!(
+                "Failed to create {:?} attachment {:?} with {}x{} pixels",
+                spec.format, label, width, height
+            )
+        })?;
+        resource_registry::register(attachment.resource_kind(), id, label, byte_size);
+        label_object(attachment.resource_kind(), id, label);
+        Ok(attachment)
+    }
+
+    fn resource_kind(&self) -> ResourceKind {
+        match self.spec.storage {
+            AttachmentStorage::Texture => ResourceKind::Texture,
+            AttachmentStorage::Renderbuffer => ResourceKind::Renderbuffer,
+        }
+    }
+
+    fn texture_target(&self) -> GLenum {
+        if self.multisample {
+            gl::TEXTURE_2D_MULTISAMPLE
+        } else {
+            gl::TEXTURE_2D
+        }
+    }
+
+    /// Attaches to the framebuffer bound to `GL_FRAMEBUFFER`.
+    fn attach(&self, attachment_point: GLenum) {
+        with_backend(|backend| match self.spec.storage {
+            AttachmentStorage::Texture => backend.framebuffer_texture_2d(
+                gl::FRAMEBUFFER,
+                attachment_point,
+                self.texture_target(),
+                self.id,
+                0,
+            ),
+            AttachmentStorage::Renderbuffer => backend.framebuffer_renderbuffer(
+                gl::FRAMEBUFFER,
+                attachment_point,
+                gl::RENDERBUFFER,
+                self.id,
+            ),
+        });
+    }
+
+    fn texture_id(&self) -> Option<GLuint> {
+        (self.spec.storage == AttachmentStorage::Texture).then_some(self.id)
+    }
+}
+
+impl Drop for Attachment {
+    fn drop(&mut self) {
+        if self.id == 0 {
+            return;
+        }
+        if let Err(err) = resource_registry::unregister(self.resource_kind(), self.id) {
+            eprintln!("Error while dropping framebuffer attachment: {}", err);
+        }
+        with_backend(|backend| match self.spec.storage {
+            AttachmentStorage::Texture => backend.delete_texture(self.id),
+            AttachmentStorage::Renderbuffer => backend.delete_renderbuffer(self.id),
+        });
+        self.id = 0;
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - FramebufferBuilder -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Describes the attachments of a `Framebuffer`, see `Framebuffer::builder`.
+#[derive(Debug, Clone)]
+pub struct FramebufferBuilder {
+    label: String,
+    width: u32,
+    height: u32,
+    samples: u32,
+    colors: Vec<AttachmentSpec>,
+    depth: Option<AttachmentSpec>,
+    stencil: Option<AttachmentSpec>,
+    clear_color: Color,
+}
+
+impl FramebufferBuilder {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            label: "framebuffer".to_string(),
+            width,
+            height,
+            samples: 0,
+            colors: Vec::new(),
+            depth: None,
+            stencil: None,
+            clear_color: Color::BLACK,
+        }
+    }
+
+    /// Name used in error messages, debug output and the resource registry.
+    pub fn label(mut self, label: &str) -> Self {
+        self.label = label.to_string();
+        self
+    }
+
+    /// Number of samples per pixel, 0 for a framebuffer without multisampling.
+    /// Multisampled framebuffers are usually resolved into a single sampled one
+    /// with `Framebuffer::resolve_to` before their textures are sampled.
+    pub fn samples(mut self, samples: u32) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    /// Adds a color attachment. Attachments are numbered in the order they are
+    /// added, which is also the `layout(location = n)` of the fragment shader
+    /// output written to them.
+    pub fn color(mut self, format: AttachmentFormat, storage: AttachmentStorage) -> Self {
+        self.colors.push(AttachmentSpec {
+            format,
+            storage,
+        });
+        self
+    }
+
+    /// Adds a color attachment stored in a texture.
+    pub fn color_texture(self, format: AttachmentFormat) -> Self {
+        self.color(format, AttachmentStorage::Texture)
+    }
+
+    /// Sets the depth attachment, a depth or combined depth/stencil format.
+    pub fn depth(mut self, format: AttachmentFormat, storage: AttachmentStorage) -> Self {
+        self.depth = Some(AttachmentSpec {
+            format,
+            storage,
+        });
+        self
+    }
+
+    /// Sets a separate stencil attachment. Few drivers support separate depth and
+    /// stencil buffers, a combined depth/stencil format is the portable choice.
+    /// The storage has to be `AttachmentStorage::Renderbuffer`.
+    pub fn stencil(mut self, format: AttachmentFormat, storage: AttachmentStorage) -> Self {
+        self.stencil = Some(AttachmentSpec {
+            format,
+            storage,
+        });
+        self
+    }
+
+    pub fn clear_color(mut self, clear_color: Color) -> Self {
+        self.clear_color = clear_color;
+        self
+    }
+
+    /// Checks the description for errors which don't depend on the driver.
+    fn validate(&self) -> Result<(), FramebufferError> {
+        if self.width == 0 || self.height == 0 {
+            return Err(FramebufferError::InvalidSize {
+                width: self.width,
+                height: self.height,
+            });
+        }
+        for spec in &self.colors {
+            expect_kind(spec, &[AttachmentKind::Color])?;
+        }
+        if let Some(depth) = &self.depth {
+            expect_kind(depth, &[AttachmentKind::Depth, AttachmentKind::DepthStencil])?;
+        }
+        if let Some(stencil) = &self.stencil {
+            expect_kind(stencil, &[AttachmentKind::Stencil])?;
+            if stencil.storage == AttachmentStorage::Texture {
+                return Err(FramebufferError::StencilTexture);
+            }
+            if self
+                .depth
+                .is_some_and(|depth| depth.format.kind() == AttachmentKind::DepthStencil)
+            {
+                return Err(FramebufferError::ConflictingStencil);
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates the framebuffer and its attachments.
+    ///
+    /// # Errors
+    /// Returns an error if the description is invalid, an attachment can't be
+    /// created, or the framebuffer is incomplete, e.g. because the driver can't
+    /// render to one of the formats.
+    pub fn build(&self) -> Result<Framebuffer> {
+        self.validate()?;
+        let max_color_attachments = max_color_attachments();
+        if self.colors.len() > max_color_attachments {
+            return Err(FramebufferError::TooManyColorAttachments {
+                requested: self.colors.len(),
+                max: max_color_attachments,
+            }
+            .into());
+        }
+
+        let id = with_backend(|backend| backend.gen_framebuffer());
+        check_gl_error()
+            .with_context(|| format!("Failed to create framebuffer {:?}", self.label))?;
+        resource_registry::register(ResourceKind::Framebuffer, id, self.label.as_str(), 0);
+        let mut framebuffer = Framebuffer {
+            id,
+            label: self.label.clone(),
+            width: self.width,
+            height: self.height,
+            samples: self.samples,
+            color_specs: self.colors.clone(),
+            depth_spec: self.depth,
+            stencil_spec: self.stencil,
+            colors: Vec::new(),
+            depth: None,
+            stencil: None,
+            draw_buffers: (0..self.colors.len()).collect(),
+            clear_color: self.clear_color,
+        };
+        // Binding the framebuffer once creates it, so it can be labeled
+        framebuffer.create_attachments(self.width, self.height)?;
+        label_object(ResourceKind::Framebuffer, id, &self.label);
+        Ok(framebuffer)
+    }
+}
+
+fn expect_kind(spec: &AttachmentSpec, allowed: &[AttachmentKind]) -> Result<(), FramebufferError> {
+    let actual = spec.format.kind();
+    if allowed.contains(&actual) {
+        Ok(())
+    } else {
+        Err(FramebufferError::InvalidFormat {
+            format: spec.format,
+            expected: allowed[0],
+            actual,
+        })
+    }
+}
+
+fn max_color_attachments() -> usize {
+    let (max_attachments, max_draw_buffers) = with_backend(|backend| {
+        (backend.get_integer(gl::MAX_COLOR_ATTACHMENTS), backend.get_integer(gl::MAX_DRAW_BUFFERS))
+    });
+    max_attachments.min(max_draw_buffers).max(0) as usize
+}
+
+/// Pairs the attachments with the attachment points they are attached to.
+fn attachment_points<'a>(
+    colors: &'a [Attachment],
+    depth: Option<&'a Attachment>,
+    stencil: Option<&'a Attachment>,
+) -> Vec<(GLenum, &'a Attachment)> {
+    let colors = colors
+        .iter()
+        .enumerate()
+        .map(|(index, color)| (gl::COLOR_ATTACHMENT0 + index as GLenum, color));
+    let depth = depth.map(|depth| match depth.spec.format.kind() {
+        AttachmentKind::DepthStencil => (gl::DEPTH_STENCIL_ATTACHMENT, depth),
+        _ => (gl::DEPTH_ATTACHMENT, depth),
+    });
+    let stencil = stencil.map(|stencil| (gl::STENCIL_ATTACHMENT, stencil));
+    colors.chain(depth).chain(stencil).collect()
+}
+
+/// The `glDrawBuffers` list for the given color attachments.
+fn draw_buffer_enums(indices: &[usize]) -> Vec<GLenum> {
+    indices.iter().map(|&index| gl::COLOR_ATTACHMENT0 + index as GLenum).collect()
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - BindingRestore -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Restores the framebuffer bindings and viewport when dropped.
+struct BindingRestore {
+    draw_framebuffer: GLuint,
+    read_framebuffer: GLuint,
+    viewport: [GLint; 4],
+}
+
+impl BindingRestore {
+    fn save() -> Self {
+        with_backend(|backend| Self {
+            draw_framebuffer: backend.get_integer(gl::DRAW_FRAMEBUFFER_BINDING) as GLuint,
+            read_framebuffer: backend.get_integer(gl::READ_FRAMEBUFFER_BINDING) as GLuint,
+            viewport: backend.get_integers(gl::VIEWPORT),
+        })
+    }
+}
+
+impl Drop for BindingRestore {
+    fn drop(&mut self) {
+        with_backend(|backend| {
+            backend.bind_framebuffer(gl::DRAW_FRAMEBUFFER, self.draw_framebuffer);
+            backend.bind_framebuffer(gl::READ_FRAMEBUFFER, self.read_framebuffer);
+            let [x, y, width, height] = self.viewport;
+            backend.viewport(x, y, width, height);
+        });
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - FramebufferBinding -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Keeps a framebuffer bound for drawing and reading, see `Framebuffer::bind`. The
+/// previous framebuffers and viewport are restored when the guard is dropped.
+#[must_use = "the previous framebuffer is bound again as soon as the guard is dropped"]
+pub struct FramebufferBinding<'a> {
+    framebuffer: &'a Framebuffer,
+    _restore: BindingRestore,
+}
+
+impl FramebufferBinding<'_> {
+    pub fn framebuffer(&self) -> &Framebuffer {
+        self.framebuffer
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - Framebuffer -
+//////////////////////////////////////////////////////////////////////////////
+
+/// An offscreen render target with color attachments and optional depth and
+/// stencil attachments, each stored in a texture or a renderbuffer.
+///
+/// All color attachments are draw buffers by default, so a fragment shader writes
+/// its outputs `layout(location = n)` to color attachment `n`. The attachments are
+/// created again with the same formats when the framebuffer is resized.
+///
+/// # Example
+/// ```ignore
+/// let mut gbuffer = Framebuffer::builder(width, height)
+///     .label("gbuffer")
+///     .color_texture(AttachmentFormat::Rgba8)
+///     .color_texture(AttachmentFormat::Rgba16F)
+///     .depth(AttachmentFormat::Depth24Stencil8, AttachmentStorage::Renderbuffer)
+///     .build()?;
+///
+/// // Every frame
+/// gbuffer.fit_to_window(&window)?;
+/// {
+///     let _binding = gbuffer.bind()?;
+///     gbuffer.clear();
+///     scene.draw()?;
+/// }
+/// gbuffer.bind_color_texture(1, 0)?;
+/// ```
+#[derive(Debug)]
+pub struct Framebuffer {
+    id: GLuint,
+    label: String,
+    width: u32,
+    height: u32,
+    samples: u32,
+    color_specs: Vec<AttachmentSpec>,
+    depth_spec: Option<AttachmentSpec>,
+    stencil_spec: Option<AttachmentSpec>,
+    colors: Vec<Attachment>,
+    depth: Option<Attachment>,
+    stencil: Option<Attachment>,
+    draw_buffers: Vec<usize>,
+    pub clear_color: Color,
+}
+
+impl Framebuffer {
+    pub fn builder(width: u32, height: u32) -> FramebufferBuilder {
+        FramebufferBuilder::new(width, height)
+    }
+
+    pub fn framebuffer_id(&self) -> GLuint {
+        self.id
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    pub fn color_attachment_count(&self) -> usize {
+        self.colors.len()
+    }
+
+    /// The texture of a color attachment, `None` if it doesn't exist or is a
+    /// renderbuffer. Multisampled textures have the `GL_TEXTURE_2D_MULTISAMPLE` target.
+    pub fn color_texture(&self, index: usize) -> Option<GLuint> {
+        self.colors.get(index).and_then(Attachment::texture_id)
+    }
+
+    /// The texture of the depth attachment, `None` if there is none or it is a
+    /// renderbuffer.
+    pub fn depth_texture(&self) -> Option<GLuint> {
+        self.depth.as_ref().and_then(Attachment::texture_id)
+    }
+
+    /// Binds the texture of a color attachment to a texture unit, for sampling it in
+    /// a later pass.
+    pub fn bind_color_texture(&self, index: usize, texture_unit: u32) -> Result<()> {
+        let attachment = self
+            .colors
+            .get(index)
+            .filter(|attachment| attachment.spec.storage == AttachmentStorage::Texture)
+            .ok_or_else(|| {
+                anyhow!("Framebuffer {:?} has no color texture {}", self.label, index)
+            })?;
+        with_backend(|backend| {
+            backend.active_texture(gl::TEXTURE0 + texture_unit);
+            backend.bind_texture(attachment.texture_target(), attachment.id);
+        });
+        check_gl_error().with_context(|| {
+            format!("Failed to bind color texture {} of framebuffer {:?}", index, self.label)
+        })
+    }
+
+    /// Generates the mip chain of a color texture from what was rendered into it and
+    /// switches it to mipmapped filtering. The last level holds the average of the
+    /// whole image, which is useful for measuring it on the GPU.
+    pub fn generate_color_mipmaps(&self, index: usize) -> Result<()> {
+        let attachment = self
+            .colors
+            .get(index)
+            .filter(|attachment| {
+                attachment.spec.storage == AttachmentStorage::Texture && !attachment.multisample
+            })
+            .ok_or_else(|| {
+                anyhow!(
+                    "Framebuffer {:?} has no single sampled color texture {}",
+                    self.label,
+                    index
+                )
+            })?;
+        with_backend(|backend| {
+            backend.bind_texture(gl::TEXTURE_2D, attachment.id);
+            backend.tex_parameter_i(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_MIN_FILTER,
+                gl::LINEAR_MIPMAP_NEAREST as GLint,
+            );
+            backend.generate_mipmap(gl::TEXTURE_2D);
+            backend.bind_texture(gl::TEXTURE_2D, 0);
+        });
+        check_gl_error().with_context(|| {
+            format!(
+                "Failed to generate mipmaps of color texture {} of framebuffer {:?}",
+                index, self.label
+            )
+        })
+    }
+
+    /// Binds the framebuffer for drawing and reading and sets the viewport to its
+    /// size, until the returned guard is dropped.
+    pub fn bind(&self) -> Result<FramebufferBinding<'_>> {
+        let restore = BindingRestore::save();
+        with_backend(|backend| {
+            backend.bind_framebuffer(gl::FRAMEBUFFER, self.id);
+            backend.viewport(0, 0, self.width as GLsizei, self.height as GLsizei);
+        });
+        check_gl_error().with_context(|| format!("Failed to bind framebuffer {:?}", self.label))?;
+        Ok(FramebufferBinding {
+            framebuffer: self,
+            _restore: restore,
+        })
+    }
+
+    /// Binds the framebuffer and sets the viewport like `bind`, but nothing is
+    /// restored. It stays bound until another framebuffer is bound.
+    pub(crate) fn make_current(&self) -> Result<()> {
+        with_backend(|backend| {
+            backend.bind_framebuffer(gl::FRAMEBUFFER, self.id);
+            backend.viewport(0, 0, self.width as GLsizei, self.height as GLsizei);
+        });
+        check_gl_error().with_context(|| format!("Failed to bind framebuffer {:?}", self.label))
+    }
+
+    /// Binds the default framebuffer of the window and sets the viewport to its size.
+    pub(crate) fn make_default_current(width: u32, height: u32) {
+        with_backend(|backend| {
+            backend.bind_framebuffer(gl::FRAMEBUFFER, 0);
+            backend.viewport(0, 0, width as GLsizei, height as GLsizei);
+        });
+    }
+
+    /// Selects the color attachments fragment shader outputs are written to.
+    /// Output `n` goes to the attachment at `indices[n]`.
+    pub fn set_draw_buffers(&mut self, indices: &[usize]) -> Result<()> {
+        if let Some(&index) = indices.iter().find(|&&index| index >= self.colors.len()) {
+            return Err(FramebufferError::InvalidDrawBuffer {
+                index,
+                count: self.colors.len(),
+            }
+            .into());
+        }
+        self.draw_buffers = indices.to_vec();
+        let _binding = self.bind()?;
+        self.apply_draw_buffers();
+        self.check_status()
+    }
+
+    /// Checks that the framebuffer can be rendered to.
+    pub fn check_status(&self) -> Result<()> {
+        let _binding = self.bind()?;
+        let status = with_backend(|backend| backend.check_framebuffer_status(gl::FRAMEBUFFER));
+        match FramebufferStatus::from_gl(status) {
+            None => Ok(()),
+            Some(status) => Err(FramebufferError::Incomplete {
+                label: self.label.clone(),
+                status,
+            }
+            .into()),
+        }
+    }
+
+    /// Creates the attachments again with the new size. Their contents are lost.
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<()> {
+        if width == self.width && height == self.height {
+            return Ok(());
+        }
+        if width == 0 || height == 0 {
+            return Err(FramebufferError::InvalidSize {
+                width,
+                height,
+            }
+            .into());
+        }
+        self.create_attachments(width, height)
+            .with_context(|| format!("Failed to resize framebuffer {:?}", self.label))
+    }
+
+    /// Resizes the framebuffer to the drawable size of the window if it changed.
+    /// Cheap enough to be called every frame. Returns `true` if it was resized.
+    pub fn fit_to_window(&mut self, window: &SdlWindow) -> Result<bool> {
+        let (width, height) = window.get_drawable_size();
+        // A minimized window has no drawable area, the old size is kept
+        if (width, height) == (self.width, self.height) || width == 0 || height == 0 {
+            return Ok(false);
+        }
+        self.resize(width, height)?;
+        Ok(true)
+    }
+
+    /// Copies the buffers in `mask` to `target`, scaling them to its size. Color is
+    /// copied from the first color attachment to all draw buffers of `target`.
+    pub fn blit_to(
+        &self,
+        target: &Framebuffer,
+        mask: RenderMask,
+        filter: BlitFilter,
+    ) -> Result<()> {
+        self.blit(target.id, target.width, target.height, mask, filter)
+            .with_context(|| format!("Failed to blit {:?} to {:?}", self.label, target.label))
+    }
+
+    /// Copies the buffers in `mask` to the window, scaling them to its drawable size.
+    pub fn blit_to_window(
+        &self,
+        window: &SdlWindow,
+        mask: RenderMask,
+        filter: BlitFilter,
+    ) -> Result<()> {
+        let (width, height) = window.get_drawable_size();
+        self.blit(0, width, height, mask, filter)
+            .with_context(|| format!("Failed to blit {:?} to the window", self.label))
+    }
+
+    /// Resolves a multisampled framebuffer into `target`, which must have the same
+    /// size. Every color attachment is copied to the attachment with the same index,
+    /// followed by depth and stencil if both framebuffers have them.
+    pub fn resolve_to(&self, target: &Framebuffer) -> Result<()> {
+        if (self.width, self.height) != (target.width, target.height) {
+            return Err(anyhow!(
+                "Can't resolve {:?} ({}x{}) to {:?} ({}x{}), the sizes differ",
+                self.label,
+                self.width,
+                self.height,
+                target.label,
+                target.width,
+                target.height
+            ));
+        }
+        let _restore = BindingRestore::save();
+        let (width, height) = (self.width as GLint, self.height as GLint);
+        let region = [0, 0, width, height];
+        with_backend(|backend| {
+            backend.bind_framebuffer(gl::READ_FRAMEBUFFER, self.id);
+            backend.bind_framebuffer(gl::DRAW_FRAMEBUFFER, target.id);
+            for index in 0..self.colors.len().min(target.colors.len()) {
+                let attachment = gl::COLOR_ATTACHMENT0 + index as GLenum;
+                backend.read_buffer(attachment);
+                backend.draw_buffers(&[attachment]);
+                backend.blit_framebuffer(region, region, gl::COLOR_BUFFER_BIT, gl::NEAREST);
+            }
+            let mut mask = 0;
+            if self.depth.is_some() && target.depth.is_some() {
+                mask |= gl::DEPTH_BUFFER_BIT;
+            }
+            if self.has_stencil() && target.has_stencil() {
+                mask |= gl::STENCIL_BUFFER_BIT;
+            }
+            if mask != 0 {
+                backend.blit_framebuffer(region, region, mask, gl::NEAREST);
+            }
+        });
+        self.apply_read_buffer();
+        target.apply_draw_buffers();
+        check_gl_error()
+            .with_context(|| format!("Failed to resolve {:?} to {:?}", self.label, target.label))
+    }
+
+    fn blit(
+        &self,
+        target_id: GLuint,
+        target_width: u32,
+        target_height: u32,
+        mask: RenderMask,
+        filter: BlitFilter,
+    ) -> Result<()> {
+        if filter == BlitFilter::Linear && (mask.depth || mask.stencil) {
+            return Err(anyhow!("Depth and stencil can only be blitted with BlitFilter::Nearest"));
+        }
+        let _restore = BindingRestore::save();
+        with_backend(|backend| {
+            backend.bind_framebuffer(gl::READ_FRAMEBUFFER, self.id);
+            backend.bind_framebuffer(gl::DRAW_FRAMEBUFFER, target_id);
+            backend.blit_framebuffer(
+                [0, 0, self.width as GLint, self.height as GLint],
+                [0, 0, target_width as GLint, target_height as GLint],
+                mask.to_opengl(),
+                filter.to_gl_enum(),
+            );
+        });
+        check_gl_error()
+    }
+
+    fn has_stencil(&self) -> bool {
+        self.stencil.is_some()
+            || self
+                .depth
+                .as_ref()
+                .is_some_and(|depth| depth.spec.format.kind() == AttachmentKind::DepthStencil)
+    }
+
+    /// Creates all attachments with the given size, replacing existing ones, and
+    /// checks the framebuffer for completeness. The size and attachments are only
+    /// changed once all attachments were created and attached.
+    fn create_attachments(&mut self, width: u32, height: u32) -> Result<()> {
+        let samples = self.samples;
+        let colors = self
+            .color_specs
+            .iter()
+            .enumerate()
+            .map(|(index, spec)| {
+                let label = format!("{} color {}", self.label, index);
+                Attachment::create(*spec, width, height, samples, &label)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let depth = self
+            .depth_spec
+            .map(|spec| {
+                let label = format!("{} depth", self.label);
+                Attachment::create(spec, width, height, samples, &label)
+            })
+            .transpose()?;
+        let stencil = self
+            .stencil_spec
+            .map(|spec| {
+                let label = format!("{} stencil", self.label);
+                Attachment::create(spec, width, height, samples, &label)
+            })
+            .transpose()?;
+
+        {
+            let _restore = BindingRestore::save();
+            with_backend(|backend| backend.bind_framebuffer(gl::FRAMEBUFFER, self.id));
+            let new_attachments = attachment_points(&colors, depth.as_ref(), stencil.as_ref());
+            for (attachment_point, attachment) in &new_attachments {
+                attachment.attach(*attachment_point);
+            }
+            self.apply_draw_buffers();
+            self.apply_read_buffer();
+            if let Err(err) = check_gl_error() {
+                // The new attachments are deleted on return, the framebuffer has to
+                // point at the old ones again
+                with_backend(|backend| {
+                    for (attachment_point, _) in &new_attachments {
+                        backend.framebuffer_texture_2d(
+                            gl::FRAMEBUFFER,
+                            *attachment_point,
+                            gl::TEXTURE_2D,
+                            0,
+                            0,
+                        );
+                    }
+                });
+                let old_attachments =
+                    attachment_points(&self.colors, self.depth.as_ref(), self.stencil.as_ref());
+                for (attachment_point, attachment) in old_attachments {
+                    attachment.attach(attachment_point);
+                }
+                return Err(
+                    err.context(format!("Failed to attach the attachments of {:?}", self.label))
+                );
+            }
+        }
+
+        // The old attachments are deleted when they are replaced
+        self.colors = colors;
+        self.depth = depth;
+        self.stencil = stencil;
+        self.width = width;
+        self.height = height;
+        self.check_status()
+    }
+
+    /// Sets the draw buffers of the framebuffer bound to `GL_DRAW_FRAMEBUFFER`.
+    fn apply_draw_buffers(&self) {
+        let draw_buffers = draw_buffer_enums(&self.draw_buffers);
+        with_backend(|backend| {
+            if draw_buffers.is_empty() {
+                backend.draw_buffers(&[gl::NONE]);
+            } else {
+                backend.draw_buffers(&draw_buffers);
+            }
+        });
+    }
+
+    /// Sets the read buffer of the framebuffer bound to `GL_READ_FRAMEBUFFER`.
+    fn apply_read_buffer(&self) {
+        let read_buffer = if self.color_specs.is_empty() {
+            gl::NONE
+        } else {
+            gl::COLOR_ATTACHMENT0
+        };
+        with_backend(|backend| backend.read_buffer(read_buffer));
+    }
+}
+
+impl Deletable for Framebuffer {
+    fn delete(&mut self) -> Result<()> {
+        self.colors.clear();
+        self.depth = None;
+        self.stencil = None;
+        if self.id != 0 {
+            resource_registry::unregister(ResourceKind::Framebuffer, self.id)?;
+            with_backend(|backend| backend.delete_framebuffer(self.id));
+            self.id = 0;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        if let Err(err) = self.delete() {
+            eprintln!("Error while dropping framebuffer: {}", err);
+        }
+    }
+}
+
+impl<'a> RenderTarget<'a> for Framebuffer {
+    /// Clears all draw buffers to `clear_color`, as well as depth and stencil.
+    fn clear(&self) {
+        let _binding = match self.bind() {
+            Ok(binding) => binding,
+            Err(err) => {
+                eprintln!("Error while clearing framebuffer {:?}: {}", self.label, err);
+                return;
+            }
+        };
+        with_backend(|backend| {
+            backend.clear_color(
+                self.clear_color.r,
+                self.clear_color.g,
+                self.clear_color.b,
+                self.clear_color.a,
+            );
+            backend.clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
+        });
+    }
+
+    fn clear_with_color(&mut self, color: impl Into<Cow<'a, Color>>) {
+        let color = color.into();
+        self.clear_color.r = color.r;
+        self.clear_color.g = color.g;
+        self.clear_color.b = color.b;
+        self.clear_color.a = color.a;
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opengl::gl_backend::{install_backend, GlCall, RecordingGlBackend};
+
+    #[test]
+    fn test_attachment_format_kinds() {
+        assert_eq!(AttachmentFormat::Rgba16F.kind(), AttachmentKind::Color);
+        assert_eq!(AttachmentFormat::Depth24.kind(), AttachmentKind::Depth);
+        assert_eq!(AttachmentFormat::Depth24Stencil8.kind(), AttachmentKind::DepthStencil);
+        assert_eq!(AttachmentFormat::Stencil8.kind(), AttachmentKind::Stencil);
+        let integer = AttachmentFormat::Custom {
+            internal_format: gl::R32UI,
+            format: gl::RED_INTEGER,
+            data_type: gl::UNSIGNED_INT,
+            bytes_per_pixel: 4,
+        };
+        assert_eq!(integer.kind(), AttachmentKind::Color);
+        assert_eq!(integer.internal_format(), gl::R32UI);
+    }
+
+    #[test]
+    fn test_builder_validation() {
+        let valid = Framebuffer::builder(64, 32)
+            .color_texture(AttachmentFormat::Rgba8)
+            .color(AttachmentFormat::Rgba16F, AttachmentStorage::Renderbuffer)
+            .depth(AttachmentFormat::Depth24Stencil8, AttachmentStorage::Renderbuffer);
+        assert!(valid.validate().is_ok());
+
+        let empty = Framebuffer::builder(0, 32).color_texture(AttachmentFormat::Rgba8);
+        assert!(matches!(empty.validate(), Err(FramebufferError::InvalidSize { .. })));
+
+        let depth_as_color = Framebuffer::builder(64, 32).color_texture(AttachmentFormat::Depth24);
+        assert!(matches!(
+            depth_as_color.validate(),
+            Err(FramebufferError::InvalidFormat {
+                expected: AttachmentKind::Color,
+                actual: AttachmentKind::Depth,
+                ..
+            })
+        ));
+
+        let two_stencils =
+            valid.stencil(AttachmentFormat::Stencil8, AttachmentStorage::Renderbuffer);
+        assert!(matches!(two_stencils.validate(), Err(FramebufferError::ConflictingStencil)));
+
+        let stencil_texture = Framebuffer::builder(64, 32)
+            .color_texture(AttachmentFormat::Rgba8)
+            .stencil(AttachmentFormat::Stencil8, AttachmentStorage::Texture);
+        assert!(matches!(stencil_texture.validate(), Err(FramebufferError::StencilTexture)));
+    }
+
+    #[test]
+    fn test_status_messages() {
+        assert_eq!(FramebufferStatus::from_gl(gl::FRAMEBUFFER_COMPLETE), None);
+        let status = FramebufferStatus::from_gl(gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT);
+        assert_eq!(status, Some(FramebufferStatus::MissingAttachment));
+        let error = FramebufferError::Incomplete {
+            label: "gbuffer".to_string(),
+            status: status.unwrap(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "Framebuffer \"gbuffer\" is incomplete: there are no attachments \
+             (GL_FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT)"
+        );
+        assert_eq!(draw_buffer_enums(&[0, 2]), vec![gl::COLOR_ATTACHMENT0, gl::COLOR_ATTACHMENT2]);
+    }
+
+    #[test]
+    fn test_failed_resize_keeps_the_old_attachments() {
+        let backend = RecordingGlBackend::new();
+        let _guard = install_backend(backend.clone());
+        let mut framebuffer = Framebuffer::builder(64, 32)
+            .label("scene")
+            .color_texture(AttachmentFormat::Rgba8)
+            .color_texture(AttachmentFormat::Rgba16F)
+            .depth(AttachmentFormat::Depth24Stencil8, AttachmentStorage::Renderbuffer)
+            .build()
+            .unwrap();
+        let id = framebuffer.framebuffer_id();
+        let attached = || {
+            [gl::COLOR_ATTACHMENT0, gl::COLOR_ATTACHMENT1, gl::DEPTH_STENCIL_ATTACHMENT]
+                .map(|attachment_point| backend.framebuffer_attachment(id, attachment_point))
+        };
+        let old_attachments = [
+            framebuffer.color_texture(0),
+            framebuffer.color_texture(1),
+            framebuffer.depth.as_ref().map(|depth| depth.id),
+        ];
+        assert_eq!(attached(), old_attachments);
+
+        // The driver rejects the second color texture of the new size
+        backend.fail_next(
+            |call| {
+                matches!(call, GlCall::FramebufferTexture2D { attachment, texture, .. }
+                    if *attachment == gl::COLOR_ATTACHMENT1 && *texture != 0)
+            },
+            gl::INVALID_OPERATION,
+        );
+        assert!(framebuffer.resize(128, 64).is_err());
+        assert_eq!((framebuffer.width(), framebuffer.height()), (64, 32));
+        assert_eq!(attached(), old_attachments);
+        // Only the old attachments and the framebuffer are alive
+        assert_eq!(backend.live_objects(), 4);
+        assert_eq!(backend.texture_size(old_attachments[0].unwrap()), Some((64, 32)));
+        framebuffer.check_status().unwrap();
+        assert_eq!(backend.bound_framebuffer(gl::DRAW_FRAMEBUFFER), 0);
+
+        framebuffer.resize(128, 64).unwrap();
+        let color = framebuffer.color_texture(0).unwrap();
+        assert_eq!(backend.texture_size(color), Some((128, 64)));
+        assert_eq!(backend.live_objects(), 4);
+    }
+}
@@ -0,0 +1,3943 @@
+use crate::gl_utils::clear_capability_cache;
+use gl::types::{
+    GLbitfield, GLboolean, GLchar, GLenum, GLint, GLintptr, GLsizei, GLsizeiptr, GLsync, GLuint,
+    GLuint64, GLDEBUGPROC,
+};
+use std::cell::{RefCell, RefMut};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::{c_void, CStr, CString};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::{fmt, ptr, slice};
+
+//////////////////////////////////////////////////////////////////////////////
+// - GlBackend -
+//////////////////////////////////////////////////////////////////////////////
+
+/// The OpenGL calls made by the wrappers of `shared_lib::opengl`, from buffers and
+/// shaders to the fixed function state, draw calls, queries, framebuffers and fences.
+///
+/// The wrappers don't call `gl::*` themselves but go through the backend of the
+/// current thread, see `with_backend`. By default that is `RealGlBackend`, tests
+/// can install a `RecordingGlBackend` to run the wrappers without a GL context.
+///
+/// The methods taking raw pointers are unsafe, as the pointers are read or written
+/// like the real GL functions do.
+pub trait GlBackend {
+    fn get_error(&mut self) -> GLenum;
+    fn get_integer(&mut self, pname: GLenum) -> GLint;
+
+    fn gen_buffer(&mut self) -> GLuint;
+    fn delete_buffer(&mut self, buffer: GLuint);
+    fn bind_buffer(&mut self, target: GLenum, buffer: GLuint);
+    /// # Safety
+    /// `data` has to be null or point to `size` readable bytes.
+    unsafe fn buffer_data(
+        &mut self,
+        target: GLenum,
+        size: GLsizeiptr,
+        data: *const c_void,
+        usage: GLenum,
+    );
+    /// # Safety
+    /// `data` has to point to `size` readable bytes.
+    unsafe fn buffer_sub_data(
+        &mut self,
+        target: GLenum,
+        offset: GLintptr,
+        size: GLsizeiptr,
+        data: *const c_void,
+    );
+    /// # Safety
+    /// `data` has to be null or point to `size` readable bytes.
+    unsafe fn buffer_storage(
+        &mut self,
+        target: GLenum,
+        size: GLsizeiptr,
+        data: *const c_void,
+        flags: GLbitfield,
+    );
+    fn copy_buffer_sub_data(
+        &mut self,
+        read_target: GLenum,
+        write_target: GLenum,
+        read_offset: GLintptr,
+        write_offset: GLintptr,
+        size: GLsizeiptr,
+    );
+    /// # Safety
+    /// `data` has to point to `size` writable bytes.
+    unsafe fn get_buffer_sub_data(
+        &mut self,
+        target: GLenum,
+        offset: GLintptr,
+        size: GLsizeiptr,
+        data: *mut c_void,
+    );
+    /// Returns a null pointer if the range couldn't be mapped.
+    fn map_buffer_range(
+        &mut self,
+        target: GLenum,
+        offset: GLintptr,
+        length: GLsizeiptr,
+        access: GLbitfield,
+    ) -> *mut c_void;
+    fn flush_mapped_buffer_range(&mut self, target: GLenum, offset: GLintptr, length: GLsizeiptr);
+    /// Returns false if the contents got corrupted while the buffer was mapped.
+    fn unmap_buffer(&mut self, target: GLenum) -> bool;
+
+    fn gen_vertex_array(&mut self) -> GLuint;
+    fn delete_vertex_array(&mut self, array: GLuint);
+    fn bind_vertex_array(&mut self, array: GLuint);
+    fn enable_vertex_attrib_array(&mut self, index: GLuint);
+    fn disable_vertex_attrib_array(&mut self, index: GLuint);
+    /// The attribute pointers take the offset into the bound array buffer.
+    fn vertex_attrib_pointer(
+        &mut self,
+        index: GLuint,
+        size: GLint,
+        data_type: GLenum,
+        normalized: bool,
+        stride: GLsizei,
+        offset: usize,
+    );
+    fn vertex_attrib_i_pointer(
+        &mut self,
+        index: GLuint,
+        size: GLint,
+        data_type: GLenum,
+        stride: GLsizei,
+        offset: usize,
+    );
+    fn vertex_attrib_l_pointer(
+        &mut self,
+        index: GLuint,
+        size: GLint,
+        data_type: GLenum,
+        stride: GLsizei,
+        offset: usize,
+    );
+    fn vertex_attrib_divisor(&mut self, index: GLuint, divisor: GLuint);
+
+    fn gen_texture(&mut self) -> GLuint;
+    fn delete_texture(&mut self, texture: GLuint);
+    fn active_texture(&mut self, unit: GLenum);
+    fn bind_texture(&mut self, target: GLenum, texture: GLuint);
+    fn tex_parameter_i(&mut self, target: GLenum, pname: GLenum, param: GLint);
+    /// # Safety
+    /// `data` has to be null or point to the whole image described by `width`,
+    /// `height`, `format` and `data_type`.
+    #[allow(clippy::too_many_arguments)]
+    unsafe fn tex_image_2d(
+        &mut self,
+        target: GLenum,
+        level: GLint,
+        internal_format: GLint,
+        width: GLsizei,
+        height: GLsizei,
+        format: GLenum,
+        data_type: GLenum,
+        data: *const c_void,
+    );
+    fn generate_mipmap(&mut self, target: GLenum);
+
+    fn create_shader(&mut self, shader_type: GLenum) -> GLuint;
+    fn delete_shader(&mut self, shader: GLuint);
+    fn shader_source(&mut self, shader: GLuint, source: &CStr);
+    fn compile_shader(&mut self, shader: GLuint);
+    fn get_shader_iv(&mut self, shader: GLuint, pname: GLenum) -> GLint;
+    fn get_shader_info_log(&mut self, shader: GLuint) -> String;
+
+    fn create_program(&mut self) -> GLuint;
+    fn delete_program(&mut self, program: GLuint);
+    fn attach_shader(&mut self, program: GLuint, shader: GLuint);
+    fn detach_shader(&mut self, program: GLuint, shader: GLuint);
+    fn transform_feedback_varyings(&mut self, program: GLuint, names: &[CString], mode: GLenum);
+    fn link_program(&mut self, program: GLuint);
+    fn use_program(&mut self, program: GLuint);
+    fn get_program_iv(&mut self, program: GLuint, pname: GLenum) -> GLint;
+    fn get_program_info_log(&mut self, program: GLuint) -> String;
+    /// Returns -1 if the program has no active uniform `name`.
+    fn get_uniform_location(&mut self, program: GLuint, name: &CStr) -> GLint;
+    /// Returns -1 if the program has no active attribute `name`.
+    fn get_attrib_location(&mut self, program: GLuint, name: &CStr) -> GLint;
+    /// Returns `None` if `index` isn't below `ACTIVE_UNIFORMS`.
+    fn get_active_uniform(&mut self, program: GLuint, index: GLuint) -> Option<ActiveVariable>;
+    /// Returns `None` if `index` isn't below `ACTIVE_ATTRIBUTES`.
+    fn get_active_attrib(&mut self, program: GLuint, index: GLuint) -> Option<ActiveVariable>;
+
+    /// Returns the values of state with up to four values, like `VIEWPORT` or
+    /// `COLOR_WRITEMASK`. Missing values are 0. Not for the lists of formats like
+    /// `COMPRESSED_TEXTURE_FORMATS`.
+    fn get_integers(&mut self, pname: GLenum) -> [GLint; 4];
+    /// Returns `None` if `index` isn't below the number of strings, e.g.
+    /// `NUM_EXTENSIONS` for `EXTENSIONS`.
+    fn get_string_i(&mut self, name: GLenum, index: GLuint) -> Option<String>;
+    /// Checks if the `gl` crate loaded the function `name`, e.g. `"glBufferStorage"`.
+    /// Only knows the functions which are checked before optional features are used.
+    fn is_function_loaded(&mut self, name: &str) -> bool;
+
+    fn enable(&mut self, capability: GLenum);
+    fn disable(&mut self, capability: GLenum);
+    fn is_enabled(&mut self, capability: GLenum) -> bool;
+    fn depth_mask(&mut self, enabled: bool);
+    fn depth_func(&mut self, func: GLenum);
+    fn cull_face(&mut self, mode: GLenum);
+    fn front_face(&mut self, mode: GLenum);
+    fn polygon_mode(&mut self, face: GLenum, mode: GLenum);
+    fn color_mask(&mut self, red: bool, green: bool, blue: bool, alpha: bool);
+    fn scissor(&mut self, x: GLint, y: GLint, width: GLsizei, height: GLsizei);
+    fn viewport(&mut self, x: GLint, y: GLint, width: GLsizei, height: GLsizei);
+    fn blend_func(&mut self, src: GLenum, dest: GLenum);
+    fn blend_func_separate(
+        &mut self,
+        src_rgb: GLenum,
+        dest_rgb: GLenum,
+        src_alpha: GLenum,
+        dest_alpha: GLenum,
+    );
+    fn stencil_func_separate(&mut self, face: GLenum, func: GLenum, reference: GLint, mask: GLuint);
+    fn stencil_mask_separate(&mut self, face: GLenum, mask: GLuint);
+    fn stencil_op_separate(
+        &mut self,
+        face: GLenum,
+        stencil_fail: GLenum,
+        depth_fail: GLenum,
+        pass: GLenum,
+    );
+    fn clear_color(&mut self, red: f32, green: f32, blue: f32, alpha: f32);
+    fn clear_stencil(&mut self, value: GLint);
+    fn clear(&mut self, mask: GLbitfield);
+    fn primitive_restart_index(&mut self, index: GLuint);
+
+    fn draw_arrays(&mut self, mode: GLenum, first: GLint, count: GLsizei);
+    fn draw_arrays_instanced(
+        &mut self,
+        mode: GLenum,
+        first: GLint,
+        count: GLsizei,
+        instance_count: GLsizei,
+    );
+    fn draw_arrays_instanced_base_instance(
+        &mut self,
+        mode: GLenum,
+        first: GLint,
+        count: GLsizei,
+        instance_count: GLsizei,
+        base_instance: GLuint,
+    );
+    /// The element draws take the byte offset into the bound element array buffer.
+    fn draw_elements(&mut self, mode: GLenum, count: GLsizei, data_type: GLenum, offset: usize);
+    fn draw_elements_base_vertex(
+        &mut self,
+        mode: GLenum,
+        count: GLsizei,
+        data_type: GLenum,
+        offset: usize,
+        base_vertex: GLint,
+    );
+    fn draw_elements_instanced(
+        &mut self,
+        mode: GLenum,
+        count: GLsizei,
+        data_type: GLenum,
+        offset: usize,
+        instance_count: GLsizei,
+    );
+    fn draw_elements_instanced_base_vertex(
+        &mut self,
+        mode: GLenum,
+        count: GLsizei,
+        data_type: GLenum,
+        offset: usize,
+        instance_count: GLsizei,
+        base_vertex: GLint,
+    );
+    #[allow(clippy::too_many_arguments)]
+    fn draw_elements_instanced_base_vertex_base_instance(
+        &mut self,
+        mode: GLenum,
+        count: GLsizei,
+        data_type: GLenum,
+        offset: usize,
+        instance_count: GLsizei,
+        base_vertex: GLint,
+        base_instance: GLuint,
+    );
+    /// The indirect draws take the byte offset into the bound draw indirect buffer.
+    fn multi_draw_arrays_indirect(
+        &mut self,
+        mode: GLenum,
+        offset: usize,
+        draw_count: GLsizei,
+        stride: GLsizei,
+    );
+    fn multi_draw_elements_indirect(
+        &mut self,
+        mode: GLenum,
+        data_type: GLenum,
+        offset: usize,
+        draw_count: GLsizei,
+        stride: GLsizei,
+    );
+
+    fn gen_query(&mut self) -> GLuint;
+    fn delete_query(&mut self, query: GLuint);
+    fn begin_query(&mut self, target: GLenum, query: GLuint);
+    fn end_query(&mut self, target: GLenum);
+    fn query_counter(&mut self, query: GLuint, target: GLenum);
+    fn get_query_object_ui(&mut self, query: GLuint, pname: GLenum) -> GLuint;
+    fn get_query_object_ui64(&mut self, query: GLuint, pname: GLenum) -> GLuint64;
+    fn begin_conditional_render(&mut self, query: GLuint, mode: GLenum);
+    fn end_conditional_render(&mut self);
+
+    fn gen_transform_feedback(&mut self) -> GLuint;
+    fn delete_transform_feedback(&mut self, transform_feedback: GLuint);
+    fn bind_transform_feedback(&mut self, target: GLenum, transform_feedback: GLuint);
+    fn begin_transform_feedback(&mut self, primitive_mode: GLenum);
+    fn end_transform_feedback(&mut self);
+    fn pause_transform_feedback(&mut self);
+    fn resume_transform_feedback(&mut self);
+    fn draw_transform_feedback(&mut self, mode: GLenum, transform_feedback: GLuint);
+    fn bind_buffer_base(&mut self, target: GLenum, index: GLuint, buffer: GLuint);
+    fn bind_buffer_range(
+        &mut self,
+        target: GLenum,
+        index: GLuint,
+        buffer: GLuint,
+        offset: GLintptr,
+        size: GLsizeiptr,
+    );
+
+    fn gen_framebuffer(&mut self) -> GLuint;
+    fn delete_framebuffer(&mut self, framebuffer: GLuint);
+    fn bind_framebuffer(&mut self, target: GLenum, framebuffer: GLuint);
+    fn check_framebuffer_status(&mut self, target: GLenum) -> GLenum;
+    fn framebuffer_texture_2d(
+        &mut self,
+        target: GLenum,
+        attachment: GLenum,
+        texture_target: GLenum,
+        texture: GLuint,
+        level: GLint,
+    );
+    fn framebuffer_renderbuffer(
+        &mut self,
+        target: GLenum,
+        attachment: GLenum,
+        renderbuffer_target: GLenum,
+        renderbuffer: GLuint,
+    );
+    fn get_framebuffer_attachment_parameter_i(
+        &mut self,
+        target: GLenum,
+        attachment: GLenum,
+        pname: GLenum,
+    ) -> GLint;
+    fn draw_buffers(&mut self, buffers: &[GLenum]);
+    fn read_buffer(&mut self, buffer: GLenum);
+    /// The rectangles are given as `[x0, y0, x1, y1]`.
+    fn blit_framebuffer(
+        &mut self,
+        source: [GLint; 4],
+        destination: [GLint; 4],
+        mask: GLbitfield,
+        filter: GLenum,
+    );
+    fn gen_renderbuffer(&mut self) -> GLuint;
+    fn delete_renderbuffer(&mut self, renderbuffer: GLuint);
+    fn bind_renderbuffer(&mut self, target: GLenum, renderbuffer: GLuint);
+    fn renderbuffer_storage_multisample(
+        &mut self,
+        target: GLenum,
+        samples: GLsizei,
+        internal_format: GLenum,
+        width: GLsizei,
+        height: GLsizei,
+    );
+    fn tex_image_2d_multisample(
+        &mut self,
+        target: GLenum,
+        samples: GLsizei,
+        internal_format: GLenum,
+        width: GLsizei,
+        height: GLsizei,
+        fixed_sample_locations: bool,
+    );
+
+    /// Returns a null sync object if the fence couldn't be created.
+    fn fence_sync(&mut self, condition: GLenum, flags: GLbitfield) -> GLsync;
+    /// # Safety
+    /// `sync` has to be a fence returned by `fence_sync` that wasn't deleted yet.
+    unsafe fn client_wait_sync(
+        &mut self,
+        sync: GLsync,
+        flags: GLbitfield,
+        timeout: GLuint64,
+    ) -> GLenum;
+    /// # Safety
+    /// `sync` has to be null or a fence returned by `fence_sync` that wasn't
+    /// deleted yet.
+    unsafe fn delete_sync(&mut self, sync: GLsync);
+
+    fn debug_message_callback(&mut self, callback: GLDEBUGPROC);
+    fn object_label(&mut self, identifier: GLenum, name: GLuint, label: &str);
+    fn push_debug_group(&mut self, source: GLenum, id: GLuint, message: &str);
+    fn pop_debug_group(&mut self);
+}
+
+/// An active uniform or attribute of a linked program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveVariable {
+    pub name: String,
+    /// The number of array elements, 1 for non-arrays.
+    pub size: GLint,
+    pub gl_type: GLenum,
+}
+
+thread_local! {
+    static BACKEND: RefCell<Box<dyn GlBackend>> = RefCell::new(Box::new(RealGlBackend));
+}
+
+/// Runs `f` with the backend of the current thread.
+///
+/// `f` must not call `with_backend` again. After the thread local storage was torn
+/// down, e.g. while statics are dropped, `RealGlBackend` is used.
+pub fn with_backend<R>(f: impl FnOnce(&mut dyn GlBackend) -> R) -> R {
+    let mut f = Some(f);
+    let result = BACKEND.try_with(|backend| {
+        let f = f.take().expect("the backend closure runs once");
+        f(backend.borrow_mut().as_mut())
+    });
+    match result {
+        Ok(result) => result,
+        Err(_) => f.take().expect("the backend closure runs once")(&mut RealGlBackend),
+    }
+}
+
+/// Replaces the backend of the current thread until the returned guard is dropped.
+///
+/// # Example
+/// ```ignore
+/// let backend = RecordingGlBackend::new();
+/// let _guard = install_backend(backend.clone());
+/// let buffer = BufferObject::new(BufferType::ArrayBuffer, BufferUsage::StaticDraw, vertices);
+/// assert_eq!(backend.bound_buffer(gl::ARRAY_BUFFER), buffer.buffer_id());
+/// ```
+///
+/// The cached capability checks are cleared, as the backend may support other
+/// capabilities, see `cached_capability`.
+pub fn install_backend(backend: impl GlBackend + 'static) -> BackendGuard {
+    let previous = BACKEND.with(|current| current.replace(Box::new(backend)));
+    clear_capability_cache();
+    BackendGuard {
+        previous: Some(previous),
+    }
+}
+
+/// Restores the previous backend of the thread when dropped, see `install_backend`.
+#[must_use = "the backend is restored as soon as the guard is dropped"]
+pub struct BackendGuard {
+    previous: Option<Box<dyn GlBackend>>,
+}
+
+impl Drop for BackendGuard {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            let _ = BACKEND.try_with(|current| current.replace(previous));
+            clear_capability_cache();
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - RealGlBackend -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Calls the functions of the `gl` crate, which need a current GL context.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RealGlBackend;
+
+impl GlBackend for RealGlBackend {
+    fn get_error(&mut self) -> GLenum {
+        unsafe { gl::GetError() }
+    }
+
+    fn get_integer(&mut self, pname: GLenum) -> GLint {
+        let mut value = 0;
+        unsafe {
+            gl::GetIntegerv(pname, &mut value);
+        }
+        value
+    }
+
+    fn gen_buffer(&mut self) -> GLuint {
+        let mut buffer = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut buffer);
+        }
+        buffer
+    }
+
+    fn delete_buffer(&mut self, buffer: GLuint) {
+        unsafe { gl::DeleteBuffers(1, &buffer) }
+    }
+
+    fn bind_buffer(&mut self, target: GLenum, buffer: GLuint) {
+        unsafe { gl::BindBuffer(target, buffer) }
+    }
+
+    unsafe fn buffer_data(
+        &mut self,
+        target: GLenum,
+        size: GLsizeiptr,
+        data: *const c_void,
+        usage: GLenum,
+    ) {
+        gl::BufferData(target, size, data, usage)
+    }
+
+    unsafe fn buffer_sub_data(
+        &mut self,
+        target: GLenum,
+        offset: GLintptr,
+        size: GLsizeiptr,
+        data: *const c_void,
+    ) {
+        gl::BufferSubData(target, offset, size, data)
+    }
+
+    unsafe fn buffer_storage(
+        &mut self,
+        target: GLenum,
+        size: GLsizeiptr,
+        data: *const c_void,
+        flags: GLbitfield,
+    ) {
+        gl::BufferStorage(target, size, data, flags)
+    }
+
+    fn copy_buffer_sub_data(
+        &mut self,
+        read_target: GLenum,
+        write_target: GLenum,
+        read_offset: GLintptr,
+        write_offset: GLintptr,
+        size: GLsizeiptr,
+    ) {
+        unsafe { gl::CopyBufferSubData(read_target, write_target, read_offset, write_offset, size) }
+    }
+
+    unsafe fn get_buffer_sub_data(
+        &mut self,
+        target: GLenum,
+        offset: GLintptr,
+        size: GLsizeiptr,
+        data: *mut c_void,
+    ) {
+        gl::GetBufferSubData(target, offset, size, data)
+    }
+
+    fn map_buffer_range(
+        &mut self,
+        target: GLenum,
+        offset: GLintptr,
+        length: GLsizeiptr,
+        access: GLbitfield,
+    ) -> *mut c_void {
+        unsafe { gl::MapBufferRange(target, offset, length, access) }
+    }
+
+    fn flush_mapped_buffer_range(&mut self, target: GLenum, offset: GLintptr, length: GLsizeiptr) {
+        unsafe { gl::FlushMappedBufferRange(target, offset, length) }
+    }
+
+    fn unmap_buffer(&mut self, target: GLenum) -> bool {
+        unsafe { gl::UnmapBuffer(target) != gl::FALSE }
+    }
+
+    fn gen_vertex_array(&mut self) -> GLuint {
+        let mut array = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut array);
+        }
+        array
+    }
+
+    fn delete_vertex_array(&mut self, array: GLuint) {
+        unsafe { gl::DeleteVertexArrays(1, &array) }
+    }
+
+    fn bind_vertex_array(&mut self, array: GLuint) {
+        unsafe { gl::BindVertexArray(array) }
+    }
+
+    fn enable_vertex_attrib_array(&mut self, index: GLuint) {
+        unsafe { gl::EnableVertexAttribArray(index) }
+    }
+
+    fn disable_vertex_attrib_array(&mut self, index: GLuint) {
+        unsafe { gl::DisableVertexAttribArray(index) }
+    }
+
+    fn vertex_attrib_pointer(
+        &mut self,
+        index: GLuint,
+        size: GLint,
+        data_type: GLenum,
+        normalized: bool,
+        stride: GLsizei,
+        offset: usize,
+    ) {
+        let normalized = if normalized {
+            gl::TRUE
+        } else {
+            gl::FALSE
+        };
+        unsafe {
+            gl::VertexAttribPointer(index, size, data_type, normalized, stride, offset as *const _)
+        }
+    }
+
+    fn vertex_attrib_i_pointer(
+        &mut self,
+        index: GLuint,
+        size: GLint,
+        data_type: GLenum,
+        stride: GLsizei,
+        offset: usize,
+    ) {
+        unsafe { gl::VertexAttribIPointer(index, size, data_type, stride, offset as *const _) }
+    }
+
+    fn vertex_attrib_l_pointer(
+        &mut self,
+        index: GLuint,
+        size: GLint,
+        data_type: GLenum,
+        stride: GLsizei,
+        offset: usize,
+    ) {
+        unsafe { gl::VertexAttribLPointer(index, size, data_type, stride, offset as *const _) }
+    }
+
+    fn vertex_attrib_divisor(&mut self, index: GLuint, divisor: GLuint) {
+        unsafe { gl::VertexAttribDivisor(index, divisor) }
+    }
+
+    fn gen_texture(&mut self) -> GLuint {
+        let mut texture = 0;
+        unsafe {
+            gl::GenTextures(1, &mut texture);
+        }
+        texture
+    }
+
+    fn delete_texture(&mut self, texture: GLuint) {
+        unsafe { gl::DeleteTextures(1, &texture) }
+    }
+
+    fn active_texture(&mut self, unit: GLenum) {
+        unsafe { gl::ActiveTexture(unit) }
+    }
+
+    fn bind_texture(&mut self, target: GLenum, texture: GLuint) {
+        unsafe { gl::BindTexture(target, texture) }
+    }
+
+    fn tex_parameter_i(&mut self, target: GLenum, pname: GLenum, param: GLint) {
+        unsafe { gl::TexParameteri(target, pname, param) }
+    }
+
+    unsafe fn tex_image_2d(
+        &mut self,
+        target: GLenum,
+        level: GLint,
+        internal_format: GLint,
+        width: GLsizei,
+        height: GLsizei,
+        format: GLenum,
+        data_type: GLenum,
+        data: *const c_void,
+    ) {
+        gl::TexImage2D(target, level, internal_format, width, height, 0, format, data_type, data)
+    }
+
+    fn generate_mipmap(&mut self, target: GLenum) {
+        unsafe { gl::GenerateMipmap(target) }
+    }
+
+    fn create_shader(&mut self, shader_type: GLenum) -> GLuint {
+        unsafe { gl::CreateShader(shader_type) }
+    }
+
+    fn delete_shader(&mut self, shader: GLuint) {
+        unsafe { gl::DeleteShader(shader) }
+    }
+
+    fn shader_source(&mut self, shader: GLuint, source: &CStr) {
+        unsafe { gl::ShaderSource(shader, 1, &source.as_ptr(), ptr::null()) }
+    }
+
+    fn compile_shader(&mut self, shader: GLuint) {
+        unsafe { gl::CompileShader(shader) }
+    }
+
+    fn get_shader_iv(&mut self, shader: GLuint, pname: GLenum) -> GLint {
+        let mut value = 0;
+        unsafe {
+            gl::GetShaderiv(shader, pname, &mut value);
+        }
+        value
+    }
+
+    fn get_shader_info_log(&mut self, shader: GLuint) -> String {
+        let len = self.get_shader_iv(shader, gl::INFO_LOG_LENGTH);
+        read_info_log(len, |len, written, log| unsafe {
+            gl::GetShaderInfoLog(shader, len, written, log)
+        })
+    }
+
+    fn create_program(&mut self) -> GLuint {
+        unsafe { gl::CreateProgram() }
+    }
+
+    fn delete_program(&mut self, program: GLuint) {
+        unsafe { gl::DeleteProgram(program) }
+    }
+
+    fn attach_shader(&mut self, program: GLuint, shader: GLuint) {
+        unsafe { gl::AttachShader(program, shader) }
+    }
+
+    fn detach_shader(&mut self, program: GLuint, shader: GLuint) {
+        unsafe { gl::DetachShader(program, shader) }
+    }
+
+    fn transform_feedback_varyings(&mut self, program: GLuint, names: &[CString], mode: GLenum) {
+        let name_ptrs: Vec<*const GLchar> = names.iter().map(|name| name.as_ptr()).collect();
+        unsafe {
+            gl::TransformFeedbackVaryings(
+                program,
+                name_ptrs.len() as GLsizei,
+                name_ptrs.as_ptr(),
+                mode,
+            )
+        }
+    }
+
+    fn link_program(&mut self, program: GLuint) {
+        unsafe { gl::LinkProgram(program) }
+    }
+
+    fn use_program(&mut self, program: GLuint) {
+        unsafe { gl::UseProgram(program) }
+    }
+
+    fn get_program_iv(&mut self, program: GLuint, pname: GLenum) -> GLint {
+        let mut value = 0;
+        unsafe {
+            gl::GetProgramiv(program, pname, &mut value);
+        }
+        value
+    }
+
+    fn get_program_info_log(&mut self, program: GLuint) -> String {
+        let len = self.get_program_iv(program, gl::INFO_LOG_LENGTH);
+        read_info_log(len, |len, written, log| unsafe {
+            gl::GetProgramInfoLog(program, len, written, log)
+        })
+    }
+
+    fn get_uniform_location(&mut self, program: GLuint, name: &CStr) -> GLint {
+        unsafe { gl::GetUniformLocation(program, name.as_ptr()) }
+    }
+
+    fn get_attrib_location(&mut self, program: GLuint, name: &CStr) -> GLint {
+        unsafe { gl::GetAttribLocation(program, name.as_ptr()) }
+    }
+
+    fn get_active_uniform(&mut self, program: GLuint, index: GLuint) -> Option<ActiveVariable> {
+        read_active_variable(|len, written, size, gl_type, name| unsafe {
+            gl::GetActiveUniform(program, index, len, written, size, gl_type, name)
+        })
+    }
+
+    fn get_active_attrib(&mut self, program: GLuint, index: GLuint) -> Option<ActiveVariable> {
+        read_active_variable(|len, written, size, gl_type, name| unsafe {
+            gl::GetActiveAttrib(program, index, len, written, size, gl_type, name)
+        })
+    }
+
+    fn get_integers(&mut self, pname: GLenum) -> [GLint; 4] {
+        let mut values = [0; 4];
+        unsafe {
+            gl::GetIntegerv(pname, values.as_mut_ptr());
+        }
+        values
+    }
+
+    fn get_string_i(&mut self, name: GLenum, index: GLuint) -> Option<String> {
+        let string = unsafe { gl::GetStringi(name, index) };
+        if string.is_null() {
+            // The index was out of range, don't leave the error pending
+            unsafe { gl::GetError() };
+            return None;
+        }
+        let string = unsafe { CStr::from_ptr(string as *const GLchar) };
+        Some(string.to_string_lossy().into_owned())
+    }
+
+    fn is_function_loaded(&mut self, name: &str) -> bool {
+        match name {
+            "glBufferStorage" => gl::BufferStorage::is_loaded(),
+            "glDebugMessageCallback" => gl::DebugMessageCallback::is_loaded(),
+            "glMultiDrawElementsIndirect" => gl::MultiDrawElementsIndirect::is_loaded(),
+            _ => false,
+        }
+    }
+
+    fn enable(&mut self, capability: GLenum) {
+        unsafe { gl::Enable(capability) }
+    }
+
+    fn disable(&mut self, capability: GLenum) {
+        unsafe { gl::Disable(capability) }
+    }
+
+    fn is_enabled(&mut self, capability: GLenum) -> bool {
+        unsafe { gl::IsEnabled(capability) == gl::TRUE }
+    }
+
+    fn depth_mask(&mut self, enabled: bool) {
+        unsafe { gl::DepthMask(enabled as GLboolean) }
+    }
+
+    fn depth_func(&mut self, func: GLenum) {
+        unsafe { gl::DepthFunc(func) }
+    }
+
+    fn cull_face(&mut self, mode: GLenum) {
+        unsafe { gl::CullFace(mode) }
+    }
+
+    fn front_face(&mut self, mode: GLenum) {
+        unsafe { gl::FrontFace(mode) }
+    }
+
+    fn polygon_mode(&mut self, face: GLenum, mode: GLenum) {
+        unsafe { gl::PolygonMode(face, mode) }
+    }
+
+    fn color_mask(&mut self, red: bool, green: bool, blue: bool, alpha: bool) {
+        unsafe {
+            gl::ColorMask(
+                red as GLboolean,
+                green as GLboolean,
+                blue as GLboolean,
+                alpha as GLboolean,
+            )
+        }
+    }
+
+    fn scissor(&mut self, x: GLint, y: GLint, width: GLsizei, height: GLsizei) {
+        unsafe { gl::Scissor(x, y, width, height) }
+    }
+
+    fn viewport(&mut self, x: GLint, y: GLint, width: GLsizei, height: GLsizei) {
+        unsafe { gl::Viewport(x, y, width, height) }
+    }
+
+    fn blend_func(&mut self, src: GLenum, dest: GLenum) {
+        unsafe { gl::BlendFunc(src, dest) }
+    }
+
+    fn blend_func_separate(
+        &mut self,
+        src_rgb: GLenum,
+        dest_rgb: GLenum,
+        src_alpha: GLenum,
+        dest_alpha: GLenum,
+    ) {
+        unsafe { gl::BlendFuncSeparate(src_rgb, dest_rgb, src_alpha, dest_alpha) }
+    }
+
+    fn stencil_func_separate(
+        &mut self,
+        face: GLenum,
+        func: GLenum,
+        reference: GLint,
+        mask: GLuint,
+    ) {
+        unsafe { gl::StencilFuncSeparate(face, func, reference, mask) }
+    }
+
+    fn stencil_mask_separate(&mut self, face: GLenum, mask: GLuint) {
+        unsafe { gl::StencilMaskSeparate(face, mask) }
+    }
+
+    fn stencil_op_separate(
+        &mut self,
+        face: GLenum,
+        stencil_fail: GLenum,
+        depth_fail: GLenum,
+        pass: GLenum,
+    ) {
+        unsafe { gl::StencilOpSeparate(face, stencil_fail, depth_fail, pass) }
+    }
+
+    fn clear_color(&mut self, red: f32, green: f32, blue: f32, alpha: f32) {
+        unsafe { gl::ClearColor(red, green, blue, alpha) }
+    }
+
+    fn clear_stencil(&mut self, value: GLint) {
+        unsafe { gl::ClearStencil(value) }
+    }
+
+    fn clear(&mut self, mask: GLbitfield) {
+        unsafe { gl::Clear(mask) }
+    }
+
+    fn primitive_restart_index(&mut self, index: GLuint) {
+        unsafe { gl::PrimitiveRestartIndex(index) }
+    }
+
+    fn draw_arrays(&mut self, mode: GLenum, first: GLint, count: GLsizei) {
+        unsafe { gl::DrawArrays(mode, first, count) }
+    }
+
+    fn draw_arrays_instanced(
+        &mut self,
+        mode: GLenum,
+        first: GLint,
+        count: GLsizei,
+        instance_count: GLsizei,
+    ) {
+        unsafe { gl::DrawArraysInstanced(mode, first, count, instance_count) }
+    }
+
+    fn draw_arrays_instanced_base_instance(
+        &mut self,
+        mode: GLenum,
+        first: GLint,
+        count: GLsizei,
+        instance_count: GLsizei,
+        base_instance: GLuint,
+    ) {
+        unsafe {
+            gl::DrawArraysInstancedBaseInstance(mode, first, count, instance_count, base_instance)
+        }
+    }
+
+    fn draw_elements(&mut self, mode: GLenum, count: GLsizei, data_type: GLenum, offset: usize) {
+        unsafe { gl::DrawElements(mode, count, data_type, offset as *const _) }
+    }
+
+    fn draw_elements_base_vertex(
+        &mut self,
+        mode: GLenum,
+        count: GLsizei,
+        data_type: GLenum,
+        offset: usize,
+        base_vertex: GLint,
+    ) {
+        unsafe {
+            gl::DrawElementsBaseVertex(mode, count, data_type, offset as *const _, base_vertex)
+        }
+    }
+
+    fn draw_elements_instanced(
+        &mut self,
+        mode: GLenum,
+        count: GLsizei,
+        data_type: GLenum,
+        offset: usize,
+        instance_count: GLsizei,
+    ) {
+        unsafe {
+            gl::DrawElementsInstanced(mode, count, data_type, offset as *const _, instance_count)
+        }
+    }
+
+    fn draw_elements_instanced_base_vertex(
+        &mut self,
+        mode: GLenum,
+        count: GLsizei,
+        data_type: GLenum,
+        offset: usize,
+        instance_count: GLsizei,
+        base_vertex: GLint,
+    ) {
+        unsafe {
+            gl::DrawElementsInstancedBaseVertex(
+                mode,
+                count,
+                data_type,
+                offset as *const _,
+                instance_count,
+                base_vertex,
+            )
+        }
+    }
+
+    fn draw_elements_instanced_base_vertex_base_instance(
+        &mut self,
+        mode: GLenum,
+        count: GLsizei,
+        data_type: GLenum,
+        offset: usize,
+        instance_count: GLsizei,
+        base_vertex: GLint,
+        base_instance: GLuint,
+    ) {
+        unsafe {
+            gl::DrawElementsInstancedBaseVertexBaseInstance(
+                mode,
+                count,
+                data_type,
+                offset as *const _,
+                instance_count,
+                base_vertex,
+                base_instance,
+            )
+        }
+    }
+
+    fn multi_draw_arrays_indirect(
+        &mut self,
+        mode: GLenum,
+        offset: usize,
+        draw_count: GLsizei,
+        stride: GLsizei,
+    ) {
+        unsafe { gl::MultiDrawArraysIndirect(mode, offset as *const _, draw_count, stride) }
+    }
+
+    fn multi_draw_elements_indirect(
+        &mut self,
+        mode: GLenum,
+        data_type: GLenum,
+        offset: usize,
+        draw_count: GLsizei,
+        stride: GLsizei,
+    ) {
+        unsafe {
+            gl::MultiDrawElementsIndirect(mode, data_type, offset as *const _, draw_count, stride)
+        }
+    }
+
+    fn gen_query(&mut self) -> GLuint {
+        let mut query = 0;
+        unsafe {
+            gl::GenQueries(1, &mut query);
+        }
+        query
+    }
+
+    fn delete_query(&mut self, query: GLuint) {
+        unsafe { gl::DeleteQueries(1, &query) }
+    }
+
+    fn begin_query(&mut self, target: GLenum, query: GLuint) {
+        unsafe { gl::BeginQuery(target, query) }
+    }
+
+    fn end_query(&mut self, target: GLenum) {
+        unsafe { gl::EndQuery(target) }
+    }
+
+    fn query_counter(&mut self, query: GLuint, target: GLenum) {
+        unsafe { gl::QueryCounter(query, target) }
+    }
+
+    fn get_query_object_ui(&mut self, query: GLuint, pname: GLenum) -> GLuint {
+        let mut value = 0;
+        unsafe {
+            gl::GetQueryObjectuiv(query, pname, &mut value);
+        }
+        value
+    }
+
+    fn get_query_object_ui64(&mut self, query: GLuint, pname: GLenum) -> GLuint64 {
+        let mut value = 0;
+        unsafe {
+            gl::GetQueryObjectui64v(query, pname, &mut value);
+        }
+        value
+    }
+
+    fn begin_conditional_render(&mut self, query: GLuint, mode: GLenum) {
+        unsafe { gl::BeginConditionalRender(query, mode) }
+    }
+
+    fn end_conditional_render(&mut self) {
+        unsafe { gl::EndConditionalRender() }
+    }
+
+    fn gen_transform_feedback(&mut self) -> GLuint {
+        let mut transform_feedback = 0;
+        unsafe {
+            gl::GenTransformFeedbacks(1, &mut transform_feedback);
+        }
+        transform_feedback
+    }
+
+    fn delete_transform_feedback(&mut self, transform_feedback: GLuint) {
+        unsafe { gl::DeleteTransformFeedbacks(1, &transform_feedback) }
+    }
+
+    fn bind_transform_feedback(&mut self, target: GLenum, transform_feedback: GLuint) {
+        unsafe { gl::BindTransformFeedback(target, transform_feedback) }
+    }
+
+    fn begin_transform_feedback(&mut self, primitive_mode: GLenum) {
+        unsafe { gl::BeginTransformFeedback(primitive_mode) }
+    }
+
+    fn end_transform_feedback(&mut self) {
+        unsafe { gl::EndTransformFeedback() }
+    }
+
+    fn pause_transform_feedback(&mut self) {
+        unsafe { gl::PauseTransformFeedback() }
+    }
+
+    fn resume_transform_feedback(&mut self) {
+        unsafe { gl::ResumeTransformFeedback() }
+    }
+
+    fn draw_transform_feedback(&mut self, mode: GLenum, transform_feedback: GLuint) {
+        unsafe { gl::DrawTransformFeedback(mode, transform_feedback) }
+    }
+
+    fn bind_buffer_base(&mut self, target: GLenum, index: GLuint, buffer: GLuint) {
+        unsafe { gl::BindBufferBase(target, index, buffer) }
+    }
+
+    fn bind_buffer_range(
+        &mut self,
+        target: GLenum,
+        index: GLuint,
+        buffer: GLuint,
+        offset: GLintptr,
+        size: GLsizeiptr,
+    ) {
+        unsafe { gl::BindBufferRange(target, index, buffer, offset, size) }
+    }
+
+    fn gen_framebuffer(&mut self) -> GLuint {
+        let mut framebuffer = 0;
+        unsafe {
+            gl::GenFramebuffers(1, &mut framebuffer);
+        }
+        framebuffer
+    }
+
+    fn delete_framebuffer(&mut self, framebuffer: GLuint) {
+        unsafe { gl::DeleteFramebuffers(1, &framebuffer) }
+    }
+
+    fn bind_framebuffer(&mut self, target: GLenum, framebuffer: GLuint) {
+        unsafe { gl::BindFramebuffer(target, framebuffer) }
+    }
+
+    fn check_framebuffer_status(&mut self, target: GLenum) -> GLenum {
+        unsafe { gl::CheckFramebufferStatus(target) }
+    }
+
+    fn framebuffer_texture_2d(
+        &mut self,
+        target: GLenum,
+        attachment: GLenum,
+        texture_target: GLenum,
+        texture: GLuint,
+        level: GLint,
+    ) {
+        unsafe { gl::FramebufferTexture2D(target, attachment, texture_target, texture, level) }
+    }
+
+    fn framebuffer_renderbuffer(
+        &mut self,
+        target: GLenum,
+        attachment: GLenum,
+        renderbuffer_target: GLenum,
+        renderbuffer: GLuint,
+    ) {
+        unsafe {
+            gl::FramebufferRenderbuffer(target, attachment, renderbuffer_target, renderbuffer)
+        }
+    }
+
+    fn get_framebuffer_attachment_parameter_i(
+        &mut self,
+        target: GLenum,
+        attachment: GLenum,
+        pname: GLenum,
+    ) -> GLint {
+        let mut value = 0;
+        unsafe {
+            gl::GetFramebufferAttachmentParameteriv(target, attachment, pname, &mut value);
+        }
+        value
+    }
+
+    fn draw_buffers(&mut self, buffers: &[GLenum]) {
+        unsafe { gl::DrawBuffers(buffers.len() as GLsizei, buffers.as_ptr()) }
+    }
+
+    fn read_buffer(&mut self, buffer: GLenum) {
+        unsafe { gl::ReadBuffer(buffer) }
+    }
+
+    fn blit_framebuffer(
+        &mut self,
+        source: [GLint; 4],
+        destination: [GLint; 4],
+        mask: GLbitfield,
+        filter: GLenum,
+    ) {
+        let [src_x0, src_y0, src_x1, src_y1] = source;
+        let [dest_x0, dest_y0, dest_x1, dest_y1] = destination;
+        unsafe {
+            gl::BlitFramebuffer(
+                src_x0, src_y0, src_x1, src_y1, dest_x0, dest_y0, dest_x1, dest_y1, mask, filter,
+            )
+        }
+    }
+
+    fn gen_renderbuffer(&mut self) -> GLuint {
+        let mut renderbuffer = 0;
+        unsafe {
+            gl::GenRenderbuffers(1, &mut renderbuffer);
+        }
+        renderbuffer
+    }
+
+    fn delete_renderbuffer(&mut self, renderbuffer: GLuint) {
+        unsafe { gl::DeleteRenderbuffers(1, &renderbuffer) }
+    }
+
+    fn bind_renderbuffer(&mut self, target: GLenum, renderbuffer: GLuint) {
+        unsafe { gl::BindRenderbuffer(target, renderbuffer) }
+    }
+
+    fn renderbuffer_storage_multisample(
+        &mut self,
+        target: GLenum,
+        samples: GLsizei,
+        internal_format: GLenum,
+        width: GLsizei,
+        height: GLsizei,
+    ) {
+        unsafe {
+            gl::RenderbufferStorageMultisample(target, samples, internal_format, width, height)
+        }
+    }
+
+    fn tex_image_2d_multisample(
+        &mut self,
+        target: GLenum,
+        samples: GLsizei,
+        internal_format: GLenum,
+        width: GLsizei,
+        height: GLsizei,
+        fixed_sample_locations: bool,
+    ) {
+        unsafe {
+            gl::TexImage2DMultisample(
+                target,
+                samples,
+                internal_format,
+                width,
+                height,
+                fixed_sample_locations as GLboolean,
+            )
+        }
+    }
+
+    fn fence_sync(&mut self, condition: GLenum, flags: GLbitfield) -> GLsync {
+        unsafe { gl::FenceSync(condition, flags) }
+    }
+
+    unsafe fn client_wait_sync(
+        &mut self,
+        sync: GLsync,
+        flags: GLbitfield,
+        timeout: GLuint64,
+    ) -> GLenum {
+        gl::ClientWaitSync(sync, flags, timeout)
+    }
+
+    unsafe fn delete_sync(&mut self, sync: GLsync) {
+        gl::DeleteSync(sync)
+    }
+
+    fn debug_message_callback(&mut self, callback: GLDEBUGPROC) {
+        unsafe { gl::DebugMessageCallback(callback, ptr::null()) }
+    }
+
+    fn object_label(&mut self, identifier: GLenum, name: GLuint, label: &str) {
+        unsafe {
+            gl::ObjectLabel(
+                identifier,
+                name,
+                label.len() as GLsizei,
+                label.as_ptr() as *const GLchar,
+            )
+        }
+    }
+
+    fn push_debug_group(&mut self, source: GLenum, id: GLuint, message: &str) {
+        unsafe {
+            gl::PushDebugGroup(
+                source,
+                id,
+                message.len() as GLsizei,
+                message.as_ptr() as *const GLchar,
+            )
+        }
+    }
+
+    fn pop_debug_group(&mut self) {
+        unsafe { gl::PopDebugGroup() }
+    }
+}
+
+/// Reads an info log of `len` bytes, including the terminating null, through `read`.
+fn read_info_log(len: GLint, read: impl FnOnce(GLsizei, *mut GLsizei, *mut GLchar)) -> String {
+    if len <= 0 {
+        return String::new();
+    }
+    let mut log = vec![0u8; len as usize];
+    let mut written = 0;
+    read(len, &mut written, log.as_mut_ptr() as *mut GLchar);
+    log.truncate(written.clamp(0, len) as usize);
+    String::from_utf8_lossy(&log).into_owned()
+}
+
+/// Reads the name, size and type of an active uniform or attribute through `read`.
+fn read_active_variable(
+    read: impl FnOnce(GLsizei, *mut GLsizei, *mut GLint, *mut GLenum, *mut GLchar),
+) -> Option<ActiveVariable> {
+    let mut name = vec![0u8; 256];
+    let mut written = 0;
+    let mut size = 0;
+    let mut gl_type = 0;
+    read(
+        name.len() as GLsizei,
+        &mut written,
+        &mut size,
+        &mut gl_type,
+        name.as_mut_ptr() as *mut GLchar,
+    );
+    if written <= 0 {
+        return None;
+    }
+    name.truncate(written as usize);
+    Some(ActiveVariable {
+        name: String::from_utf8_lossy(&name).into_owned(),
+        size,
+        gl_type,
+    })
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - RecordingGlBackend -
+//////////////////////////////////////////////////////////////////////////////
+
+/// A call which changed the state of a `RecordingGlBackend`. Queries aren't
+/// recorded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GlCall {
+    GenBuffer(GLuint),
+    DeleteBuffer(GLuint),
+    BindBuffer {
+        target: GLenum,
+        buffer: GLuint,
+    },
+    BufferData {
+        target: GLenum,
+        size: GLsizeiptr,
+        usage: GLenum,
+    },
+    BufferSubData {
+        target: GLenum,
+        offset: GLintptr,
+        size: GLsizeiptr,
+    },
+    BufferStorage {
+        target: GLenum,
+        size: GLsizeiptr,
+        flags: GLbitfield,
+    },
+    CopyBufferSubData {
+        read_target: GLenum,
+        write_target: GLenum,
+        read_offset: GLintptr,
+        write_offset: GLintptr,
+        size: GLsizeiptr,
+    },
+    MapBufferRange {
+        target: GLenum,
+        offset: GLintptr,
+        length: GLsizeiptr,
+        access: GLbitfield,
+    },
+    FlushMappedBufferRange {
+        target: GLenum,
+        offset: GLintptr,
+        length: GLsizeiptr,
+    },
+    UnmapBuffer(GLenum),
+    GenVertexArray(GLuint),
+    DeleteVertexArray(GLuint),
+    BindVertexArray(GLuint),
+    EnableVertexAttribArray(GLuint),
+    DisableVertexAttribArray(GLuint),
+    VertexAttribPointer {
+        index: GLuint,
+        size: GLint,
+        data_type: GLenum,
+        normalized: bool,
+        stride: GLsizei,
+        offset: usize,
+    },
+    VertexAttribIPointer {
+        index: GLuint,
+        size: GLint,
+        data_type: GLenum,
+        stride: GLsizei,
+        offset: usize,
+    },
+    VertexAttribLPointer {
+        index: GLuint,
+        size: GLint,
+        data_type: GLenum,
+        stride: GLsizei,
+        offset: usize,
+    },
+    VertexAttribDivisor {
+        index: GLuint,
+        divisor: GLuint,
+    },
+    GenTexture(GLuint),
+    DeleteTexture(GLuint),
+    ActiveTexture(GLenum),
+    BindTexture {
+        target: GLenum,
+        texture: GLuint,
+    },
+    TexParameter {
+        target: GLenum,
+        pname: GLenum,
+        param: GLint,
+    },
+    TexImage2D {
+        target: GLenum,
+        level: GLint,
+        internal_format: GLint,
+        width: GLsizei,
+        height: GLsizei,
+        format: GLenum,
+        data_type: GLenum,
+    },
+    GenerateMipmap(GLenum),
+    CreateShader {
+        shader_type: GLenum,
+        shader: GLuint,
+    },
+    DeleteShader(GLuint),
+    ShaderSource(GLuint),
+    CompileShader(GLuint),
+    CreateProgram(GLuint),
+    DeleteProgram(GLuint),
+    AttachShader {
+        program: GLuint,
+        shader: GLuint,
+    },
+    DetachShader {
+        program: GLuint,
+        shader: GLuint,
+    },
+    TransformFeedbackVaryings {
+        program: GLuint,
+        names: Vec<String>,
+        mode: GLenum,
+    },
+    LinkProgram(GLuint),
+    UseProgram(GLuint),
+    Enable(GLenum),
+    Disable(GLenum),
+    DepthMask(bool),
+    DepthFunc(GLenum),
+    CullFace(GLenum),
+    FrontFace(GLenum),
+    PolygonMode {
+        face: GLenum,
+        mode: GLenum,
+    },
+    ColorMask([bool; 4]),
+    Scissor {
+        x: GLint,
+        y: GLint,
+        width: GLsizei,
+        height: GLsizei,
+    },
+    Viewport {
+        x: GLint,
+        y: GLint,
+        width: GLsizei,
+        height: GLsizei,
+    },
+    BlendFunc {
+        src: GLenum,
+        dest: GLenum,
+    },
+    BlendFuncSeparate {
+        src_rgb: GLenum,
+        dest_rgb: GLenum,
+        src_alpha: GLenum,
+        dest_alpha: GLenum,
+    },
+    StencilFuncSeparate {
+        face: GLenum,
+        func: GLenum,
+        reference: GLint,
+        mask: GLuint,
+    },
+    StencilMaskSeparate {
+        face: GLenum,
+        mask: GLuint,
+    },
+    StencilOpSeparate {
+        face: GLenum,
+        stencil_fail: GLenum,
+        depth_fail: GLenum,
+        pass: GLenum,
+    },
+    ClearColor([f32; 4]),
+    ClearStencil(GLint),
+    Clear(GLbitfield),
+    PrimitiveRestartIndex(GLuint),
+    DrawArrays {
+        mode: GLenum,
+        first: GLint,
+        count: GLsizei,
+    },
+    DrawArraysInstanced {
+        mode: GLenum,
+        first: GLint,
+        count: GLsizei,
+        instance_count: GLsizei,
+    },
+    DrawArraysInstancedBaseInstance {
+        mode: GLenum,
+        first: GLint,
+        count: GLsizei,
+        instance_count: GLsizei,
+        base_instance: GLuint,
+    },
+    DrawElements {
+        mode: GLenum,
+        count: GLsizei,
+        data_type: GLenum,
+        offset: usize,
+    },
+    DrawElementsBaseVertex {
+        mode: GLenum,
+        count: GLsizei,
+        data_type: GLenum,
+        offset: usize,
+        base_vertex: GLint,
+    },
+    DrawElementsInstanced {
+        mode: GLenum,
+        count: GLsizei,
+        data_type: GLenum,
+        offset: usize,
+        instance_count: GLsizei,
+    },
+    DrawElementsInstancedBaseVertex {
+        mode: GLenum,
+        count: GLsizei,
+        data_type: GLenum,
+        offset: usize,
+        instance_count: GLsizei,
+        base_vertex: GLint,
+    },
+    DrawElementsInstancedBaseVertexBaseInstance {
+        mode: GLenum,
+        count: GLsizei,
+        data_type: GLenum,
+        offset: usize,
+        instance_count: GLsizei,
+        base_vertex: GLint,
+        base_instance: GLuint,
+    },
+    MultiDrawArraysIndirect {
+        mode: GLenum,
+        offset: usize,
+        draw_count: GLsizei,
+        stride: GLsizei,
+    },
+    MultiDrawElementsIndirect {
+        mode: GLenum,
+        data_type: GLenum,
+        offset: usize,
+        draw_count: GLsizei,
+        stride: GLsizei,
+    },
+    GenQuery(GLuint),
+    DeleteQuery(GLuint),
+    BeginQuery {
+        target: GLenum,
+        query: GLuint,
+    },
+    EndQuery(GLenum),
+    QueryCounter {
+        query: GLuint,
+        target: GLenum,
+    },
+    BeginConditionalRender {
+        query: GLuint,
+        mode: GLenum,
+    },
+    EndConditionalRender,
+    GenTransformFeedback(GLuint),
+    DeleteTransformFeedback(GLuint),
+    BindTransformFeedback {
+        target: GLenum,
+        transform_feedback: GLuint,
+    },
+    BeginTransformFeedback(GLenum),
+    EndTransformFeedback,
+    PauseTransformFeedback,
+    ResumeTransformFeedback,
+    DrawTransformFeedback {
+        mode: GLenum,
+        transform_feedback: GLuint,
+    },
+    BindBufferBase {
+        target: GLenum,
+        index: GLuint,
+        buffer: GLuint,
+    },
+    BindBufferRange {
+        target: GLenum,
+        index: GLuint,
+        buffer: GLuint,
+        offset: GLintptr,
+        size: GLsizeiptr,
+    },
+    GenFramebuffer(GLuint),
+    DeleteFramebuffer(GLuint),
+    BindFramebuffer {
+        target: GLenum,
+        framebuffer: GLuint,
+    },
+    FramebufferTexture2D {
+        target: GLenum,
+        attachment: GLenum,
+        texture_target: GLenum,
+        texture: GLuint,
+        level: GLint,
+    },
+    FramebufferRenderbuffer {
+        target: GLenum,
+        attachment: GLenum,
+        renderbuffer: GLuint,
+    },
+    DrawBuffers(Vec<GLenum>),
+    ReadBuffer(GLenum),
+    BlitFramebuffer {
+        source: [GLint; 4],
+        destination: [GLint; 4],
+        mask: GLbitfield,
+        filter: GLenum,
+    },
+    GenRenderbuffer(GLuint),
+    DeleteRenderbuffer(GLuint),
+    BindRenderbuffer(GLuint),
+    RenderbufferStorageMultisample {
+        samples: GLsizei,
+        internal_format: GLenum,
+        width: GLsizei,
+        height: GLsizei,
+    },
+    TexImage2DMultisample {
+        target: GLenum,
+        samples: GLsizei,
+        internal_format: GLenum,
+        width: GLsizei,
+        height: GLsizei,
+    },
+    FenceSync,
+    DeleteSync,
+    /// Whether a callback was installed or removed.
+    DebugMessageCallback(bool),
+    ObjectLabel {
+        identifier: GLenum,
+        name: GLuint,
+        label: String,
+    },
+    PushDebugGroup(String),
+    PopDebugGroup,
+}
+
+/// Names handed out by all recording backends of the process. They start high and
+/// never repeat, so objects of tests running in parallel don't collide in the
+/// resource registry.
+static NEXT_NAME: AtomicU32 = AtomicU32::new(1_000_001);
+
+#[derive(Debug, Default)]
+struct Recording {
+    calls: Vec<GlCall>,
+    errors: VecDeque<GLenum>,
+    /// Contents of the live buffers.
+    buffers: HashMap<GLuint, Vec<u8>>,
+    /// Bound buffer per target, except for the element array buffer.
+    bound_buffers: HashMap<GLenum, GLuint>,
+    /// Element array buffer per vertex array, 0 being no vertex array.
+    element_buffers: HashMap<GLuint, GLuint>,
+    bound_vertex_array: GLuint,
+    vertex_arrays: Vec<GLuint>,
+    /// Size of the base level of the live textures, zero until an image is given.
+    textures: HashMap<GLuint, (GLsizei, GLsizei)>,
+    /// The active texture unit, 0 being `TEXTURE0`.
+    active_texture: GLenum,
+    /// Bound texture per texture unit and target.
+    bound_textures: HashMap<(GLenum, GLenum), GLuint>,
+    shaders: HashMap<GLuint, RecordedShader>,
+    programs: HashMap<GLuint, RecordedProgram>,
+    current_program: GLuint,
+    /// State set through the backend, the other state keeps its initial value, see
+    /// `initial_state`.
+    state: HashMap<GLenum, [GLint; 4]>,
+    /// Capabilities which were enabled or disabled.
+    capabilities: HashMap<GLenum, bool>,
+    extensions: Vec<String>,
+    framebuffers: HashMap<GLuint, RecordedFramebuffer>,
+    draw_framebuffer: GLuint,
+    read_framebuffer: GLuint,
+    /// Size of the storage of the live renderbuffers, zero until storage is given.
+    renderbuffers: HashMap<GLuint, (GLsizei, GLsizei)>,
+    bound_renderbuffer: GLuint,
+    /// Result of the live queries, `None` while it isn't available.
+    queries: HashMap<GLuint, Option<u64>>,
+    /// The active query per target.
+    active_queries: HashMap<GLenum, GLuint>,
+    conditional_render: Option<GLuint>,
+    /// Capture state of the live transform feedback objects, 0 being the default
+    /// object.
+    transform_feedbacks: HashMap<GLuint, Capture>,
+    bound_transform_feedback: GLuint,
+    fences: HashSet<usize>,
+    block_fences: bool,
+    debug_callback: bool,
+    debug_groups: usize,
+    injected_errors: Vec<InjectedError>,
+    /// Receives the changes of a call that failed with an injected error.
+    discarded: Option<Box<Recording>>,
+}
+
+#[derive(Debug, Default)]
+struct RecordedFramebuffer {
+    /// The object type (`TEXTURE` or `RENDERBUFFER`) and name per attachment point.
+    attachments: HashMap<GLenum, (GLenum, GLuint)>,
+    draw_buffers: Vec<GLenum>,
+    read_buffer: GLenum,
+}
+
+/// Capture state of a transform feedback object.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Capture {
+    #[default]
+    Inactive,
+    Active,
+    Paused,
+}
+
+/// An error reported after the next recorded call matching `matches`.
+struct InjectedError {
+    matches: Box<dyn Fn(&GlCall) -> bool>,
+    error: GLenum,
+}
+
+impl fmt::Debug for InjectedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InjectedError")
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Default)]
+struct RecordedShader {
+    shader_type: GLenum,
+    source: String,
+    compiled: bool,
+    info_log: String,
+}
+
+#[derive(Debug, Default)]
+struct RecordedProgram {
+    shaders: Vec<GLuint>,
+    linked: bool,
+    info_log: String,
+    /// Active uniforms and attributes with their locations, set when linking.
+    uniforms: Vec<(ActiveVariable, GLint)>,
+    attributes: Vec<(ActiveVariable, GLint)>,
+}
+
+impl Recording {
+    fn state(&self, pname: GLenum) -> [GLint; 4] {
+        self.state.get(&pname).copied().unwrap_or_else(|| initial_state(pname))
+    }
+
+    fn set_state(&mut self, pname: GLenum, values: [GLint; 4]) {
+        self.state.insert(pname, values);
+    }
+
+    fn is_enabled(&self, capability: GLenum) -> bool {
+        self.capabilities
+            .get(&capability)
+            .copied()
+            .unwrap_or(matches!(capability, gl::DITHER | gl::MULTISAMPLE))
+    }
+
+    /// Returns the framebuffer bound to `target`, the draw framebuffer for
+    /// `FRAMEBUFFER`.
+    fn bound_framebuffer(&self, target: GLenum) -> GLuint {
+        match target {
+            gl::READ_FRAMEBUFFER => self.read_framebuffer,
+            _ => self.draw_framebuffer,
+        }
+    }
+
+    /// Attaches `object` to the framebuffer bound to `target`, recording
+    /// `INVALID_OPERATION` if the default framebuffer is bound.
+    fn attach(&mut self, target: GLenum, attachment: GLenum, object: (GLenum, GLuint)) {
+        let framebuffer = self.bound_framebuffer(target);
+        let Some(recorded) = self.framebuffers.get_mut(&framebuffer) else {
+            self.errors.push_back(gl::INVALID_OPERATION);
+            return;
+        };
+        if object.1 == 0 {
+            recorded.attachments.remove(&attachment);
+        } else {
+            recorded.attachments.insert(attachment, object);
+        }
+    }
+
+    /// The status of the framebuffer bound to `target`. Framebuffer objects are
+    /// incomplete without attachments or with an attachment which was deleted or has
+    /// no storage.
+    fn framebuffer_status(&self, target: GLenum) -> GLenum {
+        let framebuffer = self.bound_framebuffer(target);
+        let Some(recorded) = self.framebuffers.get(&framebuffer) else {
+            return gl::FRAMEBUFFER_COMPLETE;
+        };
+        if recorded.attachments.is_empty() {
+            return gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT;
+        }
+        let has_storage = |(object_type, name): &(GLenum, GLuint)| {
+            let size = if *object_type == gl::RENDERBUFFER {
+                self.renderbuffers.get(name)
+            } else {
+                self.textures.get(name)
+            };
+            matches!(size, Some((width, height)) if *width > 0 && *height > 0)
+        };
+        if recorded.attachments.values().all(has_storage) {
+            gl::FRAMEBUFFER_COMPLETE
+        } else {
+            gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT
+        }
+    }
+
+    /// Returns the capture state of the bound transform feedback object.
+    fn capture(&self) -> Capture {
+        self.transform_feedbacks
+            .get(&self.bound_transform_feedback)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Changes the capture state of the bound transform feedback object from `from`
+    /// to `to`, recording `INVALID_OPERATION` if it isn't in one of the states `from`.
+    fn transition_capture(&mut self, from: &[Capture], to: Capture) {
+        if from.contains(&self.capture()) {
+            let bound = self.bound_transform_feedback;
+            self.transform_feedbacks.insert(bound, to);
+        } else {
+            self.errors.push_back(gl::INVALID_OPERATION);
+        }
+    }
+
+    fn bound_buffer(&self, target: GLenum) -> GLuint {
+        if target == gl::ELEMENT_ARRAY_BUFFER {
+            self.element_buffers.get(&self.bound_vertex_array).copied().unwrap_or(0)
+        } else {
+            self.bound_buffers.get(&target).copied().unwrap_or(0)
+        }
+    }
+
+    /// Returns the contents of the buffer bound to `target`, or `None` after
+    /// recording `INVALID_OPERATION` if no buffer is bound.
+    fn bound_contents(&mut self, target: GLenum) -> Option<&mut Vec<u8>> {
+        let buffer = self.bound_buffer(target);
+        if !self.buffers.contains_key(&buffer) {
+            self.errors.push_back(gl::INVALID_OPERATION);
+            return None;
+        }
+        self.buffers.get_mut(&buffer)
+    }
+
+    /// Checks that `offset..offset + size` lies within `len`, recording
+    /// `INVALID_VALUE` otherwise.
+    fn check_range(&mut self, len: usize, offset: GLintptr, size: GLsizeiptr) -> bool {
+        let valid = offset >= 0 && size >= 0 && (offset + size) as usize <= len;
+        if !valid {
+            self.errors.push_back(gl::INVALID_VALUE);
+        }
+        valid
+    }
+
+    fn buffer_len(&self, target: GLenum) -> usize {
+        let buffer = self.bound_buffer(target);
+        self.buffers.get(&buffer).map_or(0, Vec::len)
+    }
+
+    fn bound_texture(&self, unit: GLenum, target: GLenum) -> GLuint {
+        self.bound_textures.get(&(unit, target)).copied().unwrap_or(0)
+    }
+
+    /// Returns the program, or `None` after recording `INVALID_VALUE` if it
+    /// doesn't exist.
+    fn program(&mut self, program: GLuint) -> Option<&mut RecordedProgram> {
+        if !self.programs.contains_key(&program) {
+            self.errors.push_back(gl::INVALID_VALUE);
+        }
+        self.programs.get_mut(&program)
+    }
+
+    /// Returns the shader, or `None` after recording `INVALID_VALUE` if it
+    /// doesn't exist.
+    fn shader(&mut self, shader: GLuint) -> Option<&mut RecordedShader> {
+        if !self.shaders.contains_key(&shader) {
+            self.errors.push_back(gl::INVALID_VALUE);
+        }
+        self.shaders.get_mut(&shader)
+    }
+
+    /// Returns the active variables of a linked program, or `None` after recording
+    /// `INVALID_OPERATION` if it isn't linked.
+    fn linked_program(&mut self, program: GLuint) -> Option<&RecordedProgram> {
+        match self.program(program) {
+            Some(recorded) if recorded.linked => self.programs.get(&program),
+            Some(_) => {
+                self.errors.push_back(gl::INVALID_OPERATION);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Links `program` from the sources of its attached shaders. Linking fails if
+    /// no shader is attached or one of them isn't compiled.
+    fn link(&mut self, program: GLuint) {
+        let Some(shaders) = self.program(program).map(|recorded| recorded.shaders.clone()) else {
+            return;
+        };
+        let mut info_log = String::new();
+        if shaders.is_empty() {
+            info_log = "error: no shaders attached".to_string();
+        }
+        let mut uniforms = Vec::new();
+        let mut attributes = Vec::new();
+        for shader in &shaders {
+            match self.shaders.get(shader) {
+                Some(recorded) if recorded.compiled => {
+                    for (variable, location) in declarations(&recorded.source, "uniform") {
+                        // Uniforms declared by several stages are only active once
+                        if !uniforms
+                            .iter()
+                            .any(|(known, _): &(ActiveVariable, _)| known.name == variable.name)
+                        {
+                            uniforms.push((variable, location));
+                        }
+                    }
+                    if recorded.shader_type == gl::VERTEX_SHADER {
+                        attributes.extend(declarations(&recorded.source, "in"));
+                    }
+                }
+                _ => info_log = format!("error: shader {} isn't compiled", shader),
+            }
+        }
+        assign_locations(&mut uniforms);
+        assign_locations(&mut attributes);
+
+        let recorded = self.programs.get_mut(&program).expect("the program exists");
+        recorded.linked = info_log.is_empty();
+        recorded.info_log = info_log;
+        if recorded.linked {
+            recorded.uniforms = uniforms;
+            recorded.attributes = attributes;
+        }
+    }
+}
+
+/// Gives the variables without a layout location the lowest free locations.
+fn assign_locations(variables: &mut [(ActiveVariable, GLint)]) {
+    let mut taken: Vec<GLint> = variables.iter().map(|(_, location)| *location).collect();
+    for (_, location) in variables.iter_mut().filter(|(_, location)| *location < 0) {
+        let free = (0..).find(|candidate| !taken.contains(candidate)).expect("a free location");
+        taken.push(free);
+        *location = free;
+    }
+}
+
+/// Parses the declarations `<keyword> <type> <name>;` of a GLSL source, optionally
+/// preceded by `layout(location = <n>)`. Declarations without a layout location get
+/// the location -1.
+fn declarations(source: &str, keyword: &str) -> Vec<(ActiveVariable, GLint)> {
+    let mut variables = Vec::new();
+    for line in source.lines() {
+        let mut line = line.trim();
+        let mut location = -1;
+        if let Some(rest) = line.strip_prefix("layout") {
+            let Some((qualifiers, rest)) = rest.split_once(')') else {
+                continue;
+            };
+            location = qualifiers
+                .split(',')
+                .filter_map(|qualifier| qualifier.split_once('='))
+                .find(|(name, _)| name.trim().trim_start_matches('(').trim() == "location")
+                .and_then(|(_, value)| value.trim().parse().ok())
+                .unwrap_or(-1);
+            line = rest.trim();
+        }
+        let mut tokens = line.split_whitespace();
+        if tokens.next() != Some(keyword) {
+            continue;
+        }
+        let mut tokens = tokens.skip_while(|token| {
+            matches!(*token, "flat" | "smooth" | "noperspective" | "lowp" | "mediump" | "highp")
+        });
+        let (Some(glsl_type), Some(name)) = (tokens.next(), tokens.next()) else {
+            continue;
+        };
+        // Skips blocks like `uniform Matrices {`
+        let Some(name) = name.strip_suffix(';') else {
+            continue;
+        };
+        let (name, size) = match name.split_once('[') {
+            Some((name, size)) => (name, size.trim_end_matches(']').parse().unwrap_or(1)),
+            None => (name, 1),
+        };
+        let variable = ActiveVariable {
+            name: name.to_string(),
+            size,
+            gl_type: glsl_type_to_gl_enum(glsl_type),
+        };
+        variables.push((variable, location));
+    }
+    variables
+}
+
+/// Maps the name of a GLSL type to its GL enum, 0 for types the recording doesn't
+/// know.
+fn glsl_type_to_gl_enum(glsl_type: &str) -> GLenum {
+    match glsl_type {
+        "float" => gl::FLOAT,
+        "vec2" => gl::FLOAT_VEC2,
+        "vec3" => gl::FLOAT_VEC3,
+        "vec4" => gl::FLOAT_VEC4,
+        "int" => gl::INT,
+        "ivec2" => gl::INT_VEC2,
+        "ivec3" => gl::INT_VEC3,
+        "ivec4" => gl::INT_VEC4,
+        "uint" => gl::UNSIGNED_INT,
+        "uvec2" => gl::UNSIGNED_INT_VEC2,
+        "uvec3" => gl::UNSIGNED_INT_VEC3,
+        "uvec4" => gl::UNSIGNED_INT_VEC4,
+        "bool" => gl::BOOL,
+        "double" => gl::DOUBLE,
+        "mat2" => gl::FLOAT_MAT2,
+        "mat3" => gl::FLOAT_MAT3,
+        "mat4" => gl::FLOAT_MAT4,
+        "sampler2D" => gl::SAMPLER_2D,
+        "samplerCube" => gl::SAMPLER_CUBE,
+        _ => 0,
+    }
+}
+
+/// The initial value of the state `pname` queried with `get_integers`, as of a fresh
+/// GL 4.6 context with a default framebuffer of 800x600 pixels and 8 stencil bits.
+fn initial_state(pname: GLenum) -> [GLint; 4] {
+    let value = match pname {
+        gl::DEPTH_WRITEMASK => 1,
+        gl::DEPTH_FUNC => gl::LESS as GLint,
+        gl::CULL_FACE_MODE => gl::BACK as GLint,
+        gl::FRONT_FACE => gl::CCW as GLint,
+        gl::POLYGON_MODE => return [gl::FILL as GLint, gl::FILL as GLint, 0, 0],
+        gl::COLOR_WRITEMASK => return [1; 4],
+        gl::VIEWPORT | gl::SCISSOR_BOX => return [0, 0, 800, 600],
+        gl::STENCIL_FUNC | gl::STENCIL_BACK_FUNC => gl::ALWAYS as GLint,
+        gl::STENCIL_VALUE_MASK
+        | gl::STENCIL_BACK_VALUE_MASK
+        | gl::STENCIL_WRITEMASK
+        | gl::STENCIL_BACK_WRITEMASK => -1,
+        gl::STENCIL_FAIL
+        | gl::STENCIL_PASS_DEPTH_FAIL
+        | gl::STENCIL_PASS_DEPTH_PASS
+        | gl::STENCIL_BACK_FAIL
+        | gl::STENCIL_BACK_PASS_DEPTH_FAIL
+        | gl::STENCIL_BACK_PASS_DEPTH_PASS => gl::KEEP as GLint,
+        gl::BLEND_SRC_RGB | gl::BLEND_SRC_ALPHA => gl::ONE as GLint,
+        gl::MAX_COLOR_ATTACHMENTS | gl::MAX_DRAW_BUFFERS => 8,
+        gl::MAJOR_VERSION => 4,
+        gl::MINOR_VERSION => 6,
+        _ => 0,
+    };
+    [value, 0, 0, 0]
+}
+
+/// A backend which needs no GL context. It hands out fake object names, tracks the
+/// bound buffers and vertex array and the buffer contents, and records every call
+/// which changes state.
+///
+/// Clones share their state, so a test keeps a clone to inspect the backend after
+/// installing it with `install_backend`.
+///
+/// # Example
+/// ```ignore
+/// let backend = RecordingGlBackend::new();
+/// let _guard = install_backend(backend.clone());
+/// vao.prepare_render();
+/// assert!(backend.calls().contains(&GlCall::EnableVertexAttribArray(0)));
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct RecordingGlBackend {
+    recording: Rc<RefCell<Recording>>,
+}
+
+impl RecordingGlBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the recorded calls.
+    pub fn calls(&self) -> Vec<GlCall> {
+        self.recording.borrow().calls.clone()
+    }
+
+    /// Returns the recorded calls and starts a new recording.
+    pub fn take_calls(&self) -> Vec<GlCall> {
+        std::mem::take(&mut self.recording.borrow_mut().calls)
+    }
+
+    /// Makes `get_error` report `error` once, e.g. to test error handling.
+    pub fn push_error(&self, error: GLenum) {
+        self.recording.borrow_mut().errors.push_back(error);
+    }
+
+    /// Returns the buffer bound to `target`. The element array buffer is the one of
+    /// the bound vertex array.
+    pub fn bound_buffer(&self, target: GLenum) -> GLuint {
+        self.recording.borrow().bound_buffer(target)
+    }
+
+    pub fn bound_vertex_array(&self) -> GLuint {
+        self.recording.borrow().bound_vertex_array
+    }
+
+    /// Returns the contents of a buffer, `None` if it doesn't exist.
+    pub fn buffer_contents(&self, buffer: GLuint) -> Option<Vec<u8>> {
+        self.recording.borrow().buffers.get(&buffer).cloned()
+    }
+
+    /// Returns the texture bound to `target` of the texture unit `unit`, e.g.
+    /// `gl::TEXTURE0`.
+    pub fn bound_texture(&self, unit: GLenum, target: GLenum) -> GLuint {
+        self.recording.borrow().bound_texture(unit - gl::TEXTURE0, target)
+    }
+
+    /// Returns the size of the base level of a texture, `None` if it doesn't exist.
+    pub fn texture_size(&self, texture: GLuint) -> Option<(GLsizei, GLsizei)> {
+        self.recording.borrow().textures.get(&texture).copied()
+    }
+
+    pub fn current_program(&self) -> GLuint {
+        self.recording.borrow().current_program
+    }
+
+    /// Returns the shaders attached to a program, `None` if it doesn't exist.
+    pub fn attached_shaders(&self, program: GLuint) -> Option<Vec<GLuint>> {
+        self.recording
+            .borrow()
+            .programs
+            .get(&program)
+            .map(|recorded| recorded.shaders.clone())
+    }
+
+    /// Makes the next recorded call matching `matches` fail with `error`, e.g. to
+    /// make one of several similar calls fail. Like in OpenGL, the failing call has
+    /// no effect, and the values it returns are meaningless.
+    pub fn fail_next(&self, matches: impl Fn(&GlCall) -> bool + 'static, error: GLenum) {
+        self.recording.borrow_mut().injected_errors.push(InjectedError {
+            matches: Box::new(matches),
+            error,
+        });
+    }
+
+    pub fn is_enabled(&self, capability: GLenum) -> bool {
+        self.recording.borrow().is_enabled(capability)
+    }
+
+    /// Returns the state `pname` as `get_integers` does.
+    pub fn state(&self, pname: GLenum) -> [GLint; 4] {
+        self.recording.borrow().state(pname)
+    }
+
+    /// Overrides the state `pname`, e.g. to start a test from a non-default state.
+    pub fn set_state(&self, pname: GLenum, values: [GLint; 4]) {
+        self.recording.borrow_mut().set_state(pname, values);
+    }
+
+    /// Makes the backend report the GL version `major.minor`. It defaults to 4.6.
+    pub fn set_version(&self, major: GLint, minor: GLint) {
+        let mut recording = self.recording.borrow_mut();
+        recording.set_state(gl::MAJOR_VERSION, [major, 0, 0, 0]);
+        recording.set_state(gl::MINOR_VERSION, [minor, 0, 0, 0]);
+    }
+
+    /// Makes the backend report the extension `name`.
+    pub fn add_extension(&self, name: &str) {
+        self.recording.borrow_mut().extensions.push(name.to_string());
+    }
+
+    /// Returns the framebuffer bound to `target`, the draw framebuffer for
+    /// `gl::FRAMEBUFFER`.
+    pub fn bound_framebuffer(&self, target: GLenum) -> GLuint {
+        self.recording.borrow().bound_framebuffer(target)
+    }
+
+    /// Returns the name of the object attached to `attachment` of a framebuffer,
+    /// `None` if nothing is attached or the framebuffer doesn't exist.
+    pub fn framebuffer_attachment(
+        &self,
+        framebuffer: GLuint,
+        attachment: GLenum,
+    ) -> Option<GLuint> {
+        let recording = self.recording.borrow();
+        let recorded = recording.framebuffers.get(&framebuffer)?;
+        recorded.attachments.get(&attachment).map(|(_, name)| *name)
+    }
+
+    /// Returns the capture state of a transform feedback object, `None` if it doesn't
+    /// exist.
+    pub fn transform_feedback_capture(&self, transform_feedback: GLuint) -> Option<Capture> {
+        let recording = self.recording.borrow();
+        if transform_feedback == 0 {
+            return Some(recording.transform_feedbacks.get(&0).copied().unwrap_or_default());
+        }
+        recording.transform_feedbacks.get(&transform_feedback).copied()
+    }
+
+    /// Returns the query active on `target`, 0 if there is none.
+    pub fn active_query(&self, target: GLenum) -> GLuint {
+        self.recording.borrow().active_queries.get(&target).copied().unwrap_or(0)
+    }
+
+    /// Sets the result of a query, `None` making it unavailable. Ending a query
+    /// makes its result available as 0.
+    pub fn set_query_result(&self, query: GLuint, result: Option<u64>) {
+        if let Some(recorded) = self.recording.borrow_mut().queries.get_mut(&query) {
+            *recorded = result;
+        }
+    }
+
+    /// Makes waiting for fences time out, as if the GPU never caught up.
+    pub fn block_fences(&self, blocked: bool) {
+        self.recording.borrow_mut().block_fences = blocked;
+    }
+
+    /// Returns the number of debug groups which weren't popped.
+    pub fn debug_group_depth(&self) -> usize {
+        self.recording.borrow().debug_groups
+    }
+
+    /// Returns the number of objects which weren't deleted.
+    pub fn live_objects(&self) -> usize {
+        let recording = self.recording.borrow();
+        recording.buffers.len()
+            + recording.vertex_arrays.len()
+            + recording.textures.len()
+            + recording.shaders.len()
+            + recording.programs.len()
+            + recording.framebuffers.len()
+            + recording.renderbuffers.len()
+            + recording.queries.len()
+            + recording.transform_feedbacks.keys().filter(|name| **name != 0).count()
+            + recording.fences.len()
+    }
+
+    fn record(&self, call: GlCall) -> RefMut<'_, Recording> {
+        let mut recording = self.recording.borrow_mut();
+        let injected = recording
+            .injected_errors
+            .iter()
+            .position(|injected| (injected.matches)(&call))
+            .map(|index| recording.injected_errors.remove(index));
+        recording.calls.push(call);
+        match injected {
+            Some(injected) => {
+                recording.errors.push_back(injected.error);
+                // A failing call has no effect, so it changes a scratch recording
+                RefMut::map(recording, |recording| {
+                    &mut **recording.discarded.insert(Box::default())
+                })
+            }
+            None => recording,
+        }
+    }
+}
+
+impl GlBackend for RecordingGlBackend {
+    fn get_error(&mut self) -> GLenum {
+        self.recording.borrow_mut().errors.pop_front().unwrap_or(gl::NO_ERROR)
+    }
+
+    fn get_integer(&mut self, pname: GLenum) -> GLint {
+        let recording = self.recording.borrow();
+        let binding = |target| recording.bound_buffer(target) as GLint;
+        match pname {
+            gl::ARRAY_BUFFER_BINDING => binding(gl::ARRAY_BUFFER),
+            gl::ELEMENT_ARRAY_BUFFER_BINDING => binding(gl::ELEMENT_ARRAY_BUFFER),
+            gl::COPY_READ_BUFFER_BINDING => binding(gl::COPY_READ_BUFFER),
+            gl::COPY_WRITE_BUFFER_BINDING => binding(gl::COPY_WRITE_BUFFER),
+            gl::UNIFORM_BUFFER_BINDING => binding(gl::UNIFORM_BUFFER),
+            gl::DRAW_INDIRECT_BUFFER_BINDING => binding(gl::DRAW_INDIRECT_BUFFER),
+            gl::VERTEX_ARRAY_BINDING => recording.bound_vertex_array as GLint,
+            gl::ACTIVE_TEXTURE => (gl::TEXTURE0 + recording.active_texture) as GLint,
+            gl::TEXTURE_BINDING_2D => {
+                recording.bound_texture(recording.active_texture, gl::TEXTURE_2D) as GLint
+            }
+            gl::CURRENT_PROGRAM => recording.current_program as GLint,
+            gl::DRAW_FRAMEBUFFER_BINDING => recording.draw_framebuffer as GLint,
+            gl::READ_FRAMEBUFFER_BINDING => recording.read_framebuffer as GLint,
+            gl::RENDERBUFFER_BINDING => recording.bound_renderbuffer as GLint,
+            gl::TRANSFORM_FEEDBACK_BINDING => recording.bound_transform_feedback as GLint,
+            gl::NUM_EXTENSIONS => recording.extensions.len() as GLint,
+            _ => recording.state(pname)[0],
+        }
+    }
+
+    fn gen_buffer(&mut self) -> GLuint {
+        let buffer = NEXT_NAME.fetch_add(1, Ordering::Relaxed);
+        self.record(GlCall::GenBuffer(buffer)).buffers.insert(buffer, Vec::new());
+        buffer
+    }
+
+    fn delete_buffer(&mut self, buffer: GLuint) {
+        let mut recording = self.record(GlCall::DeleteBuffer(buffer));
+        recording.buffers.remove(&buffer);
+        // Deleting a bound buffer unbinds it
+        recording.bound_buffers.retain(|_, bound| *bound != buffer);
+        recording.element_buffers.retain(|_, bound| *bound != buffer);
+    }
+
+    fn bind_buffer(&mut self, target: GLenum, buffer: GLuint) {
+        let mut recording = self.record(GlCall::BindBuffer {
+            target,
+            buffer,
+        });
+        if buffer != 0 && !recording.buffers.contains_key(&buffer) {
+            recording.errors.push_back(gl::INVALID_OPERATION);
+        } else if target == gl::ELEMENT_ARRAY_BUFFER {
+            let vertex_array = recording.bound_vertex_array;
+            recording.element_buffers.insert(vertex_array, buffer);
+        } else {
+            recording.bound_buffers.insert(target, buffer);
+        }
+    }
+
+    unsafe fn buffer_data(
+        &mut self,
+        target: GLenum,
+        size: GLsizeiptr,
+        data: *const c_void,
+        usage: GLenum,
+    ) {
+        let mut recording = self.record(GlCall::BufferData {
+            target,
+            size,
+            usage,
+        });
+        if !recording.check_range(usize::MAX, 0, size) {
+            return;
+        }
+        if let Some(contents) = recording.bound_contents(target) {
+            *contents = if data.is_null() {
+                vec![0; size as usize]
+            } else {
+                slice::from_raw_parts(data as *const u8, size as usize).to_vec()
+            };
+        }
+    }
+
+    unsafe fn buffer_sub_data(
+        &mut self,
+        target: GLenum,
+        offset: GLintptr,
+        size: GLsizeiptr,
+        data: *const c_void,
+    ) {
+        let mut recording = self.record(GlCall::BufferSubData {
+            target,
+            offset,
+            size,
+        });
+        let len = recording.buffer_len(target);
+        if !recording.check_range(len, offset, size) {
+            return;
+        }
+        if let Some(contents) = recording.bound_contents(target) {
+            let range = offset as usize..(offset + size) as usize;
+            contents[range]
+                .copy_from_slice(slice::from_raw_parts(data as *const u8, size as usize));
+        }
+    }
+
+    unsafe fn buffer_storage(
+        &mut self,
+        target: GLenum,
+        size: GLsizeiptr,
+        data: *const c_void,
+        flags: GLbitfield,
+    ) {
+        let mut recording = self.record(GlCall::BufferStorage {
+            target,
+            size,
+            flags,
+        });
+        if !recording.check_range(usize::MAX, 0, size) {
+            return;
+        }
+        if let Some(contents) = recording.bound_contents(target) {
+            *contents = if data.is_null() {
+                vec![0; size as usize]
+            } else {
+                slice::from_raw_parts(data as *const u8, size as usize).to_vec()
+            };
+        }
+    }
+
+    fn copy_buffer_sub_data(
+        &mut self,
+        read_target: GLenum,
+        write_target: GLenum,
+        read_offset: GLintptr,
+        write_offset: GLintptr,
+        size: GLsizeiptr,
+    ) {
+        let mut recording = self.record(GlCall::CopyBufferSubData {
+            read_target,
+            write_target,
+            read_offset,
+            write_offset,
+            size,
+        });
+        let read_len = recording.buffer_len(read_target);
+        let write_len = recording.buffer_len(write_target);
+        if !recording.check_range(read_len, read_offset, size)
+            || !recording.check_range(write_len, write_offset, size)
+        {
+            return;
+        }
+        let Some(source) = recording.bound_contents(read_target) else {
+            return;
+        };
+        let copied = source[read_offset as usize..(read_offset + size) as usize].to_vec();
+        if let Some(destination) = recording.bound_contents(write_target) {
+            destination[write_offset as usize..(write_offset + size) as usize]
+                .copy_from_slice(&copied);
+        }
+    }
+
+    unsafe fn get_buffer_sub_data(
+        &mut self,
+        target: GLenum,
+        offset: GLintptr,
+        size: GLsizeiptr,
+        data: *mut c_void,
+    ) {
+        let mut recording = self.recording.borrow_mut();
+        let len = recording.buffer_len(target);
+        if !recording.check_range(len, offset, size) {
+            return;
+        }
+        if let Some(contents) = recording.bound_contents(target) {
+            let source = &contents[offset as usize..(offset + size) as usize];
+            ptr::copy_nonoverlapping(source.as_ptr(), data as *mut u8, source.len());
+        }
+    }
+
+    fn map_buffer_range(
+        &mut self,
+        target: GLenum,
+        offset: GLintptr,
+        length: GLsizeiptr,
+        access: GLbitfield,
+    ) -> *mut c_void {
+        let mut recording = self.record(GlCall::MapBufferRange {
+            target,
+            offset,
+            length,
+            access,
+        });
+        let len = recording.buffer_len(target);
+        if !recording.check_range(len, offset, length) {
+            return ptr::null_mut();
+        }
+        // The contents aren't resized while mapped, so the pointer stays valid
+        match recording.bound_contents(target) {
+            Some(contents) => contents[offset as usize..].as_mut_ptr() as *mut c_void,
+            None => ptr::null_mut(),
+        }
+    }
+
+    fn flush_mapped_buffer_range(&mut self, target: GLenum, offset: GLintptr, length: GLsizeiptr) {
+        self.record(GlCall::FlushMappedBufferRange {
+            target,
+            offset,
+            length,
+        });
+    }
+
+    fn unmap_buffer(&mut self, target: GLenum) -> bool {
+        self.record(GlCall::UnmapBuffer(target));
+        true
+    }
+
+    fn gen_vertex_array(&mut self) -> GLuint {
+        let array = NEXT_NAME.fetch_add(1, Ordering::Relaxed);
+        self.record(GlCall::GenVertexArray(array)).vertex_arrays.push(array);
+        array
+    }
+
+    fn delete_vertex_array(&mut self, array: GLuint) {
+        let mut recording = self.record(GlCall::DeleteVertexArray(array));
+        recording.vertex_arrays.retain(|live| *live != array);
+        recording.element_buffers.remove(&array);
+        if recording.bound_vertex_array == array {
+            recording.bound_vertex_array = 0;
+        }
+    }
+
+    fn bind_vertex_array(&mut self, array: GLuint) {
+        let mut recording = self.record(GlCall::BindVertexArray(array));
+        if array != 0 && !recording.vertex_arrays.contains(&array) {
+            recording.errors.push_back(gl::INVALID_OPERATION);
+        } else {
+            recording.bound_vertex_array = array;
+        }
+    }
+
+    fn enable_vertex_attrib_array(&mut self, index: GLuint) {
+        self.record(GlCall::EnableVertexAttribArray(index));
+    }
+
+    fn disable_vertex_attrib_array(&mut self, index: GLuint) {
+        self.record(GlCall::DisableVertexAttribArray(index));
+    }
+
+    fn vertex_attrib_pointer(
+        &mut self,
+        index: GLuint,
+        size: GLint,
+        data_type: GLenum,
+        normalized: bool,
+        stride: GLsizei,
+        offset: usize,
+    ) {
+        self.record(GlCall::VertexAttribPointer {
+            index,
+            size,
+            data_type,
+            normalized,
+            stride,
+            offset,
+        });
+    }
+
+    fn vertex_attrib_i_pointer(
+        &mut self,
+        index: GLuint,
+        size: GLint,
+        data_type: GLenum,
+        stride: GLsizei,
+        offset: usize,
+    ) {
+        self.record(GlCall::VertexAttribIPointer {
+            index,
+            size,
+            data_type,
+            stride,
+            offset,
+        });
+    }
+
+    fn vertex_attrib_l_pointer(
+        &mut self,
+        index: GLuint,
+        size: GLint,
+        data_type: GLenum,
+        stride: GLsizei,
+        offset: usize,
+    ) {
+        self.record(GlCall::VertexAttribLPointer {
+            index,
+            size,
+            data_type,
+            stride,
+            offset,
+        });
+    }
+
+    fn vertex_attrib_divisor(&mut self, index: GLuint, divisor: GLuint) {
+        self.record(GlCall::VertexAttribDivisor {
+            index,
+            divisor,
+        });
+    }
+
+    fn gen_texture(&mut self) -> GLuint {
+        let texture = NEXT_NAME.fetch_add(1, Ordering::Relaxed);
+        self.record(GlCall::GenTexture(texture)).textures.insert(texture, (0, 0));
+        texture
+    }
+
+    fn delete_texture(&mut self, texture: GLuint) {
+        let mut recording = self.record(GlCall::DeleteTexture(texture));
+        recording.textures.remove(&texture);
+        // Deleting a bound texture unbinds it from all units
+        recording.bound_textures.retain(|_, bound| *bound != texture);
+    }
+
+    fn active_texture(&mut self, unit: GLenum) {
+        let mut recording = self.record(GlCall::ActiveTexture(unit));
+        if (gl::TEXTURE0..=gl::TEXTURE31).contains(&unit) {
+            recording.active_texture = unit - gl::TEXTURE0;
+        } else {
+            recording.errors.push_back(gl::INVALID_ENUM);
+        }
+    }
+
+    fn bind_texture(&mut self, target: GLenum, texture: GLuint) {
+        let mut recording = self.record(GlCall::BindTexture {
+            target,
+            texture,
+        });
+        if texture != 0 && !recording.textures.contains_key(&texture) {
+            recording.errors.push_back(gl::INVALID_OPERATION);
+        } else {
+            let unit = recording.active_texture;
+            recording.bound_textures.insert((unit, target), texture);
+        }
+    }
+
+    fn tex_parameter_i(&mut self, target: GLenum, pname: GLenum, param: GLint) {
+        self.record(GlCall::TexParameter {
+            target,
+            pname,
+            param,
+        });
+    }
+
+    unsafe fn tex_image_2d(
+        &mut self,
+        target: GLenum,
+        level: GLint,
+        internal_format: GLint,
+        width: GLsizei,
+        height: GLsizei,
+        format: GLenum,
+        data_type: GLenum,
+        _data: *const c_void,
+    ) {
+        let mut recording = self.record(GlCall::TexImage2D {
+            target,
+            level,
+            internal_format,
+            width,
+            height,
+            format,
+            data_type,
+        });
+        if level < 0 || width < 0 || height < 0 {
+            recording.errors.push_back(gl::INVALID_VALUE);
+            return;
+        }
+        let texture = recording.bound_texture(recording.active_texture, target);
+        match recording.textures.get_mut(&texture) {
+            Some(size) if level == 0 => *size = (width, height),
+            Some(_) => {}
+            None => recording.errors.push_back(gl::INVALID_OPERATION),
+        }
+    }
+
+    fn generate_mipmap(&mut self, target: GLenum) {
+        let mut recording = self.record(GlCall::GenerateMipmap(target));
+        let texture = recording.bound_texture(recording.active_texture, target);
+        if !recording.textures.contains_key(&texture) {
+            recording.errors.push_back(gl::INVALID_OPERATION);
+        }
+    }
+
+    fn create_shader(&mut self, shader_type: GLenum) -> GLuint {
+        let shader = NEXT_NAME.fetch_add(1, Ordering::Relaxed);
+        let recorded = RecordedShader {
+            shader_type,
+            ..RecordedShader::default()
+        };
+        self.record(GlCall::CreateShader {
+            shader_type,
+            shader,
+        })
+        .shaders
+        .insert(shader, recorded);
+        shader
+    }
+
+    fn delete_shader(&mut self, shader: GLuint) {
+        self.record(GlCall::DeleteShader(shader)).shaders.remove(&shader);
+    }
+
+    fn shader_source(&mut self, shader: GLuint, source: &CStr) {
+        if let Some(recorded) = self.record(GlCall::ShaderSource(shader)).shader(shader) {
+            recorded.source = source.to_string_lossy().into_owned();
+        }
+    }
+
+    /// Compilation fails for sources with an `#error` directive, which is reported
+    /// in the info log like by the GLSL compilers.
+    fn compile_shader(&mut self, shader: GLuint) {
+        let mut recording = self.record(GlCall::CompileShader(shader));
+        let Some(recorded) = recording.shader(shader) else {
+            return;
+        };
+        let error = recorded
+            .source
+            .lines()
+            .enumerate()
+            .find(|(_, line)| line.trim_start().starts_with("#error"));
+        recorded.compiled = error.is_none();
+        recorded.info_log = match error {
+            Some((line, directive)) => format!("0:{}: error: {}", line + 1, directive.trim()),
+            None => String::new(),
+        };
+    }
+
+    fn get_shader_iv(&mut self, shader: GLuint, pname: GLenum) -> GLint {
+        let mut recording = self.recording.borrow_mut();
+        let Some(recorded) = recording.shader(shader) else {
+            return 0;
+        };
+        match pname {
+            gl::SHADER_TYPE => recorded.shader_type as GLint,
+            gl::COMPILE_STATUS => recorded.compiled as GLint,
+            // The length includes the terminating null
+            gl::INFO_LOG_LENGTH if recorded.info_log.is_empty() => 0,
+            gl::INFO_LOG_LENGTH => recorded.info_log.len() as GLint + 1,
+            _ => {
+                recording.errors.push_back(gl::INVALID_ENUM);
+                0
+            }
+        }
+    }
+
+    fn get_shader_info_log(&mut self, shader: GLuint) -> String {
+        let mut recording = self.recording.borrow_mut();
+        recording
+            .shader(shader)
+            .map(|recorded| recorded.info_log.clone())
+            .unwrap_or_default()
+    }
+
+    fn create_program(&mut self) -> GLuint {
+        let program = NEXT_NAME.fetch_add(1, Ordering::Relaxed);
+        self.record(GlCall::CreateProgram(program))
+            .programs
+            .insert(program, RecordedProgram::default());
+        program
+    }
+
+    fn delete_program(&mut self, program: GLuint) {
+        self.record(GlCall::DeleteProgram(program)).programs.remove(&program);
+    }
+
+    fn attach_shader(&mut self, program: GLuint, shader: GLuint) {
+        let mut recording = self.record(GlCall::AttachShader {
+            program,
+            shader,
+        });
+        if recording.shader(shader).is_none() {
+            return;
+        }
+        if let Some(recorded) = recording.program(program) {
+            if recorded.shaders.contains(&shader) {
+                recording.errors.push_back(gl::INVALID_OPERATION);
+            } else {
+                recorded.shaders.push(shader);
+            }
+        }
+    }
+
+    fn detach_shader(&mut self, program: GLuint, shader: GLuint) {
+        let mut recording = self.record(GlCall::DetachShader {
+            program,
+            shader,
+        });
+        if let Some(recorded) = recording.program(program) {
+            if recorded.shaders.contains(&shader) {
+                recorded.shaders.retain(|attached| *attached != shader);
+            } else {
+                recording.errors.push_back(gl::INVALID_OPERATION);
+            }
+        }
+    }
+
+    fn transform_feedback_varyings(&mut self, program: GLuint, names: &[CString], mode: GLenum) {
+        let names = names.iter().map(|name| name.to_string_lossy().into_owned()).collect();
+        let mut recording = self.record(GlCall::TransformFeedbackVaryings {
+            program,
+            names,
+            mode,
+        });
+        recording.program(program);
+    }
+
+    fn link_program(&mut self, program: GLuint) {
+        self.record(GlCall::LinkProgram(program)).link(program);
+    }
+
+    fn use_program(&mut self, program: GLuint) {
+        let mut recording = self.record(GlCall::UseProgram(program));
+        if program == 0 {
+            recording.current_program = 0;
+        } else if recording.linked_program(program).is_some() {
+            recording.current_program = program;
+        }
+    }
+
+    fn get_program_iv(&mut self, program: GLuint, pname: GLenum) -> GLint {
+        let mut recording = self.recording.borrow_mut();
+        let Some(recorded) = recording.program(program) else {
+            return 0;
+        };
+        match pname {
+            gl::LINK_STATUS => recorded.linked as GLint,
+            gl::ATTACHED_SHADERS => recorded.shaders.len() as GLint,
+            gl::ACTIVE_UNIFORMS => recorded.uniforms.len() as GLint,
+            gl::ACTIVE_ATTRIBUTES => recorded.attributes.len() as GLint,
+            // The length includes the terminating null
+            gl::INFO_LOG_LENGTH if recorded.info_log.is_empty() => 0,
+            gl::INFO_LOG_LENGTH => recorded.info_log.len() as GLint + 1,
+            _ => {
+                recording.errors.push_back(gl::INVALID_ENUM);
+                0
+            }
+        }
+    }
+
+    fn get_program_info_log(&mut self, program: GLuint) -> String {
+        let mut recording = self.recording.borrow_mut();
+        recording
+            .program(program)
+            .map(|recorded| recorded.info_log.clone())
+            .unwrap_or_default()
+    }
+
+    fn get_uniform_location(&mut self, program: GLuint, name: &CStr) -> GLint {
+        let mut recording = self.recording.borrow_mut();
+        let name = name.to_string_lossy();
+        recording
+            .linked_program(program)
+            .and_then(|recorded| find_location(&recorded.uniforms, &name))
+            .unwrap_or(-1)
+    }
+
+    fn get_attrib_location(&mut self, program: GLuint, name: &CStr) -> GLint {
+        let mut recording = self.recording.borrow_mut();
+        let name = name.to_string_lossy();
+        recording
+            .linked_program(program)
+            .and_then(|recorded| find_location(&recorded.attributes, &name))
+            .unwrap_or(-1)
+    }
+
+    fn get_active_uniform(&mut self, program: GLuint, index: GLuint) -> Option<ActiveVariable> {
+        let mut recording = self.recording.borrow_mut();
+        let variable = recording
+            .program(program)
+            .and_then(|recorded| recorded.uniforms.get(index as usize))
+            .map(|(variable, _)| variable.clone());
+        variable
+    }
+
+    fn get_active_attrib(&mut self, program: GLuint, index: GLuint) -> Option<ActiveVariable> {
+        let mut recording = self.recording.borrow_mut();
+        let variable = recording
+            .program(program)
+            .and_then(|recorded| recorded.attributes.get(index as usize))
+            .map(|(variable, _)| variable.clone());
+        variable
+    }
+
+    fn get_integers(&mut self, pname: GLenum) -> [GLint; 4] {
+        // The first value also covers the bindings tracked outside of the state
+        let mut values = self.recording.borrow().state(pname);
+        values[0] = self.get_integer(pname);
+        values
+    }
+
+    fn get_string_i(&mut self, name: GLenum, index: GLuint) -> Option<String> {
+        let mut recording = self.recording.borrow_mut();
+        let string = match name {
+            gl::EXTENSIONS => recording.extensions.get(index as usize).cloned(),
+            _ => None,
+        };
+        if string.is_none() {
+            recording.errors.push_back(gl::INVALID_VALUE);
+        }
+        string
+    }
+
+    fn is_function_loaded(&mut self, _name: &str) -> bool {
+        true
+    }
+
+    fn enable(&mut self, capability: GLenum) {
+        self.record(GlCall::Enable(capability)).capabilities.insert(capability, true);
+    }
+
+    fn disable(&mut self, capability: GLenum) {
+        self.record(GlCall::Disable(capability)).capabilities.insert(capability, false);
+    }
+
+    fn is_enabled(&mut self, capability: GLenum) -> bool {
+        self.recording.borrow().is_enabled(capability)
+    }
+
+    fn depth_mask(&mut self, enabled: bool) {
+        self.record(GlCall::DepthMask(enabled))
+            .set_state(gl::DEPTH_WRITEMASK, [enabled as GLint, 0, 0, 0]);
+    }
+
+    fn depth_func(&mut self, func: GLenum) {
+        self.record(GlCall::DepthFunc(func))
+            .set_state(gl::DEPTH_FUNC, [func as GLint, 0, 0, 0]);
+    }
+
+    fn cull_face(&mut self, mode: GLenum) {
+        self.record(GlCall::CullFace(mode))
+            .set_state(gl::CULL_FACE_MODE, [mode as GLint, 0, 0, 0]);
+    }
+
+    fn front_face(&mut self, mode: GLenum) {
+        self.record(GlCall::FrontFace(mode))
+            .set_state(gl::FRONT_FACE, [mode as GLint, 0, 0, 0]);
+    }
+
+    fn polygon_mode(&mut self, face: GLenum, mode: GLenum) {
+        let mut recording = self.record(GlCall::PolygonMode {
+            face,
+            mode,
+        });
+        // The core profile only knows FRONT_AND_BACK
+        if face != gl::FRONT_AND_BACK {
+            recording.errors.push_back(gl::INVALID_ENUM);
+            return;
+        }
+        recording.set_state(gl::POLYGON_MODE, [mode as GLint, mode as GLint, 0, 0]);
+    }
+
+    fn color_mask(&mut self, red: bool, green: bool, blue: bool, alpha: bool) {
+        let mask = [red, green, blue, alpha];
+        self.record(GlCall::ColorMask(mask))
+            .set_state(gl::COLOR_WRITEMASK, mask.map(GLint::from));
+    }
+
+    fn scissor(&mut self, x: GLint, y: GLint, width: GLsizei, height: GLsizei) {
+        let mut recording = self.record(GlCall::Scissor {
+            x,
+            y,
+            width,
+            height,
+        });
+        if width < 0 || height < 0 {
+            recording.errors.push_back(gl::INVALID_VALUE);
+            return;
+        }
+        recording.set_state(gl::SCISSOR_BOX, [x, y, width, height]);
+    }
+
+    fn viewport(&mut self, x: GLint, y: GLint, width: GLsizei, height: GLsizei) {
+        let mut recording = self.record(GlCall::Viewport {
+            x,
+            y,
+            width,
+            height,
+        });
+        if width < 0 || height < 0 {
+            recording.errors.push_back(gl::INVALID_VALUE);
+            return;
+        }
+        recording.set_state(gl::VIEWPORT, [x, y, width, height]);
+    }
+
+    fn blend_func(&mut self, src: GLenum, dest: GLenum) {
+        let mut recording = self.record(GlCall::BlendFunc {
+            src,
+            dest,
+        });
+        for (pname, factor) in [
+            (gl::BLEND_SRC_RGB, src),
+            (gl::BLEND_SRC_ALPHA, src),
+            (gl::BLEND_DST_RGB, dest),
+            (gl::BLEND_DST_ALPHA, dest),
+        ] {
+            recording.set_state(pname, [factor as GLint, 0, 0, 0]);
+        }
+    }
+
+    fn blend_func_separate(
+        &mut self,
+        src_rgb: GLenum,
+        dest_rgb: GLenum,
+        src_alpha: GLenum,
+        dest_alpha: GLenum,
+    ) {
+        let mut recording = self.record(GlCall::BlendFuncSeparate {
+            src_rgb,
+            dest_rgb,
+            src_alpha,
+            dest_alpha,
+        });
+        for (pname, factor) in [
+            (gl::BLEND_SRC_RGB, src_rgb),
+            (gl::BLEND_SRC_ALPHA, src_alpha),
+            (gl::BLEND_DST_RGB, dest_rgb),
+            (gl::BLEND_DST_ALPHA, dest_alpha),
+        ] {
+            recording.set_state(pname, [factor as GLint, 0, 0, 0]);
+        }
+    }
+
+    fn stencil_func_separate(
+        &mut self,
+        face: GLenum,
+        func: GLenum,
+        reference: GLint,
+        mask: GLuint,
+    ) {
+        let mut recording = self.record(GlCall::StencilFuncSeparate {
+            face,
+            func,
+            reference,
+            mask,
+        });
+        for (front, back, value) in [
+            (gl::STENCIL_FUNC, gl::STENCIL_BACK_FUNC, func as GLint),
+            (gl::STENCIL_REF, gl::STENCIL_BACK_REF, reference),
+            (gl::STENCIL_VALUE_MASK, gl::STENCIL_BACK_VALUE_MASK, mask as GLint),
+        ] {
+            set_face_state(&mut recording, face, front, back, value);
+        }
+    }
+
+    fn stencil_mask_separate(&mut self, face: GLenum, mask: GLuint) {
+        let mut recording = self.record(GlCall::StencilMaskSeparate {
+            face,
+            mask,
+        });
+        let (front, back) = (gl::STENCIL_WRITEMASK, gl::STENCIL_BACK_WRITEMASK);
+        set_face_state(&mut recording, face, front, back, mask as GLint);
+    }
+
+    fn stencil_op_separate(
+        &mut self,
+        face: GLenum,
+        stencil_fail: GLenum,
+        depth_fail: GLenum,
+        pass: GLenum,
+    ) {
+        let mut recording = self.record(GlCall::StencilOpSeparate {
+            face,
+            stencil_fail,
+            depth_fail,
+            pass,
+        });
+        for (front, back, value) in [
+            (gl::STENCIL_FAIL, gl::STENCIL_BACK_FAIL, stencil_fail),
+            (gl::STENCIL_PASS_DEPTH_FAIL, gl::STENCIL_BACK_PASS_DEPTH_FAIL, depth_fail),
+            (gl::STENCIL_PASS_DEPTH_PASS, gl::STENCIL_BACK_PASS_DEPTH_PASS, pass),
+        ] {
+            set_face_state(&mut recording, face, front, back, value as GLint);
+        }
+    }
+
+    fn clear_color(&mut self, red: f32, green: f32, blue: f32, alpha: f32) {
+        self.record(GlCall::ClearColor([red, green, blue, alpha]));
+    }
+
+    fn clear_stencil(&mut self, value: GLint) {
+        self.record(GlCall::ClearStencil(value))
+            .set_state(gl::STENCIL_CLEAR_VALUE, [value, 0, 0, 0]);
+    }
+
+    fn clear(&mut self, mask: GLbitfield) {
+        self.record(GlCall::Clear(mask));
+    }
+
+    fn primitive_restart_index(&mut self, index: GLuint) {
+        self.record(GlCall::PrimitiveRestartIndex(index))
+            .set_state(gl::PRIMITIVE_RESTART_INDEX, [index as GLint, 0, 0, 0]);
+    }
+
+    fn draw_arrays(&mut self, mode: GLenum, first: GLint, count: GLsizei) {
+        self.record(GlCall::DrawArrays {
+            mode,
+            first,
+            count,
+        });
+    }
+
+    fn draw_arrays_instanced(
+        &mut self,
+        mode: GLenum,
+        first: GLint,
+        count: GLsizei,
+        instance_count: GLsizei,
+    ) {
+        self.record(GlCall::DrawArraysInstanced {
+            mode,
+            first,
+            count,
+            instance_count,
+        });
+    }
+
+    fn draw_arrays_instanced_base_instance(
+        &mut self,
+        mode: GLenum,
+        first: GLint,
+        count: GLsizei,
+        instance_count: GLsizei,
+        base_instance: GLuint,
+    ) {
+        self.record(GlCall::DrawArraysInstancedBaseInstance {
+            mode,
+            first,
+            count,
+            instance_count,
+            base_instance,
+        });
+    }
+
+    fn draw_elements(&mut self, mode: GLenum, count: GLsizei, data_type: GLenum, offset: usize) {
+        self.record(GlCall::DrawElements {
+            mode,
+            count,
+            data_type,
+            offset,
+        });
+    }
+
+    fn draw_elements_base_vertex(
+        &mut self,
+        mode: GLenum,
+        count: GLsizei,
+        data_type: GLenum,
+        offset: usize,
+        base_vertex: GLint,
+    ) {
+        self.record(GlCall::DrawElementsBaseVertex {
+            mode,
+            count,
+            data_type,
+            offset,
+            base_vertex,
+        });
+    }
+
+    fn draw_elements_instanced(
+        &mut self,
+        mode: GLenum,
+        count: GLsizei,
+        data_type: GLenum,
+        offset: usize,
+        instance_count: GLsizei,
+    ) {
+        self.record(GlCall::DrawElementsInstanced {
+            mode,
+            count,
+            data_type,
+            offset,
+            instance_count,
+        });
+    }
+
+    fn draw_elements_instanced_base_vertex(
+        &mut self,
+        mode: GLenum,
+        count: GLsizei,
+        data_type: GLenum,
+        offset: usize,
+        instance_count: GLsizei,
+        base_vertex: GLint,
+    ) {
+        self.record(GlCall::DrawElementsInstancedBaseVertex {
+            mode,
+            count,
+            data_type,
+            offset,
+            instance_count,
+            base_vertex,
+        });
+    }
+
+    fn draw_elements_instanced_base_vertex_base_instance(
+        &mut self,
+        mode: GLenum,
+        count: GLsizei,
+        data_type: GLenum,
+        offset: usize,
+        instance_count: GLsizei,
+        base_vertex: GLint,
+        base_instance: GLuint,
+    ) {
+        self.record(GlCall::DrawElementsInstancedBaseVertexBaseInstance {
+            mode,
+            count,
+            data_type,
+            offset,
+            instance_count,
+            base_vertex,
+            base_instance,
+        });
+    }
+
+    fn multi_draw_arrays_indirect(
+        &mut self,
+        mode: GLenum,
+        offset: usize,
+        draw_count: GLsizei,
+        stride: GLsizei,
+    ) {
+        self.record(GlCall::MultiDrawArraysIndirect {
+            mode,
+            offset,
+            draw_count,
+            stride,
+        });
+    }
+
+    fn multi_draw_elements_indirect(
+        &mut self,
+        mode: GLenum,
+        data_type: GLenum,
+        offset: usize,
+        draw_count: GLsizei,
+        stride: GLsizei,
+    ) {
+        self.record(GlCall::MultiDrawElementsIndirect {
+            mode,
+            data_type,
+            offset,
+            draw_count,
+            stride,
+        });
+    }
+
+    fn gen_query(&mut self) -> GLuint {
+        let query = NEXT_NAME.fetch_add(1, Ordering::Relaxed);
+        self.record(GlCall::GenQuery(query)).queries.insert(query, None);
+        query
+    }
+
+    fn delete_query(&mut self, query: GLuint) {
+        let mut recording = self.record(GlCall::DeleteQuery(query));
+        recording.queries.remove(&query);
+        // Deleting an active query ends it
+        recording.active_queries.retain(|_, active| *active != query);
+    }
+
+    fn begin_query(&mut self, target: GLenum, query: GLuint) {
+        let mut recording = self.record(GlCall::BeginQuery {
+            target,
+            query,
+        });
+        let in_use = recording.active_queries.contains_key(&target)
+            || recording.active_queries.values().any(|active| *active == query)
+            || recording.conditional_render == Some(query);
+        match recording.queries.get_mut(&query) {
+            Some(result) if !in_use => *result = None,
+            _ => {
+                recording.errors.push_back(gl::INVALID_OPERATION);
+                return;
+            }
+        }
+        recording.active_queries.insert(target, query);
+    }
+
+    fn end_query(&mut self, target: GLenum) {
+        let mut recording = self.record(GlCall::EndQuery(target));
+        match recording.active_queries.remove(&target) {
+            Some(query) => {
+                recording.queries.insert(query, Some(0));
+            }
+            None => recording.errors.push_back(gl::INVALID_OPERATION),
+        }
+    }
+
+    fn query_counter(&mut self, query: GLuint, target: GLenum) {
+        let mut recording = self.record(GlCall::QueryCounter {
+            query,
+            target,
+        });
+        let active = recording.active_queries.values().any(|active| *active == query);
+        match recording.queries.get_mut(&query) {
+            Some(result) if !active && target == gl::TIMESTAMP => *result = Some(0),
+            _ => recording.errors.push_back(gl::INVALID_OPERATION),
+        }
+    }
+
+    fn get_query_object_ui(&mut self, query: GLuint, pname: GLenum) -> GLuint {
+        self.get_query_object_ui64(query, pname) as GLuint
+    }
+
+    fn get_query_object_ui64(&mut self, query: GLuint, pname: GLenum) -> GLuint64 {
+        let mut recording = self.recording.borrow_mut();
+        let active = recording.active_queries.values().any(|active| *active == query);
+        let result = match recording.queries.get(&query) {
+            Some(result) if !active => *result,
+            _ => {
+                recording.errors.push_back(gl::INVALID_OPERATION);
+                return 0;
+            }
+        };
+        match pname {
+            gl::QUERY_RESULT_AVAILABLE => result.is_some() as GLuint64,
+            gl::QUERY_RESULT | gl::QUERY_RESULT_NO_WAIT => result.unwrap_or(0),
+            _ => {
+                recording.errors.push_back(gl::INVALID_ENUM);
+                0
+            }
+        }
+    }
+
+    fn begin_conditional_render(&mut self, query: GLuint, mode: GLenum) {
+        let mut recording = self.record(GlCall::BeginConditionalRender {
+            query,
+            mode,
+        });
+        let active = recording.active_queries.values().any(|active| *active == query);
+        if recording.conditional_render.is_some()
+            || active
+            || !recording.queries.contains_key(&query)
+        {
+            recording.errors.push_back(gl::INVALID_OPERATION);
+        } else {
+            recording.conditional_render = Some(query);
+        }
+    }
+
+    fn end_conditional_render(&mut self) {
+        let mut recording = self.record(GlCall::EndConditionalRender);
+        if recording.conditional_render.take().is_none() {
+            recording.errors.push_back(gl::INVALID_OPERATION);
+        }
+    }
+
+    fn gen_transform_feedback(&mut self) -> GLuint {
+        let transform_feedback = NEXT_NAME.fetch_add(1, Ordering::Relaxed);
+        self.record(GlCall::GenTransformFeedback(transform_feedback))
+            .transform_feedbacks
+            .insert(transform_feedback, Capture::Inactive);
+        transform_feedback
+    }
+
+    fn delete_transform_feedback(&mut self, transform_feedback: GLuint) {
+        let mut recording = self.record(GlCall::DeleteTransformFeedback(transform_feedback));
+        let capture = recording.transform_feedbacks.get(&transform_feedback).copied();
+        if capture.is_some_and(|capture| capture != Capture::Inactive) {
+            recording.errors.push_back(gl::INVALID_OPERATION);
+            return;
+        }
+        recording.transform_feedbacks.remove(&transform_feedback);
+        // Deleting the bound object binds the default one
+        if recording.bound_transform_feedback == transform_feedback {
+            recording.bound_transform_feedback = 0;
+        }
+    }
+
+    fn bind_transform_feedback(&mut self, target: GLenum, transform_feedback: GLuint) {
+        let mut recording = self.record(GlCall::BindTransformFeedback {
+            target,
+            transform_feedback,
+        });
+        // The bound object can't change while capturing, only while paused
+        if recording.capture() == Capture::Active
+            || (transform_feedback != 0
+                && !recording.transform_feedbacks.contains_key(&transform_feedback))
+        {
+            recording.errors.push_back(gl::INVALID_OPERATION);
+        } else {
+            recording.bound_transform_feedback = transform_feedback;
+        }
+    }
+
+    fn begin_transform_feedback(&mut self, primitive_mode: GLenum) {
+        self.record(GlCall::BeginTransformFeedback(primitive_mode))
+            .transition_capture(&[Capture::Inactive], Capture::Active);
+    }
+
+    fn end_transform_feedback(&mut self) {
+        self.record(GlCall::EndTransformFeedback)
+            .transition_capture(&[Capture::Active, Capture::Paused], Capture::Inactive);
+    }
+
+    fn pause_transform_feedback(&mut self) {
+        self.record(GlCall::PauseTransformFeedback)
+            .transition_capture(&[Capture::Active], Capture::Paused);
+    }
+
+    fn resume_transform_feedback(&mut self) {
+        self.record(GlCall::ResumeTransformFeedback)
+            .transition_capture(&[Capture::Paused], Capture::Active);
+    }
+
+    fn draw_transform_feedback(&mut self, mode: GLenum, transform_feedback: GLuint) {
+        let mut recording = self.record(GlCall::DrawTransformFeedback {
+            mode,
+            transform_feedback,
+        });
+        if !recording.transform_feedbacks.contains_key(&transform_feedback) {
+            recording.errors.push_back(gl::INVALID_VALUE);
+        }
+    }
+
+    fn bind_buffer_base(&mut self, target: GLenum, index: GLuint, buffer: GLuint) {
+        let mut recording = self.record(GlCall::BindBufferBase {
+            target,
+            index,
+            buffer,
+        });
+        // Binding an indexed target also binds the generic one
+        if buffer != 0 && !recording.buffers.contains_key(&buffer) {
+            recording.errors.push_back(gl::INVALID_OPERATION);
+        } else {
+            recording.bound_buffers.insert(target, buffer);
+        }
+    }
+
+    fn bind_buffer_range(
+        &mut self,
+        target: GLenum,
+        index: GLuint,
+        buffer: GLuint,
+        offset: GLintptr,
+        size: GLsizeiptr,
+    ) {
+        let mut recording = self.record(GlCall::BindBufferRange {
+            target,
+            index,
+            buffer,
+            offset,
+            size,
+        });
+        if buffer != 0 && !recording.buffers.contains_key(&buffer) {
+            recording.errors.push_back(gl::INVALID_OPERATION);
+        } else if offset < 0 || size <= 0 {
+            recording.errors.push_back(gl::INVALID_VALUE);
+        } else {
+            recording.bound_buffers.insert(target, buffer);
+        }
+    }
+
+    fn gen_framebuffer(&mut self) -> GLuint {
+        let framebuffer = NEXT_NAME.fetch_add(1, Ordering::Relaxed);
+        let recorded = RecordedFramebuffer {
+            draw_buffers: vec![gl::COLOR_ATTACHMENT0],
+            read_buffer: gl::COLOR_ATTACHMENT0,
+            ..RecordedFramebuffer::default()
+        };
+        self.record(GlCall::GenFramebuffer(framebuffer))
+            .framebuffers
+            .insert(framebuffer, recorded);
+        framebuffer
+    }
+
+    fn delete_framebuffer(&mut self, framebuffer: GLuint) {
+        let mut recording = self.record(GlCall::DeleteFramebuffer(framebuffer));
+        if framebuffer == 0 {
+            return;
+        }
+        recording.framebuffers.remove(&framebuffer);
+        // Deleting a bound framebuffer binds the default framebuffer
+        if recording.draw_framebuffer == framebuffer {
+            recording.draw_framebuffer = 0;
+        }
+        if recording.read_framebuffer == framebuffer {
+            recording.read_framebuffer = 0;
+        }
+    }
+
+    fn bind_framebuffer(&mut self, target: GLenum, framebuffer: GLuint) {
+        let mut recording = self.record(GlCall::BindFramebuffer {
+            target,
+            framebuffer,
+        });
+        if framebuffer != 0 && !recording.framebuffers.contains_key(&framebuffer) {
+            recording.errors.push_back(gl::INVALID_OPERATION);
+            return;
+        }
+        if target != gl::READ_FRAMEBUFFER {
+            recording.draw_framebuffer = framebuffer;
+        }
+        if target != gl::DRAW_FRAMEBUFFER {
+            recording.read_framebuffer = framebuffer;
+        }
+    }
+
+    fn check_framebuffer_status(&mut self, target: GLenum) -> GLenum {
+        self.recording.borrow().framebuffer_status(target)
+    }
+
+    fn framebuffer_texture_2d(
+        &mut self,
+        target: GLenum,
+        attachment: GLenum,
+        texture_target: GLenum,
+        texture: GLuint,
+        level: GLint,
+    ) {
+        let mut recording = self.record(GlCall::FramebufferTexture2D {
+            target,
+            attachment,
+            texture_target,
+            texture,
+            level,
+        });
+        if texture != 0 && !recording.textures.contains_key(&texture) {
+            recording.errors.push_back(gl::INVALID_OPERATION);
+            return;
+        }
+        recording.attach(target, attachment, (gl::TEXTURE, texture));
+    }
+
+    fn framebuffer_renderbuffer(
+        &mut self,
+        target: GLenum,
+        attachment: GLenum,
+        _renderbuffer_target: GLenum,
+        renderbuffer: GLuint,
+    ) {
+        let mut recording = self.record(GlCall::FramebufferRenderbuffer {
+            target,
+            attachment,
+            renderbuffer,
+        });
+        if renderbuffer != 0 && !recording.renderbuffers.contains_key(&renderbuffer) {
+            recording.errors.push_back(gl::INVALID_OPERATION);
+            return;
+        }
+        recording.attach(target, attachment, (gl::RENDERBUFFER, renderbuffer));
+    }
+
+    fn get_framebuffer_attachment_parameter_i(
+        &mut self,
+        target: GLenum,
+        attachment: GLenum,
+        pname: GLenum,
+    ) -> GLint {
+        let recording = self.recording.borrow();
+        let framebuffer = recording.bound_framebuffer(target);
+        let Some(recorded) = recording.framebuffers.get(&framebuffer) else {
+            // The default framebuffer has color, 24 depth and 8 stencil bits
+            return match (pname, attachment) {
+                (gl::FRAMEBUFFER_ATTACHMENT_OBJECT_TYPE, _) => gl::FRAMEBUFFER_DEFAULT as GLint,
+                (gl::FRAMEBUFFER_ATTACHMENT_DEPTH_SIZE, gl::DEPTH) => 24,
+                (gl::FRAMEBUFFER_ATTACHMENT_STENCIL_SIZE, gl::STENCIL) => 8,
+                _ => 0,
+            };
+        };
+        let attached = recorded.attachments.get(&attachment);
+        match pname {
+            gl::FRAMEBUFFER_ATTACHMENT_OBJECT_TYPE => {
+                attached.map_or(gl::NONE, |(object_type, _)| *object_type) as GLint
+            }
+            gl::FRAMEBUFFER_ATTACHMENT_OBJECT_NAME => {
+                attached.map_or(0, |(_, name)| *name) as GLint
+            }
+            _ => 0,
+        }
+    }
+
+    fn draw_buffers(&mut self, buffers: &[GLenum]) {
+        let mut recording = self.record(GlCall::DrawBuffers(buffers.to_vec()));
+        let framebuffer = recording.draw_framebuffer;
+        if let Some(recorded) = recording.framebuffers.get_mut(&framebuffer) {
+            recorded.draw_buffers = buffers.to_vec();
+        }
+    }
+
+    fn read_buffer(&mut self, buffer: GLenum) {
+        let mut recording = self.record(GlCall::ReadBuffer(buffer));
+        let framebuffer = recording.read_framebuffer;
+        if let Some(recorded) = recording.framebuffers.get_mut(&framebuffer) {
+            recorded.read_buffer = buffer;
+        }
+    }
+
+    fn blit_framebuffer(
+        &mut self,
+        source: [GLint; 4],
+        destination: [GLint; 4],
+        mask: GLbitfield,
+        filter: GLenum,
+    ) {
+        let mut recording = self.record(GlCall::BlitFramebuffer {
+            source,
+            destination,
+            mask,
+            filter,
+        });
+        let complete = [gl::READ_FRAMEBUFFER, gl::DRAW_FRAMEBUFFER]
+            .iter()
+            .all(|target| recording.framebuffer_status(*target) == gl::FRAMEBUFFER_COMPLETE);
+        if !complete {
+            recording.errors.push_back(gl::INVALID_FRAMEBUFFER_OPERATION);
+        }
+    }
+
+    fn gen_renderbuffer(&mut self) -> GLuint {
+        let renderbuffer = NEXT_NAME.fetch_add(1, Ordering::Relaxed);
+        self.record(GlCall::GenRenderbuffer(renderbuffer))
+            .renderbuffers
+            .insert(renderbuffer, (0, 0));
+        renderbuffer
+    }
+
+    fn delete_renderbuffer(&mut self, renderbuffer: GLuint) {
+        let mut recording = self.record(GlCall::DeleteRenderbuffer(renderbuffer));
+        recording.renderbuffers.remove(&renderbuffer);
+        if recording.bound_renderbuffer == renderbuffer {
+            recording.bound_renderbuffer = 0;
+        }
+    }
+
+    fn bind_renderbuffer(&mut self, _target: GLenum, renderbuffer: GLuint) {
+        let mut recording = self.record(GlCall::BindRenderbuffer(renderbuffer));
+        if renderbuffer != 0 && !recording.renderbuffers.contains_key(&renderbuffer) {
+            recording.errors.push_back(gl::INVALID_OPERATION);
+        } else {
+            recording.bound_renderbuffer = renderbuffer;
+        }
+    }
+
+    fn renderbuffer_storage_multisample(
+        &mut self,
+        _target: GLenum,
+        samples: GLsizei,
+        internal_format: GLenum,
+        width: GLsizei,
+        height: GLsizei,
+    ) {
+        let mut recording = self.record(GlCall::RenderbufferStorageMultisample {
+            samples,
+            internal_format,
+            width,
+            height,
+        });
+        if samples < 0 || width < 0 || height < 0 {
+            recording.errors.push_back(gl::INVALID_VALUE);
+            return;
+        }
+        let renderbuffer = recording.bound_renderbuffer;
+        match recording.renderbuffers.get_mut(&renderbuffer) {
+            Some(size) => *size = (width, height),
+            None => recording.errors.push_back(gl::INVALID_OPERATION),
+        }
+    }
+
+    fn tex_image_2d_multisample(
+        &mut self,
+        target: GLenum,
+        samples: GLsizei,
+        internal_format: GLenum,
+        width: GLsizei,
+        height: GLsizei,
+        _fixed_sample_locations: bool,
+    ) {
+        let mut recording = self.record(GlCall::TexImage2DMultisample {
+            target,
+            samples,
+            internal_format,
+            width,
+            height,
+        });
+        if samples <= 0 || width < 0 || height < 0 {
+            recording.errors.push_back(gl::INVALID_VALUE);
+            return;
+        }
+        let texture = recording.bound_texture(recording.active_texture, target);
+        match recording.textures.get_mut(&texture) {
+            Some(size) => *size = (width, height),
+            None => recording.errors.push_back(gl::INVALID_OPERATION),
+        }
+    }
+
+    fn fence_sync(&mut self, _condition: GLenum, _flags: GLbitfield) -> GLsync {
+        let fence = NEXT_NAME.fetch_add(1, Ordering::Relaxed) as usize;
+        self.record(GlCall::FenceSync).fences.insert(fence);
+        fence as GLsync
+    }
+
+    unsafe fn client_wait_sync(
+        &mut self,
+        sync: GLsync,
+        _flags: GLbitfield,
+        _timeout: GLuint64,
+    ) -> GLenum {
+        let mut recording = self.recording.borrow_mut();
+        if !recording.fences.contains(&(sync as usize)) {
+            recording.errors.push_back(gl::INVALID_VALUE);
+            gl::WAIT_FAILED
+        } else if recording.block_fences {
+            gl::TIMEOUT_EXPIRED
+        } else {
+            gl::ALREADY_SIGNALED
+        }
+    }
+
+    unsafe fn delete_sync(&mut self, sync: GLsync) {
+        let mut recording = self.record(GlCall::DeleteSync);
+        // Deleting the null sync object is silently ignored
+        if !sync.is_null() && !recording.fences.remove(&(sync as usize)) {
+            recording.errors.push_back(gl::INVALID_VALUE);
+        }
+    }
+
+    fn debug_message_callback(&mut self, callback: GLDEBUGPROC) {
+        self.record(GlCall::DebugMessageCallback(callback.is_some())).debug_callback =
+            callback.is_some();
+    }
+
+    fn object_label(&mut self, identifier: GLenum, name: GLuint, label: &str) {
+        self.record(GlCall::ObjectLabel {
+            identifier,
+            name,
+            label: label.to_string(),
+        });
+    }
+
+    fn push_debug_group(&mut self, _source: GLenum, _id: GLuint, message: &str) {
+        self.record(GlCall::PushDebugGroup(message.to_string())).debug_groups += 1;
+    }
+
+    fn pop_debug_group(&mut self) {
+        let mut recording = self.record(GlCall::PopDebugGroup);
+        match recording.debug_groups.checked_sub(1) {
+            Some(depth) => recording.debug_groups = depth,
+            None => recording.errors.push_back(gl::STACK_UNDERFLOW),
+        }
+    }
+}
+
+/// Sets the state `front` and/or `back` of a stencil face.
+fn set_face_state(
+    recording: &mut Recording,
+    face: GLenum,
+    front: GLenum,
+    back: GLenum,
+    value: GLint,
+) {
+    if face != gl::BACK {
+        recording.set_state(front, [value, 0, 0, 0]);
+    }
+    if face != gl::FRONT {
+        recording.set_state(back, [value, 0, 0, 0]);
+    }
+}
+
+/// Returns the location of the variable `name`. Arrays are also found by the name
+/// of their first element, e.g. `lights[0]`.
+fn find_location(variables: &[(ActiveVariable, GLint)], name: &str) -> Option<GLint> {
+    let name = name.strip_suffix("[0]").unwrap_or(name);
+    variables
+        .iter()
+        .find(|(variable, _)| variable.name == name)
+        .map(|(_, location)| *location)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recording_tracks_bindings() {
+        let mut backend = RecordingGlBackend::new();
+        let array = backend.gen_vertex_array();
+        let vertices = backend.gen_buffer();
+        let indices = backend.gen_buffer();
+
+        backend.bind_vertex_array(array);
+        backend.bind_buffer(gl::ARRAY_BUFFER, vertices);
+        backend.bind_buffer(gl::ELEMENT_ARRAY_BUFFER, indices);
+        assert_eq!(backend.get_integer(gl::ARRAY_BUFFER_BINDING), vertices as GLint);
+        assert_eq!(backend.get_integer(gl::VERTEX_ARRAY_BINDING), array as GLint);
+
+        // The element array buffer belongs to the vertex array
+        backend.bind_vertex_array(0);
+        assert_eq!(backend.bound_buffer(gl::ELEMENT_ARRAY_BUFFER), 0);
+        backend.bind_vertex_array(array);
+        assert_eq!(backend.bound_buffer(gl::ELEMENT_ARRAY_BUFFER), indices);
+
+        backend.delete_buffer(vertices);
+        assert_eq!(backend.bound_buffer(gl::ARRAY_BUFFER), 0);
+        backend.bind_buffer(gl::ARRAY_BUFFER, vertices);
+        assert_eq!(backend.get_error(), gl::INVALID_OPERATION);
+        assert_eq!(backend.get_error(), gl::NO_ERROR);
+        assert_eq!(backend.live_objects(), 2);
+    }
+
+    #[test]
+    fn test_recording_buffer_contents() {
+        let mut backend = RecordingGlBackend::new();
+        let buffer = backend.gen_buffer();
+        backend.bind_buffer(gl::COPY_WRITE_BUFFER, buffer);
+        let data = [1u8, 2, 3, 4];
+        unsafe {
+            backend.buffer_data(gl::COPY_WRITE_BUFFER, 6, ptr::null(), gl::STATIC_DRAW);
+            backend.buffer_sub_data(gl::COPY_WRITE_BUFFER, 2, 4, data.as_ptr() as *const _);
+        }
+        assert_eq!(backend.buffer_contents(buffer), Some(vec![0, 0, 1, 2, 3, 4]));
+
+        let mapped = backend.map_buffer_range(gl::COPY_WRITE_BUFFER, 1, 2, gl::MAP_WRITE_BIT);
+        unsafe { *(mapped as *mut u8) = 9 };
+        assert!(backend.unmap_buffer(gl::COPY_WRITE_BUFFER));
+        assert_eq!(backend.buffer_contents(buffer), Some(vec![0, 9, 1, 2, 3, 4]));
+
+        // Writes outside of the buffer are rejected
+        unsafe {
+            backend.buffer_sub_data(gl::COPY_WRITE_BUFFER, 4, 4, data.as_ptr() as *const _);
+        }
+        assert_eq!(backend.get_error(), gl::INVALID_VALUE);
+        assert_eq!(backend.buffer_contents(buffer), Some(vec![0, 9, 1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn test_recording_framebuffer_completeness() {
+        let mut backend = RecordingGlBackend::new();
+        let framebuffer = backend.gen_framebuffer();
+        backend.bind_framebuffer(gl::FRAMEBUFFER, framebuffer);
+        assert_eq!(
+            backend.check_framebuffer_status(gl::FRAMEBUFFER),
+            gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT
+        );
+
+        let texture = backend.gen_texture();
+        backend.framebuffer_texture_2d(
+            gl::FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            gl::TEXTURE_2D,
+            texture,
+            0,
+        );
+        // The texture has no storage yet
+        assert_eq!(
+            backend.check_framebuffer_status(gl::FRAMEBUFFER),
+            gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT
+        );
+        backend.bind_texture(gl::TEXTURE_2D, texture);
+        unsafe {
+            backend.tex_image_2d(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA8 as GLint,
+                4,
+                4,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                ptr::null(),
+            );
+        }
+        assert_eq!(backend.check_framebuffer_status(gl::FRAMEBUFFER), gl::FRAMEBUFFER_COMPLETE);
+        assert_eq!(
+            backend.framebuffer_attachment(framebuffer, gl::COLOR_ATTACHMENT0),
+            Some(texture)
+        );
+
+        // Deleting the framebuffer binds the default framebuffer, which can't get attachments
+        backend.delete_framebuffer(framebuffer);
+        assert_eq!(backend.bound_framebuffer(gl::DRAW_FRAMEBUFFER), 0);
+        backend.framebuffer_texture_2d(
+            gl::FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            gl::TEXTURE_2D,
+            texture,
+            0,
+        );
+        assert_eq!(backend.get_error(), gl::INVALID_OPERATION);
+        assert_eq!(backend.get_error(), gl::NO_ERROR);
+    }
+
+    #[test]
+    fn test_recording_fences_and_injected_errors() {
+        let mut backend = RecordingGlBackend::new();
+        let fence = backend.fence_sync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+        unsafe {
+            assert_eq!(backend.client_wait_sync(fence, 0, 0), gl::ALREADY_SIGNALED);
+            backend.block_fences(true);
+            assert_eq!(backend.client_wait_sync(fence, 0, 0), gl::TIMEOUT_EXPIRED);
+            backend.delete_sync(fence);
+            // The recording doesn't dereference fences, so a deleted one is reported
+            assert_eq!(backend.client_wait_sync(fence, 0, 0), gl::WAIT_FAILED);
+        }
+        assert_eq!(backend.get_error(), gl::INVALID_VALUE);
+        assert_eq!(backend.live_objects(), 0);
+
+        // An injected error is raised once, by the first matching call, which has no effect
+        backend.fail_next(|call| matches!(call, GlCall::Enable(gl::BLEND)), gl::OUT_OF_MEMORY);
+        backend.enable(gl::DEPTH_TEST);
+        assert_eq!(backend.get_error(), gl::NO_ERROR);
+        backend.enable(gl::BLEND);
+        assert_eq!(backend.get_error(), gl::OUT_OF_MEMORY);
+        assert!(!backend.is_enabled(gl::BLEND));
+        backend.enable(gl::BLEND);
+        assert_eq!(backend.get_error(), gl::NO_ERROR);
+        assert!(backend.is_enabled(gl::BLEND));
+        assert!(backend
+            .calls()
+            .ends_with(&[GlCall::Enable(gl::BLEND), GlCall::Enable(gl::BLEND)]));
+    }
+
+    #[test]
+    fn test_install_backend_is_scoped() {
+        let backend = RecordingGlBackend::new();
+        {
+            let _guard = install_backend(backend.clone());
+            let buffer = with_backend(|current| current.gen_buffer());
+            with_backend(|current| current.bind_buffer(gl::ARRAY_BUFFER, buffer));
+            assert_eq!(backend.take_calls().len(), 2);
+        }
+        // Calls after the guard was dropped don't reach the recording any more
+        let _guard = install_backend(RecordingGlBackend::new());
+        with_backend(|current| current.enable_vertex_attrib_array(0));
+        assert!(backend.calls().is_empty());
+    }
+}
@@ -0,0 +1,339 @@
+use crate::gl_utils::check_gl_error;
+use anyhow::{Context, Result};
+use gl::types::GLint;
+use image::{imageops, Rgba, RgbaImage};
+use std::env;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Environment variable which makes `check_golden_image` write the rendered images
+/// as new golden images instead of comparing them.
+pub const UPDATE_GOLDEN_IMAGES_VAR: &str = "UPDATE_GOLDEN_IMAGES";
+
+/// The largest possible YIQ difference of two colors.
+const MAX_YIQ_DELTA: f64 = 35215.0;
+
+//////////////////////////////////////////////////////////////////////////////
+// - ImageTolerance -
+//////////////////////////////////////////////////////////////////////////////
+
+/// How much a rendered image may deviate from its golden image.
+///
+/// Colors are compared by their perceived difference in the YIQ color space, so
+/// small changes in dark or saturated colors, which software and hardware
+/// rasterizers tend to disagree about, are tolerated better than changes of the
+/// brightness. A few differing pixels are allowed for anti-aliased edges.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageTolerance {
+    /// Perceived difference from 0.0 to 1.0 above which a pixel counts as different.
+    pub threshold: f64,
+    /// Fraction of pixels which may differ, from 0.0 to 1.0.
+    pub max_differing_fraction: f64,
+}
+
+impl Default for ImageTolerance {
+    fn default() -> Self {
+        Self {
+            threshold: 0.1,
+            max_differing_fraction: 0.001,
+        }
+    }
+}
+
+impl ImageTolerance {
+    /// Requires all pixels to be identical.
+    pub fn exact() -> Self {
+        Self {
+            threshold: 0.0,
+            max_differing_fraction: 0.0,
+        }
+    }
+
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn max_differing_fraction(mut self, max_differing_fraction: f64) -> Self {
+        self.max_differing_fraction = max_differing_fraction;
+        self
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - ImageDifference -
+//////////////////////////////////////////////////////////////////////////////
+
+/// The result of `compare_images`.
+#[derive(Debug, Clone)]
+pub struct ImageDifference {
+    pub differing_pixels: usize,
+    pub total_pixels: usize,
+    /// Largest perceived difference of a pixel, from 0.0 to 1.0.
+    pub max_difference: f64,
+    /// The expected image faded to gray, with the differing pixels in red.
+    pub diff_image: RgbaImage,
+}
+
+impl ImageDifference {
+    pub fn differing_fraction(&self) -> f64 {
+        if self.total_pixels == 0 {
+            0.0
+        } else {
+            self.differing_pixels as f64 / self.total_pixels as f64
+        }
+    }
+
+    pub fn is_within(&self, tolerance: &ImageTolerance) -> bool {
+        self.differing_fraction() <= tolerance.max_differing_fraction
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum GoldenImageError {
+    #[error("Golden image {path:?} doesn't exist, set UPDATE_GOLDEN_IMAGES=1 to create it")]
+    Missing {
+        path: PathBuf,
+    },
+    #[error("Golden image {path:?} is {expected:?}, but the rendered image is {actual:?}")]
+    SizeMismatch {
+        path: PathBuf,
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    #[error(
+        "{differing_pixels} pixels ({percent:.3}%) differ from {path:?}, see {diff_path:?}",
+        percent = .fraction * 100.0
+    )]
+    Mismatch {
+        path: PathBuf,
+        differing_pixels: usize,
+        fraction: f64,
+        diff_path: PathBuf,
+    },
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - Functions -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Reads the color buffer of the bound read framebuffer, with the first row at the
+/// top like in image files.
+///
+/// # Errors
+/// Returns an error if OpenGL reports an error.
+pub fn capture_framebuffer(width: u32, height: u32) -> Result<RgbaImage> {
+    let mut pixels = vec![0u8; width as usize * height as usize * 4];
+    unsafe {
+        let mut pack_alignment: GLint = 0;
+        gl::GetIntegerv(gl::PACK_ALIGNMENT, &mut pack_alignment);
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(
+            0,
+            0,
+            width as GLint,
+            height as GLint,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            pixels.as_mut_ptr() as *mut _,
+        );
+        gl::PixelStorei(gl::PACK_ALIGNMENT, pack_alignment);
+    }
+    check_gl_error().context("Failed to read the framebuffer")?;
+
+    let image = RgbaImage::from_raw(width, height, pixels)
+        .context("The pixel data doesn't match the framebuffer size")?;
+    // OpenGL returns the bottom row first
+    Ok(imageops::flip_vertical(&image))
+}
+
+/// Compares two images of the same size pixel by pixel, see `ImageTolerance`.
+///
+/// # Errors
+/// Returns an error if the sizes of the images differ.
+pub fn compare_images(
+    expected: &RgbaImage,
+    actual: &RgbaImage,
+    tolerance: &ImageTolerance,
+) -> Result<ImageDifference> {
+    if expected.dimensions() != actual.dimensions() {
+        anyhow::bail!(
+            "Can't compare a {:?} image with a {:?} image",
+            expected.dimensions(),
+            actual.dimensions()
+        );
+    }
+
+    let max_delta = MAX_YIQ_DELTA * tolerance.threshold * tolerance.threshold;
+    let mut differing_pixels = 0;
+    let mut max_difference: f64 = 0.0;
+    let mut diff_image = RgbaImage::new(expected.width(), expected.height());
+    for ((expected_pixel, actual_pixel), diff_pixel) in
+        expected.pixels().zip(actual.pixels()).zip(diff_image.pixels_mut())
+    {
+        let delta = yiq_delta(expected_pixel, actual_pixel);
+        max_difference = max_difference.max(delta / MAX_YIQ_DELTA);
+        *diff_pixel = if delta > max_delta {
+            differing_pixels += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            faded_gray(expected_pixel)
+        };
+    }
+
+    Ok(ImageDifference {
+        differing_pixels,
+        total_pixels: expected.width() as usize * expected.height() as usize,
+        max_difference,
+        diff_image,
+    })
+}
+
+/// Compares a rendered image with the golden image at `path`.
+///
+/// If the image differs, it is written next to the golden image with the extension
+/// `.actual.png`, together with a diff image with the extension `.diff.png`. If the
+/// environment variable `UPDATE_GOLDEN_IMAGES` is set, the rendered image replaces
+/// the golden image instead.
+///
+/// # Errors
+/// Returns a `GoldenImageError` if the golden image is missing or differs, or an
+/// error if reading or writing the images fails.
+pub fn check_golden_image<P: AsRef<Path>>(
+    path: P,
+    actual: &RgbaImage,
+    tolerance: &ImageTolerance,
+) -> Result<()> {
+    let path = path.as_ref();
+    if env::var_os(UPDATE_GOLDEN_IMAGES_VAR).is_some() {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        return actual
+            .save(path)
+            .with_context(|| format!("Failed to write the golden image {:?}", path));
+    }
+    if !path.exists() {
+        return Err(GoldenImageError::Missing {
+            path: path.to_path_buf(),
+        }
+        .into());
+    }
+
+    let expected = image::open(path)
+        .with_context(|| format!("Failed to read the golden image {:?}", path))?
+        .to_rgba8();
+    if expected.dimensions() != actual.dimensions() {
+        return Err(GoldenImageError::SizeMismatch {
+            path: path.to_path_buf(),
+            expected: expected.dimensions(),
+            actual: actual.dimensions(),
+        }
+        .into());
+    }
+
+    let difference = compare_images(&expected, actual, tolerance)?;
+    if difference.is_within(tolerance) {
+        return Ok(());
+    }
+    let actual_path = path.with_extension("actual.png");
+    let diff_path = path.with_extension("diff.png");
+    actual.save(&actual_path)?;
+    difference.diff_image.save(&diff_path)?;
+    Err(GoldenImageError::Mismatch {
+        path: path.to_path_buf(),
+        differing_pixels: difference.differing_pixels,
+        fraction: difference.differing_fraction(),
+        diff_path,
+    }
+    .into())
+}
+
+/// Returns the squared perceived difference of two colors, see "Measuring perceived
+/// color difference using YIQ NTSC transmission color space in mobile applications"
+/// by Kotsarenko and Ramos. Transparent colors are blended with white first.
+fn yiq_delta(first: &Rgba<u8>, second: &Rgba<u8>) -> f64 {
+    if first == second {
+        return 0.0;
+    }
+    let [r1, g1, b1] = blend_with_white(first);
+    let [r2, g2, b2] = blend_with_white(second);
+
+    let y = luminance(r1, g1, b1) - luminance(r2, g2, b2);
+    let i = (r1 - r2) * 0.59597799 - (g1 - g2) * 0.2741761 - (b1 - b2) * 0.32180189;
+    let q = (r1 - r2) * 0.21147017 - (g1 - g2) * 0.52261711 + (b1 - b2) * 0.31114694;
+    0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q
+}
+
+fn luminance(r: f64, g: f64, b: f64) -> f64 {
+    r * 0.29889531 + g * 0.58662247 + b * 0.11448223
+}
+
+fn blend_with_white(color: &Rgba<u8>) -> [f64; 3] {
+    let alpha = color[3] as f64 / 255.0;
+    [0, 1, 2].map(|channel| 255.0 + (color[channel] as f64 - 255.0) * alpha)
+}
+
+/// Fades a color to a light gray, which keeps matching areas recognizable in diff
+/// images without distracting from the differences.
+fn faded_gray(color: &Rgba<u8>) -> Rgba<u8> {
+    let [r, g, b] = blend_with_white(color);
+    let gray = 255.0 + (luminance(r, g, b) - 255.0) * 0.1;
+    let gray = gray.round() as u8;
+    Rgba([gray, gray, gray, 255])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkerboard(size: u32) -> RgbaImage {
+        RgbaImage::from_fn(size, size, |x, y| {
+            if (x + y) % 2 == 0 {
+                Rgba([20, 20, 60, 255])
+            } else {
+                Rgba([230, 120, 0, 255])
+            }
+        })
+    }
+
+    #[test]
+    fn test_yiq_delta() {
+        let black = Rgba([0, 0, 0, 255]);
+        let white = Rgba([255, 255, 255, 255]);
+        assert_eq!(yiq_delta(&black, &black), 0.0);
+        let black_to_white = yiq_delta(&black, &white) / MAX_YIQ_DELTA;
+        assert!(black_to_white > 0.9 && black_to_white <= 1.0);
+        // Transparent pixels look white
+        assert_eq!(yiq_delta(&Rgba([0, 0, 0, 0]), &white), 0.0);
+        // Brightness changes weigh more than hue changes of the same size
+        let gray = Rgba([128, 128, 128, 255]);
+        let brighter = Rgba([138, 138, 138, 255]);
+        let bluer = Rgba([128, 128, 138, 255]);
+        assert!(yiq_delta(&gray, &brighter) > yiq_delta(&gray, &bluer));
+    }
+
+    #[test]
+    fn test_compare_images() {
+        let expected = checkerboard(10);
+        let mut actual = expected.clone();
+        // Tiny rasterization differences are tolerated
+        actual.put_pixel(1, 1, Rgba([22, 21, 60, 255]));
+        let difference = compare_images(&expected, &actual, &ImageTolerance::default()).unwrap();
+        assert_eq!(difference.differing_pixels, 0);
+        assert!(difference.max_difference > 0.0);
+
+        actual.put_pixel(5, 4, Rgba([255, 255, 255, 255]));
+        let difference = compare_images(&expected, &actual, &ImageTolerance::default()).unwrap();
+        assert_eq!(difference.differing_pixels, 1);
+        assert_eq!(difference.differing_fraction(), 0.01);
+        assert_eq!(difference.diff_image.get_pixel(5, 4), &Rgba([255, 0, 0, 255]));
+        assert!(!difference.is_within(&ImageTolerance::default()));
+        assert!(difference.is_within(&ImageTolerance::default().max_differing_fraction(0.01)));
+
+        let difference = compare_images(&expected, &actual, &ImageTolerance::exact()).unwrap();
+        assert_eq!(difference.differing_pixels, 2);
+
+        assert!(compare_images(&expected, &checkerboard(8), &ImageTolerance::default()).is_err());
+    }
+}
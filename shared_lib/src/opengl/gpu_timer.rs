@@ -0,0 +1,332 @@
+use crate::opengl::query::{Query, QueryTarget};
+use anyhow::Result;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// Number of frames whose queries are in flight. Results are read two frames after
+/// they were recorded, by which time the GPU has usually finished them.
+const FRAMES_IN_FLIGHT: usize = 2;
+
+//////////////////////////////////////////////////////////////////////////////
+// - GpuTiming -
+//////////////////////////////////////////////////////////////////////////////
+
+/// The GPU time of a named scope, in milliseconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GpuTiming {
+    pub name: String,
+    /// Time of the most recent frame with a result.
+    pub last_ms: f64,
+    /// Average over the frames kept by the timer.
+    pub average_ms: f64,
+    pub max_ms: f64,
+    /// Number of frames the average is based on.
+    pub samples: usize,
+}
+
+impl Display for GpuTiming {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {:.2} ms", self.name, self.average_ms)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - ScopeStats -
+//////////////////////////////////////////////////////////////////////////////
+
+/// The last samples of a scope, one per frame.
+#[derive(Debug, Default)]
+struct ScopeStats {
+    samples: VecDeque<f64>,
+}
+
+impl ScopeStats {
+    fn push(&mut self, milliseconds: f64, max_samples: usize) {
+        while self.samples.len() >= max_samples.max(1) {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(milliseconds);
+    }
+
+    fn timing(&self, name: &str) -> Option<GpuTiming> {
+        let last_ms = *self.samples.back()?;
+        let sum: f64 = self.samples.iter().sum();
+        Some(GpuTiming {
+            name: name.to_string(),
+            last_ms,
+            average_ms: sum / self.samples.len() as f64,
+            max_ms: self.samples.iter().copied().fold(f64::MIN, f64::max),
+            samples: self.samples.len(),
+        })
+    }
+}
+
+/// Sums the durations of equally named scopes, so a scope which is entered several
+/// times per frame yields one sample. The order of first appearance is kept.
+fn sum_by_name<'a>(durations: impl IntoIterator<Item = (&'a str, f64)>) -> Vec<(&'a str, f64)> {
+    let mut sums: Vec<(&str, f64)> = Vec::new();
+    for (name, milliseconds) in durations {
+        match sums.iter_mut().find(|(sum_name, _)| *sum_name == name) {
+            Some((_, sum)) => *sum += milliseconds,
+            None => sums.push((name, milliseconds)),
+        }
+    }
+    sums
+}
+
+fn nanoseconds_to_ms(start: u64, end: u64) -> f64 {
+    end.saturating_sub(start) as f64 / 1_000_000.0
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - GpuTimer -
+//////////////////////////////////////////////////////////////////////////////
+
+/// A started scope, which has to be passed to `GpuTimer::end_scope`.
+#[must_use = "a GPU scope has to be ended with GpuTimer::end_scope"]
+#[derive(Debug)]
+pub struct GpuScope {
+    frame: u64,
+    index: usize,
+}
+
+/// A pair of timestamp queries around a scope.
+#[derive(Debug)]
+struct ScopeQueries {
+    name: String,
+    start: Query,
+    end: Query,
+    ended: bool,
+}
+
+/// The queries recorded in one frame. The queries are kept and reused when the
+/// frame slot comes around again.
+#[derive(Debug, Default)]
+struct FrameQueries {
+    scopes: Vec<ScopeQueries>,
+    used: usize,
+}
+
+/// Measures the GPU time of named scopes with timestamp queries.
+///
+/// Every frame uses its own set of queries, which is read `FRAMES_IN_FLIGHT`
+/// frames later. Results which still aren't available then are dropped instead of
+/// waiting for the GPU, so the timer never stalls the pipeline. Scopes may be
+/// nested and entered several times per frame, their times are summed up per frame
+/// and averaged over the last frames.
+///
+/// # Example
+/// ```ignore
+/// let mut timer = GpuTimer::new(60);
+/// loop {
+///     timer.begin_frame();
+///     let scene_scope = timer.begin_scope("scene draw")?;
+///     scene.draw()?;
+///     let text_scope = timer.begin_scope("text")?;
+///     text_renderer.render_text(&fps_text, position, 1.0)?;
+///     timer.end_scope(text_scope)?;
+///     timer.end_scope(scene_scope)?;
+///     window.swap();
+///     println!("{}", timer.summary());
+/// }
+/// ```
+#[derive(Debug)]
+pub struct GpuTimer {
+    frames: Vec<FrameQueries>,
+    frame: u64,
+    averaging_frames: usize,
+    stats: HashMap<String, ScopeStats>,
+    /// Scope names in order of their first appearance.
+    names: Vec<String>,
+    dropped_samples: usize,
+    enabled: bool,
+}
+
+impl Default for GpuTimer {
+    fn default() -> Self {
+        Self::new(60)
+    }
+}
+
+impl GpuTimer {
+    /// Creates a timer which averages over the last `averaging_frames` frames.
+    /// No GL objects are created until the first scope begins.
+    pub fn new(averaging_frames: usize) -> Self {
+        Self {
+            frames: (0..FRAMES_IN_FLIGHT).map(|_| FrameQueries::default()).collect(),
+            frame: 0,
+            averaging_frames: averaging_frames.max(1),
+            stats: HashMap::new(),
+            names: Vec::new(),
+            dropped_samples: 0,
+            enabled: true,
+        }
+    }
+
+    /// Enables or disables the measurements, disabled timers don't issue queries.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Starts a new frame. Reads the results of the frame which used the same
+    /// queries before, if they are available.
+    pub fn begin_frame(&mut self) {
+        self.frame += 1;
+        let slot = self.slot();
+        let frame_queries = &mut self.frames[slot];
+
+        let mut durations = Vec::new();
+        let mut complete = true;
+        for scope in &frame_queries.scopes[..frame_queries.used] {
+            let start = scope.start.try_result().ok().flatten();
+            let end = scope.end.try_result().ok().flatten();
+            match (start, end, scope.ended) {
+                (Some(start), Some(end), true) => {
+                    durations.push((scope.name.as_str(), nanoseconds_to_ms(start, end)))
+                }
+                _ => complete = false,
+            }
+        }
+        frame_queries.used = 0;
+
+        // A frame with missing results would report too little time
+        if !complete {
+            self.dropped_samples += 1;
+            return;
+        }
+        for (name, milliseconds) in sum_by_name(durations) {
+            if !self.stats.contains_key(name) {
+                self.names.push(name.to_string());
+            }
+            self.stats
+                .entry(name.to_string())
+                .or_default()
+                .push(milliseconds, self.averaging_frames);
+        }
+    }
+
+    /// Records the start of a named scope in the current frame.
+    ///
+    /// # Errors
+    /// Returns an error if OpenGL fails to record the timestamp.
+    pub fn begin_scope(&mut self, name: &str) -> Result<GpuScope> {
+        let slot = self.slot();
+        let frame_queries = &mut self.frames[slot];
+        let index = frame_queries.used;
+        if self.enabled {
+            if index == frame_queries.scopes.len() {
+                frame_queries.scopes.push(ScopeQueries {
+                    name: String::new(),
+                    start: Query::new(QueryTarget::Timestamp),
+                    end: Query::new(QueryTarget::Timestamp),
+                    ended: false,
+                });
+            }
+            let scope = &mut frame_queries.scopes[index];
+            scope.name.clear();
+            scope.name.push_str(name);
+            scope.ended = false;
+            scope.start.record_timestamp()?;
+            frame_queries.used += 1;
+        }
+        Ok(GpuScope {
+            frame: self.frame,
+            index,
+        })
+    }
+
+    /// Records the end of a scope started by `begin_scope`. Scopes which were begun
+    /// in an earlier frame or while the timer was disabled are ignored.
+    ///
+    /// # Errors
+    /// Returns an error if OpenGL fails to record the timestamp.
+    pub fn end_scope(&mut self, scope: GpuScope) -> Result<()> {
+        let slot = self.slot();
+        let frame_queries = &mut self.frames[slot];
+        if scope.frame != self.frame || scope.index >= frame_queries.used {
+            return Ok(());
+        }
+        let queries = &mut frame_queries.scopes[scope.index];
+        queries.end.record_timestamp()?;
+        queries.ended = true;
+        Ok(())
+    }
+
+    /// Returns the timing of a scope, `None` until its first result arrived.
+    pub fn timing(&self, name: &str) -> Option<GpuTiming> {
+        self.stats.get(name)?.timing(name)
+    }
+
+    /// Returns the average GPU time of a scope in milliseconds.
+    pub fn average_ms(&self, name: &str) -> Option<f64> {
+        self.timing(name).map(|timing| timing.average_ms)
+    }
+
+    /// Returns the timings of all scopes with results, in order of their first
+    /// appearance.
+    pub fn timings(&self) -> Vec<GpuTiming> {
+        self.names.iter().filter_map(|name| self.timing(name)).collect()
+    }
+
+    /// Returns the number of frames whose results weren't available in time.
+    pub fn dropped_samples(&self) -> usize {
+        self.dropped_samples
+    }
+
+    /// Formats the averages of all scopes in one line, e.g. for the window title.
+    pub fn summary(&self) -> String {
+        let timings: Vec<String> = self.timings().iter().map(GpuTiming::to_string).collect();
+        timings.join(", ")
+    }
+
+    /// Forgets all collected samples, the queries in flight are kept.
+    pub fn reset(&mut self) {
+        self.stats.clear();
+        self.names.clear();
+        self.dropped_samples = 0;
+    }
+
+    fn slot(&self) -> usize {
+        (self.frame % FRAMES_IN_FLIGHT as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scope_stats_average() {
+        let mut stats = ScopeStats::default();
+        assert!(stats.timing("post").is_none());
+
+        for milliseconds in [4.0, 1.0, 2.0, 3.0] {
+            stats.push(milliseconds, 3);
+        }
+        // The first sample fell out of the window
+        let timing = stats.timing("post").unwrap();
+        assert_eq!(timing.samples, 3);
+        assert_eq!(timing.last_ms, 3.0);
+        assert_eq!(timing.average_ms, 2.0);
+        assert_eq!(timing.max_ms, 3.0);
+        assert_eq!(timing.to_string(), "post: 2.00 ms");
+    }
+
+    #[test]
+    fn test_sum_by_name() {
+        let durations = [("scene draw", 2.0), ("text", 0.25), ("scene draw", 1.0)];
+        assert_eq!(sum_by_name(durations), vec![("scene draw", 3.0), ("text", 0.25)]);
+    }
+
+    #[test]
+    fn test_nanoseconds_to_ms() {
+        assert_eq!(nanoseconds_to_ms(1_000_000, 3_500_000), 2.5);
+        // Timestamps of a reset clock don't produce negative times
+        assert_eq!(nanoseconds_to_ms(10, 5), 0.0);
+    }
+}
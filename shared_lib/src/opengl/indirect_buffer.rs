@@ -0,0 +1,131 @@
+use crate::gl_draw::IndirectCommand;
+use crate::gl_traits::Bindable;
+use crate::gl_types::{BufferType, BufferUsage};
+use crate::gl_utils::{cached_capability, gl_has_extension, gl_version_at_least};
+use crate::opengl::buffer_object::BufferObject;
+use crate::opengl::gl_backend::with_backend;
+use anyhow::{anyhow, Result};
+
+/// Checks if the current context can draw the commands of an `IndirectBuffer`
+/// with a single multi-draw call (OpenGL 4.3 or `GL_ARB_multi_draw_indirect`).
+pub fn supports_multi_draw_indirect() -> bool {
+    cached_capability("multi_draw_indirect", || {
+        let has_multi_draw_indirect =
+            gl_version_at_least(4, 3) || gl_has_extension("GL_ARB_multi_draw_indirect");
+        has_multi_draw_indirect
+            && with_backend(|backend| backend.is_function_loaded("glMultiDrawElementsIndirect"))
+    })
+}
+
+/// Checks if the current context can draw commands with a base instance other than
+/// 0 (OpenGL 4.2 or `GL_ARB_base_instance`).
+pub fn supports_base_instance() -> bool {
+    cached_capability("base_instance", || {
+        gl_version_at_least(4, 2) || gl_has_extension("GL_ARB_base_instance")
+    })
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - IndirectBuffer -
+//////////////////////////////////////////////////////////////////////////////
+
+/// A `DrawIndirectBuffer` holding draw commands, which are read by the GPU.
+///
+/// The commands are drawn with `gl_draw::multi_draw_elements_indirect`,
+/// `gl_draw::multi_draw_arrays_indirect` or `VertexArrayObject::multi_draw_indirect`.
+/// A CPU copy of the commands is kept, which is used to draw them one by one if the
+/// context doesn't support multi-draw indirect. Commands with a base instance can
+/// only be drawn this way if the context supports base instances, which OpenGL 3.3
+/// doesn't, see `supports_base_instance`.
+///
+/// # Example
+/// ```ignore
+/// let mut commands = IndirectBuffer::new(BufferUsage::DynamicDraw);
+/// for (index, mesh) in meshes.iter().enumerate() {
+///     commands.push(
+///         DrawElementsIndirectCommand::new(mesh.index_count)
+///             .first_index(mesh.first_index)
+///             .base_vertex(mesh.first_vertex as i32)
+///             .base_instance(index as u32),
+///     )?;
+/// }
+/// vao.multi_draw_indirect(PrimitiveType::Triangles, &commands)?;
+/// ```
+pub struct IndirectBuffer<C: IndirectCommand> {
+    buffer: BufferObject<C>,
+}
+
+impl<C: IndirectCommand> IndirectBuffer<C> {
+    /// Creates an empty indirect buffer.
+    pub fn new(usage: BufferUsage) -> Self {
+        Self::with_commands(usage, Vec::new())
+    }
+
+    /// Creates an indirect buffer and uploads the given commands.
+    pub fn with_commands(usage: BufferUsage, commands: Vec<C>) -> Self {
+        Self {
+            buffer: BufferObject::new(BufferType::DrawIndirectBuffer, usage, commands),
+        }
+    }
+
+    /// Replaces all commands of the buffer.
+    pub fn set_commands(&mut self, commands: Vec<C>) {
+        self.buffer.update_data(commands, None);
+    }
+
+    /// Appends a command to the buffer.
+    pub fn push(&mut self, command: C) -> Result<()> {
+        self.buffer.update_range(self.buffer.data_len(), &[command])
+    }
+
+    /// Replaces the command at `index`.
+    ///
+    /// # Errors
+    /// Returns an error if there is no command at `index`.
+    pub fn update_command(&mut self, index: usize, command: C) -> Result<()> {
+        if index >= self.len() {
+            return Err(anyhow!(
+                "Command index {} is out of range, the buffer contains {} commands",
+                index,
+                self.len()
+            ));
+        }
+        self.buffer.update_range(index, &[command])
+    }
+
+    /// Removes all commands, while keeping the GPU storage.
+    pub fn clear(&mut self) {
+        self.buffer.update_data(Vec::new(), None);
+    }
+
+    /// Returns the CPU copy of the commands.
+    pub fn commands(&self) -> &[C] {
+        self.buffer.data()
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.data_len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn buffer(&self) -> &BufferObject<C> {
+        &self.buffer
+    }
+}
+
+impl<C: IndirectCommand> Bindable for IndirectBuffer<C> {
+    fn bind(&self) -> Result<()> {
+        self.buffer.bind()
+    }
+
+    fn unbind(&self) -> Result<()> {
+        self.buffer.unbind()
+    }
+
+    fn is_bound(&self) -> Result<bool> {
+        self.buffer.is_bound()
+    }
+}
@@ -0,0 +1,187 @@
+use crate::opengl::pipeline_state::{ColorMask, DepthFunc};
+use crate::opengl::query::{ConditionalRender, ConditionalRenderMode, Query, QueryTarget};
+use crate::opengl::state_guard::{ColorMaskGuard, DepthGuard};
+use anyhow::{anyhow, Context, Result};
+
+//////////////////////////////////////////////////////////////////////////////
+// - Visibility -
+//////////////////////////////////////////////////////////////////////////////
+
+/// What is known about the visibility of an object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    /// No query result was read yet, the object has to be treated as visible.
+    Unknown,
+    /// The number of samples of the proxy that passed the depth test. For
+    /// `AnySamplesPassed` targets this is 0 or 1.
+    Samples(u64),
+}
+
+impl Visibility {
+    /// Checks if the object should be drawn, objects with fewer than `min_samples`
+    /// samples count as hidden. Unknown visibility always counts as visible.
+    pub fn is_visible(&self, min_samples: u64) -> bool {
+        match self {
+            Visibility::Unknown => true,
+            Visibility::Samples(samples) => *samples >= min_samples.max(1),
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - OcclusionCuller -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Skips objects hidden behind others by drawing a cheap proxy, usually the bounding
+/// box, with an occlusion query per object.
+///
+/// Results are never waited for. The proxies drawn in one frame decide what is drawn
+/// in one of the following frames, so objects can pop in a frame late when they
+/// become visible. A new query is only started for an object after the result of its
+/// previous one was read, so a slow GPU just makes visibility update less often.
+///
+/// # Example
+/// ```ignore
+/// let mut culler = OcclusionCuller::new(QueryTarget::AnySamplesPassed, cubes.len())?;
+///
+/// // Every frame, after the occluders were drawn
+/// culler.update_results()?;
+/// for (index, cube) in cubes.iter().enumerate() {
+///     if culler.is_visible(index) {
+///         cube.draw()?;
+///     }
+/// }
+/// culler.draw_proxies(|index| bounding_box.draw_at(cubes[index].position))?;
+/// ```
+pub struct OcclusionCuller {
+    target: QueryTarget,
+    queries: Vec<Query>,
+    visibility: Vec<Visibility>,
+    min_samples: u64,
+}
+
+impl OcclusionCuller {
+    /// Creates a culler for `count` objects.
+    ///
+    /// # Errors
+    /// Returns an error if `target` doesn't count samples.
+    pub fn new(target: QueryTarget, count: usize) -> Result<Self> {
+        if !target.is_occlusion() {
+            return Err(anyhow!("{:?} can't be used for occlusion culling", target));
+        }
+        let mut culler = Self {
+            target,
+            queries: Vec::new(),
+            visibility: Vec::new(),
+            min_samples: 1,
+        };
+        culler.resize(count);
+        Ok(culler)
+    }
+
+    /// Sets the number of samples an object needs to count as visible. Only useful
+    /// with `QueryTarget::SamplesPassed`, to skip objects covering a few pixels.
+    pub fn min_samples(mut self, min_samples: u64) -> Self {
+        self.min_samples = min_samples.max(1);
+        self
+    }
+
+    /// Changes the number of objects. New objects have unknown visibility.
+    pub fn resize(&mut self, count: usize) {
+        self.queries.truncate(count);
+        self.visibility.truncate(count);
+        while self.queries.len() < count {
+            self.queries.push(Query::new(self.target));
+            self.visibility.push(Visibility::Unknown);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.queries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queries.is_empty()
+    }
+
+    /// Reads the results of all finished queries without waiting for the others.
+    pub fn update_results(&mut self) -> Result<()> {
+        for (query, visibility) in self.queries.iter().zip(self.visibility.iter_mut()) {
+            if let Some(samples) = query.try_result()? {
+                *visibility = Visibility::Samples(samples);
+            }
+        }
+        Ok(())
+    }
+
+    /// Draws the proxy of every object whose previous query result was read, with
+    /// color and depth writes disabled. `draw_proxy` is called with the index of the
+    /// object and has to draw its proxy with the program and buffers of the caller.
+    ///
+    /// The depth test uses `LessEqual`, so proxies should be drawn after the
+    /// occluders of the frame and slightly larger than the objects they stand for.
+    pub fn draw_proxies<F>(&mut self, mut draw_proxy: F) -> Result<()>
+    where
+        F: FnMut(usize) -> Result<()>,
+    {
+        let _color_mask = ColorMaskGuard::new(ColorMask::NONE)?;
+        let _depth = DepthGuard::new(true, false, DepthFunc::LessEqual)?;
+        for (index, query) in self.queries.iter().enumerate() {
+            if query.is_pending() {
+                continue;
+            }
+            query.begin()?;
+            let drawn = draw_proxy(index);
+            query.end()?;
+            drawn.with_context(|| format!("Failed to draw the proxy of object {}", index))?;
+        }
+        Ok(())
+    }
+
+    /// The visibility known from the last result read for the object.
+    pub fn visibility(&self, index: usize) -> Visibility {
+        self.visibility.get(index).copied().unwrap_or(Visibility::Unknown)
+    }
+
+    /// Checks if the object should be drawn, see `Visibility::is_visible`.
+    pub fn is_visible(&self, index: usize) -> bool {
+        self.visibility(index).is_visible(self.min_samples)
+    }
+
+    /// Number of objects which should be drawn.
+    pub fn visible_count(&self) -> usize {
+        (0..self.len()).filter(|&index| self.is_visible(index)).count()
+    }
+
+    /// Lets the GPU decide if the object is drawn, based on its latest proxy query.
+    /// Unlike `is_visible` this uses results which weren't read back yet, but the
+    /// draw calls are still submitted. Returns `None` if the proxy was never drawn.
+    pub fn conditional_render(
+        &self,
+        index: usize,
+        mode: ConditionalRenderMode,
+    ) -> Result<Option<ConditionalRender<'_>>> {
+        let query = self
+            .queries
+            .get(index)
+            .ok_or_else(|| anyhow!("No object with index {}", index))?;
+        if !query.has_ended() {
+            return Ok(None);
+        }
+        query.begin_conditional_render(mode).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_visibility() {
+        assert!(Visibility::Unknown.is_visible(100));
+        assert!(!Visibility::Samples(0).is_visible(0));
+        assert!(Visibility::Samples(1).is_visible(1));
+        assert!(!Visibility::Samples(40).is_visible(50));
+        assert!(Visibility::Samples(50).is_visible(50));
+    }
+}
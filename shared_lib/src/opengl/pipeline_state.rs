@@ -0,0 +1,603 @@
+use crate::gl_types::Capability;
+use crate::gl_utils::check_gl_error;
+use crate::opengl::blend_guard::SeparateBlend;
+use crate::opengl::gl_backend::{with_backend, GlBackend};
+use anyhow::{Context, Result};
+use gl::types::{GLenum, GLint, GLsizei};
+
+//////////////////////////////////////////////////////////////////////////////
+// - DepthFunc -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Comparison used by the depth test, the incoming depth is on the left side.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum DepthFunc {
+    Never,
+    #[default]
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    Always,
+}
+
+impl DepthFunc {
+    pub fn to_gl_enum(&self) -> GLenum {
+        match self {
+            DepthFunc::Never => gl::NEVER,
+            DepthFunc::Less => gl::LESS,
+            DepthFunc::Equal => gl::EQUAL,
+            DepthFunc::LessEqual => gl::LEQUAL,
+            DepthFunc::Greater => gl::GREATER,
+            DepthFunc::NotEqual => gl::NOTEQUAL,
+            DepthFunc::GreaterEqual => gl::GEQUAL,
+            DepthFunc::Always => gl::ALWAYS,
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - CullMode -
+//////////////////////////////////////////////////////////////////////////////
+
+/// The faces discarded by face culling.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CullMode {
+    Front,
+    #[default]
+    Back,
+    FrontAndBack,
+}
+
+impl CullMode {
+    pub fn to_gl_enum(&self) -> GLenum {
+        match self {
+            CullMode::Front => gl::FRONT,
+            CullMode::Back => gl::BACK,
+            CullMode::FrontAndBack => gl::FRONT_AND_BACK,
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - FrontFace -
+//////////////////////////////////////////////////////////////////////////////
+
+/// The winding order of front facing polygons in window coordinates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum FrontFace {
+    #[default]
+    CounterClockwise,
+    Clockwise,
+}
+
+impl FrontFace {
+    pub fn to_gl_enum(&self) -> GLenum {
+        match self {
+            FrontFace::CounterClockwise => gl::CCW,
+            FrontFace::Clockwise => gl::CW,
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - PolygonMode -
+//////////////////////////////////////////////////////////////////////////////
+
+/// How polygons are rasterized, applied to front and back faces alike.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PolygonMode {
+    #[default]
+    Fill,
+    Line,
+    Point,
+}
+
+impl PolygonMode {
+    pub fn to_gl_enum(&self) -> GLenum {
+        match self {
+            PolygonMode::Fill => gl::FILL,
+            PolygonMode::Line => gl::LINE,
+            PolygonMode::Point => gl::POINT,
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - ColorMask -
+//////////////////////////////////////////////////////////////////////////////
+
+/// The color channels written by fragments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ColorMask {
+    pub red: bool,
+    pub green: bool,
+    pub blue: bool,
+    pub alpha: bool,
+}
+
+impl ColorMask {
+    pub const ALL: ColorMask = ColorMask::new(true, true, true, true);
+    pub const NONE: ColorMask = ColorMask::new(false, false, false, false);
+
+    pub const fn new(red: bool, green: bool, blue: bool, alpha: bool) -> Self {
+        Self {
+            red,
+            green,
+            blue,
+            alpha,
+        }
+    }
+}
+
+impl Default for ColorMask {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - ScissorRect -
+//////////////////////////////////////////////////////////////////////////////
+
+/// The scissor rectangle in window coordinates, with the origin in the lower left.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ScissorRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ScissorRect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Returns the overlap of both rectangles, which has a zero size if they don't
+    /// overlap.
+    pub fn intersect(&self, other: &ScissorRect) -> ScissorRect {
+        let left = self.x.max(other.x);
+        let bottom = self.y.max(other.y);
+        let right = (self.x + self.width as i32).min(other.x + other.width as i32);
+        let top = (self.y + self.height as i32).min(other.y + other.height as i32);
+        ScissorRect::new(left, bottom, (right - left).max(0) as u32, (top - bottom).max(0) as u32)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - PipelineState -
+//////////////////////////////////////////////////////////////////////////////
+
+/// An immutable description of the fixed function state used for drawing.
+///
+/// The default value matches the initial state of an OpenGL context. States are
+/// built by chaining the builder methods and applied through a `StateCache`, which
+/// only issues the GL calls for the parts that differ from the last applied state.
+///
+/// # Example
+/// ```ignore
+/// let opaque = PipelineState::default()
+///     .depth_test(true)
+///     .cull_mode(Some(CullMode::Back));
+/// let transparent = opaque
+///     .depth_write(false)
+///     .blend(Some(SeparateBlend::new(
+///         gl::SRC_ALPHA,
+///         gl::ONE_MINUS_SRC_ALPHA,
+///         gl::ONE,
+///         gl::ONE_MINUS_SRC_ALPHA,
+///     )));
+///
+/// state_cache.apply(&opaque)?;
+/// draw_opaque_models()?;
+/// // Only glDepthMask, glEnable(GL_BLEND) and glBlendFuncSeparate are called
+/// state_cache.apply(&transparent)?;
+/// draw_transparent_models()?;
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PipelineState {
+    depth_test: bool,
+    depth_write: bool,
+    depth_func: DepthFunc,
+    cull_mode: Option<CullMode>,
+    front_face: FrontFace,
+    blend: Option<SeparateBlend>,
+    polygon_mode: PolygonMode,
+    color_mask: ColorMask,
+    scissor: Option<ScissorRect>,
+}
+
+impl Default for PipelineState {
+    fn default() -> Self {
+        Self {
+            depth_test: false,
+            depth_write: true,
+            depth_func: DepthFunc::default(),
+            cull_mode: None,
+            front_face: FrontFace::default(),
+            blend: None,
+            polygon_mode: PolygonMode::default(),
+            color_mask: ColorMask::default(),
+            scissor: None,
+        }
+    }
+}
+
+impl PipelineState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn depth_test(mut self, enabled: bool) -> Self {
+        self.depth_test = enabled;
+        self
+    }
+
+    pub fn depth_write(mut self, enabled: bool) -> Self {
+        self.depth_write = enabled;
+        self
+    }
+
+    pub fn depth_func(mut self, depth_func: DepthFunc) -> Self {
+        self.depth_func = depth_func;
+        self
+    }
+
+    /// Sets the culled faces, `None` disables face culling.
+    pub fn cull_mode(mut self, cull_mode: Option<CullMode>) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn front_face(mut self, front_face: FrontFace) -> Self {
+        self.front_face = front_face;
+        self
+    }
+
+    /// Sets the blend functions, `None` disables blending.
+    pub fn blend(mut self, blend: Option<SeparateBlend>) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn polygon_mode(mut self, polygon_mode: PolygonMode) -> Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn color_mask(mut self, color_mask: ColorMask) -> Self {
+        self.color_mask = color_mask;
+        self
+    }
+
+    /// Sets the scissor rectangle, `None` disables the scissor test.
+    pub fn scissor(mut self, scissor: Option<ScissorRect>) -> Self {
+        self.scissor = scissor;
+        self
+    }
+
+    pub fn is_depth_test_enabled(&self) -> bool {
+        self.depth_test
+    }
+
+    pub fn is_depth_write_enabled(&self) -> bool {
+        self.depth_write
+    }
+
+    pub fn get_depth_func(&self) -> DepthFunc {
+        self.depth_func
+    }
+
+    pub fn get_cull_mode(&self) -> Option<CullMode> {
+        self.cull_mode
+    }
+
+    pub fn get_front_face(&self) -> FrontFace {
+        self.front_face
+    }
+
+    pub fn get_blend(&self) -> Option<SeparateBlend> {
+        self.blend
+    }
+
+    pub fn get_polygon_mode(&self) -> PolygonMode {
+        self.polygon_mode
+    }
+
+    pub fn get_color_mask(&self) -> ColorMask {
+        self.color_mask
+    }
+
+    pub fn get_scissor(&self) -> Option<ScissorRect> {
+        self.scissor
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - StateCache -
+//////////////////////////////////////////////////////////////////////////////
+
+/// The GL state as far as the cache knows it, `None` means unknown.
+///
+/// The parameters of disabled features are tracked separately from the enable
+/// flags, because OpenGL keeps them while the feature is disabled.
+#[derive(Debug, Default)]
+struct KnownState {
+    depth_test: Option<bool>,
+    depth_write: Option<bool>,
+    depth_func: Option<DepthFunc>,
+    cull_face: Option<bool>,
+    cull_mode: Option<CullMode>,
+    front_face: Option<FrontFace>,
+    blend: Option<bool>,
+    blend_func: Option<SeparateBlend>,
+    polygon_mode: Option<PolygonMode>,
+    color_mask: Option<ColorMask>,
+    scissor_test: Option<bool>,
+    scissor: Option<ScissorRect>,
+}
+
+/// Stores `value` and returns `true` if it differs from the known value.
+fn update<T: PartialEq + Copy>(known: &mut Option<T>, value: T) -> bool {
+    if *known == Some(value) {
+        return false;
+    }
+    *known = Some(value);
+    true
+}
+
+/// Enables or disables `capability`.
+fn set_capability(backend: &mut dyn GlBackend, capability: Capability, enabled: bool) {
+    if enabled {
+        backend.enable(capability.to_gl_enum());
+    } else {
+        backend.disable(capability.to_gl_enum());
+    }
+}
+
+/// Applies `PipelineState`s and skips the GL calls for state which is already set.
+///
+/// The parameters of disabled features, like the depth function with a disabled
+/// depth test, are only set once the feature is enabled. The cache assumes that it
+/// is the only one changing these states; call `invalidate` after other code, e.g.
+/// a `BlendGuard`, changed them behind its back.
+#[derive(Debug, Default)]
+pub struct StateCache {
+    known: KnownState,
+    current: Option<PipelineState>,
+}
+
+impl StateCache {
+    /// Returns the last applied state, `None` before the first `apply` and after
+    /// `invalidate`.
+    pub fn current(&self) -> Option<&PipelineState> {
+        self.current.as_ref()
+    }
+
+    /// Forgets the known GL state, so the next `apply` issues all calls.
+    pub fn invalidate(&mut self) {
+        self.known = KnownState::default();
+        self.current = None;
+    }
+
+    /// Makes `state` the current GL state, issuing only the calls which change it.
+    ///
+    /// # Errors
+    /// Returns an error if OpenGL reports an error for the issued calls.
+    pub fn apply(&mut self, state: &PipelineState) -> Result<()> {
+        if self.current.as_ref() == Some(state) {
+            return Ok(());
+        }
+        let known = &mut self.known;
+
+        with_backend(|backend| {
+            if update(&mut known.depth_test, state.depth_test) {
+                set_capability(backend, Capability::DepthTest, state.depth_test);
+            }
+            if update(&mut known.depth_write, state.depth_write) {
+                backend.depth_mask(state.depth_write);
+            }
+            if state.depth_test && update(&mut known.depth_func, state.depth_func) {
+                backend.depth_func(state.depth_func.to_gl_enum());
+            }
+
+            if update(&mut known.cull_face, state.cull_mode.is_some()) {
+                set_capability(backend, Capability::CullFace, state.cull_mode.is_some());
+            }
+            if let Some(cull_mode) = state.cull_mode {
+                if update(&mut known.cull_mode, cull_mode) {
+                    backend.cull_face(cull_mode.to_gl_enum());
+                }
+            }
+            if update(&mut known.front_face, state.front_face) {
+                backend.front_face(state.front_face.to_gl_enum());
+            }
+
+            if update(&mut known.blend, state.blend.is_some()) {
+                set_capability(backend, Capability::Blend, state.blend.is_some());
+            }
+            if let Some(blend) = state.blend {
+                if update(&mut known.blend_func, blend) {
+                    backend.blend_func_separate(
+                        blend.src_rgb,
+                        blend.dest_rgb,
+                        blend.src_alpha,
+                        blend.dest_alpha,
+                    );
+                }
+            }
+
+            if update(&mut known.polygon_mode, state.polygon_mode) {
+                backend.polygon_mode(gl::FRONT_AND_BACK, state.polygon_mode.to_gl_enum());
+            }
+            if update(&mut known.color_mask, state.color_mask) {
+                let mask = state.color_mask;
+                backend.color_mask(mask.red, mask.green, mask.blue, mask.alpha);
+            }
+
+            if update(&mut known.scissor_test, state.scissor.is_some()) {
+                set_capability(backend, Capability::ScissorTest, state.scissor.is_some());
+            }
+            if let Some(scissor) = state.scissor {
+                if update(&mut known.scissor, scissor) {
+                    backend.scissor(
+                        scissor.x as GLint,
+                        scissor.y as GLint,
+                        scissor.width as GLsizei,
+                        scissor.height as GLsizei,
+                    );
+                }
+            }
+        });
+
+        self.current = Some(*state);
+        check_gl_error().context("Failed to apply pipeline state")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opengl::gl_backend::{install_backend, GlCall, RecordingGlBackend};
+
+    fn alpha_blend() -> SeparateBlend {
+        SeparateBlend::new(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA, gl::ONE, gl::ONE_MINUS_SRC_ALPHA)
+    }
+
+    fn blend_func_call(blend: SeparateBlend) -> GlCall {
+        GlCall::BlendFuncSeparate {
+            src_rgb: blend.src_rgb,
+            dest_rgb: blend.dest_rgb,
+            src_alpha: blend.src_alpha,
+            dest_alpha: blend.dest_alpha,
+        }
+    }
+
+    #[test]
+    fn test_first_apply_sets_everything() {
+        let backend = RecordingGlBackend::new();
+        let _guard = install_backend(backend.clone());
+        let mut cache = StateCache::default();
+        cache.apply(&PipelineState::default()).unwrap();
+        assert_eq!(
+            backend.take_calls(),
+            vec![
+                GlCall::Disable(gl::DEPTH_TEST),
+                GlCall::DepthMask(true),
+                GlCall::Disable(gl::CULL_FACE),
+                GlCall::FrontFace(gl::CCW),
+                GlCall::Disable(gl::BLEND),
+                GlCall::PolygonMode {
+                    face: gl::FRONT_AND_BACK,
+                    mode: gl::FILL,
+                },
+                GlCall::ColorMask([true; 4]),
+                GlCall::Disable(gl::SCISSOR_TEST),
+            ]
+        );
+
+        // Applying the same state again is free
+        cache.apply(&PipelineState::default()).unwrap();
+        assert!(backend.calls().is_empty());
+    }
+
+    #[test]
+    fn test_only_changes_are_issued() {
+        let backend = RecordingGlBackend::new();
+        let _guard = install_backend(backend.clone());
+        let mut cache = StateCache::default();
+        let opaque = PipelineState::new().depth_test(true).cull_mode(Some(CullMode::Back));
+        cache.apply(&opaque).unwrap();
+        backend.take_calls();
+
+        let transparent = opaque.depth_write(false).blend(Some(alpha_blend()));
+        cache.apply(&transparent).unwrap();
+        assert_eq!(
+            backend.take_calls(),
+            vec![
+                GlCall::DepthMask(false),
+                GlCall::Enable(gl::BLEND),
+                blend_func_call(alpha_blend()),
+            ]
+        );
+        assert!(backend.is_enabled(gl::BLEND));
+
+        cache.apply(&opaque).unwrap();
+        assert_eq!(backend.take_calls(), vec![GlCall::DepthMask(true), GlCall::Disable(gl::BLEND)]);
+        assert_eq!(cache.current(), Some(&opaque));
+    }
+
+    #[test]
+    fn test_disabled_parameters_are_kept() {
+        let backend = RecordingGlBackend::new();
+        let _guard = install_backend(backend.clone());
+        let mut cache = StateCache::default();
+        let culled = PipelineState::new().cull_mode(Some(CullMode::Front));
+        cache.apply(&culled).unwrap();
+        cache.apply(&PipelineState::new()).unwrap();
+        backend.take_calls();
+
+        // GL still knows the cull face, only the capability has to be enabled
+        cache.apply(&culled).unwrap();
+        assert_eq!(backend.take_calls(), vec![GlCall::Enable(gl::CULL_FACE)]);
+        assert_eq!(backend.state(gl::CULL_FACE_MODE)[0], gl::FRONT as GLint);
+
+        // The depth function is only set while the depth test is enabled
+        let greater = PipelineState::new().depth_func(DepthFunc::Greater);
+        cache.apply(&greater).unwrap();
+        assert_eq!(backend.take_calls(), vec![GlCall::Disable(gl::CULL_FACE)]);
+        cache.apply(&greater.depth_test(true)).unwrap();
+        assert_eq!(
+            backend.take_calls(),
+            vec![GlCall::Enable(gl::DEPTH_TEST), GlCall::DepthFunc(gl::GREATER)]
+        );
+    }
+
+    #[test]
+    fn test_scissor_and_invalidate() {
+        let backend = RecordingGlBackend::new();
+        let _guard = install_backend(backend.clone());
+        let mut cache = StateCache::default();
+        let clipped = PipelineState::new()
+            .scissor(Some(ScissorRect::new(10, 20, 300, 200)))
+            .polygon_mode(PolygonMode::Line)
+            .color_mask(ColorMask::new(true, true, true, false));
+        cache.apply(&clipped).unwrap();
+        backend.take_calls();
+
+        let moved = clipped.scissor(Some(ScissorRect::new(0, 0, 300, 200)));
+        cache.apply(&moved).unwrap();
+        assert_eq!(
+            backend.take_calls(),
+            vec![GlCall::Scissor {
+                x: 0,
+                y: 0,
+                width: 300,
+                height: 200,
+            }]
+        );
+
+        cache.invalidate();
+        assert!(cache.current().is_none());
+        cache.apply(&moved).unwrap();
+        assert_eq!(backend.calls().len(), 9);
+    }
+
+    #[test]
+    fn test_apply_reports_gl_errors() {
+        let backend = RecordingGlBackend::new();
+        let _guard = install_backend(backend.clone());
+        let mut cache = StateCache::default();
+        backend.fail_next(|call| matches!(call, GlCall::PolygonMode { .. }), gl::INVALID_ENUM);
+        let error = cache.apply(&PipelineState::new()).unwrap_err();
+        assert!(format!("{:#}", error).contains("Failed to apply pipeline state"));
+    }
+}
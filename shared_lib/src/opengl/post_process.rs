@@ -0,0 +1,438 @@
+use crate::gl_draw::DrawCommand;
+use crate::gl_types::{PrimitiveType, RenderMask, ShaderType};
+use crate::opengl::framebuffer::{AttachmentFormat, AttachmentStorage, BlitFilter, Framebuffer};
+use crate::opengl::shader_program::ShaderProgram;
+use crate::opengl::state_guard::{BlendStateGuard, CullGuard, DepthGuard};
+use crate::opengl::vertex_array_object::VertexArrayObject;
+use crate::sdl_window::SdlWindow;
+use crate::RenderTarget;
+use anyhow::{anyhow, Context, Result};
+use std::any::Any;
+
+pub mod bloom;
+pub mod chromatic_aberration;
+pub mod color_filter;
+pub mod film_grain;
+pub mod fxaa;
+pub mod tonemap;
+pub mod vignette;
+
+use bloom::Bloom;
+use chromatic_aberration::ChromaticAberration;
+use color_filter::ColorFilter;
+use film_grain::FilmGrain;
+use fxaa::Fxaa;
+use tonemap::Tonemap;
+use vignette::Vignette;
+
+/// Vertex shader shared by all passes. It covers the screen with a single triangle
+/// built from `gl_VertexID`, so no vertex buffer is needed, and passes the texture
+/// coordinates of the screen to the fragment shader as `v_uv`.
+pub const FULLSCREEN_VERTEX_SHADER: &str = r#"#version 330 core
+out vec2 v_uv;
+
+void main() {
+    // Vertex 0, 1 and 2 become (0, 0), (2, 0) and (0, 2)
+    vec2 position = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+    v_uv = position;
+    gl_Position = vec4(position * 2.0 - 1.0, 0.0, 1.0);
+}
+"#;
+
+/// Compiles a pass program from `FULLSCREEN_VERTEX_SHADER` and `fragment_source`.
+/// Missing uniforms are ignored, so passes can set the common uniforms whether
+/// their shader uses them or not.
+pub fn compile_pass_program(fragment_source: &str) -> Result<ShaderProgram> {
+    let mut program = ShaderProgram::new();
+    program.add_source(ShaderType::Vertex, FULLSCREEN_VERTEX_SHADER)?;
+    program.add_source(ShaderType::Fragment, fragment_source)?;
+    program.compile()?;
+    program.set_ignore_missing_uniforms(true);
+    Ok(program)
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - FullscreenTriangle -
+//////////////////////////////////////////////////////////////////////////////
+
+/// The vertex array object all passes draw their fullscreen triangle with.
+pub struct FullscreenTriangle {
+    vao: VertexArrayObject,
+}
+
+impl FullscreenTriangle {
+    pub fn new() -> Self {
+        Self {
+            vao: VertexArrayObject::default(),
+        }
+    }
+
+    /// Runs `program` for every pixel of `output`. The color texture `0` of `input`
+    /// is bound to texture unit 0 as `u_input`, and `u_texel_size` is set to the size
+    /// of one of its pixels in texture coordinates. `program` has to be active.
+    pub fn draw(
+        &self,
+        program: &ShaderProgram,
+        input: &Framebuffer,
+        output: &Framebuffer,
+    ) -> Result<()> {
+        let _binding = output.bind()?;
+        input.bind_color_texture(0, 0)?;
+        program.set_uniform("u_input", 0)?;
+        program.set_uniform("u_texel_size", texel_size(input))?;
+        self.vao
+            .draw(DrawCommand::arrays(PrimitiveType::Triangles, 3))
+            .with_context(|| {
+                format!("Failed to draw the fullscreen triangle to {:?}", output.label())
+            })
+    }
+}
+
+impl Default for FullscreenTriangle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The size of one pixel of `framebuffer` in texture coordinates.
+pub fn texel_size(framebuffer: &Framebuffer) -> (f32, f32) {
+    (1.0 / framebuffer.width() as f32, 1.0 / framebuffer.height() as f32)
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - PingPongTargets -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Two framebuffers of the same size and format, which passes alternately read
+/// from and render to.
+pub struct PingPongTargets {
+    targets: [Framebuffer; 2],
+}
+
+impl PingPongTargets {
+    pub fn new(label: &str, width: u32, height: u32, format: AttachmentFormat) -> Result<Self> {
+        let create = |index: usize| {
+            Framebuffer::builder(width, height)
+                .label(&format!("{} {}", label, index))
+                .color_texture(format)
+                .build()
+        };
+        Ok(Self {
+            targets: [create(0)?, create(1)?],
+        })
+    }
+
+    pub fn get(&self, index: usize) -> &Framebuffer {
+        &self.targets[index % 2]
+    }
+
+    pub fn width(&self) -> u32 {
+        self.targets[0].width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.targets[0].height()
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<()> {
+        for target in &mut self.targets {
+            target.resize(width, height)?;
+        }
+        Ok(())
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - PostProcessPass -
+//////////////////////////////////////////////////////////////////////////////
+
+/// What a pass needs besides its input and output.
+pub struct PassFrame<'a> {
+    pub fullscreen: &'a FullscreenTriangle,
+    /// Elapsed time in seconds, for animated effects.
+    pub time: f32,
+}
+
+/// A fullscreen effect reading the color texture `0` of `input` and rendering to
+/// `output`. Passes own their programs and keep their parameters in public fields,
+/// which can be changed at any time.
+pub trait PostProcessPass {
+    /// Unique name of the pass in a chain, e.g. `"bloom"`.
+    fn name(&self) -> &'static str;
+
+    fn is_enabled(&self) -> bool;
+
+    fn set_enabled(&mut self, enabled: bool);
+
+    fn apply(&mut self, frame: &PassFrame, input: &Framebuffer, output: &Framebuffer)
+        -> Result<()>;
+
+    /// Allows `PostProcessChain::pass_mut` to return the concrete pass.
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - PostProcessChain -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Where a pass of the chain reads from or renders to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PassTarget {
+    Scene,
+    PingPong(usize),
+}
+
+/// The input and output of each of `pass_count` enabled passes, followed by the
+/// target holding the final image.
+fn plan_passes(pass_count: usize) -> (Vec<(PassTarget, PassTarget)>, PassTarget) {
+    let steps = (0..pass_count)
+        .map(|index| {
+            let input = match index {
+                0 => PassTarget::Scene,
+                _ => PassTarget::PingPong((index - 1) % 2),
+            };
+            (input, PassTarget::PingPong(index % 2))
+        })
+        .collect();
+    let result = match pass_count {
+        0 => PassTarget::Scene,
+        _ => PassTarget::PingPong((pass_count - 1) % 2),
+    };
+    (steps, result)
+}
+
+/// Renders a scene offscreen and runs the enabled passes over it in order, before
+/// the result is copied to the window.
+///
+/// # Example
+/// ```ignore
+/// let mut chain = PostProcessChain::with_standard_passes(width, height)?;
+/// chain.set_enabled(Bloom::NAME, true);
+/// chain.pass_mut::<Bloom>().unwrap().intensity = 1.2;
+///
+/// // Every frame
+/// chain.begin_frame(&window)?;
+/// scene.draw()?;
+/// chain.end_frame(&window, elapsed_time)?;
+/// ```
+pub struct PostProcessChain {
+    fullscreen: FullscreenTriangle,
+    scene_target: Framebuffer,
+    ping_pong: PingPongTargets,
+    color_format: AttachmentFormat,
+    passes: Vec<Box<dyn PostProcessPass>>,
+}
+
+impl PostProcessChain {
+    /// Creates a chain without passes, which renders into 8-bit color targets.
+    pub fn new(width: u32, height: u32) -> Result<Self> {
+        Self::with_format(width, height, AttachmentFormat::Rgba8)
+    }
+
+    /// Creates a chain without passes, whose scene and ping-pong targets have the
+    /// color format `format`.
+    pub fn with_format(width: u32, height: u32, format: AttachmentFormat) -> Result<Self> {
+        let scene_target = Framebuffer::builder(width, height)
+            .label("post process scene")
+            .color_texture(format)
+            .depth(AttachmentFormat::Depth24Stencil8, AttachmentStorage::Renderbuffer)
+            .build()?;
+        Ok(Self {
+            fullscreen: FullscreenTriangle::new(),
+            scene_target,
+            ping_pong: PingPongTargets::new("post process", width, height, format)?,
+            color_format: format,
+            passes: Vec::new(),
+        })
+    }
+
+    /// Creates a chain with all passes of this module, all disabled, in the order
+    /// bloom, FXAA, chromatic aberration, color filter, vignette and film grain.
+    pub fn with_standard_passes(width: u32, height: u32) -> Result<Self> {
+        let mut chain = Self::new(width, height)?;
+        chain.add_pass(Bloom::new()?);
+        chain.add_pass(Fxaa::new()?);
+        chain.add_pass(ChromaticAberration::new()?);
+        chain.add_pass(ColorFilter::new()?);
+        chain.add_pass(Vignette::new()?);
+        chain.add_pass(FilmGrain::new()?);
+        Ok(chain)
+    }
+
+    /// Creates a chain which renders the scene into floating point RGBA16F targets,
+    /// so colors can exceed 1.0. It has the standard passes with the tonemap pass
+    /// after bloom, which works on the HDR colors. The tonemap pass is enabled and
+    /// does the conversion to display colors, the passes after it work on those.
+    ///
+    /// The scene has to be rendered in linear colors, e.g. with color textures
+    /// loaded as `SRGB8_ALPHA8`, as the tonemap pass encodes its output to sRGB.
+    /// Scenes shading display encoded colors set the encoding of the `Tonemap`
+    /// pass to `OutputEncoding::Linear` instead.
+    pub fn with_hdr_passes(width: u32, height: u32) -> Result<Self> {
+        let mut chain = Self::with_format(width, height, AttachmentFormat::Rgba16F)?;
+        chain.add_pass(Bloom::new()?);
+        let mut tonemap = Tonemap::new()?;
+        tonemap.set_enabled(true);
+        chain.add_pass(tonemap);
+        chain.add_pass(Fxaa::new()?);
+        chain.add_pass(ChromaticAberration::new()?);
+        chain.add_pass(ColorFilter::new()?);
+        chain.add_pass(Vignette::new()?);
+        chain.add_pass(FilmGrain::new()?);
+        Ok(chain)
+    }
+
+    /// Appends a pass, which runs after all passes added before.
+    ///
+    /// # Panics
+    /// Panics if the chain already has a pass with the same name.
+    pub fn add_pass<P: PostProcessPass + 'static>(&mut self, pass: P) {
+        assert!(
+            self.pass(pass.name()).is_none(),
+            "The chain already has a pass named {:?}",
+            pass.name()
+        );
+        self.passes.push(Box::new(pass));
+    }
+
+    pub fn pass(&self, name: &str) -> Option<&dyn PostProcessPass> {
+        self.passes.iter().find(|pass| pass.name() == name).map(|pass| pass.as_ref())
+    }
+
+    /// Returns the first pass of type `P`, to change its parameters.
+    pub fn pass_mut<P: PostProcessPass + 'static>(&mut self) -> Option<&mut P> {
+        self.passes.iter_mut().find_map(|pass| pass.as_any_mut().downcast_mut::<P>())
+    }
+
+    pub fn pass_names(&self) -> Vec<&'static str> {
+        self.passes.iter().map(|pass| pass.name()).collect()
+    }
+
+    /// Enables or disables the pass named `name`.
+    ///
+    /// # Errors
+    /// Returns an error if there is no pass with this name.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> Result<()> {
+        let pass = self
+            .passes
+            .iter_mut()
+            .find(|pass| pass.name() == name)
+            .ok_or_else(|| anyhow!("There is no post process pass named {:?}", name))?;
+        pass.set_enabled(enabled);
+        Ok(())
+    }
+
+    /// Enables the pass named `name` if it is disabled and vice versa. Returns
+    /// whether it is enabled now.
+    pub fn toggle(&mut self, name: &str) -> Result<bool> {
+        let enabled = self
+            .pass(name)
+            .map(|pass| !pass.is_enabled())
+            .ok_or_else(|| anyhow!("There is no post process pass named {:?}", name))?;
+        self.set_enabled(name, enabled)?;
+        Ok(enabled)
+    }
+
+    /// The color format of the scene and ping-pong targets.
+    pub fn color_format(&self) -> AttachmentFormat {
+        self.color_format
+    }
+
+    /// Returns whether the scene is rendered with floating point colors.
+    pub fn is_hdr(&self) -> bool {
+        matches!(
+            self.color_format,
+            AttachmentFormat::R11fG11fB10f | AttachmentFormat::Rgba16F | AttachmentFormat::Rgba32F
+        )
+    }
+
+    /// The target scenes render to between `begin_frame` and `end_frame`.
+    pub fn scene_target(&self) -> &Framebuffer {
+        &self.scene_target
+    }
+
+    pub fn scene_target_mut(&mut self) -> &mut Framebuffer {
+        &mut self.scene_target
+    }
+
+    /// Resizes the targets to the window, binds the scene target and clears it with
+    /// the clear color of the window. The scene target stays bound for drawing the
+    /// scene until `end_frame`.
+    pub fn begin_frame(&mut self, window: &SdlWindow) -> Result<()> {
+        if self.scene_target.fit_to_window(window)? {
+            let (width, height) = (self.scene_target.width(), self.scene_target.height());
+            self.ping_pong.resize(width, height)?;
+        }
+        self.scene_target.clear_color = window.clear_color;
+        self.scene_target.clear();
+        self.scene_target.make_current()
+    }
+
+    /// Runs the enabled passes over the scene target and copies the result to the
+    /// window, which is bound again afterwards. `time` is the elapsed time in
+    /// seconds, used by animated passes.
+    pub fn end_frame(&mut self, window: &SdlWindow, time: f32) -> Result<()> {
+        let (width, height) = window.get_drawable_size();
+        Framebuffer::make_default_current(width, height);
+
+        let result = {
+            // Fullscreen passes overwrite every pixel and must not be culled
+            let _depth = DepthGuard::disabled()?;
+            let _cull = CullGuard::disabled()?;
+            // Passes replace the pixels of their output
+            let _blend = BlendStateGuard::disabled()?;
+            self.run_passes(time)?
+        };
+        let result = match result {
+            PassTarget::Scene => &self.scene_target,
+            PassTarget::PingPong(index) => self.ping_pong.get(index),
+        };
+        result.blit_to_window(window, RenderMask::new().color(), BlitFilter::Nearest)
+    }
+
+    fn run_passes(&mut self, time: f32) -> Result<PassTarget> {
+        let frame = PassFrame {
+            fullscreen: &self.fullscreen,
+            time,
+        };
+        let mut enabled: Vec<&mut Box<dyn PostProcessPass>> =
+            self.passes.iter_mut().filter(|pass| pass.is_enabled()).collect();
+        let (steps, result) = plan_passes(enabled.len());
+        for (pass, (input, output)) in enabled.iter_mut().zip(steps) {
+            let input = match input {
+                PassTarget::Scene => &self.scene_target,
+                PassTarget::PingPong(index) => self.ping_pong.get(index),
+            };
+            let output = match output {
+                PassTarget::Scene => &self.scene_target,
+                PassTarget::PingPong(index) => self.ping_pong.get(index),
+            };
+            pass.apply(&frame, input, output)
+                .with_context(|| format!("Post process pass {:?} failed", pass.name()))?;
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_passes() {
+        let (steps, result) = plan_passes(0);
+        assert!(steps.is_empty());
+        assert_eq!(result, PassTarget::Scene);
+
+        let (steps, result) = plan_passes(3);
+        assert_eq!(
+            steps,
+            vec![
+                (PassTarget::Scene, PassTarget::PingPong(0)),
+                (PassTarget::PingPong(0), PassTarget::PingPong(1)),
+                (PassTarget::PingPong(1), PassTarget::PingPong(0)),
+            ]
+        );
+        assert_eq!(result, PassTarget::PingPong(0));
+    }
+}
@@ -0,0 +1,179 @@
+use crate::opengl::framebuffer::{AttachmentFormat, Framebuffer};
+use crate::opengl::post_process::{
+    compile_pass_program, PassFrame, PingPongTargets, PostProcessPass,
+};
+use crate::opengl::shader_program::ShaderProgram;
+use anyhow::Result;
+use std::any::Any;
+
+const BRIGHT_PASS_SHADER: &str = r#"#version 330 core
+in vec2 v_uv;
+out vec4 frag_color;
+
+uniform sampler2D u_input;
+uniform float u_threshold;
+
+void main() {
+    vec3 color = texture(u_input, v_uv).rgb;
+    float brightness = max(color.r, max(color.g, color.b));
+    // Keeps the part of the color above the threshold
+    float contribution = max(brightness - u_threshold, 0.0) / max(brightness, 0.0001);
+    frag_color = vec4(color * contribution, 1.0);
+}
+"#;
+
+const BLUR_SHADER: &str = r#"#version 330 core
+in vec2 v_uv;
+out vec4 frag_color;
+
+uniform sampler2D u_input;
+uniform vec2 u_texel_size;
+uniform vec2 u_direction;
+uniform float u_radius;
+
+const float WEIGHTS[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+void main() {
+    vec2 sample_step = u_direction * u_texel_size * u_radius;
+    vec3 color = texture(u_input, v_uv).rgb * WEIGHTS[0];
+    for (int i = 1; i < 5; ++i) {
+        color += texture(u_input, v_uv + sample_step * float(i)).rgb * WEIGHTS[i];
+        color += texture(u_input, v_uv - sample_step * float(i)).rgb * WEIGHTS[i];
+    }
+    frag_color = vec4(color, 1.0);
+}
+"#;
+
+const COMPOSITE_SHADER: &str = r#"#version 330 core
+in vec2 v_uv;
+out vec4 frag_color;
+
+uniform sampler2D u_input;
+uniform sampler2D u_bloom;
+uniform float u_intensity;
+
+void main() {
+    vec4 color = texture(u_input, v_uv);
+    frag_color = vec4(color.rgb + texture(u_bloom, v_uv).rgb * u_intensity, color.a);
+}
+"#;
+
+/// Makes bright parts of the image glow. The parts above a threshold are blurred
+/// at half resolution with a separable Gaussian blur and added to the image.
+pub struct Bloom {
+    bright_pass: ShaderProgram,
+    blur: ShaderProgram,
+    composite: ShaderProgram,
+    /// Created with the size of the first input.
+    targets: Option<PingPongTargets>,
+    enabled: bool,
+    /// Brightness of the brightest channel above which pixels glow.
+    pub threshold: f32,
+    /// Factor of the blurred bright parts added to the image.
+    pub intensity: f32,
+    /// Number of horizontal and vertical blur passes, more make a wider glow.
+    pub blur_iterations: u32,
+    /// Distance between the blur samples in pixels of the half size image.
+    pub blur_radius: f32,
+}
+
+impl Bloom {
+    pub const NAME: &'static str = "bloom";
+
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            bright_pass: compile_pass_program(BRIGHT_PASS_SHADER)?,
+            blur: compile_pass_program(BLUR_SHADER)?,
+            composite: compile_pass_program(COMPOSITE_SHADER)?,
+            targets: None,
+            enabled: false,
+            threshold: 0.8,
+            intensity: 0.7,
+            blur_iterations: 3,
+            blur_radius: 1.0,
+        })
+    }
+
+    /// Creates or resizes the blur targets for an input of the given size.
+    fn prepare_targets(&mut self, width: u32, height: u32) -> Result<()> {
+        let (width, height) = blur_size(width, height);
+        match &mut self.targets {
+            Some(targets) => targets.resize(width, height)?,
+            None => {
+                let targets =
+                    PingPongTargets::new("bloom", width, height, AttachmentFormat::Rgba16F)?;
+                self.targets = Some(targets);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Size of the blur targets, half the size of the input.
+fn blur_size(width: u32, height: u32) -> (u32, u32) {
+    ((width / 2).max(1), (height / 2).max(1))
+}
+
+impl PostProcessPass for Bloom {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        // The blur targets are only kept while the pass is used
+        if !enabled {
+            self.targets = None;
+        }
+    }
+
+    fn apply(
+        &mut self,
+        frame: &PassFrame,
+        input: &Framebuffer,
+        output: &Framebuffer,
+    ) -> Result<()> {
+        let (threshold, iterations, radius) =
+            (self.threshold, self.blur_iterations, self.blur_radius);
+        self.prepare_targets(input.width(), input.height())?;
+        let targets = self.targets.as_ref().expect("bloom targets were just prepared");
+
+        self.bright_pass.activate();
+        self.bright_pass.set_uniform("u_threshold", threshold)?;
+        frame.fullscreen.draw(&self.bright_pass, input, targets.get(0))?;
+
+        self.blur.activate();
+        self.blur.set_uniform("u_radius", radius)?;
+        for _ in 0..iterations {
+            self.blur.set_uniform("u_direction", (1.0f32, 0.0f32))?;
+            frame.fullscreen.draw(&self.blur, targets.get(0), targets.get(1))?;
+            self.blur.set_uniform("u_direction", (0.0f32, 1.0f32))?;
+            frame.fullscreen.draw(&self.blur, targets.get(1), targets.get(0))?;
+        }
+
+        self.composite.activate();
+        self.composite.set_uniform("u_intensity", self.intensity)?;
+        self.composite.set_uniform("u_bloom", 1)?;
+        targets.get(0).bind_color_texture(0, 1)?;
+        frame.fullscreen.draw(&self.composite, input, output)
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blur_size() {
+        assert_eq!(blur_size(800, 600), (400, 300));
+        assert_eq!(blur_size(801, 1), (400, 1));
+    }
+}
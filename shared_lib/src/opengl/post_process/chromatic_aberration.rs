@@ -0,0 +1,73 @@
+use crate::opengl::framebuffer::Framebuffer;
+use crate::opengl::post_process::{compile_pass_program, PassFrame, PostProcessPass};
+use crate::opengl::shader_program::ShaderProgram;
+use anyhow::Result;
+use std::any::Any;
+
+const FRAGMENT_SHADER: &str = r#"#version 330 core
+in vec2 v_uv;
+out vec4 frag_color;
+
+uniform sampler2D u_input;
+uniform vec2 u_texel_size;
+uniform float u_strength;
+
+void main() {
+    // Grows from nothing in the center to `u_strength` pixels at the edges
+    vec2 offset = (v_uv - vec2(0.5)) * 2.0 * u_strength * u_texel_size;
+    float red = texture(u_input, v_uv + offset).r;
+    vec4 center = texture(u_input, v_uv);
+    float blue = texture(u_input, v_uv - offset).b;
+    frag_color = vec4(red, center.g, blue, center.a);
+}
+"#;
+
+/// Shifts the red and blue channels apart towards the edges of the image, like a
+/// cheap lens does.
+pub struct ChromaticAberration {
+    program: ShaderProgram,
+    enabled: bool,
+    /// Shift of the red and blue channels at the edges, in pixels.
+    pub strength: f32,
+}
+
+impl ChromaticAberration {
+    pub const NAME: &'static str = "chromatic aberration";
+
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            program: compile_pass_program(FRAGMENT_SHADER)?,
+            enabled: false,
+            strength: 4.0,
+        })
+    }
+}
+
+impl PostProcessPass for ChromaticAberration {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn apply(
+        &mut self,
+        frame: &PassFrame,
+        input: &Framebuffer,
+        output: &Framebuffer,
+    ) -> Result<()> {
+        self.program.activate();
+        self.program.set_uniform("u_strength", self.strength)?;
+        frame.fullscreen.draw(&self.program, input, output)
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
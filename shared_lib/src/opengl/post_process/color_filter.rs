@@ -0,0 +1,101 @@
+use crate::opengl::framebuffer::Framebuffer;
+use crate::opengl::post_process::{compile_pass_program, PassFrame, PostProcessPass};
+use crate::opengl::shader_program::ShaderProgram;
+use anyhow::Result;
+use std::any::Any;
+
+const FRAGMENT_SHADER: &str = r#"#version 330 core
+in vec2 v_uv;
+out vec4 frag_color;
+
+uniform sampler2D u_input;
+uniform int u_mode;
+uniform float u_strength;
+
+const int MODE_GRAYSCALE = 0;
+
+void main() {
+    vec4 color = texture(u_input, v_uv);
+    vec3 filtered;
+    if (u_mode == MODE_GRAYSCALE) {
+        filtered = vec3(dot(color.rgb, vec3(0.2126, 0.7152, 0.0722)));
+    } else {
+        // Columns of the usual sepia matrix
+        filtered = mat3(
+            0.393, 0.349, 0.272,
+            0.769, 0.686, 0.534,
+            0.189, 0.168, 0.131
+        ) * color.rgb;
+    }
+    frag_color = vec4(mix(color.rgb, filtered, u_strength), color.a);
+}
+"#;
+
+/// The colors `ColorFilter` turns the image into.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorFilterMode {
+    #[default]
+    Grayscale,
+    Sepia,
+}
+
+impl ColorFilterMode {
+    fn to_uniform(self) -> i32 {
+        match self {
+            ColorFilterMode::Grayscale => 0,
+            ColorFilterMode::Sepia => 1,
+        }
+    }
+}
+
+/// Converts the image to grayscale or sepia tones.
+pub struct ColorFilter {
+    program: ShaderProgram,
+    enabled: bool,
+    pub mode: ColorFilterMode,
+    /// Blends between the original image at 0.0 and the filtered one at 1.0.
+    pub strength: f32,
+}
+
+impl ColorFilter {
+    pub const NAME: &'static str = "color filter";
+
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            program: compile_pass_program(FRAGMENT_SHADER)?,
+            enabled: false,
+            mode: ColorFilterMode::default(),
+            strength: 1.0,
+        })
+    }
+}
+
+impl PostProcessPass for ColorFilter {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn apply(
+        &mut self,
+        frame: &PassFrame,
+        input: &Framebuffer,
+        output: &Framebuffer,
+    ) -> Result<()> {
+        self.program.activate();
+        self.program.set_uniform("u_mode", self.mode.to_uniform())?;
+        self.program.set_uniform("u_strength", self.strength)?;
+        frame.fullscreen.draw(&self.program, input, output)
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
@@ -0,0 +1,84 @@
+use crate::opengl::framebuffer::Framebuffer;
+use crate::opengl::post_process::{compile_pass_program, PassFrame, PostProcessPass};
+use crate::opengl::shader_program::ShaderProgram;
+use anyhow::Result;
+use std::any::Any;
+
+const FRAGMENT_SHADER: &str = r#"#version 330 core
+in vec2 v_uv;
+out vec4 frag_color;
+
+uniform sampler2D u_input;
+uniform vec2 u_texel_size;
+uniform float u_time;
+uniform float u_intensity;
+uniform float u_grain_size;
+
+float hash(vec2 position) {
+    return fract(sin(dot(position, vec2(12.9898, 78.233))) * 43758.5453);
+}
+
+void main() {
+    vec4 color = texture(u_input, v_uv);
+    vec2 cell = floor(v_uv / (u_texel_size * u_grain_size));
+    float noise = hash(cell + fract(u_time) * 113.0) - 0.5;
+    // Grain is most visible in the mid tones
+    float luminance = dot(color.rgb, vec3(0.2126, 0.7152, 0.0722));
+    float response = 1.0 - abs(luminance * 2.0 - 1.0);
+    frag_color = vec4(color.rgb + noise * u_intensity * response, color.a);
+}
+"#;
+
+/// Adds noise which changes every frame, like the grain of analog film.
+pub struct FilmGrain {
+    program: ShaderProgram,
+    enabled: bool,
+    /// Maximum change of a color channel.
+    pub intensity: f32,
+    /// Size of a grain in pixels.
+    pub grain_size: f32,
+}
+
+impl FilmGrain {
+    pub const NAME: &'static str = "film grain";
+
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            program: compile_pass_program(FRAGMENT_SHADER)?,
+            enabled: false,
+            intensity: 0.1,
+            grain_size: 1.5,
+        })
+    }
+}
+
+impl PostProcessPass for FilmGrain {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn apply(
+        &mut self,
+        frame: &PassFrame,
+        input: &Framebuffer,
+        output: &Framebuffer,
+    ) -> Result<()> {
+        self.program.activate();
+        self.program.set_uniform("u_time", frame.time)?;
+        self.program.set_uniform("u_intensity", self.intensity)?;
+        self.program.set_uniform("u_grain_size", self.grain_size.max(1.0))?;
+        frame.fullscreen.draw(&self.program, input, output)
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
@@ -0,0 +1,122 @@
+use crate::opengl::framebuffer::Framebuffer;
+use crate::opengl::post_process::{compile_pass_program, PassFrame, PostProcessPass};
+use crate::opengl::shader_program::ShaderProgram;
+use anyhow::Result;
+use std::any::Any;
+
+// A compact variant of FXAA by Timothy Lottes. It finds the direction of an edge
+// from the luma of the diagonal neighbours and blurs along it.
+const FRAGMENT_SHADER: &str = r#"#version 330 core
+in vec2 v_uv;
+out vec4 frag_color;
+
+uniform sampler2D u_input;
+uniform vec2 u_texel_size;
+uniform float u_edge_threshold;
+uniform float u_edge_threshold_min;
+uniform float u_span_max;
+uniform float u_reduce_mul;
+
+float luma(vec3 color) {
+    return dot(color, vec3(0.299, 0.587, 0.114));
+}
+
+void main() {
+    vec4 center = texture(u_input, v_uv);
+    float luma_m = luma(center.rgb);
+    float luma_nw = luma(texture(u_input, v_uv + vec2(-1.0, -1.0) * u_texel_size).rgb);
+    float luma_ne = luma(texture(u_input, v_uv + vec2(1.0, -1.0) * u_texel_size).rgb);
+    float luma_sw = luma(texture(u_input, v_uv + vec2(-1.0, 1.0) * u_texel_size).rgb);
+    float luma_se = luma(texture(u_input, v_uv + vec2(1.0, 1.0) * u_texel_size).rgb);
+
+    float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+    if (luma_max - luma_min < max(u_edge_threshold_min, luma_max * u_edge_threshold)) {
+        frag_color = center;
+        return;
+    }
+
+    vec2 direction = vec2(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se)
+    );
+    float direction_reduce =
+        max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * u_reduce_mul, 1.0 / 128.0);
+    float inverse_direction_min = 1.0 / (min(abs(direction.x), abs(direction.y)) + direction_reduce);
+    direction = clamp(direction * inverse_direction_min, vec2(-u_span_max), vec2(u_span_max))
+        * u_texel_size;
+
+    vec3 color_a = 0.5 * (
+        texture(u_input, v_uv + direction * (1.0 / 3.0 - 0.5)).rgb
+            + texture(u_input, v_uv + direction * (2.0 / 3.0 - 0.5)).rgb
+    );
+    vec3 color_b = color_a * 0.5 + 0.25 * (
+        texture(u_input, v_uv - direction * 0.5).rgb
+            + texture(u_input, v_uv + direction * 0.5).rgb
+    );
+    float luma_b = luma(color_b);
+    vec3 color = (luma_b < luma_min || luma_b > luma_max) ? color_a : color_b;
+    frag_color = vec4(color, center.a);
+}
+"#;
+
+/// Fast approximate anti-aliasing, which smooths jagged edges found in the image.
+pub struct Fxaa {
+    program: ShaderProgram,
+    enabled: bool,
+    /// Minimum local contrast, relative to the brightest pixel, to count as edge.
+    pub edge_threshold: f32,
+    /// Minimum local contrast to count as edge, which skips dark areas.
+    pub edge_threshold_min: f32,
+    /// Maximum length of the blur along an edge, in pixels.
+    pub span_max: f32,
+    /// Limits the blur of edges in bright areas.
+    pub reduce_mul: f32,
+}
+
+impl Fxaa {
+    pub const NAME: &'static str = "fxaa";
+
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            program: compile_pass_program(FRAGMENT_SHADER)?,
+            enabled: false,
+            edge_threshold: 0.125,
+            edge_threshold_min: 0.0312,
+            span_max: 8.0,
+            reduce_mul: 0.125,
+        })
+    }
+}
+
+impl PostProcessPass for Fxaa {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn apply(
+        &mut self,
+        frame: &PassFrame,
+        input: &Framebuffer,
+        output: &Framebuffer,
+    ) -> Result<()> {
+        self.program.activate();
+        self.program.set_uniform("u_edge_threshold", self.edge_threshold)?;
+        self.program.set_uniform("u_edge_threshold_min", self.edge_threshold_min)?;
+        self.program.set_uniform("u_span_max", self.span_max)?;
+        self.program.set_uniform("u_reduce_mul", self.reduce_mul)?;
+        frame.fullscreen.draw(&self.program, input, output)
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
@@ -0,0 +1,410 @@
+use crate::opengl::framebuffer::{AttachmentFormat, Framebuffer};
+use crate::opengl::post_process::{
+    compile_pass_program, PassFrame, PingPongTargets, PostProcessPass,
+};
+use crate::opengl::shader_program::ShaderProgram;
+use anyhow::Result;
+use std::any::Any;
+use std::fmt::{Display, Formatter};
+
+/// Size of the square log luminance target whose mip chain is averaged for eye
+/// adaptation. A power of two, so every mip level halves it exactly.
+const LUMINANCE_SIZE: u32 = 256;
+
+const LUMINANCE_SHADER: &str = r#"#version 330 core
+in vec2 v_uv;
+out vec4 frag_color;
+
+uniform sampler2D u_input;
+
+void main() {
+    vec3 color = texture(u_input, v_uv).rgb;
+    float luminance = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    // The mip chain averages the logarithm, which gives the geometric mean
+    frag_color = vec4(log(max(luminance, 0.0001)), 0.0, 0.0, 1.0);
+}
+"#;
+
+const ADAPTATION_SHADER: &str = r#"#version 330 core
+in vec2 v_uv;
+out vec4 frag_color;
+
+uniform sampler2D u_input;
+uniform sampler2D u_previous;
+uniform float u_max_level;
+uniform float u_adaptation;
+uniform bool u_reset;
+uniform float u_min_luminance;
+uniform float u_max_luminance;
+
+void main() {
+    float current = exp(textureLod(u_input, vec2(0.5), u_max_level).r);
+    float previous = texelFetch(u_previous, ivec2(0), 0).r;
+    float adapted = u_reset ? current : previous + (current - previous) * u_adaptation;
+    frag_color = vec4(clamp(adapted, u_min_luminance, u_max_luminance), 0.0, 0.0, 1.0);
+}
+"#;
+
+const TONEMAP_SHADER: &str = r#"#version 330 core
+in vec2 v_uv;
+out vec4 frag_color;
+
+uniform sampler2D u_input;
+uniform sampler2D u_adapted_luminance;
+uniform bool u_auto_exposure;
+uniform float u_exposure;
+uniform float u_key;
+uniform int u_operator;
+uniform float u_white_point;
+uniform int u_encoding;
+uniform float u_gamma;
+
+const int OPERATOR_REINHARD = 0;
+const int OPERATOR_ACES_FILMIC = 1;
+const int ENCODING_SRGB = 0;
+const int ENCODING_GAMMA = 1;
+
+// Reinhard with a white point, colors at the white point become 1.0
+vec3 reinhard(vec3 color) {
+    return color * (1.0 + color / (u_white_point * u_white_point)) / (1.0 + color);
+}
+
+// Krzysztof Narkowicz's fit of the ACES reference rendering transform
+vec3 aces_filmic(vec3 color) {
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), 0.0, 1.0);
+}
+
+// John Hable's filmic curve from Uncharted 2
+vec3 uncharted2_curve(vec3 x) {
+    const float A = 0.15;
+    const float B = 0.50;
+    const float C = 0.10;
+    const float D = 0.20;
+    const float E = 0.02;
+    const float F = 0.30;
+    return ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F;
+}
+
+vec3 uncharted2(vec3 color) {
+    const float exposure_bias = 2.0;
+    return uncharted2_curve(color * exposure_bias) / uncharted2_curve(vec3(u_white_point));
+}
+
+vec3 linear_to_srgb(vec3 color) {
+    color = clamp(color, 0.0, 1.0);
+    vec3 low = color * 12.92;
+    vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+    return mix(low, high, step(vec3(0.0031308), color));
+}
+
+void main() {
+    vec4 color = texture(u_input, v_uv);
+    float exposure = u_exposure;
+    if (u_auto_exposure) {
+        float adapted = texelFetch(u_adapted_luminance, ivec2(0), 0).r;
+        exposure *= u_key / max(adapted, 0.0001);
+    }
+    vec3 exposed = max(color.rgb * exposure, vec3(0.0));
+
+    vec3 mapped;
+    if (u_operator == OPERATOR_REINHARD) {
+        mapped = reinhard(exposed);
+    } else if (u_operator == OPERATOR_ACES_FILMIC) {
+        mapped = aces_filmic(exposed);
+    } else {
+        mapped = uncharted2(exposed);
+    }
+
+    vec3 encoded;
+    if (u_encoding == ENCODING_SRGB) {
+        encoded = linear_to_srgb(mapped);
+    } else if (u_encoding == ENCODING_GAMMA) {
+        encoded = pow(clamp(mapped, 0.0, 1.0), vec3(1.0 / u_gamma));
+    } else {
+        encoded = mapped;
+    }
+    frag_color = vec4(encoded, color.a);
+}
+"#;
+
+//////////////////////////////////////////////////////////////////////////////
+// - ToneMapOperator -
+//////////////////////////////////////////////////////////////////////////////
+
+/// The curve mapping exposed HDR colors to the displayable range.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    /// Reinhard with a white point, soft but flat looking.
+    Reinhard,
+    /// Fit of the ACES filmic curve, with a strong contrast and saturated highlights.
+    #[default]
+    AcesFilmic,
+    /// John Hable's filmic curve from Uncharted 2.
+    Uncharted2,
+}
+
+impl ToneMapOperator {
+    /// Returns the operator following this one, for cycling through all of them.
+    pub fn next(self) -> Self {
+        match self {
+            ToneMapOperator::Reinhard => ToneMapOperator::AcesFilmic,
+            ToneMapOperator::AcesFilmic => ToneMapOperator::Uncharted2,
+            ToneMapOperator::Uncharted2 => ToneMapOperator::Reinhard,
+        }
+    }
+
+    fn to_uniform(self) -> i32 {
+        match self {
+            ToneMapOperator::Reinhard => 0,
+            ToneMapOperator::AcesFilmic => 1,
+            ToneMapOperator::Uncharted2 => 2,
+        }
+    }
+}
+
+impl Display for ToneMapOperator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ToneMapOperator::Reinhard => write!(f, "Reinhard"),
+            ToneMapOperator::AcesFilmic => write!(f, "ACES filmic"),
+            ToneMapOperator::Uncharted2 => write!(f, "Uncharted 2"),
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - OutputEncoding -
+//////////////////////////////////////////////////////////////////////////////
+
+/// How the tone mapped linear colors are encoded for the display. This is the only
+/// place the frame is converted, `GL_FRAMEBUFFER_SRGB` has to stay disabled.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OutputEncoding {
+    /// The piecewise sRGB transfer function, correct for almost all displays.
+    #[default]
+    Srgb,
+    /// A plain power curve with the given gamma, usually 2.2.
+    Gamma(f32),
+    /// No conversion, for targets which are processed further in linear space.
+    Linear,
+}
+
+impl OutputEncoding {
+    fn to_uniforms(self) -> (i32, f32) {
+        match self {
+            OutputEncoding::Srgb => (0, 2.2),
+            OutputEncoding::Gamma(gamma) => (1, gamma.max(0.01)),
+            OutputEncoding::Linear => (2, 1.0),
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - Tonemap -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Resolves an HDR image to displayable colors: exposes it, applies a tone mapping
+/// operator and encodes the result, see `OutputEncoding`. Passes after this one
+/// work on display encoded colors.
+///
+/// With `auto_exposure` the exposure follows the average luminance of the image,
+/// like an eye adapting to the dark or to bright light. The log luminance is
+/// rendered to a small texture whose mip chain is generated, so its last level
+/// holds the average without reading anything back from the GPU.
+pub struct Tonemap {
+    tonemap: ShaderProgram,
+    luminance: ShaderProgram,
+    adaptation: ShaderProgram,
+    /// Created when auto exposure is first used.
+    luminance_target: Option<Framebuffer>,
+    /// The adapted luminance of the last and the current frame, 1x1 pixels each.
+    adapted_targets: Option<PingPongTargets>,
+    adapted_index: usize,
+    last_time: Option<f32>,
+    enabled: bool,
+    pub operator: ToneMapOperator,
+    /// Manual exposure in stops, added to the automatic exposure if enabled.
+    pub exposure_ev: f32,
+    /// Input value which becomes white with Reinhard and Uncharted 2.
+    pub white_point: f32,
+    pub encoding: OutputEncoding,
+    /// Adapts the exposure to the average luminance of the image.
+    pub auto_exposure: bool,
+    /// Luminance the average luminance is exposed to by auto exposure.
+    pub key_value: f32,
+    /// How fast auto exposure adapts, larger is faster.
+    pub adaptation_speed: f32,
+    /// Range of the adapted luminance, which limits the automatic exposure.
+    pub min_luminance: f32,
+    pub max_luminance: f32,
+}
+
+impl Tonemap {
+    pub const NAME: &'static str = "tonemap";
+
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            tonemap: compile_pass_program(TONEMAP_SHADER)?,
+            luminance: compile_pass_program(LUMINANCE_SHADER)?,
+            adaptation: compile_pass_program(ADAPTATION_SHADER)?,
+            luminance_target: None,
+            adapted_targets: None,
+            adapted_index: 0,
+            last_time: None,
+            enabled: false,
+            operator: ToneMapOperator::default(),
+            exposure_ev: 0.0,
+            white_point: 11.2,
+            encoding: OutputEncoding::default(),
+            auto_exposure: false,
+            key_value: 0.18,
+            adaptation_speed: 1.5,
+            min_luminance: 0.02,
+            max_luminance: 20.0,
+        })
+    }
+
+    /// Measures the average luminance of `input` and moves the adapted luminance
+    /// towards it. Returns the target holding the new adapted luminance.
+    fn adapt(&mut self, frame: &PassFrame, input: &Framebuffer) -> Result<&Framebuffer> {
+        if self.luminance_target.is_none() {
+            let target = Framebuffer::builder(LUMINANCE_SIZE, LUMINANCE_SIZE)
+                .label("tonemap luminance")
+                .color_texture(AttachmentFormat::R16F)
+                .build()?;
+            self.luminance_target = Some(target);
+        }
+        // The first frame adapts at once, so scenes don't start with a fade
+        let reset = self.adapted_targets.is_none() || self.last_time.is_none();
+        if self.adapted_targets.is_none() {
+            let targets = PingPongTargets::new("tonemap adaptation", 1, 1, AttachmentFormat::R16F)?;
+            self.adapted_targets = Some(targets);
+        }
+        let delta_time = (frame.time - self.last_time.unwrap_or(frame.time)).max(0.0);
+        self.last_time = Some(frame.time);
+
+        let luminance_target = self.luminance_target.as_ref().expect("created above");
+        self.luminance.activate();
+        frame.fullscreen.draw(&self.luminance, input, luminance_target)?;
+        luminance_target.generate_color_mipmaps(0)?;
+
+        let targets = self.adapted_targets.as_ref().expect("created above");
+        let previous = targets.get(self.adapted_index);
+        let current = targets.get(self.adapted_index + 1);
+        self.adaptation.activate();
+        self.adaptation.set_uniform("u_previous", 1)?;
+        self.adaptation
+            .set_uniform("u_max_level", max_mip_level(LUMINANCE_SIZE) as f32)?;
+        self.adaptation
+            .set_uniform("u_adaptation", adaptation_factor(delta_time, self.adaptation_speed))?;
+        self.adaptation.set_uniform("u_reset", reset)?;
+        self.adaptation.set_uniform("u_min_luminance", self.min_luminance)?;
+        self.adaptation.set_uniform("u_max_luminance", self.max_luminance)?;
+        previous.bind_color_texture(0, 1)?;
+        frame.fullscreen.draw(&self.adaptation, luminance_target, current)?;
+
+        self.adapted_index = (self.adapted_index + 1) % 2;
+        Ok(current)
+    }
+}
+
+/// Index of the 1x1 mip level of a square texture with `size` pixels per side.
+fn max_mip_level(size: u32) -> u32 {
+    size.max(1).ilog2()
+}
+
+/// The fraction of the remaining difference auto exposure adapts in `delta_time`
+/// seconds. Exponential, so the result doesn't depend on the frame rate.
+fn adaptation_factor(delta_time: f32, speed: f32) -> f32 {
+    1.0 - (-delta_time * speed.max(0.0)).exp()
+}
+
+/// The factor colors are multiplied with for an exposure in stops.
+fn exposure_factor(exposure_ev: f32) -> f32 {
+    exposure_ev.exp2()
+}
+
+impl PostProcessPass for Tonemap {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.luminance_target = None;
+            self.adapted_targets = None;
+            self.last_time = None;
+        }
+    }
+
+    fn apply(
+        &mut self,
+        frame: &PassFrame,
+        input: &Framebuffer,
+        output: &Framebuffer,
+    ) -> Result<()> {
+        let auto_exposure = self.auto_exposure;
+        let adapted_texture = if auto_exposure {
+            let adapted = self.adapt(frame, input)?;
+            adapted.color_texture(0)
+        } else {
+            self.last_time = None;
+            None
+        };
+
+        let (encoding, gamma) = self.encoding.to_uniforms();
+        self.tonemap.activate();
+        self.tonemap.set_uniform("u_auto_exposure", adapted_texture.is_some())?;
+        self.tonemap.set_uniform("u_exposure", exposure_factor(self.exposure_ev))?;
+        self.tonemap.set_uniform("u_key", self.key_value)?;
+        self.tonemap.set_uniform("u_operator", self.operator.to_uniform())?;
+        self.tonemap.set_uniform("u_white_point", self.white_point.max(0.01))?;
+        self.tonemap.set_uniform("u_encoding", encoding)?;
+        self.tonemap.set_uniform("u_gamma", gamma)?;
+        self.tonemap.set_uniform("u_adapted_luminance", 1)?;
+        if let Some(targets) = &self.adapted_targets {
+            // `adapt` already advanced the index to the target it rendered to
+            targets.get(self.adapted_index).bind_color_texture(0, 1)?;
+        }
+        frame.fullscreen.draw(&self.tonemap, input, output)
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exposure_helpers() {
+        assert_eq!(max_mip_level(LUMINANCE_SIZE), 8);
+        assert_eq!(max_mip_level(1), 0);
+        assert_eq!(exposure_factor(0.0), 1.0);
+        assert_eq!(exposure_factor(-1.0), 0.5);
+        assert_eq!(adaptation_factor(0.0, 1.5), 0.0);
+        // Half of the difference is gone after ln(2) / speed seconds
+        let half_time = std::f32::consts::LN_2 / 1.5;
+        assert!((adaptation_factor(half_time, 1.5) - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_operator_cycle() {
+        let operator = ToneMapOperator::Reinhard;
+        assert_eq!(operator.next(), ToneMapOperator::AcesFilmic);
+        assert_eq!(operator.next().next().next(), operator);
+        assert_eq!(OutputEncoding::Gamma(2.4).to_uniforms(), (1, 2.4));
+    }
+}
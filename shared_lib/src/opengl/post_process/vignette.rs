@@ -0,0 +1,80 @@
+use crate::opengl::framebuffer::Framebuffer;
+use crate::opengl::post_process::{compile_pass_program, PassFrame, PostProcessPass};
+use crate::opengl::shader_program::ShaderProgram;
+use anyhow::Result;
+use std::any::Any;
+
+const FRAGMENT_SHADER: &str = r#"#version 330 core
+in vec2 v_uv;
+out vec4 frag_color;
+
+uniform sampler2D u_input;
+uniform float u_radius;
+uniform float u_softness;
+uniform float u_strength;
+
+void main() {
+    vec4 color = texture(u_input, v_uv);
+    // 1.0 in the corners
+    float distance_to_center = length(v_uv - vec2(0.5)) * sqrt(2.0);
+    float vignette = smoothstep(u_radius, u_radius - u_softness, distance_to_center);
+    frag_color = vec4(color.rgb * mix(1.0, vignette, u_strength), color.a);
+}
+"#;
+
+/// Darkens the image towards its corners.
+pub struct Vignette {
+    program: ShaderProgram,
+    enabled: bool,
+    /// Distance from the center where the image is darkest, 1.0 is a corner.
+    pub radius: f32,
+    /// Width of the transition towards `radius`.
+    pub softness: f32,
+    /// How dark the darkest parts get, from 0.0 to 1.0.
+    pub strength: f32,
+}
+
+impl Vignette {
+    pub const NAME: &'static str = "vignette";
+
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            program: compile_pass_program(FRAGMENT_SHADER)?,
+            enabled: false,
+            radius: 1.1,
+            softness: 0.6,
+            strength: 0.8,
+        })
+    }
+}
+
+impl PostProcessPass for Vignette {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn apply(
+        &mut self,
+        frame: &PassFrame,
+        input: &Framebuffer,
+        output: &Framebuffer,
+    ) -> Result<()> {
+        self.program.activate();
+        self.program.set_uniform("u_radius", self.radius)?;
+        self.program.set_uniform("u_softness", self.softness)?;
+        self.program.set_uniform("u_strength", self.strength)?;
+        frame.fullscreen.draw(&self.program, input, output)
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
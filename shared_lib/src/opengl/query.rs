@@ -0,0 +1,460 @@
+use crate::gl_traits::Deletable;
+use crate::gl_utils::check_gl_error;
+use crate::opengl::gl_backend::with_backend;
+use crate::opengl::resource_registry::{self, ResourceKind};
+use anyhow::{anyhow, Context, Result};
+use gl::types::{GLenum, GLuint};
+use std::cell::Cell;
+
+//////////////////////////////////////////////////////////////////////////////
+// - QueryTarget -
+//////////////////////////////////////////////////////////////////////////////
+
+/// What a `Query` counts or measures between `begin` and `end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QueryTarget {
+    /// Number of samples passing the depth and stencil tests.
+    SamplesPassed,
+    /// Non-zero if any sample passed the depth and stencil tests.
+    AnySamplesPassed,
+    /// Like `AnySamplesPassed`, but may report false positives in exchange for speed.
+    AnySamplesPassedConservative,
+    /// Number of primitives emitted by the last vertex processing stage.
+    PrimitivesGenerated,
+    /// Number of primitives written into transform feedback buffers.
+    TransformFeedbackPrimitivesWritten,
+    /// GPU time in nanoseconds.
+    TimeElapsed,
+    /// GPU clock in nanoseconds when the GPU reached the query, see
+    /// `Query::record_timestamp`.
+    Timestamp,
+}
+
+impl QueryTarget {
+    pub fn to_gl_enum(&self) -> GLenum {
+        match self {
+            QueryTarget::SamplesPassed => gl::SAMPLES_PASSED,
+            QueryTarget::AnySamplesPassed => gl::ANY_SAMPLES_PASSED,
+            QueryTarget::AnySamplesPassedConservative => gl::ANY_SAMPLES_PASSED_CONSERVATIVE,
+            QueryTarget::PrimitivesGenerated => gl::PRIMITIVES_GENERATED,
+            QueryTarget::TransformFeedbackPrimitivesWritten => {
+                gl::TRANSFORM_FEEDBACK_PRIMITIVES_WRITTEN
+            }
+            QueryTarget::TimeElapsed => gl::TIME_ELAPSED,
+            QueryTarget::Timestamp => gl::TIMESTAMP,
+        }
+    }
+
+    /// Checks if the target counts samples, which makes it usable for occlusion
+    /// culling and conditional rendering.
+    pub fn is_occlusion(&self) -> bool {
+        matches!(
+            self,
+            QueryTarget::SamplesPassed
+                | QueryTarget::AnySamplesPassed
+                | QueryTarget::AnySamplesPassedConservative
+        )
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - ConditionalRenderMode -
+//////////////////////////////////////////////////////////////////////////////
+
+/// How conditional rendering treats an occlusion query whose result isn't
+/// available yet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ConditionalRenderMode {
+    /// Waits for the result.
+    Wait,
+    /// Renders if the result isn't available, so nothing stalls.
+    #[default]
+    NoWait,
+    /// Like `Wait`, but the result may be evaluated per framebuffer region.
+    ByRegionWait,
+    /// Like `NoWait`, but the result may be evaluated per framebuffer region.
+    ByRegionNoWait,
+}
+
+impl ConditionalRenderMode {
+    pub fn to_gl_enum(&self) -> GLenum {
+        match self {
+            ConditionalRenderMode::Wait => gl::QUERY_WAIT,
+            ConditionalRenderMode::NoWait => gl::QUERY_NO_WAIT,
+            ConditionalRenderMode::ByRegionWait => gl::QUERY_BY_REGION_WAIT,
+            ConditionalRenderMode::ByRegionNoWait => gl::QUERY_BY_REGION_NO_WAIT,
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - Query -
+//////////////////////////////////////////////////////////////////////////////
+
+/// An OpenGL query object.
+///
+/// Results arrive some frames later, so `try_result` should be preferred over the
+/// blocking `result`, which stalls until the GPU has caught up.
+///
+/// # Example
+/// ```ignore
+/// let query = Query::new(QueryTarget::PrimitivesGenerated);
+/// query.begin()?;
+/// vao.render(false, vertex_count)?;
+/// query.end()?;
+/// // Some frames later
+/// if let Some(primitives) = query.try_result()? {
+///     println!("{} primitives", primitives);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Query {
+    id: GLuint,
+    target: QueryTarget,
+    active: Cell<bool>,
+    /// Set by `end`, cleared after the result was read.
+    pending: Cell<bool>,
+    /// Set by the first `end`, conditional rendering needs a finished query.
+    ended_once: Cell<bool>,
+    /// Set while a `ConditionalRender` uses the result of the query.
+    conditional: Cell<bool>,
+}
+
+impl Query {
+    pub fn new(target: QueryTarget) -> Self {
+        let id = with_backend(|backend| backend.gen_query());
+        resource_registry::register(ResourceKind::Query, id, format!("{:?} query", target), 0);
+        Self {
+            id,
+            target,
+            active: Cell::new(false),
+            pending: Cell::new(false),
+            ended_once: Cell::new(false),
+            conditional: Cell::new(false),
+        }
+    }
+
+    pub fn query_id(&self) -> GLuint {
+        self.id
+    }
+
+    pub fn target(&self) -> QueryTarget {
+        self.target
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.get()
+    }
+
+    /// Returns `true` between `end` and reading the result.
+    pub fn is_pending(&self) -> bool {
+        self.pending.get()
+    }
+
+    /// Returns `true` once the query was ended at least once.
+    pub fn has_ended(&self) -> bool {
+        self.ended_once.get()
+    }
+
+    /// Starts counting. Only one query per target can be active at a time.
+    ///
+    /// # Errors
+    /// Returns an error if the query is already active, conditional rendering uses
+    /// its result, or OpenGL reports an error.
+    pub fn begin(&self) -> Result<()> {
+        if self.target == QueryTarget::Timestamp {
+            return Err(anyhow!(
+                "Timestamp query {} can't be begun, use record_timestamp",
+                self.id
+            ));
+        }
+        if self.active.get() {
+            return Err(anyhow!("Query {} is already active", self.id));
+        }
+        if self.conditional.get() {
+            return Err(anyhow!("Query {} is used for conditional rendering", self.id));
+        }
+        with_backend(|backend| backend.begin_query(self.target.to_gl_enum(), self.id));
+        check_gl_error().with_context(|| format!("Failed to begin query {}", self.id))?;
+        self.active.set(true);
+        self.pending.set(false);
+        Ok(())
+    }
+
+    /// Stops counting, the result becomes available later.
+    ///
+    /// # Errors
+    /// Returns an error if the query isn't active or OpenGL reports an error.
+    pub fn end(&self) -> Result<()> {
+        if !self.active.get() {
+            return Err(anyhow!("Query {} isn't active", self.id));
+        }
+        with_backend(|backend| backend.end_query(self.target.to_gl_enum()));
+        check_gl_error().with_context(|| format!("Failed to end query {}", self.id))?;
+        self.active.set(false);
+        self.pending.set(true);
+        self.ended_once.set(true);
+        Ok(())
+    }
+
+    /// Records the GPU time once all previous commands have been processed. The
+    /// result is the GPU clock in nanoseconds, so the difference of two timestamps
+    /// is the GPU time spent between them. Unlike `TimeElapsed` queries, timestamps
+    /// can be nested and overlapped freely.
+    ///
+    /// # Errors
+    /// Returns an error if this isn't a `Timestamp` query or OpenGL reports an error.
+    pub fn record_timestamp(&self) -> Result<()> {
+        if self.target != QueryTarget::Timestamp {
+            return Err(anyhow!("Query {} isn't a timestamp query", self.id));
+        }
+        with_backend(|backend| backend.query_counter(self.id, gl::TIMESTAMP));
+        check_gl_error().with_context(|| format!("Failed to record timestamp {}", self.id))?;
+        self.pending.set(true);
+        Ok(())
+    }
+
+    /// Checks if the result of the last `end` or `record_timestamp` can be read
+    /// without stalling.
+    pub fn is_result_available(&self) -> bool {
+        if !self.pending.get() {
+            return false;
+        }
+        with_backend(|backend| backend.get_query_object_ui(self.id, gl::QUERY_RESULT_AVAILABLE))
+            != 0
+    }
+
+    /// Returns the result if it is available, without waiting for the GPU. `None` is
+    /// also returned if the query wasn't ended since the result was last read.
+    pub fn try_result(&self) -> Result<Option<u64>> {
+        if !self.is_result_available() {
+            return Ok(None);
+        }
+        self.read_result().map(Some)
+    }
+
+    /// Waits for the GPU and returns the result.
+    ///
+    /// # Errors
+    /// Returns an error if the query wasn't ended since the result was last read.
+    pub fn result(&self) -> Result<u64> {
+        if !self.pending.get() {
+            return Err(anyhow!("Query {} has no pending result", self.id));
+        }
+        self.read_result()
+    }
+
+    /// Starts rendering conditionally on the result of this occlusion query. Draw
+    /// calls are skipped by the GPU if no samples passed, without reading the result
+    /// back. Rendering is unconditional again once the returned guard is dropped.
+    ///
+    /// # Example
+    /// ```ignore
+    /// // `query` counted the samples of the bounding box of `mesh`
+    /// let _condition = query.begin_conditional_render(ConditionalRenderMode::NoWait)?;
+    /// mesh.render()?;
+    /// ```
+    ///
+    /// # Errors
+    /// Returns an error if this isn't an occlusion query, the query is active or was
+    /// never ended, or OpenGL reports an error, e.g. for nested conditional rendering.
+    pub fn begin_conditional_render(
+        &self,
+        mode: ConditionalRenderMode,
+    ) -> Result<ConditionalRender<'_>> {
+        if !self.target.is_occlusion() {
+            return Err(anyhow!("Query {} isn't an occlusion query", self.id));
+        }
+        if self.active.get() || !self.ended_once.get() {
+            return Err(anyhow!("Query {} has no result to render conditionally on", self.id));
+        }
+        with_backend(|backend| backend.begin_conditional_render(self.id, mode.to_gl_enum()));
+        check_gl_error()
+            .with_context(|| format!("Failed to begin conditional rendering on {}", self.id))?;
+        self.conditional.set(true);
+        Ok(ConditionalRender {
+            query: self,
+        })
+    }
+
+    fn read_result(&self) -> Result<u64> {
+        let result =
+            with_backend(|backend| backend.get_query_object_ui64(self.id, gl::QUERY_RESULT));
+        check_gl_error().with_context(|| format!("Failed to read query {}", self.id))?;
+        self.pending.set(false);
+        Ok(result)
+    }
+}
+
+impl Deletable for Query {
+    fn delete(&mut self) -> Result<()> {
+        if self.id != 0 {
+            resource_registry::unregister(ResourceKind::Query, self.id)?;
+            with_backend(|backend| backend.delete_query(self.id));
+            self.id = 0;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Query {
+    fn drop(&mut self) {
+        if let Err(err) = self.delete() {
+            eprintln!("Error while dropping Query: {}", err);
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - ConditionalRender -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Ends conditional rendering when dropped, see `Query::begin_conditional_render`.
+/// It borrows the query, which therefore can't be deleted meanwhile, and `begin`
+/// refuses to restart the query until the guard is dropped.
+#[must_use = "conditional rendering ends as soon as the guard is dropped"]
+pub struct ConditionalRender<'a> {
+    query: &'a Query,
+}
+
+impl Drop for ConditionalRender<'_> {
+    fn drop(&mut self) {
+        with_backend(|backend| backend.end_conditional_render());
+        self.query.conditional.set(false);
+        if let Err(err) = check_gl_error() {
+            eprintln!("Error while dropping ConditionalRender: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opengl::gl_backend::{install_backend, GlCall, RecordingGlBackend};
+
+    #[test]
+    fn test_occlusion_targets() {
+        assert!(QueryTarget::SamplesPassed.is_occlusion());
+        assert!(QueryTarget::AnySamplesPassed.is_occlusion());
+        assert!(QueryTarget::AnySamplesPassedConservative.is_occlusion());
+        assert!(!QueryTarget::PrimitivesGenerated.is_occlusion());
+        assert!(!QueryTarget::TimeElapsed.is_occlusion());
+        assert_eq!(ConditionalRenderMode::default().to_gl_enum(), gl::QUERY_NO_WAIT);
+    }
+
+    #[test]
+    fn test_begin_refuses_query_of_conditional_render() {
+        // Id 0 isn't deleted when dropped
+        let query = Query {
+            id: 0,
+            target: QueryTarget::SamplesPassed,
+            active: Cell::new(false),
+            pending: Cell::new(false),
+            ended_once: Cell::new(true),
+            conditional: Cell::new(true),
+        };
+        let err = query.begin().unwrap_err();
+        assert!(err.to_string().contains("conditional rendering"));
+        assert!(!query.is_active());
+    }
+
+    #[test]
+    fn test_pending_and_ended_transitions() {
+        let backend = RecordingGlBackend::new();
+        let _backend_guard = install_backend(backend.clone());
+        let query = Query::new(QueryTarget::SamplesPassed);
+        assert!(!query.is_active() && !query.is_pending() && !query.has_ended());
+        assert_eq!(query.try_result().unwrap(), None);
+        assert!(query.result().is_err());
+        assert!(query.end().is_err());
+        assert!(query.record_timestamp().is_err());
+
+        query.begin().unwrap();
+        assert!(query.is_active() && !query.is_pending());
+        assert_eq!(backend.active_query(gl::SAMPLES_PASSED), query.query_id());
+        assert!(query.begin().is_err());
+
+        query.end().unwrap();
+        assert!(!query.is_active() && query.is_pending() && query.has_ended());
+        assert_eq!(backend.active_query(gl::SAMPLES_PASSED), 0);
+
+        // The result stays pending until the GPU has finished
+        backend.set_query_result(query.query_id(), None);
+        assert_eq!(query.try_result().unwrap(), None);
+        assert!(query.is_pending());
+        backend.set_query_result(query.query_id(), Some(42));
+        assert_eq!(query.try_result().unwrap(), Some(42));
+        assert!(!query.is_pending() && query.has_ended());
+        assert_eq!(query.try_result().unwrap(), None);
+
+        // Restarting clears a result that was never read
+        query.begin().unwrap();
+        query.end().unwrap();
+        query.begin().unwrap();
+        assert!(!query.is_pending());
+        query.end().unwrap();
+        assert_eq!(query.result().unwrap(), 0);
+        assert!(check_gl_error().is_ok());
+    }
+
+    #[test]
+    fn test_failed_begin_leaves_query_inactive() {
+        let backend = RecordingGlBackend::new();
+        let _backend_guard = install_backend(backend.clone());
+        let first = Query::new(QueryTarget::TimeElapsed);
+        let second = Query::new(QueryTarget::TimeElapsed);
+
+        // Only one query per target can be active
+        first.begin().unwrap();
+        assert!(second.begin().is_err());
+        assert!(!second.is_active());
+        first.end().unwrap();
+        second.begin().unwrap();
+        second.end().unwrap();
+        assert!(!first.is_active() && !second.is_active());
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let backend = RecordingGlBackend::new();
+        let _backend_guard = install_backend(backend.clone());
+        let timestamp = Query::new(QueryTarget::Timestamp);
+        assert!(timestamp.begin().is_err());
+
+        timestamp.record_timestamp().unwrap();
+        assert!(timestamp.is_pending() && !timestamp.has_ended());
+        backend.set_query_result(timestamp.query_id(), Some(1_000));
+        assert_eq!(timestamp.result().unwrap(), 1_000);
+        assert!(!timestamp.is_pending());
+    }
+
+    #[test]
+    fn test_conditional_render_transitions() {
+        let backend = RecordingGlBackend::new();
+        let _backend_guard = install_backend(backend.clone());
+        let query = Query::new(QueryTarget::AnySamplesPassed);
+        assert!(query.begin_conditional_render(ConditionalRenderMode::Wait).is_err());
+        query.begin().unwrap();
+        assert!(query.begin_conditional_render(ConditionalRenderMode::Wait).is_err());
+        query.end().unwrap();
+
+        {
+            let _condition = query.begin_conditional_render(ConditionalRenderMode::Wait).unwrap();
+            assert!(backend.calls().contains(&GlCall::BeginConditionalRender {
+                query: query.query_id(),
+                mode: gl::QUERY_WAIT,
+            }));
+            assert!(query.begin().is_err());
+            assert!(!query.is_active());
+        }
+        assert_eq!(backend.calls().last(), Some(&GlCall::EndConditionalRender));
+        query.begin().unwrap();
+        query.end().unwrap();
+
+        let primitives = Query::new(QueryTarget::PrimitivesGenerated);
+        primitives.begin().unwrap();
+        primitives.end().unwrap();
+        assert!(primitives.begin_conditional_render(ConditionalRenderMode::NoWait).is_err());
+        assert!(check_gl_error().is_ok());
+    }
+}
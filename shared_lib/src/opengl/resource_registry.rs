@@ -0,0 +1,305 @@
+use once_cell::sync::Lazy;
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex, MutexGuard};
+use thiserror::Error;
+
+//////////////////////////////////////////////////////////////////////////////
+// - ResourceKind -
+//////////////////////////////////////////////////////////////////////////////
+
+/// The kinds of OpenGL objects tracked by the registry. Every kind has its own
+/// namespace of GL names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ResourceKind {
+    Buffer,
+    Texture,
+    VertexArray,
+    Shader,
+    Program,
+    Query,
+    TransformFeedback,
+    Framebuffer,
+    Renderbuffer,
+}
+
+impl Display for ResourceKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ResourceKind::Buffer => "Buffer",
+            ResourceKind::Texture => "Texture",
+            ResourceKind::VertexArray => "VertexArray",
+            ResourceKind::Shader => "Shader",
+            ResourceKind::Program => "Program",
+            ResourceKind::Query => "Query",
+            ResourceKind::TransformFeedback => "TransformFeedback",
+            ResourceKind::Framebuffer => "Framebuffer",
+            ResourceKind::Renderbuffer => "Renderbuffer",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - ResourceInfo -
+//////////////////////////////////////////////////////////////////////////////
+
+/// A live GPU resource, as returned by `live_resources`.
+#[derive(Debug, Clone)]
+pub struct ResourceInfo {
+    pub kind: ResourceKind,
+    pub gl_name: u32,
+    pub label: String,
+    /// Approximate size of the GPU storage in bytes, 0 if unknown.
+    pub byte_size: usize,
+    /// Where the resource was created, only captured in debug builds with
+    /// `RUST_LIB_BACKTRACE=1` or `RUST_BACKTRACE=1`, see `capture_backtrace`.
+    pub backtrace: Option<Arc<Backtrace>>,
+}
+
+impl Display for ResourceInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} \"{}\" ({} bytes)", self.kind, self.gl_name, self.label, self.byte_size)
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ResourceError {
+    #[error("{kind} {gl_name} was already deleted")]
+    AlreadyDeleted {
+        kind: ResourceKind,
+        gl_name: u32,
+    },
+    #[error("{kind} {gl_name} isn't owned by a registered wrapper")]
+    NotRegistered {
+        kind: ResourceKind,
+        gl_name: u32,
+    },
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - Registry -
+//////////////////////////////////////////////////////////////////////////////
+
+#[derive(Default)]
+struct Registry {
+    live: HashMap<(ResourceKind, u32), ResourceInfo>,
+    /// Names deleted since they were last registered, to tell double deletes apart
+    /// from names which were never registered.
+    deleted: HashSet<(ResourceKind, u32)>,
+}
+
+static REGISTRY: Lazy<Mutex<Registry>> = Lazy::new(|| Mutex::new(Registry::default()));
+
+fn registry() -> MutexGuard<'static, Registry> {
+    // A panic while holding the lock doesn't leave the maps inconsistent
+    REGISTRY.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Records a newly created GPU resource. The wrapper types call this when they
+/// create their GL object, so only code which creates GL objects itself needs it.
+///
+/// Registering a name which is still live replaces its entry, as OpenGL hands out
+/// names of deleted objects again.
+pub fn register(kind: ResourceKind, gl_name: u32, label: impl Into<String>, byte_size: usize) {
+    if gl_name == 0 {
+        return;
+    }
+    let info = ResourceInfo {
+        kind,
+        gl_name,
+        label: label.into(),
+        byte_size,
+        backtrace: capture_backtrace(),
+    };
+
+    let mut registry = registry();
+    registry.deleted.remove(&(kind, gl_name));
+    if registry.live.insert((kind, gl_name), info).is_some() {
+        eprintln!(
+            "{} {} was registered twice, was it deleted without the registry?",
+            kind, gl_name
+        );
+    }
+}
+
+/// Captures where a resource is created. Capturing is slow and resources like
+/// per-frame buffers are created often, so backtraces are only captured in debug
+/// builds and if enabled with the standard `RUST_LIB_BACKTRACE` or `RUST_BACKTRACE`
+/// environment variables. `Backtrace::capture` checks them only once.
+fn capture_backtrace() -> Option<Arc<Backtrace>> {
+    if !cfg!(debug_assertions) {
+        return None;
+    }
+    let backtrace = Backtrace::capture();
+    (backtrace.status() == BacktraceStatus::Captured).then(|| Arc::new(backtrace))
+}
+
+/// Removes a resource which is about to be deleted. Wrappers call this before
+/// deleting their GL object and skip the deletion on an error.
+///
+/// # Errors
+/// - `ResourceError::AlreadyDeleted` if the name was already unregistered.
+/// - `ResourceError::NotRegistered` if the name was never registered, e.g. because
+///   it belongs to a non-owning wrapper.
+pub fn unregister(kind: ResourceKind, gl_name: u32) -> Result<(), ResourceError> {
+    let mut registry = registry();
+    if registry.live.remove(&(kind, gl_name)).is_some() {
+        registry.deleted.insert((kind, gl_name));
+        Ok(())
+    } else if registry.deleted.contains(&(kind, gl_name)) {
+        Err(ResourceError::AlreadyDeleted {
+            kind,
+            gl_name,
+        })
+    } else {
+        Err(ResourceError::NotRegistered {
+            kind,
+            gl_name,
+        })
+    }
+}
+
+/// Checks if a resource is registered and wasn't deleted.
+pub fn is_live(kind: ResourceKind, gl_name: u32) -> bool {
+    registry().live.contains_key(&(kind, gl_name))
+}
+
+/// Replaces the debug label of a live resource.
+pub fn set_label(kind: ResourceKind, gl_name: u32, label: impl Into<String>) {
+    if let Some(info) = registry().live.get_mut(&(kind, gl_name)) {
+        info.label = label.into();
+    }
+}
+
+/// Updates the size of a live resource, e.g. after its storage grew.
+pub fn set_byte_size(kind: ResourceKind, gl_name: u32, byte_size: usize) {
+    if let Some(info) = registry().live.get_mut(&(kind, gl_name)) {
+        info.byte_size = byte_size;
+    }
+}
+
+/// Returns a snapshot of all live resources, ordered by kind and name.
+pub fn live_resources() -> Vec<ResourceInfo> {
+    let mut resources: Vec<_> = registry().live.values().cloned().collect();
+    resources.sort_by_key(|info| (info.kind, info.gl_name));
+    resources
+}
+
+/// Returns the total byte size of the live resources.
+pub fn live_byte_size() -> usize {
+    registry().live.values().map(|info| info.byte_size).sum()
+}
+
+/// Describes the given resources as leaked, including their creation backtraces
+/// if they were captured. Returns `None` if there are no resources.
+pub fn leak_report(resources: &[ResourceInfo]) -> Option<String> {
+    if resources.is_empty() {
+        return None;
+    }
+    let total_size: usize = resources.iter().map(|info| info.byte_size).sum();
+    let mut report =
+        format!("{} GPU resources ({} bytes) are still alive:\n", resources.len(), total_size);
+    for info in resources {
+        report.push_str(&format!("  {}\n", info));
+        if let Some(backtrace) = &info.backtrace {
+            for line in backtrace.to_string().lines() {
+                report.push_str(&format!("      {}\n", line));
+            }
+        }
+    }
+    Some(report)
+}
+
+/// Prints a leak report of all live resources to stderr, see `leak_report`. This
+/// is called when the `SdlWindow` and its OpenGL context are torn down.
+pub fn print_leak_report() {
+    if let Some(report) = leak_report(&live_resources()) {
+        eprintln!("{}", report);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The registry is shared by all tests, so every test uses its own names
+
+    #[test]
+    fn test_register_and_unregister() {
+        register(ResourceKind::Buffer, 100_001, "vertices", 256);
+        register(ResourceKind::Texture, 100_001, "albedo", 1024);
+        assert!(is_live(ResourceKind::Buffer, 100_001));
+
+        set_byte_size(ResourceKind::Buffer, 100_001, 512);
+        set_label(ResourceKind::Buffer, 100_001, "positions");
+        let info = live_resources()
+            .into_iter()
+            .find(|info| info.kind == ResourceKind::Buffer && info.gl_name == 100_001)
+            .unwrap();
+        assert_eq!(info.label, "positions");
+        assert_eq!(info.byte_size, 512);
+        // Backtraces are only kept if they were enabled and captured
+        if let Some(backtrace) = &info.backtrace {
+            assert_eq!(backtrace.status(), BacktraceStatus::Captured);
+        }
+
+        // Names are separate per kind
+        assert_eq!(unregister(ResourceKind::Buffer, 100_001), Ok(()));
+        assert!(!is_live(ResourceKind::Buffer, 100_001));
+        assert!(is_live(ResourceKind::Texture, 100_001));
+        assert_eq!(unregister(ResourceKind::Texture, 100_001), Ok(()));
+    }
+
+    #[test]
+    fn test_invalid_deletes() {
+        register(ResourceKind::VertexArray, 100_002, "quad", 0);
+        assert_eq!(unregister(ResourceKind::VertexArray, 100_002), Ok(()));
+        assert_eq!(
+            unregister(ResourceKind::VertexArray, 100_002),
+            Err(ResourceError::AlreadyDeleted {
+                kind: ResourceKind::VertexArray,
+                gl_name: 100_002
+            })
+        );
+        assert_eq!(
+            unregister(ResourceKind::VertexArray, 100_003),
+            Err(ResourceError::NotRegistered {
+                kind: ResourceKind::VertexArray,
+                gl_name: 100_003
+            })
+        );
+
+        // Reused names can be deleted again
+        register(ResourceKind::VertexArray, 100_002, "quad", 0);
+        assert_eq!(unregister(ResourceKind::VertexArray, 100_002), Ok(()));
+    }
+
+    #[test]
+    fn test_leak_report() {
+        assert!(leak_report(&[]).is_none());
+
+        let resources = [
+            ResourceInfo {
+                kind: ResourceKind::Program,
+                gl_name: 3,
+                label: "shaders/sprite".to_string(),
+                byte_size: 0,
+                backtrace: None,
+            },
+            ResourceInfo {
+                kind: ResourceKind::Buffer,
+                gl_name: 7,
+                label: "instances".to_string(),
+                byte_size: 4096,
+                backtrace: None,
+            },
+        ];
+        let report = leak_report(&resources).unwrap();
+        assert!(report.starts_with("2 GPU resources (4096 bytes) are still alive"));
+        assert!(report.contains("Program 3 \"shaders/sprite\" (0 bytes)"));
+        assert!(report.contains("Buffer 7 \"instances\" (4096 bytes)"));
+    }
+}
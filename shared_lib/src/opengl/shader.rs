@@ -1,13 +1,13 @@
 use crate::gl_types::ShaderType;
 use crate::gl_utils::check_gl_error;
-use crate::string_utils::create_whitespace_cstring_with_len;
+use crate::opengl::gl_backend::with_backend;
+use crate::opengl::resource_registry::{self, ResourceKind};
 use anyhow::{anyhow, Context, Result};
-use gl::types::{GLchar, GLenum, GLint};
+use gl::types::{GLenum, GLint};
 use std::ffi::CString;
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
-use std::ptr;
 use crate::gl_traits::Deletable;
 
 //////////////////////////////////////////////////////////////////////////////
@@ -22,34 +22,31 @@ pub struct Shader {
 
 impl Shader {
     pub fn from_source(source: &str, shader_type: ShaderType) -> Result<Shader> {
-        let id = unsafe {
-            let shader_type = shader_type.to_gl_enum() as GLenum;
-            let shader = gl::CreateShader(shader_type);
-            let error = gl::GetError();
-            if error != gl::NO_ERROR {
-                println!("Error !!");
-            }
-            let c_str = CString::new(source.as_bytes())
-                .context("Failed to create CString from shader source")?;
-            gl::ShaderSource(shader, 1, &c_str.as_ptr(), ptr::null());
+        let c_str = CString::new(source.as_bytes())
+            .context("Failed to create CString from shader source")?;
+        let id = with_backend(|backend| backend.create_shader(shader_type.to_gl_enum() as GLenum));
+        check_gl_error().context("Failed to create shader")?;
+
+        // The shader is deleted again if it doesn't compile
+        let compile = || -> Result<()> {
+            with_backend(|backend| backend.shader_source(id, &c_str));
             check_gl_error()?;
-
-            gl::CompileShader(shader);
+            with_backend(|backend| backend.compile_shader(id));
             check_gl_error()?;
 
             // Error checking
-            let mut success = gl::FALSE as GLint;
-            gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
-            if success != gl::TRUE as GLint {
-                let mut len = 0;
-                gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut len);
-                let error = create_whitespace_cstring_with_len(len as usize);
-                gl::GetShaderInfoLog(shader, len, ptr::null_mut(), error.as_ptr() as *mut GLchar);
-                return Err(anyhow!(error.into_string().unwrap()));
-            }
-
-            shader
+            with_backend(|backend| {
+                if backend.get_shader_iv(id, gl::COMPILE_STATUS) != gl::TRUE as GLint {
+                    return Err(anyhow!(backend.get_shader_info_log(id)));
+                }
+                Ok(())
+            })
         };
+        if let Err(err) = compile() {
+            with_backend(|backend| backend.delete_shader(id));
+            return Err(err);
+        }
+        resource_registry::register(ResourceKind::Shader, id, format!("{} shader", shader_type), 0);
 
         Ok(Shader {
             id,
@@ -180,11 +177,10 @@ impl Deletable for Shader {
     /// shader.delete(); // Explicitly delete the shader when done
     /// ```
     fn delete(&mut self) -> Result<()> {
-        unsafe {
-            if self.id != 0 {
-                gl::DeleteShader(self.id);
-                self.id = 0;
-            }
+        if self.id != 0 {
+            resource_registry::unregister(ResourceKind::Shader, self.id)?;
+            with_backend(|backend| backend.delete_shader(self.id));
+            self.id = 0;
         }
         Ok(())
     }
@@ -198,3 +194,43 @@ impl Drop for Shader {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opengl::gl_backend::{install_backend, GlCall, RecordingGlBackend};
+
+    #[test]
+    fn test_from_source_reports_compile_errors() {
+        let backend = RecordingGlBackend::new();
+        let _guard = install_backend(backend.clone());
+
+        let shader = Shader::load_vertex_shader("void main() {}").unwrap();
+        let id = shader.get_shader_id();
+        assert_eq!(
+            backend.calls()[..3],
+            [
+                GlCall::CreateShader {
+                    shader_type: gl::VERTEX_SHADER,
+                    shader: id,
+                },
+                GlCall::ShaderSource(id),
+                GlCall::CompileShader(id),
+            ]
+        );
+        drop(shader);
+        assert_eq!(backend.live_objects(), 0);
+
+        // The info log is returned and the broken shader deleted
+        let Err(err) = Shader::load_fragment_shader("void main() {\n#error broken\n}") else {
+            panic!("Expected a compile error");
+        };
+        assert!(format!("{:#}", err).contains("0:2: error: #error broken"));
+        assert_eq!(backend.live_objects(), 0);
+
+        // Sources with null bytes are rejected before creating a shader
+        backend.take_calls();
+        assert!(Shader::load_fragment_shader("void main() {}\0").is_err());
+        assert!(backend.calls().is_empty());
+    }
+}
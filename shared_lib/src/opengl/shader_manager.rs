@@ -1,11 +1,18 @@
+use crate::gl_types::ShaderType;
+use crate::opengl::debug_output::label_object;
+use crate::opengl::resource_registry::ResourceKind;
 use crate::opengl::shader_program::ShaderProgram;
 use anyhow::{anyhow, Result};
+use cgmath::{Matrix4, SquareMatrix};
 use std::collections::HashMap;
 
 #[derive(Default, Debug)]
 pub struct ShaderManager {
     shaders: HashMap<String, Vec<String>>,
     shader_programs: HashMap<String, ShaderProgram>,
+    shader_errors: HashMap<String, String>,
+    graceful_mode: bool,
+    fallback_program: Option<ShaderProgram>,
 }
 
 impl ShaderManager {
@@ -15,38 +22,91 @@ impl ShaderManager {
     }
 
     pub fn get_shader(&mut self, key: &str) -> Result<&ShaderProgram> {
+        let use_fallback = self.prepare_shader(key)?;
+        let shader_program = if use_fallback {
+            self.fallback_program.as_ref()
+        } else {
+            self.shader_programs.get(key)
+        };
+        shader_program.ok_or_else(|| anyhow!("No shader found for key: {}", key))
+    }
+
+    pub fn get_shader_mut(&mut self, key: &str) -> Result<&mut ShaderProgram> {
+        let use_fallback = self.prepare_shader(key)?;
+        let shader_program = if use_fallback {
+            self.fallback_program.as_mut()
+        } else {
+            self.shader_programs.get_mut(key)
+        };
+        shader_program.ok_or_else(|| anyhow!("No shader found for key: {}", key))
+    }
+
+    /// Makes sure the shader program for `key` is compiled. Returns true if the
+    /// fallback program has to be used in its place.
+    fn prepare_shader(&mut self, key: &str) -> Result<bool> {
         // Check if the shader already exists.
         if self.shader_programs.contains_key(key) {
-            // Safe to unwrap because we just checked it exists.
-            return Ok(self.shader_programs.get(key).unwrap());
+            return Ok(false);
         }
 
-        // If the shader program does not exist, check if source is available to compile
-        if !self.shaders.contains_key(key) {
-            return Err(anyhow!("No shader found for key: {}", key));
+        // Don't recompile shaders which failed already in graceful mode
+        if self.graceful_mode {
+            if let Some(shader_error) = self.shader_errors.get(key) {
+                let shader_error = shader_error.clone();
+                return self.create_fallback_program().map(|_| true).map_err(|e| {
+                    anyhow!("{} (creating the fallback program failed: {:#})", shader_error, e)
+                });
+            }
         }
 
-        // Compile the shader, handling any error from compilation
-        self.compile_shader(key)
+        // If the shader program does not exist, check if source is available to compile
+        let result = if self.shaders.contains_key(key) {
+            self.compile_shader(key).map(|_| ())
+        } else {
+            Err(anyhow!("No shader found for key: {}", key))
+        };
+
+        match result {
+            Ok(()) => Ok(false),
+            Err(e) if self.graceful_mode => {
+                let shader_error = format!("{:#}", e);
+                eprintln!("Using fallback for shader {}: {}", key, shader_error);
+                self.shader_errors.insert(key.to_string(), shader_error);
+                self.prepare_shader(key)
+            }
+            Err(e) => Err(e),
+        }
     }
 
-    pub fn get_shader_mut(&mut self, key: &str) -> Result<&mut ShaderProgram> {
-        // Check if the shader already exists.
-        if self.shader_programs.contains_key(key) {
-            // Safe to unwrap because we just checked it exists.
-            return Ok(self.shader_programs.get_mut(key).unwrap());
+    /// Compiles the built-in solid magenta program, if not done already.
+    fn create_fallback_program(&mut self) -> Result<()> {
+        if self.fallback_program.is_some() {
+            return Ok(());
         }
 
-        // If the shader program does not exist, check if source is available to compile
-        if !self.shaders.contains_key(key) {
-            return Err(anyhow!("No shader found for key: {}", key));
+        let mut shader_program = ShaderProgram::new();
+        shader_program.add_source(
+            ShaderType::Vertex,
+            include_bytes!("../../resources/shaders/fallback_magenta.vert"),
+        )?;
+        shader_program.add_source(
+            ShaderType::Fragment,
+            include_bytes!("../../resources/shaders/fallback_magenta.frag"),
+        )?;
+        shader_program.compile()?;
+        shader_program.set_ignore_missing_uniforms(true);
+        label_object(ResourceKind::Program, shader_program.program_id(), "fallback_magenta");
+
+        // Scenes which don't set the matrices still get a visible result
+        let identity = Matrix4::<f32>::identity();
+        shader_program.activate();
+        for name in ["model", "view", "projection"] {
+            shader_program.set_uniform_matrix(name, false, &identity)?;
         }
+        shader_program.deactivate();
 
-        // Compile the shader, handling any error from compilation
-        self.compile_shader(key)?;
-
-        // Now retrieve and return a mutable reference to the newly compiled shader
-        Ok(self.shader_programs.get_mut(key).unwrap())
+        self.fallback_program = Some(shader_program);
+        Ok(())
     }
 
     pub fn compile_shader(&mut self, key: &str) -> Result<&ShaderProgram> {
@@ -56,6 +116,7 @@ impl ShaderManager {
 
             // Compile the shader program and add if to the map
             let shader_program = ShaderProgram::from_files(&path_slices)?;
+            label_object(ResourceKind::Program, shader_program.program_id(), key);
             self.shader_programs.insert(key.to_string(), shader_program);
 
             // Retrieve a reference to the newly inserted shader to return it
@@ -78,6 +139,36 @@ impl ShaderManager {
         self.shader_programs.keys().map(|sp| sp.to_string()).collect()
     }
 
+    /// Enables or disables the graceful mode.
+    ///
+    /// In graceful mode a shader program which fails to load, compile or link is
+    /// replaced by a built-in program that renders everything in solid magenta.
+    /// Uniforms which the fallback program doesn't know are silently ignored.
+    /// The original error is recorded and can be queried with `shader_error`; the
+    /// shader is not compiled again until its error was removed with `clear_error`.
+    pub fn set_graceful_mode(&mut self, enabled: bool) {
+        self.graceful_mode = enabled;
+    }
+
+    pub fn graceful_mode(&self) -> bool {
+        self.graceful_mode
+    }
+
+    /// Returns the recorded error of the shader program with the given key.
+    pub fn shader_error(&self, key: &str) -> Option<&str> {
+        self.shader_errors.get(key).map(|error| error.as_str())
+    }
+
+    pub fn has_error(&self, key: &str) -> bool {
+        self.shader_errors.contains_key(key)
+    }
+
+    /// Removes the recorded error, so that the next request compiles the shader
+    /// program again.
+    pub fn clear_error(&mut self, key: &str) {
+        self.shader_errors.remove(key);
+    }
+
     /// Attempts to activate a shader by its key.
     ///
     /// This method tries to retrieve a shader associated with the given key and activate it. 
@@ -1,21 +1,24 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::ffi::CString;
+use std::fs;
 use std::str::from_utf8;
-use std::{fs, ptr};
 
 use crate::check_gl_panic;
 use anyhow::{anyhow, Context, Result};
-use gl::types::{GLchar, GLint, GLuint};
+use gl::types::{GLenum, GLint, GLuint};
 
 use crate::core::file_utils;
 use crate::gl_traits::Deletable;
-use crate::gl_types::ShaderType;
+use crate::gl_types::{ShaderType, VertexDataType};
 use crate::gl_utils::check_gl_error;
+use crate::opengl::gl_backend::with_backend;
+use crate::opengl::resource_registry::{self, ResourceKind};
 use crate::opengl::shader::Shader;
 use crate::opengl::shader_uniform_matrix::UniformMatrix;
 use crate::opengl::shader_uniform_value::UniformValue;
-use crate::string_utils::{create_whitespace_cstring_with_len, readable_bytes};
+use crate::opengl::transform_feedback::{TransformFeedbackMode, TransformFeedbackVaryings};
+use crate::string_utils::readable_bytes;
 
 //////////////////////////////////////////////////////////////////////////////
 // - ShaderProgram -
@@ -27,6 +30,8 @@ pub struct ShaderProgram {
     uniform_ids: RefCell<HashMap<String, i32>>,
     shader_sources: HashMap<ShaderType, String>,
     shader_files: HashMap<ShaderType, String>,
+    ignore_missing_uniforms: bool,
+    transform_feedback_varyings: Option<TransformFeedbackVaryings>,
 }
 
 impl ShaderProgram {
@@ -36,11 +41,13 @@ impl ShaderProgram {
             uniform_ids: RefCell::new(HashMap::new()),
             shader_sources: HashMap::new(),
             shader_files: HashMap::new(),
+            ignore_missing_uniforms: false,
+            transform_feedback_varyings: None,
         }
     }
 
     pub fn from_files(shader_files: &[&str]) -> Result<ShaderProgram> {
-        let program_id = unsafe { gl::CreateProgram() };
+        let program_id = with_backend(|backend| backend.create_program());
 
         let mut shader_types_and_files = Vec::<(ShaderType, String)>::new();
 
@@ -59,46 +66,34 @@ impl ShaderProgram {
             let mut shader: Shader = Shader::from_file(filename, shader_type)
                 .with_context(|| format!("Failed loading shader: {}", filename))?;
 
-            unsafe {
-                gl::AttachShader(program_id, shader.get_shader_id());
-                check_gl_error()?;
-            }
+            with_backend(|backend| backend.attach_shader(program_id, shader.get_shader_id()));
+            check_gl_error()?;
 
             shaders.push(shader);
         }
 
         // Link program
-        unsafe {
-            gl::LinkProgram(program_id);
-            check_gl_error()?;
-
-            // Check for linking errors
-            let mut success = gl::FALSE as GLint;
-            gl::GetProgramiv(program_id, gl::LINK_STATUS, &mut success);
-            if success != gl::TRUE as GLint {
-                let mut len = 0;
-                gl::GetProgramiv(program_id, gl::INFO_LOG_LENGTH, &mut len);
-                let error = create_whitespace_cstring_with_len(len as usize);
-                gl::GetProgramInfoLog(
-                    program_id,
-                    len,
-                    ptr::null_mut(),
-                    error.as_ptr() as *mut GLchar,
-                );
-                return Err(anyhow::anyhow!(error.to_string_lossy().into_owned()));
-            }
+        with_backend(|backend| backend.link_program(program_id));
+        check_gl_error()?;
+
+        // Check for linking errors
+        let link_error = with_backend(|backend| {
+            let linked = backend.get_program_iv(program_id, gl::LINK_STATUS) == gl::TRUE as GLint;
+            (!linked).then(|| backend.get_program_info_log(program_id))
+        });
+        if let Some(link_error) = link_error {
+            return Err(anyhow::anyhow!(link_error));
         }
 
         // Detach shaders after successful linking
-        unsafe {
-            for shader in shaders.iter_mut() {
-                gl::DetachShader(program_id, shader.get_shader_id());
-                shader.delete()?;
-            }
+        for shader in shaders.iter_mut() {
+            with_backend(|backend| backend.detach_shader(program_id, shader.get_shader_id()));
+            shader.delete()?;
         }
 
         // Initialize a ShaderProgram with a specific program ID and
         // add shader types and source files
+        resource_registry::register(ResourceKind::Program, program_id, shader_files.join(", "), 0);
         let mut shader_program = ShaderProgram::default();
         shader_program.id = program_id;
         shader_program.shader_files.extend(shader_types_and_files);
@@ -113,23 +108,15 @@ impl ShaderProgram {
     }
 
     pub fn activate(&self) {
-        unsafe {
-            gl::UseProgram(self.id);
-        }
+        with_backend(|backend| backend.use_program(self.id));
     }
 
     pub fn deactivate(&self) {
-        unsafe {
-            gl::UseProgram(0);
-        }
+        with_backend(|backend| backend.use_program(0));
     }
 
     pub fn is_active(&self) -> bool {
-        unsafe {
-            let mut program_id: GLint = 0;
-            gl::GetIntegerv(gl::CURRENT_PROGRAM, &mut program_id);
-            program_id == self.id as i32
-        }
+        with_backend(|backend| backend.get_integer(gl::CURRENT_PROGRAM)) == self.id as i32
     }
 
     pub fn clear_uniform_locations(&self) {
@@ -137,6 +124,20 @@ impl ShaderProgram {
         uniforms.clear();
     }
 
+    /// Makes `set_uniform` and `set_uniform_matrix` succeed silently for uniforms
+    /// that don't exist in the program.
+    ///
+    /// This is used for stand-in programs (e.g. the fallback program of the
+    /// `ShaderManager`) which only implement a subset of the uniforms of the
+    /// program they replace.
+    pub fn set_ignore_missing_uniforms(&mut self, ignore: bool) {
+        self.ignore_missing_uniforms = ignore;
+    }
+
+    pub fn ignores_missing_uniforms(&self) -> bool {
+        self.ignore_missing_uniforms
+    }
+
     /// Retrieves the location of a uniform variable within the shader program.
     ///
     /// This method looks up the location of a uniform variable in the shader program.
@@ -167,7 +168,7 @@ impl ShaderProgram {
         }
 
         let c_str = CString::new(name).unwrap();
-        let location = unsafe { gl::GetUniformLocation(self.id, c_str.as_ptr()) };
+        let location = with_backend(|backend| backend.get_uniform_location(self.id, &c_str));
 
         if location != -1 {
             let mut uniforms = self.uniform_ids.borrow_mut();
@@ -212,7 +213,10 @@ impl ShaderProgram {
     /// The actual setting of the uniform is delegated to the `set_uniform` method of the `UniformValue` trait,
     /// which must be implemented for each type that can be used as a uniform.
     pub fn set_uniform<T: UniformValue>(&self, name: &str, value: T) -> Result<()> {
-        let location = self.get_uniform_location(name)?;
+        let location = match self.get_uniform_location(name) {
+            Err(_) if self.ignore_missing_uniforms => return Ok(()),
+            location => location?,
+        };
         if location == -1 {
             return Err(anyhow!("Uniform '{}' not found in shader", name));
         }
@@ -261,7 +265,10 @@ impl ShaderProgram {
         transpose: bool,
         matrix: &T,
     ) -> Result<()> {
-        let location = self.get_uniform_location(name)?;
+        let location = match self.get_uniform_location(name) {
+            Err(_) if self.ignore_missing_uniforms => return Ok(()),
+            location => location?,
+        };
         if location == -1 {
             return Err(anyhow!("Uniform '{}' not found in shader", name));
         }
@@ -340,39 +347,56 @@ impl ShaderProgram {
     /// this size. The method uses OpenGLs `gl::GetActiveUniform` function for querying uniform
     /// information.
     pub fn get_all_uniform_names(&self) -> Result<Vec<String>> {
-        let mut num_uniforms = 0;
-        unsafe {
-            gl::GetProgramiv(self.id, gl::ACTIVE_UNIFORMS, &mut num_uniforms);
-        }
+        let num_uniforms =
+            with_backend(|backend| backend.get_program_iv(self.id, gl::ACTIVE_UNIFORMS));
 
         let mut names = Vec::new();
         for i in 0..num_uniforms {
-            let mut len = 0;
-            let mut size = 0;
-            let mut u_type = 0;
-            let mut name_buf = vec![0; 256];
-
-            unsafe {
-                gl::GetActiveUniform(
-                    self.id,
-                    i as GLuint,
-                    name_buf.len() as i32,
-                    &mut len,
-                    &mut size,
-                    &mut u_type,
-                    name_buf.as_mut_ptr() as *mut GLchar,
-                );
+            match with_backend(|backend| backend.get_active_uniform(self.id, i as GLuint)) {
+                Some(uniform) => names.push(uniform.name),
+                None => {
+                    return Err(anyhow!("Failed to retrieve the name for uniform at index {}", i))
+                }
             }
+        }
 
-            if len > 0 {
-                let name = String::from_utf8_lossy(&name_buf[..len as usize]).to_string();
-                names.push(name);
-            } else {
-                return Err(anyhow!("Failed to retrieve the name for uniform at index {}", i));
+        Ok(names)
+    }
+
+    /// Returns the location of the vertex attribute `name`, or `None` if the program
+    /// has no active attribute with that name.
+    pub fn get_attribute_location(&self, name: &str) -> Option<u32> {
+        let c_str = CString::new(name).ok()?;
+        let location = with_backend(|backend| backend.get_attrib_location(self.id, &c_str));
+        (location >= 0).then_some(location as u32)
+    }
+
+    /// Retrieves all active vertex attributes of the program together with their
+    /// locations and GLSL types.
+    ///
+    /// Built-in attributes like `gl_VertexID` have no location and are skipped.
+    pub fn get_active_attributes(&self) -> Result<Vec<ActiveAttribute>> {
+        let num_attributes =
+            with_backend(|backend| backend.get_program_iv(self.id, gl::ACTIVE_ATTRIBUTES));
+
+        let mut attributes = Vec::new();
+        for i in 0..num_attributes {
+            let Some(attribute) =
+                with_backend(|backend| backend.get_active_attrib(self.id, i as GLuint))
+            else {
+                return Err(anyhow!("Failed to retrieve the name for attribute at index {}", i));
+            };
+            if let Some(location) = self.get_attribute_location(&attribute.name) {
+                attributes.push(ActiveAttribute {
+                    name: attribute.name,
+                    location,
+                    gl_type: attribute.gl_type,
+                    size: attribute.size,
+                });
             }
         }
 
-        Ok(names)
+        Ok(attributes)
     }
 
     pub fn add_file(&mut self, r#type: ShaderType, file: &str) -> Result<()> {
@@ -394,6 +418,59 @@ impl ShaderProgram {
         Ok(())
     }
 
+    /// Declares the output variables of the vertex (or geometry) shader which are
+    /// captured by a `TransformFeedback`. The varyings are applied when the program is
+    /// linked by `compile`.
+    ///
+    /// # Errors
+    /// Returns an error if the program is already compiled, or if `names` is empty.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut program = ShaderProgram::new();
+    /// program.add_file(ShaderType::Vertex, "shaders/particles/update.vert")?;
+    /// program.set_transform_feedback_varyings(
+    ///     &["out_position", "out_velocity"],
+    ///     TransformFeedbackMode::Interleaved,
+    /// )?;
+    /// program.compile()?;
+    /// ```
+    pub fn set_transform_feedback_varyings(
+        &mut self,
+        names: &[&str],
+        mode: TransformFeedbackMode,
+    ) -> Result<()> {
+        if self.id != 0 {
+            return Err(anyhow!(
+                "Transform feedback varyings have to be set before program {} is linked",
+                self.id
+            ));
+        }
+        if names.is_empty() {
+            return Err(anyhow!("No transform feedback varyings given"));
+        }
+        self.transform_feedback_varyings = Some(TransformFeedbackVaryings {
+            names: names.iter().map(|name| name.to_string()).collect(),
+            mode,
+        });
+        Ok(())
+    }
+
+    pub fn transform_feedback_varyings(&self) -> Option<&TransformFeedbackVaryings> {
+        self.transform_feedback_varyings.as_ref()
+    }
+
+    /// Describes the program by its shader files, used as label of the GPU resource.
+    fn label(&self) -> String {
+        let mut files: Vec<_> = self.shader_files.values().map(String::as_str).collect();
+        files.sort();
+        if files.is_empty() {
+            "ShaderProgram (sources)".to_string()
+        } else {
+            files.join(", ")
+        }
+    }
+
     pub fn is_type_defined(&self, r#type: &ShaderType) -> bool {
         self.shader_sources.contains_key(r#type) || self.shader_files.contains_key(r#type)
     }
@@ -409,39 +486,71 @@ impl ShaderProgram {
             let source = CString::new(shader_source.1.as_bytes())?;
             shader_sources.insert(shader_source.0.clone(), source);
         }
+        let varying_names = match &self.transform_feedback_varyings {
+            Some(varyings) => varyings
+                .names
+                .iter()
+                .map(|name| CString::new(name.as_bytes()))
+                .collect::<Result<Vec<_>, _>>()?,
+            None => Vec::new(),
+        };
 
-        unsafe {
-            let shader_program = gl::CreateProgram();
-            check_gl_panic!("Failed to create shader program");
-
-            let mut shader_ids = Vec::new();
-
-            println!("Shader program created: {}", shader_program);
-
-            // Compile shaders
-            for shader_source in shader_sources {
-                let shader_type_name = shader_source.0.to_string();
-                let shader = gl::CreateShader(shader_source.0.into());
-                gl::ShaderSource(shader, 1, &shader_source.1.as_ptr(), ptr::null());
-                gl::CompileShader(shader);
-                check_compile_errors(shader, &shader_type_name)?;
-                check_gl_panic!("Failed to compile shader");
-                gl::AttachShader(shader_program, shader);
-                check_gl_panic!("Attempt to attach a shader failed");
-                shader_ids.push(shader);
+        let shader_program = with_backend(|backend| backend.create_program());
+        check_gl_panic!("Failed to create shader program");
+
+        let mut shader_ids = Vec::new();
+
+        println!("Shader program created: {}", shader_program);
+
+        // Compile shaders
+        for shader_source in shader_sources {
+            let shader_type_name = shader_source.0.to_string();
+            let shader = with_backend(|backend| {
+                let shader = backend.create_shader(shader_source.0.into());
+                backend.shader_source(shader, &shader_source.1);
+                backend.compile_shader(shader);
+                shader
+            });
+            check_compile_errors(shader, &shader_type_name)?;
+            check_gl_panic!("Failed to compile shader");
+            with_backend(|backend| backend.attach_shader(shader_program, shader));
+            check_gl_panic!("Attempt to attach a shader failed");
+            shader_ids.push(shader);
+        }
+
+        // Varyings only take effect when the program is linked
+        if let Some(varyings) = &self.transform_feedback_varyings {
+            with_backend(|backend| {
+                backend.transform_feedback_varyings(
+                    shader_program,
+                    &varying_names,
+                    varyings.mode.to_gl_enum(),
+                )
+            });
+            if let Err(err) = check_gl_error() {
+                with_backend(|backend| {
+                    for shader_id in shader_ids {
+                        backend.delete_shader(shader_id);
+                    }
+                    backend.delete_program(shader_program);
+                });
+                return Err(err.context("Failed to set transform feedback varyings"));
             }
+        }
 
-            // Link program
-            gl::LinkProgram(shader_program);
-            check_compile_errors(shader_program, "PROGRAM")?;
+        // Link program
+        with_backend(|backend| backend.link_program(shader_program));
+        check_compile_errors(shader_program, "PROGRAM")?;
 
-            // Delete shaders
+        // Delete shaders
+        with_backend(|backend| {
             for shader_id in shader_ids {
-                gl::DeleteShader(shader_id);
+                backend.delete_shader(shader_id);
             }
+        });
 
-            self.id = shader_program as u32;
-        }
+        self.id = shader_program;
+        resource_registry::register(ResourceKind::Program, self.id, self.label(), 0);
 
         Ok(())
     }
@@ -491,12 +600,115 @@ impl ShaderProgram {
     // fragment_shader.delete()?;
 }
 
+//////////////////////////////////////////////////////////////////////////////
+// - ActiveAttribute -
+//////////////////////////////////////////////////////////////////////////////
+
+/// The scalar type of a vertex attribute as declared in GLSL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeScalar {
+    Float,
+    Int,
+    UnsignedInt,
+    Double,
+}
+
+/// An active vertex attribute of a linked shader program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveAttribute {
+    pub name: String,
+    pub location: u32,
+    /// The GLSL type, e.g. `gl::FLOAT_VEC3`.
+    pub gl_type: GLenum,
+    /// The number of array elements, 1 for attributes which aren't arrays.
+    pub size: i32,
+}
+
+impl ActiveAttribute {
+    /// Returns the scalar type, the number of columns and the number of rows of the
+    /// GLSL type. Vectors have a single column. `None` is returned for unknown types.
+    pub fn shape(&self) -> Option<(AttributeScalar, u8, u8)> {
+        use AttributeScalar::*;
+        let shape = match self.gl_type {
+            gl::FLOAT => (Float, 1, 1),
+            gl::FLOAT_VEC2 => (Float, 1, 2),
+            gl::FLOAT_VEC3 => (Float, 1, 3),
+            gl::FLOAT_VEC4 => (Float, 1, 4),
+            gl::FLOAT_MAT2 => (Float, 2, 2),
+            gl::FLOAT_MAT3 => (Float, 3, 3),
+            gl::FLOAT_MAT4 => (Float, 4, 4),
+            gl::FLOAT_MAT2x3 => (Float, 2, 3),
+            gl::FLOAT_MAT2x4 => (Float, 2, 4),
+            gl::FLOAT_MAT3x2 => (Float, 3, 2),
+            gl::FLOAT_MAT3x4 => (Float, 3, 4),
+            gl::FLOAT_MAT4x2 => (Float, 4, 2),
+            gl::FLOAT_MAT4x3 => (Float, 4, 3),
+            gl::INT => (Int, 1, 1),
+            gl::INT_VEC2 => (Int, 1, 2),
+            gl::INT_VEC3 => (Int, 1, 3),
+            gl::INT_VEC4 => (Int, 1, 4),
+            gl::UNSIGNED_INT => (UnsignedInt, 1, 1),
+            gl::UNSIGNED_INT_VEC2 => (UnsignedInt, 1, 2),
+            gl::UNSIGNED_INT_VEC3 => (UnsignedInt, 1, 3),
+            gl::UNSIGNED_INT_VEC4 => (UnsignedInt, 1, 4),
+            gl::DOUBLE => (Double, 1, 1),
+            gl::DOUBLE_VEC2 => (Double, 1, 2),
+            gl::DOUBLE_VEC3 => (Double, 1, 3),
+            gl::DOUBLE_VEC4 => (Double, 1, 4),
+            gl::DOUBLE_MAT2 => (Double, 2, 2),
+            gl::DOUBLE_MAT3 => (Double, 3, 3),
+            gl::DOUBLE_MAT4 => (Double, 4, 4),
+            _ => return None,
+        };
+        Some(shape)
+    }
+
+    /// Returns the GLSL name of the type for error messages, e.g. `"ivec3"`.
+    pub fn type_name(&self) -> String {
+        let Some((scalar, columns, rows)) = self.shape() else {
+            return format!("0x{:X}", self.gl_type);
+        };
+        let prefix = match scalar {
+            AttributeScalar::Float => "",
+            AttributeScalar::Int => "i",
+            AttributeScalar::UnsignedInt => "u",
+            AttributeScalar::Double => "d",
+        };
+        match (columns, rows) {
+            (1, 1) => match scalar {
+                AttributeScalar::Float => "float".to_string(),
+                AttributeScalar::Int => "int".to_string(),
+                AttributeScalar::UnsignedInt => "uint".to_string(),
+                AttributeScalar::Double => "double".to_string(),
+            },
+            (1, rows) => format!("{}vec{}", prefix, rows),
+            (columns, rows) if columns == rows => format!("{}mat{}", prefix, columns),
+            (columns, rows) => format!("{}mat{}x{}", prefix, columns, rows),
+        }
+    }
+
+    /// Checks if vertex data of `data_type` can be read by the attribute.
+    ///
+    /// Integer attributes need integer data which isn't normalized, double attributes
+    /// need double data. Float attributes read every other kind of data, including
+    /// normalized integers.
+    pub fn accepts_data_type(&self, data_type: VertexDataType, normalized: bool) -> bool {
+        let is_integer = data_type.is_integer() && !normalized;
+        let is_double = data_type == VertexDataType::Double;
+        match self.shape() {
+            None => true,
+            Some((AttributeScalar::Float, _, _)) => !is_integer && !is_double,
+            Some((AttributeScalar::Int | AttributeScalar::UnsignedInt, _, _)) => is_integer,
+            Some((AttributeScalar::Double, _, _)) => is_double,
+        }
+    }
+}
+
 impl Deletable for ShaderProgram {
     fn delete(&mut self) -> Result<()> {
         if self.id != 0 {
-            unsafe {
-                gl::DeleteProgram(self.id);
-            }
+            resource_registry::unregister(ResourceKind::Program, self.id)?;
+            with_backend(|backend| backend.delete_program(self.id));
             self.id = 0;
         }
         Ok(())
@@ -513,32 +725,114 @@ impl Drop for ShaderProgram {
 }
 
 fn check_compile_errors(shader: GLuint, shader_type: &str) -> Result<()> {
-    let mut success: GLint = 1;
-    let mut info_log = vec![0; 1024];
-
-    unsafe {
-        match shader_type {
-            "PROGRAM" => gl::GetProgramiv(shader, gl::LINK_STATUS, &mut success),
-            _ => gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success),
+    let error_message = with_backend(|backend| {
+        let success = match shader_type {
+            "PROGRAM" => backend.get_program_iv(shader, gl::LINK_STATUS),
+            _ => backend.get_shader_iv(shader, gl::COMPILE_STATUS),
         };
-
-        if success == 0 {
-            // Get error message for shader or program
-            let func = match shader_type {
-                "PROGRAM" => gl::GetProgramInfoLog,
-                _ => gl::GetShaderInfoLog,
-            };
-            func(shader, 1024, ptr::null_mut(), info_log.as_mut_ptr() as *mut GLchar);
-
-            // Format message to string (str)
-            let error_message = from_utf8(&info_log).unwrap_or("Failed to read log");
-            return Err(anyhow!(
-                "ERROR::SHADER_COMPILATION_ERROR of type: {}\n{}\n",
-                shader_type,
-                error_message
-            ));
+        if success != 0 {
+            return None;
         }
+
+        // Get error message for shader or program
+        Some(match shader_type {
+            "PROGRAM" => backend.get_program_info_log(shader),
+            _ => backend.get_shader_info_log(shader),
+        })
+    });
+
+    if let Some(error_message) = error_message {
+        return Err(anyhow!(
+            "ERROR::SHADER_COMPILATION_ERROR of type: {}\n{}\n",
+            shader_type,
+            error_message
+        ));
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opengl::gl_backend::{install_backend, GlCall, RecordingGlBackend};
+
+    const VERTEX_SOURCE: &str = "#version 330 core
+layout(location = 1) in vec3 aPos;
+in vec2 aTexCoord;
+out vec3 out_position;
+uniform mat4 transform;
+void main() {}
+";
+    const FRAGMENT_SOURCE: &str = "#version 330 core
+uniform sampler2D texture1;
+void main() {}
+";
+
+    #[test]
+    fn test_compile_from_sources() {
+        let backend = RecordingGlBackend::new();
+        let _guard = install_backend(backend.clone());
+
+        let mut program = ShaderProgram::new();
+        program.add_source(ShaderType::Vertex, VERTEX_SOURCE).unwrap();
+        program.add_source(ShaderType::Fragment, FRAGMENT_SOURCE).unwrap();
+        program
+            .set_transform_feedback_varyings(&["out_position"], TransformFeedbackMode::Interleaved)
+            .unwrap();
+        program.compile().unwrap();
+        let id = program.program_id();
+
+        // The shaders are deleted after linking but stay attached, like in GL
+        assert_eq!(backend.attached_shaders(id).map(|shaders| shaders.len()), Some(2));
+        assert_eq!(backend.live_objects(), 1);
+        assert!(backend.calls().contains(&GlCall::TransformFeedbackVaryings {
+            program: id,
+            names: vec!["out_position".to_string()],
+            mode: gl::INTERLEAVED_ATTRIBS,
+        }));
+
+        let mut uniforms = program.get_all_uniform_names().unwrap();
+        uniforms.sort();
+        assert_eq!(uniforms, ["texture1", "transform"]);
+        assert!(program.get_uniform_location("transform").is_ok());
+        assert!(program.get_uniform_location("missing").is_err());
+        assert_eq!(
+            program.get_active_attributes().unwrap(),
+            [
+                ActiveAttribute {
+                    name: "aPos".to_string(),
+                    location: 1,
+                    gl_type: gl::FLOAT_VEC3,
+                    size: 1,
+                },
+                ActiveAttribute {
+                    name: "aTexCoord".to_string(),
+                    location: 0,
+                    gl_type: gl::FLOAT_VEC2,
+                    size: 1,
+                },
+            ]
+        );
+
+        program.activate();
+        assert!(program.is_active());
+        program.deactivate();
+        assert!(!program.is_active());
+
+        drop(program);
+        assert_eq!(backend.live_objects(), 0);
+    }
+
+    #[test]
+    fn test_compile_reports_errors() {
+        let backend = RecordingGlBackend::new();
+        let _guard = install_backend(backend.clone());
+
+        let mut program = ShaderProgram::new();
+        program.add_source(ShaderType::Fragment, "#error missing main\n").unwrap();
+        let err = program.compile().unwrap_err();
+        assert!(err.to_string().contains("0:1: error: #error missing main"));
+        assert_eq!(program.program_id(), 0);
+    }
+}
@@ -0,0 +1,980 @@
+use crate::gl_types::Capability;
+use crate::gl_utils::check_gl_error;
+use crate::opengl::blend_guard::SeparateBlend;
+use crate::opengl::gl_backend::with_backend;
+use crate::opengl::pipeline_state::{
+    ColorMask, CullMode, DepthFunc, FrontFace, PolygonMode, ScissorRect,
+};
+use crate::opengl::stencil::{StencilFace, StencilState};
+use anyhow::{Context, Result};
+use gl::types::{GLenum, GLint, GLsizei, GLuint};
+use std::cell::{Cell, RefCell};
+
+// Every guard reads the state it changes when it is created and restores it when
+// it is dropped. Guards of the same kind therefore have to be dropped in reverse
+// order of their creation, which Rust's scoping already does unless guards are
+// moved around. Out of order drops are reported on stderr.
+
+//////////////////////////////////////////////////////////////////////////////
+// - GuardScope -
+//////////////////////////////////////////////////////////////////////////////
+
+thread_local! {
+    static GUARD_STACK: RefCell<Vec<(&'static str, u64)>> = const { RefCell::new(Vec::new()) };
+    static NEXT_GUARD_ID: Cell<u64> = const { Cell::new(0) };
+}
+
+/// The position of a guard in the stack of live guards on this thread.
+#[derive(Debug)]
+struct GuardScope {
+    kind: &'static str,
+    id: u64,
+}
+
+impl GuardScope {
+    fn enter(kind: &'static str) -> Self {
+        let id = NEXT_GUARD_ID.with(|next| {
+            let id = next.get();
+            next.set(id + 1);
+            id
+        });
+        GUARD_STACK.with(|stack| stack.borrow_mut().push((kind, id)));
+        Self {
+            kind,
+            id,
+        }
+    }
+
+    /// Removes the guard from the stack. Returns `false` if a newer guard of the
+    /// same kind is still alive, in which case that guard will restore stale state.
+    fn leave(&self) -> bool {
+        GUARD_STACK.with(|stack| {
+            let mut stack = stack.borrow_mut();
+            let innermost =
+                stack.iter().rev().find(|(kind, _)| *kind == self.kind).map(|(_, id)| *id);
+            stack.retain(|(_, id)| *id != self.id);
+            innermost == Some(self.id)
+        })
+    }
+
+    fn leave_and_report(&self) {
+        if !self.leave() {
+            eprintln!(
+                "{} was dropped before a guard created after it, the state it restored will be overwritten",
+                self.kind
+            );
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - GL helpers -
+//////////////////////////////////////////////////////////////////////////////
+
+fn is_enabled(capability: Capability) -> bool {
+    with_backend(|backend| backend.is_enabled(capability.to_gl_enum()))
+}
+
+fn set_enabled(capability: Capability, enabled: bool) {
+    if enabled {
+        capability.enable();
+    } else {
+        capability.disable();
+    }
+}
+
+fn get_integers(name: GLenum) -> [GLint; 4] {
+    with_backend(|backend| backend.get_integers(name))
+}
+
+fn get_integer(name: GLenum) -> GLint {
+    with_backend(|backend| backend.get_integer(name))
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - DepthGuard -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Changes the depth test, depth writes and depth function until it is dropped.
+///
+/// # Example
+/// ```ignore
+/// // Draw an overlay on top of the scene without touching its depth values
+/// let _depth_guard = DepthGuard::disabled()?;
+/// rectangle.draw()?;
+/// ```
+#[derive(Debug)]
+pub struct DepthGuard {
+    scope: GuardScope,
+    original_test: bool,
+    original_write: bool,
+    original_func: GLenum,
+}
+
+impl DepthGuard {
+    pub fn new(test: bool, write: bool, func: DepthFunc) -> Result<Self> {
+        let guard = Self {
+            scope: GuardScope::enter("DepthGuard"),
+            original_test: is_enabled(Capability::DepthTest),
+            original_write: get_integer(gl::DEPTH_WRITEMASK) != 0,
+            original_func: get_integer(gl::DEPTH_FUNC) as GLenum,
+        };
+        set_enabled(Capability::DepthTest, test);
+        with_backend(|backend| {
+            backend.depth_mask(write);
+            backend.depth_func(func.to_gl_enum());
+        });
+        check_gl_error().context("Failed to change the depth state")?;
+        Ok(guard)
+    }
+
+    /// Disables the depth test and depth writes.
+    pub fn disabled() -> Result<Self> {
+        Self::new(false, false, DepthFunc::default())
+    }
+
+    fn restore(&self) -> Result<()> {
+        set_enabled(Capability::DepthTest, self.original_test);
+        with_backend(|backend| {
+            backend.depth_mask(self.original_write);
+            backend.depth_func(self.original_func);
+        });
+        check_gl_error()
+    }
+}
+
+impl Drop for DepthGuard {
+    fn drop(&mut self) {
+        self.scope.leave_and_report();
+        if let Err(err) = self.restore() {
+            eprintln!("Error while dropping DepthGuard: {}", err);
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - StencilGuard -
+//////////////////////////////////////////////////////////////////////////////
+
+/// The stencil state of one face as read from OpenGL.
+#[derive(Debug, Clone, Copy)]
+struct RawStencilFace {
+    func: GLenum,
+    reference: GLint,
+    read_mask: GLuint,
+    write_mask: GLuint,
+    stencil_fail: GLenum,
+    depth_fail: GLenum,
+    pass: GLenum,
+}
+
+impl RawStencilFace {
+    fn query(back: bool) -> Self {
+        let name = |front: GLenum, back_name: GLenum| {
+            if back {
+                back_name
+            } else {
+                front
+            }
+        };
+        Self {
+            func: get_integer(name(gl::STENCIL_FUNC, gl::STENCIL_BACK_FUNC)) as GLenum,
+            reference: get_integer(name(gl::STENCIL_REF, gl::STENCIL_BACK_REF)),
+            read_mask: get_integer(name(gl::STENCIL_VALUE_MASK, gl::STENCIL_BACK_VALUE_MASK))
+                as GLuint,
+            write_mask: get_integer(name(gl::STENCIL_WRITEMASK, gl::STENCIL_BACK_WRITEMASK))
+                as GLuint,
+            stencil_fail: get_integer(name(gl::STENCIL_FAIL, gl::STENCIL_BACK_FAIL)) as GLenum,
+            depth_fail: get_integer(name(
+                gl::STENCIL_PASS_DEPTH_FAIL,
+                gl::STENCIL_BACK_PASS_DEPTH_FAIL,
+            )) as GLenum,
+            pass: get_integer(name(gl::STENCIL_PASS_DEPTH_PASS, gl::STENCIL_BACK_PASS_DEPTH_PASS))
+                as GLenum,
+        }
+    }
+
+    fn apply(&self, face: GLenum) {
+        with_backend(|backend| {
+            backend.stencil_func_separate(face, self.func, self.reference, self.read_mask);
+            backend.stencil_mask_separate(face, self.write_mask);
+            backend.stencil_op_separate(face, self.stencil_fail, self.depth_fail, self.pass);
+        });
+    }
+}
+
+/// Enables the stencil test with the given function and operations until it is
+/// dropped. Front and back face state are both restored.
+///
+/// # Example
+/// ```ignore
+/// // Only draw where a previous pass wrote a 1 into the stencil buffer
+/// let clip = StencilState::new().func(StencilFunc::Equal, 1, 0xFF).write_mask(0);
+/// let _stencil_guard = StencilGuard::new(clip)?;
+/// text_renderer.render_text("Clipped", position, 1.0)?;
+/// ```
+#[derive(Debug)]
+pub struct StencilGuard {
+    scope: GuardScope,
+    original_test: bool,
+    original_front: RawStencilFace,
+    original_back: RawStencilFace,
+}
+
+impl StencilGuard {
+    pub fn new(state: StencilState) -> Result<Self> {
+        let guard = Self::capture();
+        Capability::StencilTest.enable();
+        state.apply()?;
+        Ok(guard)
+    }
+
+    /// Like `new`, but with different states for front and back faces.
+    pub fn separate(front: StencilState, back: StencilState) -> Result<Self> {
+        let guard = Self::capture();
+        Capability::StencilTest.enable();
+        front.apply_to_face(StencilFace::Front)?;
+        back.apply_to_face(StencilFace::Back)?;
+        Ok(guard)
+    }
+
+    /// Disables the stencil test.
+    pub fn disabled() -> Result<Self> {
+        let guard = Self::capture();
+        Capability::StencilTest.disable();
+        check_gl_error().context("Failed to disable the stencil test")?;
+        Ok(guard)
+    }
+
+    fn capture() -> Self {
+        Self {
+            scope: GuardScope::enter("StencilGuard"),
+            original_test: is_enabled(Capability::StencilTest),
+            original_front: RawStencilFace::query(false),
+            original_back: RawStencilFace::query(true),
+        }
+    }
+
+    fn restore(&self) -> Result<()> {
+        set_enabled(Capability::StencilTest, self.original_test);
+        self.original_front.apply(gl::FRONT);
+        self.original_back.apply(gl::BACK);
+        check_gl_error()
+    }
+}
+
+impl Drop for StencilGuard {
+    fn drop(&mut self) {
+        self.scope.leave_and_report();
+        if let Err(err) = self.restore() {
+            eprintln!("Error while dropping StencilGuard: {}", err);
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - ScissorGuard -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Enables the scissor test with the given rectangle until it is dropped.
+///
+/// # Example
+/// ```ignore
+/// let _panel = ScissorGuard::new(ScissorRect::new(0, 0, 400, 300))?;
+/// // Nested clip rectangles only narrow the visible area
+/// let _label = ScissorGuard::intersect(ScissorRect::new(350, 10, 200, 40))?;
+/// ```
+#[derive(Debug)]
+pub struct ScissorGuard {
+    scope: GuardScope,
+    original_test: bool,
+    original_rect: [GLint; 4],
+}
+
+impl ScissorGuard {
+    pub fn new(rect: ScissorRect) -> Result<Self> {
+        let guard = Self {
+            scope: GuardScope::enter("ScissorGuard"),
+            original_test: is_enabled(Capability::ScissorTest),
+            original_rect: get_integers(gl::SCISSOR_BOX),
+        };
+        Capability::ScissorTest.enable();
+        with_backend(|backend| {
+            backend.scissor(rect.x, rect.y, rect.width as GLsizei, rect.height as GLsizei)
+        });
+        check_gl_error().context("Failed to change the scissor rectangle")?;
+        Ok(guard)
+    }
+
+    /// Like `new`, but clips `rect` to the current scissor rectangle if the scissor
+    /// test is already enabled, e.g. by an outer `ScissorGuard`.
+    pub fn intersect(rect: ScissorRect) -> Result<Self> {
+        if !is_enabled(Capability::ScissorTest) {
+            return Self::new(rect);
+        }
+        let [x, y, width, height] = get_integers(gl::SCISSOR_BOX);
+        let current = ScissorRect::new(x, y, width as u32, height as u32);
+        Self::new(current.intersect(&rect))
+    }
+
+    fn restore(&self) -> Result<()> {
+        set_enabled(Capability::ScissorTest, self.original_test);
+        let [x, y, width, height] = self.original_rect;
+        with_backend(|backend| backend.scissor(x, y, width, height));
+        check_gl_error()
+    }
+}
+
+impl Drop for ScissorGuard {
+    fn drop(&mut self) {
+        self.scope.leave_and_report();
+        if let Err(err) = self.restore() {
+            eprintln!("Error while dropping ScissorGuard: {}", err);
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - ViewportGuard -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Changes the viewport until it is dropped, e.g. to render into a smaller
+/// texture or a part of the window.
+#[derive(Debug)]
+pub struct ViewportGuard {
+    scope: GuardScope,
+    original_viewport: [GLint; 4],
+}
+
+impl ViewportGuard {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Result<Self> {
+        let guard = Self {
+            scope: GuardScope::enter("ViewportGuard"),
+            original_viewport: get_integers(gl::VIEWPORT),
+        };
+        with_backend(|backend| backend.viewport(x, y, width as GLsizei, height as GLsizei));
+        check_gl_error().context("Failed to change the viewport")?;
+        Ok(guard)
+    }
+
+    fn restore(&self) -> Result<()> {
+        let [x, y, width, height] = self.original_viewport;
+        with_backend(|backend| backend.viewport(x, y, width, height));
+        check_gl_error()
+    }
+}
+
+impl Drop for ViewportGuard {
+    fn drop(&mut self) {
+        self.scope.leave_and_report();
+        if let Err(err) = self.restore() {
+            eprintln!("Error while dropping ViewportGuard: {}", err);
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - CullGuard -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Changes face culling and the front face winding until it is dropped.
+#[derive(Debug)]
+pub struct CullGuard {
+    scope: GuardScope,
+    original_cull: bool,
+    original_mode: GLenum,
+    original_front_face: GLenum,
+}
+
+impl CullGuard {
+    /// Culls the faces given by `cull_mode`, `None` disables face culling.
+    pub fn new(cull_mode: Option<CullMode>, front_face: FrontFace) -> Result<Self> {
+        let guard = Self {
+            scope: GuardScope::enter("CullGuard"),
+            original_cull: is_enabled(Capability::CullFace),
+            original_mode: get_integer(gl::CULL_FACE_MODE) as GLenum,
+            original_front_face: get_integer(gl::FRONT_FACE) as GLenum,
+        };
+        set_enabled(Capability::CullFace, cull_mode.is_some());
+        with_backend(|backend| {
+            if let Some(cull_mode) = cull_mode {
+                backend.cull_face(cull_mode.to_gl_enum());
+            }
+            backend.front_face(front_face.to_gl_enum());
+        });
+        check_gl_error().context("Failed to change face culling")?;
+        Ok(guard)
+    }
+
+    /// Disables face culling, e.g. for flat shapes which may face away.
+    pub fn disabled() -> Result<Self> {
+        Self::new(None, FrontFace::default())
+    }
+
+    fn restore(&self) -> Result<()> {
+        set_enabled(Capability::CullFace, self.original_cull);
+        with_backend(|backend| {
+            backend.cull_face(self.original_mode);
+            backend.front_face(self.original_front_face);
+        });
+        check_gl_error()
+    }
+}
+
+impl Drop for CullGuard {
+    fn drop(&mut self) {
+        self.scope.leave_and_report();
+        if let Err(err) = self.restore() {
+            eprintln!("Error while dropping CullGuard: {}", err);
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - ColorMaskGuard -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Changes the written color channels until it is dropped, e.g. to only write
+/// depth or stencil values.
+#[derive(Debug)]
+pub struct ColorMaskGuard {
+    scope: GuardScope,
+    original_mask: [bool; 4],
+}
+
+impl ColorMaskGuard {
+    pub fn new(mask: ColorMask) -> Result<Self> {
+        let guard = Self {
+            scope: GuardScope::enter("ColorMaskGuard"),
+            original_mask: get_integers(gl::COLOR_WRITEMASK).map(|written| written != 0),
+        };
+        with_backend(|backend| backend.color_mask(mask.red, mask.green, mask.blue, mask.alpha));
+        check_gl_error().context("Failed to change the color mask")?;
+        Ok(guard)
+    }
+
+    fn restore(&self) -> Result<()> {
+        let [red, green, blue, alpha] = self.original_mask;
+        with_backend(|backend| backend.color_mask(red, green, blue, alpha));
+        check_gl_error()
+    }
+}
+
+impl Drop for ColorMaskGuard {
+    fn drop(&mut self) {
+        self.scope.leave_and_report();
+        if let Err(err) = self.restore() {
+            eprintln!("Error while dropping ColorMaskGuard: {}", err);
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - PolygonModeGuard -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Changes the polygon mode of front and back faces until it is dropped.
+///
+/// # Example
+/// ```ignore
+/// // Keep text readable while the scene is drawn as wireframe
+/// let _fill_guard = PolygonModeGuard::new(PolygonMode::Fill)?;
+/// text_renderer.render_text("FPS: 60", position, 1.0)?;
+/// ```
+#[derive(Debug)]
+pub struct PolygonModeGuard {
+    scope: GuardScope,
+    original_mode: GLenum,
+}
+
+impl PolygonModeGuard {
+    pub fn new(polygon_mode: PolygonMode) -> Result<Self> {
+        // Compatibility contexts report front and back mode, core contexts only one
+        let [original_mode, ..] = get_integers(gl::POLYGON_MODE);
+        let guard = Self {
+            scope: GuardScope::enter("PolygonModeGuard"),
+            original_mode: original_mode as GLenum,
+        };
+        with_backend(|backend| backend.polygon_mode(gl::FRONT_AND_BACK, polygon_mode.to_gl_enum()));
+        check_gl_error().context("Failed to change the polygon mode")?;
+        Ok(guard)
+    }
+
+    fn restore(&self) -> Result<()> {
+        with_backend(|backend| backend.polygon_mode(gl::FRONT_AND_BACK, self.original_mode));
+        check_gl_error()
+    }
+}
+
+impl Drop for PolygonModeGuard {
+    fn drop(&mut self) {
+        self.scope.leave_and_report();
+        if let Err(err) = self.restore() {
+            eprintln!("Error while dropping PolygonModeGuard: {}", err);
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - BlendStateGuard -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Changes blending and the blend function until it is dropped. Unlike
+/// `BlendGuard`, which keeps the blend function for enabling and disabling
+/// blending repeatedly, it restores the exact state it replaced.
+///
+/// # Example
+/// ```ignore
+/// // A fullscreen pass replaces every pixel of its output
+/// let _blend_guard = BlendStateGuard::disabled()?;
+/// fullscreen.draw()?;
+/// ```
+#[derive(Debug)]
+pub struct BlendStateGuard {
+    scope: GuardScope,
+    original_blend: bool,
+    original_func: SeparateBlend,
+}
+
+impl BlendStateGuard {
+    /// Blends with the given function, `None` disables blending.
+    pub fn new(blend: Option<SeparateBlend>) -> Result<Self> {
+        let guard = Self {
+            scope: GuardScope::enter("BlendStateGuard"),
+            original_blend: is_enabled(Capability::Blend),
+            original_func: SeparateBlend::new(
+                get_integer(gl::BLEND_SRC_RGB) as GLenum,
+                get_integer(gl::BLEND_DST_RGB) as GLenum,
+                get_integer(gl::BLEND_SRC_ALPHA) as GLenum,
+                get_integer(gl::BLEND_DST_ALPHA) as GLenum,
+            ),
+        };
+        set_enabled(Capability::Blend, blend.is_some());
+        if let Some(blend) = blend {
+            apply_blend_func(&blend);
+        }
+        check_gl_error().context("Failed to change blending")?;
+        Ok(guard)
+    }
+
+    /// Disables blending.
+    pub fn disabled() -> Result<Self> {
+        Self::new(None)
+    }
+
+    fn restore(&self) -> Result<()> {
+        set_enabled(Capability::Blend, self.original_blend);
+        apply_blend_func(&self.original_func);
+        check_gl_error()
+    }
+}
+
+fn apply_blend_func(blend: &SeparateBlend) {
+    with_backend(|backend| {
+        backend.blend_func_separate(
+            blend.src_rgb,
+            blend.dest_rgb,
+            blend.src_alpha,
+            blend.dest_alpha,
+        )
+    });
+}
+
+impl Drop for BlendStateGuard {
+    fn drop(&mut self) {
+        self.scope.leave_and_report();
+        if let Err(err) = self.restore() {
+            eprintln!("Error while dropping BlendStateGuard: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opengl::gl_backend::{install_backend, GlCall, RecordingGlBackend};
+    use crate::opengl::stencil::{StencilFunc, StencilOp};
+
+    /// Draws with the current state, failing if `backend.fail_next` matches the draw.
+    fn draw() -> Result<()> {
+        with_backend(|backend| backend.draw_arrays(gl::TRIANGLES, 0, 3));
+        check_gl_error().context("Failed to draw")
+    }
+
+    fn is_draw(call: &GlCall) -> bool {
+        matches!(call, GlCall::DrawArrays { .. })
+    }
+
+    #[test]
+    fn test_nested_scopes() {
+        let outer = GuardScope::enter("DepthGuard");
+        let inner = GuardScope::enter("DepthGuard");
+        let other = GuardScope::enter("CullGuard");
+        // Guards of other kinds don't affect the order
+        assert!(inner.leave());
+        assert!(other.leave());
+        assert!(outer.leave());
+        GUARD_STACK.with(|stack| assert!(stack.borrow().is_empty()));
+    }
+
+    #[test]
+    fn test_out_of_order_scopes() {
+        let outer = GuardScope::enter("ScissorGuard");
+        let inner = GuardScope::enter("ScissorGuard");
+        assert!(!outer.leave());
+        // The inner guard is now the innermost one again
+        assert!(inner.leave());
+        GUARD_STACK.with(|stack| assert!(stack.borrow().is_empty()));
+    }
+
+    #[test]
+    fn test_scissor_intersection() {
+        let panel = ScissorRect::new(0, 0, 400, 300);
+        assert_eq!(
+            panel.intersect(&ScissorRect::new(350, 10, 200, 40)),
+            ScissorRect::new(350, 10, 50, 40)
+        );
+        assert_eq!(
+            panel.intersect(&ScissorRect::new(500, 10, 20, 20)),
+            ScissorRect::new(500, 10, 0, 20)
+        );
+    }
+
+    #[test]
+    fn test_depth_guard_restores_nested_state() {
+        let backend = RecordingGlBackend::new();
+        let _backend_guard = install_backend(backend.clone());
+        let depth_state = || {
+            (
+                backend.is_enabled(gl::DEPTH_TEST),
+                backend.state(gl::DEPTH_WRITEMASK)[0],
+                backend.state(gl::DEPTH_FUNC)[0] as GLenum,
+            )
+        };
+
+        {
+            let _outer = DepthGuard::new(true, true, DepthFunc::LessEqual).unwrap();
+            assert_eq!(depth_state(), (true, 1, gl::LEQUAL));
+            {
+                let _inner = DepthGuard::disabled().unwrap();
+                assert_eq!(depth_state(), (false, 0, gl::LESS));
+            }
+            assert_eq!(depth_state(), (true, 1, gl::LEQUAL));
+        }
+        assert_eq!(depth_state(), (false, 1, gl::LESS));
+
+        // A failing constructor restores the state before returning the error
+        backend.fail_next(|call| matches!(call, GlCall::DepthFunc(_)), gl::INVALID_ENUM);
+        assert!(DepthGuard::new(true, false, DepthFunc::Always).is_err());
+        assert_eq!(depth_state(), (false, 1, gl::LESS));
+    }
+
+    #[test]
+    fn test_depth_guard_restores_on_early_return() {
+        let backend = RecordingGlBackend::new();
+        let _backend_guard = install_backend(backend.clone());
+        backend.fail_next(is_draw, gl::INVALID_OPERATION);
+        let overlay = || -> Result<()> {
+            let _depth_guard = DepthGuard::disabled()?;
+            draw()?;
+            unreachable!("the draw fails");
+        };
+        Capability::DepthTest.enable();
+        assert!(overlay().is_err());
+        assert!(backend.is_enabled(gl::DEPTH_TEST));
+        assert_eq!(backend.state(gl::DEPTH_WRITEMASK)[0], 1);
+    }
+
+    #[test]
+    fn test_stencil_guard_restores_both_faces() {
+        let backend = RecordingGlBackend::new();
+        let _backend_guard = install_backend(backend.clone());
+        let face_state = |back: bool| {
+            let name = |front, back_name| {
+                if back {
+                    back_name
+                } else {
+                    front
+                }
+            };
+            [
+                name(gl::STENCIL_FUNC, gl::STENCIL_BACK_FUNC),
+                name(gl::STENCIL_REF, gl::STENCIL_BACK_REF),
+                name(gl::STENCIL_WRITEMASK, gl::STENCIL_BACK_WRITEMASK),
+                name(gl::STENCIL_PASS_DEPTH_PASS, gl::STENCIL_BACK_PASS_DEPTH_PASS),
+            ]
+            .map(|pname| backend.state(pname)[0])
+        };
+        let initial = [gl::ALWAYS as GLint, 0, -1, gl::KEEP as GLint];
+        let mark = StencilState::new()
+            .func(StencilFunc::Always, 1, 0xFF)
+            .ops(StencilOp::Keep, StencilOp::Keep, StencilOp::Replace)
+            .write_mask(0xFF);
+        let clip = StencilState::new().func(StencilFunc::Equal, 1, 0xFF).write_mask(0);
+
+        {
+            let _outer = StencilGuard::new(mark).unwrap();
+            let marked = [gl::ALWAYS as GLint, 1, 0xFF, gl::REPLACE as GLint];
+            assert!(backend.is_enabled(gl::STENCIL_TEST));
+            {
+                let _inner = StencilGuard::separate(clip, StencilState::new()).unwrap();
+                assert_eq!(face_state(false), [gl::EQUAL as GLint, 1, 0, gl::KEEP as GLint]);
+                assert_eq!(face_state(true), initial);
+            }
+            assert_eq!(face_state(false), marked);
+            assert_eq!(face_state(true), marked);
+            {
+                let _disabled = StencilGuard::disabled().unwrap();
+                assert!(!backend.is_enabled(gl::STENCIL_TEST));
+            }
+            assert!(backend.is_enabled(gl::STENCIL_TEST));
+        }
+        assert!(!backend.is_enabled(gl::STENCIL_TEST));
+        assert_eq!(face_state(false), initial);
+        assert_eq!(face_state(true), initial);
+
+        backend.fail_next(is_draw, gl::INVALID_OPERATION);
+        let clipped = || -> Result<()> {
+            let _stencil_guard = StencilGuard::new(clip)?;
+            draw()?;
+            unreachable!("the draw fails");
+        };
+        assert!(clipped().is_err());
+        assert!(!backend.is_enabled(gl::STENCIL_TEST));
+        assert_eq!(face_state(false), initial);
+
+        // The constructor fails while applying the back face
+        backend.fail_next(
+            |call| matches!(call, GlCall::StencilOpSeparate { face, .. } if *face == gl::BACK),
+            gl::INVALID_ENUM,
+        );
+        assert!(StencilGuard::separate(mark, clip).is_err());
+        assert!(!backend.is_enabled(gl::STENCIL_TEST));
+        assert_eq!(face_state(false), initial);
+        assert_eq!(face_state(true), initial);
+    }
+
+    #[test]
+    fn test_scissor_guard_restores_nested_state() {
+        let backend = RecordingGlBackend::new();
+        let _backend_guard = install_backend(backend.clone());
+        let initial = backend.state(gl::SCISSOR_BOX);
+
+        {
+            let _panel = ScissorGuard::new(ScissorRect::new(0, 0, 400, 300)).unwrap();
+            assert!(backend.is_enabled(gl::SCISSOR_TEST));
+            {
+                let _label = ScissorGuard::intersect(ScissorRect::new(350, 10, 200, 40)).unwrap();
+                assert_eq!(backend.state(gl::SCISSOR_BOX), [350, 10, 50, 40]);
+            }
+            assert_eq!(backend.state(gl::SCISSOR_BOX), [0, 0, 400, 300]);
+        }
+        assert!(!backend.is_enabled(gl::SCISSOR_TEST));
+        assert_eq!(backend.state(gl::SCISSOR_BOX), initial);
+
+        // Without an enclosing guard the rectangle isn't clipped
+        {
+            let _label = ScissorGuard::intersect(ScissorRect::new(350, 10, 200, 40)).unwrap();
+            assert_eq!(backend.state(gl::SCISSOR_BOX), [350, 10, 200, 40]);
+        }
+
+        backend.fail_next(is_draw, gl::INVALID_OPERATION);
+        let clipped = || -> Result<()> {
+            let _scissor_guard = ScissorGuard::new(ScissorRect::new(1, 2, 3, 4))?;
+            draw()?;
+            unreachable!("the draw fails");
+        };
+        assert!(clipped().is_err());
+        assert!(!backend.is_enabled(gl::SCISSOR_TEST));
+        assert_eq!(backend.state(gl::SCISSOR_BOX), initial);
+
+        backend.fail_next(|call| matches!(call, GlCall::Scissor { .. }), gl::INVALID_VALUE);
+        assert!(ScissorGuard::new(ScissorRect::new(1, 2, 3, 4)).is_err());
+        assert!(!backend.is_enabled(gl::SCISSOR_TEST));
+        assert_eq!(backend.state(gl::SCISSOR_BOX), initial);
+    }
+
+    #[test]
+    fn test_viewport_guard_restores_nested_state() {
+        let backend = RecordingGlBackend::new();
+        let _backend_guard = install_backend(backend.clone());
+        let initial = backend.state(gl::VIEWPORT);
+
+        {
+            let _outer = ViewportGuard::new(0, 0, 256, 256).unwrap();
+            {
+                let _inner = ViewportGuard::new(64, 32, 16, 8).unwrap();
+                assert_eq!(backend.state(gl::VIEWPORT), [64, 32, 16, 8]);
+            }
+            assert_eq!(backend.state(gl::VIEWPORT), [0, 0, 256, 256]);
+        }
+        assert_eq!(backend.state(gl::VIEWPORT), initial);
+
+        backend.fail_next(is_draw, gl::INVALID_OPERATION);
+        let render_to_texture = || -> Result<()> {
+            let _viewport_guard = ViewportGuard::new(0, 0, 256, 256)?;
+            draw()?;
+            unreachable!("the draw fails");
+        };
+        assert!(render_to_texture().is_err());
+        assert_eq!(backend.state(gl::VIEWPORT), initial);
+
+        backend.fail_next(|call| matches!(call, GlCall::Viewport { .. }), gl::INVALID_VALUE);
+        assert!(ViewportGuard::new(0, 0, 256, 256).is_err());
+        assert_eq!(backend.state(gl::VIEWPORT), initial);
+    }
+
+    #[test]
+    fn test_cull_guard_restores_nested_state() {
+        let backend = RecordingGlBackend::new();
+        let _backend_guard = install_backend(backend.clone());
+        let cull_state = || {
+            (
+                backend.is_enabled(gl::CULL_FACE),
+                backend.state(gl::CULL_FACE_MODE)[0] as GLenum,
+                backend.state(gl::FRONT_FACE)[0] as GLenum,
+            )
+        };
+
+        {
+            let _outer = CullGuard::new(Some(CullMode::Front), FrontFace::Clockwise).unwrap();
+            assert_eq!(cull_state(), (true, gl::FRONT, gl::CW));
+            {
+                let _inner = CullGuard::disabled().unwrap();
+                // The cull mode is kept while culling is disabled
+                assert_eq!(cull_state(), (false, gl::FRONT, gl::CCW));
+            }
+            assert_eq!(cull_state(), (true, gl::FRONT, gl::CW));
+        }
+        assert_eq!(cull_state(), (false, gl::BACK, gl::CCW));
+
+        backend.fail_next(is_draw, gl::INVALID_OPERATION);
+        let draw_culled = || -> Result<()> {
+            let _cull_guard = CullGuard::new(Some(CullMode::Back), FrontFace::Clockwise)?;
+            draw()?;
+            unreachable!("the draw fails");
+        };
+        assert!(draw_culled().is_err());
+        assert_eq!(cull_state(), (false, gl::BACK, gl::CCW));
+
+        backend.fail_next(|call| matches!(call, GlCall::FrontFace(_)), gl::INVALID_ENUM);
+        assert!(CullGuard::new(Some(CullMode::Front), FrontFace::Clockwise).is_err());
+        assert_eq!(cull_state(), (false, gl::BACK, gl::CCW));
+    }
+
+    #[test]
+    fn test_color_mask_guard_restores_nested_state() {
+        let backend = RecordingGlBackend::new();
+        let _backend_guard = install_backend(backend.clone());
+
+        {
+            let _outer = ColorMaskGuard::new(ColorMask::new(true, false, true, false)).unwrap();
+            assert_eq!(backend.state(gl::COLOR_WRITEMASK), [1, 0, 1, 0]);
+            {
+                let _inner = ColorMaskGuard::new(ColorMask::NONE).unwrap();
+                assert_eq!(backend.state(gl::COLOR_WRITEMASK), [0; 4]);
+            }
+            assert_eq!(backend.state(gl::COLOR_WRITEMASK), [1, 0, 1, 0]);
+        }
+        assert_eq!(backend.state(gl::COLOR_WRITEMASK), [1; 4]);
+
+        backend.fail_next(is_draw, gl::INVALID_OPERATION);
+        let depth_only = || -> Result<()> {
+            let _color_mask_guard = ColorMaskGuard::new(ColorMask::NONE)?;
+            draw()?;
+            unreachable!("the draw fails");
+        };
+        assert!(depth_only().is_err());
+        assert_eq!(backend.state(gl::COLOR_WRITEMASK), [1; 4]);
+
+        backend.fail_next(|call| matches!(call, GlCall::ColorMask(_)), gl::INVALID_OPERATION);
+        assert!(ColorMaskGuard::new(ColorMask::NONE).is_err());
+        assert_eq!(backend.state(gl::COLOR_WRITEMASK), [1; 4]);
+    }
+
+    #[test]
+    fn test_polygon_mode_guard_restores_nested_state() {
+        let backend = RecordingGlBackend::new();
+        let _backend_guard = install_backend(backend.clone());
+        let mode = || backend.state(gl::POLYGON_MODE)[0] as GLenum;
+
+        {
+            let _outer = PolygonModeGuard::new(PolygonMode::Line).unwrap();
+            assert_eq!(mode(), gl::LINE);
+            {
+                let _inner = PolygonModeGuard::new(PolygonMode::Fill).unwrap();
+                assert_eq!(mode(), gl::FILL);
+            }
+            assert_eq!(mode(), gl::LINE);
+        }
+        assert_eq!(mode(), gl::FILL);
+
+        backend.fail_next(is_draw, gl::INVALID_OPERATION);
+        let wireframe = || -> Result<()> {
+            let _polygon_mode_guard = PolygonModeGuard::new(PolygonMode::Line)?;
+            draw()?;
+            unreachable!("the draw fails");
+        };
+        assert!(wireframe().is_err());
+        assert_eq!(mode(), gl::FILL);
+
+        backend.fail_next(|call| matches!(call, GlCall::PolygonMode { .. }), gl::INVALID_ENUM);
+        assert!(PolygonModeGuard::new(PolygonMode::Point).is_err());
+        assert_eq!(mode(), gl::FILL);
+        assert!(check_gl_error().is_ok());
+    }
+
+    #[test]
+    fn test_blend_state_guard_restores_nested_state() {
+        let backend = RecordingGlBackend::new();
+        let _backend_guard = install_backend(backend.clone());
+        let blend_state = || {
+            (
+                backend.is_enabled(gl::BLEND),
+                [gl::BLEND_SRC_RGB, gl::BLEND_DST_RGB, gl::BLEND_SRC_ALPHA, gl::BLEND_DST_ALPHA]
+                    .map(|pname| backend.state(pname)[0] as GLenum),
+            )
+        };
+        let initial = (false, [gl::ONE, gl::ZERO, gl::ONE, gl::ZERO]);
+        let alpha = SeparateBlend::new(
+            gl::SRC_ALPHA,
+            gl::ONE_MINUS_SRC_ALPHA,
+            gl::ONE,
+            gl::ONE_MINUS_SRC_ALPHA,
+        );
+        let additive = SeparateBlend::new(gl::ONE, gl::ONE, gl::ONE, gl::ONE);
+
+        {
+            let _outer = BlendStateGuard::new(Some(alpha)).unwrap();
+            let blended =
+                (true, [gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA, gl::ONE, gl::ONE_MINUS_SRC_ALPHA]);
+            assert_eq!(blend_state(), blended);
+            {
+                let _inner = BlendStateGuard::new(Some(additive)).unwrap();
+                assert_eq!(blend_state(), (true, [gl::ONE; 4]));
+            }
+            assert_eq!(blend_state(), blended);
+            {
+                let _disabled = BlendStateGuard::disabled().unwrap();
+                // The blend function is kept while blending is disabled
+                assert_eq!(blend_state(), (false, blended.1));
+            }
+            assert_eq!(blend_state(), blended);
+        }
+        assert_eq!(blend_state(), initial);
+
+        backend.fail_next(is_draw, gl::INVALID_OPERATION);
+        let fullscreen = || -> Result<()> {
+            let _blend_guard = BlendStateGuard::new(Some(additive))?;
+            draw()?;
+            unreachable!("the draw fails");
+        };
+        assert!(fullscreen().is_err());
+        assert_eq!(blend_state(), initial);
+
+        backend
+            .fail_next(|call| matches!(call, GlCall::BlendFuncSeparate { .. }), gl::INVALID_ENUM);
+        assert!(BlendStateGuard::new(Some(additive)).is_err());
+        assert_eq!(blend_state(), initial);
+    }
+}
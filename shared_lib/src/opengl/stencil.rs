@@ -0,0 +1,271 @@
+use crate::gl_types::Capability;
+use crate::gl_utils::check_gl_error;
+use crate::opengl::gl_backend::with_backend;
+use anyhow::{Context, Result};
+use gl::types::{GLenum, GLuint};
+
+//////////////////////////////////////////////////////////////////////////////
+// - StencilFunc -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Comparison used by the stencil test. The masked reference value is on the left
+/// side, the masked stencil buffer value on the right side.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum StencilFunc {
+    Never,
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    #[default]
+    Always,
+}
+
+impl StencilFunc {
+    pub fn to_gl_enum(&self) -> GLenum {
+        match self {
+            StencilFunc::Never => gl::NEVER,
+            StencilFunc::Less => gl::LESS,
+            StencilFunc::Equal => gl::EQUAL,
+            StencilFunc::LessEqual => gl::LEQUAL,
+            StencilFunc::Greater => gl::GREATER,
+            StencilFunc::NotEqual => gl::NOTEQUAL,
+            StencilFunc::GreaterEqual => gl::GEQUAL,
+            StencilFunc::Always => gl::ALWAYS,
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - StencilOp -
+//////////////////////////////////////////////////////////////////////////////
+
+/// What happens to the stored stencil value after the stencil and depth tests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum StencilOp {
+    #[default]
+    Keep,
+    Zero,
+    /// Stores the reference value.
+    Replace,
+    /// Increments and clamps to the maximum value.
+    Increment,
+    /// Increments and wraps to zero.
+    IncrementWrap,
+    /// Decrements and clamps to zero.
+    Decrement,
+    /// Decrements and wraps to the maximum value.
+    DecrementWrap,
+    Invert,
+}
+
+impl StencilOp {
+    pub fn to_gl_enum(&self) -> GLenum {
+        match self {
+            StencilOp::Keep => gl::KEEP,
+            StencilOp::Zero => gl::ZERO,
+            StencilOp::Replace => gl::REPLACE,
+            StencilOp::Increment => gl::INCR,
+            StencilOp::IncrementWrap => gl::INCR_WRAP,
+            StencilOp::Decrement => gl::DECR,
+            StencilOp::DecrementWrap => gl::DECR_WRAP,
+            StencilOp::Invert => gl::INVERT,
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - StencilFace -
+//////////////////////////////////////////////////////////////////////////////
+
+/// The polygon faces a stencil state applies to. Points and lines count as front
+/// faces.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum StencilFace {
+    Front,
+    Back,
+    #[default]
+    FrontAndBack,
+}
+
+impl StencilFace {
+    pub fn to_gl_enum(&self) -> GLenum {
+        match self {
+            StencilFace::Front => gl::FRONT,
+            StencilFace::Back => gl::BACK,
+            StencilFace::FrontAndBack => gl::FRONT_AND_BACK,
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - StencilState -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Stencil function and operations, applied to front and back faces alike.
+///
+/// The default value matches the initial state of an OpenGL context, a test
+/// which always passes and keeps the stored values.
+///
+/// # Example
+/// ```ignore
+/// // Write 1 wherever something is drawn
+/// let write_mask = StencilState::default()
+///     .func(StencilFunc::Always, 1, 0xFF)
+///     .ops(StencilOp::Keep, StencilOp::Keep, StencilOp::Replace);
+/// // Only draw where the mask was written
+/// let clip = StencilState::default().func(StencilFunc::Equal, 1, 0xFF).write_mask(0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StencilState {
+    pub func: StencilFunc,
+    pub reference: i32,
+    /// Mask applied to the reference and the stored value before comparing them.
+    pub read_mask: u32,
+    /// Bits of the stored value which can be changed.
+    pub write_mask: u32,
+    /// Operation when the stencil test fails.
+    pub stencil_fail: StencilOp,
+    /// Operation when the stencil test passes, but the depth test fails.
+    pub depth_fail: StencilOp,
+    /// Operation when both tests pass.
+    pub pass: StencilOp,
+}
+
+impl Default for StencilState {
+    fn default() -> Self {
+        Self {
+            func: StencilFunc::default(),
+            reference: 0,
+            read_mask: u32::MAX,
+            write_mask: u32::MAX,
+            stencil_fail: StencilOp::default(),
+            depth_fail: StencilOp::default(),
+            pass: StencilOp::default(),
+        }
+    }
+}
+
+impl StencilState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn func(mut self, func: StencilFunc, reference: i32, read_mask: u32) -> Self {
+        self.func = func;
+        self.reference = reference;
+        self.read_mask = read_mask;
+        self
+    }
+
+    pub fn ops(mut self, stencil_fail: StencilOp, depth_fail: StencilOp, pass: StencilOp) -> Self {
+        self.stencil_fail = stencil_fail;
+        self.depth_fail = depth_fail;
+        self.pass = pass;
+        self
+    }
+
+    pub fn write_mask(mut self, write_mask: u32) -> Self {
+        self.write_mask = write_mask;
+        self
+    }
+
+    /// Sets this state for front and back faces. The stencil test itself has to be
+    /// enabled separately, see `set_stencil_test` or `StencilGuard`.
+    ///
+    /// # Errors
+    /// Returns an error if OpenGL reports an error.
+    pub fn apply(&self) -> Result<()> {
+        self.apply_to_face(StencilFace::FrontAndBack)
+    }
+
+    /// Sets this state for the given faces only, e.g. to count front and back faces
+    /// in opposite directions.
+    ///
+    /// # Errors
+    /// Returns an error if OpenGL reports an error.
+    pub fn apply_to_face(&self, face: StencilFace) -> Result<()> {
+        let face = face.to_gl_enum();
+        with_backend(|backend| {
+            backend.stencil_func_separate(
+                face,
+                self.func.to_gl_enum(),
+                self.reference,
+                self.read_mask,
+            );
+            backend.stencil_mask_separate(face, self.write_mask);
+            backend.stencil_op_separate(
+                face,
+                self.stencil_fail.to_gl_enum(),
+                self.depth_fail.to_gl_enum(),
+                self.pass.to_gl_enum(),
+            );
+        });
+        check_gl_error().context("Failed to set the stencil state")
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - Functions -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Enables or disables the stencil test.
+pub fn set_stencil_test(enabled: bool) {
+    if enabled {
+        Capability::StencilTest.enable();
+    } else {
+        Capability::StencilTest.disable();
+    }
+}
+
+/// Clears the whole stencil buffer of the bound framebuffer to `value`, ignoring
+/// the current stencil write mask. The scissor test still applies.
+///
+/// # Errors
+/// Returns an error if OpenGL reports an error.
+pub fn clear_stencil(value: i32) -> Result<()> {
+    with_backend(|backend| {
+        let write_mask = backend.get_integer(gl::STENCIL_WRITEMASK);
+        backend.stencil_mask_separate(gl::FRONT, u32::MAX);
+        backend.clear_stencil(value);
+        backend.clear(gl::STENCIL_BUFFER_BIT);
+        backend.stencil_mask_separate(gl::FRONT, write_mask as u32);
+    });
+    check_gl_error().context("Failed to clear the stencil buffer")
+}
+
+/// Returns the number of stencil bits of the default framebuffer, 0 if the window
+/// was created without a stencil buffer. See `SdlWindowOptions::stencil_bits`.
+///
+/// The default framebuffer is bound while querying, as the attachment names differ
+/// for framebuffer objects. The size is only queried if there is a stencil buffer,
+/// which is an error otherwise.
+///
+/// # Errors
+/// Returns an error if OpenGL reports one, e.g. for a context without a default
+/// framebuffer. The error isn't left pending.
+pub fn default_framebuffer_stencil_bits() -> Result<u32> {
+    let bits = with_backend(|backend| {
+        let previous_framebuffer = backend.get_integer(gl::DRAW_FRAMEBUFFER_BINDING);
+        backend.bind_framebuffer(gl::DRAW_FRAMEBUFFER, 0);
+        let object_type = backend.get_framebuffer_attachment_parameter_i(
+            gl::DRAW_FRAMEBUFFER,
+            gl::STENCIL,
+            gl::FRAMEBUFFER_ATTACHMENT_OBJECT_TYPE,
+        );
+        let mut bits = 0;
+        if object_type as GLenum != gl::NONE {
+            bits = backend.get_framebuffer_attachment_parameter_i(
+                gl::DRAW_FRAMEBUFFER,
+                gl::STENCIL,
+                gl::FRAMEBUFFER_ATTACHMENT_STENCIL_SIZE,
+            );
+        }
+        backend.bind_framebuffer(gl::DRAW_FRAMEBUFFER, previous_framebuffer as GLuint);
+        bits
+    });
+    check_gl_error().context("Failed to query the stencil bits of the default framebuffer")?;
+    Ok(bits.max(0) as u32)
+}
@@ -0,0 +1,185 @@
+use crate::color::Color;
+use crate::gl_types::ShaderType;
+use crate::opengl::debug_output::DebugGroup;
+use crate::opengl::shader_program::ShaderProgram;
+use crate::opengl::state_guard::{DepthGuard, StencilGuard};
+use crate::opengl::stencil::{StencilFunc, StencilOp, StencilState};
+use anyhow::{Context, Result};
+use cgmath::Matrix4;
+
+const OUTLINE_VERTEX_SHADER: &str = "
+    #version 330 core
+    layout (location = 0) in vec3 aPos;
+
+    uniform mat4 model;
+    uniform mat4 view;
+    uniform mat4 projection;
+    uniform float outline_scale;
+
+    void main() {
+        gl_Position = projection * view * model * vec4(aPos * outline_scale, 1.0);
+    }";
+const OUTLINE_FRAGMENT_SHADER: &str = "
+    #version 330 core
+    out vec4 FragColor;
+
+    uniform vec4 outline_color;
+
+    void main() {
+        FragColor = outline_color;
+    }";
+
+//////////////////////////////////////////////////////////////////////////////
+// - StencilOutline -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Draws a solid outline around selected 3D objects, using the stencil buffer.
+///
+/// The object is drawn normally while its pixels are marked in the stencil buffer.
+/// Then a slightly scaled up version is drawn in a solid color everywhere except
+/// on the marked pixels, so only a border remains. The outline is drawn on top of
+/// everything else, which keeps selected objects visible behind others.
+///
+/// The window needs a stencil buffer, see `SdlWindowOptions::stencil_bits`, and the
+/// stencil buffer has to be cleared every frame. The scaling happens around the
+/// origin of the model, so objects should be centered.
+///
+/// # Example
+/// ```ignore
+/// let outline = StencilOutline::new()?.color(Color::new(1.0, 0.6, 0.0, 1.0));
+/// outline.draw(
+///     &model,
+///     &view,
+///     &projection,
+///     || {
+///         shader.activate();
+///         shader.set_uniform_matrix("model", false, &model)?;
+///         cube.render()
+///     },
+///     || cube.render(),
+/// )?;
+/// ```
+pub struct StencilOutline {
+    shader: ShaderProgram,
+    color: Color,
+    scale: f32,
+    reference: i32,
+}
+
+impl StencilOutline {
+    /// Creates an orange outline, which is 5% larger than the object.
+    ///
+    /// # Errors
+    /// Returns an error if the outline shader fails to compile.
+    pub fn new() -> Result<Self> {
+        let mut shader = ShaderProgram::new();
+        shader.add_source(ShaderType::Vertex, OUTLINE_VERTEX_SHADER)?;
+        shader.add_source(ShaderType::Fragment, OUTLINE_FRAGMENT_SHADER)?;
+        shader.compile().context("Failed to compile the outline shader")?;
+        Ok(Self {
+            shader,
+            color: Color::new(1.0, 0.6, 0.0, 1.0),
+            scale: 1.05,
+            reference: 1,
+        })
+    }
+
+    pub fn color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Sets the scale of the outline geometry relative to the object.
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Sets the stencil value used to mark the object, 1 by default.
+    pub fn reference(mut self, reference: i32) -> Self {
+        self.reference = reference;
+        self
+    }
+
+    /// The shader used for the outline pass. Its `aPos` input is at location 0.
+    pub fn shader(&self) -> &ShaderProgram {
+        &self.shader
+    }
+
+    /// Draws an object with an outline.
+    ///
+    /// `draw_object` draws the object as usual, with its own shader. `draw_outline`
+    /// is called with the outline shader active and should only issue the draw call
+    /// for the same geometry.
+    ///
+    /// # Errors
+    /// Returns an error if one of the closures fails or OpenGL reports an error.
+    pub fn draw<O, L>(
+        &self,
+        model: &Matrix4<f32>,
+        view: &Matrix4<f32>,
+        projection: &Matrix4<f32>,
+        draw_object: O,
+        draw_outline: L,
+    ) -> Result<()>
+    where
+        O: FnOnce() -> Result<()>,
+        L: FnOnce() -> Result<()>,
+    {
+        let _debug_group = DebugGroup::push("Stencil outline");
+
+        // Mark the pixels of the object
+        {
+            let _stencil_guard = StencilGuard::new(mark_state(self.reference))?;
+            draw_object()?;
+        }
+
+        // Draw the enlarged object around the marked pixels
+        let _stencil_guard = StencilGuard::new(outside_state(self.reference))?;
+        let _depth_guard = DepthGuard::disabled()?;
+
+        let color: [f32; 4] = self.color.into();
+        self.shader.activate();
+        self.shader.set_uniform_matrix("model", false, model)?;
+        self.shader.set_uniform_matrix("view", false, view)?;
+        self.shader.set_uniform_matrix("projection", false, projection)?;
+        self.shader.set_uniform("outline_scale", self.scale)?;
+        self.shader.set_uniform("outline_color", color)?;
+        draw_outline()
+    }
+}
+
+/// The stencil state while drawing the object, which marks its pixels with
+/// `reference`, also where it is hidden behind others.
+fn mark_state(reference: i32) -> StencilState {
+    StencilState::new().func(StencilFunc::Always, reference, u32::MAX).ops(
+        StencilOp::Keep,
+        StencilOp::Replace,
+        StencilOp::Replace,
+    )
+}
+
+/// The stencil state while drawing the outline, which skips the marked pixels.
+fn outside_state(reference: i32) -> StencilState {
+    StencilState::new()
+        .func(StencilFunc::NotEqual, reference, u32::MAX)
+        .write_mask(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outline_states() {
+        let mark = mark_state(3);
+        assert_eq!((mark.func, mark.reference), (StencilFunc::Always, 3));
+        // Hidden parts are marked too, the outline is drawn on top of everything
+        assert_eq!((mark.depth_fail, mark.pass), (StencilOp::Replace, StencilOp::Replace));
+        assert_ne!(mark.write_mask, 0);
+
+        let outside = outside_state(3);
+        assert_eq!((outside.func, outside.reference), (StencilFunc::NotEqual, 3));
+        assert_eq!(outside.write_mask, 0);
+    }
+}
@@ -0,0 +1,480 @@
+use std::cell::RefCell;
+use std::mem::size_of;
+use std::ptr;
+
+use anyhow::{anyhow, Context, Result};
+use gl::types::GLsync;
+
+use crate::gl_types::{BufferMapFlags, BufferType, BufferUsage};
+use crate::gl_utils::{check_gl_error, gl_has_extension, gl_version_at_least};
+use crate::opengl::buffer_object::BufferObject;
+use crate::opengl::gl_backend::with_backend;
+
+/// Number of frames the shared vertex stream can be ahead of the GPU.
+const SHARED_STREAM_FRAME_COUNT: usize = 3;
+/// Number of floats the shared vertex stream can hand out per frame (1 MiB).
+const SHARED_STREAM_FRAME_CAPACITY: usize = 256 * 1024;
+/// Timeout for a single wait on a fence in nanoseconds.
+const FENCE_WAIT_TIMEOUT: u64 = 1_000_000_000;
+/// Number of timed out waits on a fence before the GPU is considered hung.
+const MAX_FENCE_WAITS: u32 = 5;
+
+//////////////////////////////////////////////////////////////////////////////
+// - StreamMode -
+//////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamMode {
+    /// The buffer is mapped once with `GL_MAP_PERSISTENT_BIT` and every frame
+    /// region is protected by a fence before it is written again.
+    /// Requires OpenGL 4.4 or `GL_ARB_buffer_storage`.
+    PersistentMapped,
+    /// The buffer is orphaned whenever the ring wraps around, and written with
+    /// unsynchronized mappings in between. Works with every OpenGL 3.3 context.
+    Orphaning,
+}
+
+impl StreamMode {
+    /// Returns the best mode supported by the current context.
+    pub fn detect() -> Self {
+        let has_buffer_storage =
+            gl_version_at_least(4, 4) || gl_has_extension("GL_ARB_buffer_storage");
+        if has_buffer_storage
+            && with_backend(|backend| backend.is_function_loaded("glBufferStorage"))
+        {
+            StreamMode::PersistentMapped
+        } else {
+            StreamMode::Orphaning
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - StreamAllocation -
+//////////////////////////////////////////////////////////////////////////////
+
+/// A range of a `StreamBuffer` which has been written for the current frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamAllocation {
+    /// Offset of the first element in the buffer, in elements.
+    pub offset: usize,
+    /// Number of written elements.
+    pub count: usize,
+}
+
+impl StreamAllocation {
+    /// Returns the offset of the first element in bytes, e.g. for
+    /// `glVertexAttribPointer`.
+    pub fn byte_offset<T>(&self) -> usize {
+        self.offset * size_of::<T>()
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - StreamBuffer -
+//////////////////////////////////////////////////////////////////////////////
+
+/// A ring buffer for data which is rebuilt every frame, like text or UI vertices.
+///
+/// The underlying `BufferObject` is split into one region per frame in flight.
+/// Each call to `allocate` writes the given values behind the previous ones of
+/// the current region and returns where they have been placed. `next_frame`
+/// moves on to the next region, which is only written again once the GPU has
+/// finished reading it.
+///
+/// How this is guaranteed depends on the `StreamMode`: with persistent mapping
+/// every region is fenced with `glFenceSync` and waited for before reuse. On
+/// contexts without `glBufferStorage` the buffer is orphaned instead whenever the
+/// ring starts over at the first region.
+///
+/// # Example
+/// ```no-run
+/// let mut stream = StreamBuffer::<f32>::new(BufferType::ArrayBuffer, 4096, 3)?;
+/// let allocation = stream.allocate(&vertices)?;
+/// stream.buffer().bind()?;
+/// // Set up the attribute pointers with allocation.byte_offset::<f32>() and draw
+/// stream.next_frame()?;
+/// ```
+pub struct StreamBuffer<T: Copy> {
+    buffer: BufferObject<T>,
+    mode: StreamMode,
+    frame_capacity: usize,
+    frame_count: usize,
+    frame_index: usize,
+    cursor: usize,
+    fences: Vec<GLsync>,
+    mapped_ptr: *mut T,
+}
+
+impl<T: Copy> StreamBuffer<T> {
+    /// Creates a stream buffer using the best mode of the current context.
+    ///
+    /// # Arguments
+    /// * `buffer_type` - The type of the buffer, e.g. `BufferType::ArrayBuffer`.
+    /// * `frame_capacity` - The number of elements which can be allocated per frame.
+    /// * `frame_count` - The number of frame regions, i.e. the number of frames the
+    ///   CPU may be ahead of the GPU before it has to wait.
+    pub fn new(buffer_type: BufferType, frame_capacity: usize, frame_count: usize) -> Result<Self> {
+        Self::with_mode(buffer_type, frame_capacity, frame_count, StreamMode::detect())
+    }
+
+    /// Creates a stream buffer with the given mode. See `new` for the arguments.
+    pub fn with_mode(
+        buffer_type: BufferType,
+        frame_capacity: usize,
+        frame_count: usize,
+        mode: StreamMode,
+    ) -> Result<Self> {
+        if frame_capacity == 0 || frame_count == 0 {
+            return Err(anyhow!(
+                "Invalid stream buffer size: {} element(s) per frame, {} frame(s)",
+                frame_capacity,
+                frame_count
+            ));
+        }
+
+        let capacity = frame_capacity * frame_count;
+        let mut mapped_ptr = ptr::null_mut();
+        let buffer = match mode {
+            StreamMode::PersistentMapped => {
+                let mut buffer =
+                    BufferObject::empty(buffer_type, BufferUsage::StreamDraw).without_shadow_copy();
+                let flags = gl::MAP_WRITE_BIT | gl::MAP_PERSISTENT_BIT | gl::MAP_COHERENT_BIT;
+                buffer.allocate_immutable_storage(capacity, flags)?;
+                with_backend(|backend| {
+                    backend.bind_buffer(gl::COPY_WRITE_BUFFER, buffer.buffer_id());
+                    mapped_ptr = backend.map_buffer_range(
+                        gl::COPY_WRITE_BUFFER,
+                        0,
+                        buffer.capacity_size() as isize,
+                        flags,
+                    ) as *mut T;
+                    backend.bind_buffer(gl::COPY_WRITE_BUFFER, 0);
+                });
+                check_gl_error().context("Failed to map stream buffer persistently")?;
+                if mapped_ptr.is_null() {
+                    return Err(anyhow!("Failed to map stream buffer persistently"));
+                }
+                buffer
+            }
+            StreamMode::Orphaning => {
+                BufferObject::with_capacity(buffer_type, BufferUsage::StreamDraw, capacity)
+                    .without_shadow_copy()
+            }
+        };
+
+        Ok(Self {
+            buffer,
+            mode,
+            frame_capacity,
+            frame_count,
+            frame_index: 0,
+            cursor: 0,
+            fences: vec![ptr::null(); frame_count],
+            mapped_ptr,
+        })
+    }
+
+    pub fn mode(&self) -> StreamMode {
+        self.mode
+    }
+
+    /// Returns the underlying buffer object, e.g. to bind it for drawing.
+    pub fn buffer(&self) -> &BufferObject<T> {
+        &self.buffer
+    }
+
+    pub fn buffer_id(&self) -> u32 {
+        self.buffer.buffer_id()
+    }
+
+    pub fn frame_capacity(&self) -> usize {
+        self.frame_capacity
+    }
+
+    pub fn frame_index(&self) -> usize {
+        self.frame_index
+    }
+
+    /// Returns the number of elements which can still be allocated in the current
+    /// frame region.
+    pub fn remaining(&self) -> usize {
+        self.frame_capacity - self.cursor
+    }
+
+    /// Writes the values into the current frame region and returns their position
+    /// in the buffer. If the region is full, the stream moves on to the next one.
+    pub fn allocate(&mut self, values: &[T]) -> Result<StreamAllocation> {
+        self.allocate_aligned(values, 1)
+    }
+
+    /// Like `allocate`, but places the values at an offset into the buffer which is
+    /// a multiple of `alignment` elements. This allows to address the values by
+    /// vertex index (e.g. the `first` argument of `glDrawArrays`) instead of by byte
+    /// offset.
+    ///
+    /// # Errors
+    /// Returns an error if the values don't fit into an empty frame region, which
+    /// may start at an unaligned offset if the frame capacity isn't a multiple of
+    /// `alignment`.
+    pub fn allocate_aligned(&mut self, values: &[T], alignment: usize) -> Result<StreamAllocation> {
+        let alignment = alignment.max(1);
+        let len = values.len();
+        let cursor = match place_allocation(
+            self.region_start(self.frame_index),
+            self.cursor,
+            len,
+            alignment,
+            self.frame_capacity,
+        ) {
+            Some(cursor) => cursor,
+            None => {
+                // Check the next region before moving on, so a failed allocation
+                // doesn't skip the rest of the current one
+                let next_index = (self.frame_index + 1) % self.frame_count;
+                let next_region_start = self.region_start(next_index);
+                let Some(cursor) =
+                    place_allocation(next_region_start, 0, len, alignment, self.frame_capacity)
+                else {
+                    return Err(anyhow!(
+                        "Stream allocation of {} elements aligned to {} exceeds the frame capacity of {}",
+                        len,
+                        alignment,
+                        self.frame_capacity
+                    ));
+                };
+                self.next_frame()?;
+                cursor
+            }
+        };
+
+        let offset = self.region_start(self.frame_index) + cursor;
+        if !values.is_empty() {
+            match self.mode {
+                StreamMode::PersistentMapped => unsafe {
+                    ptr::copy_nonoverlapping(
+                        values.as_ptr(),
+                        self.mapped_ptr.add(offset),
+                        values.len(),
+                    );
+                },
+                StreamMode::Orphaning => {
+                    let flags = BufferMapFlags::new().invalidate_range().unsynchronized();
+                    let mut range = self.buffer.map_range(offset, values.len(), flags)?;
+                    range.write(0, values)?;
+                }
+            }
+        }
+
+        self.cursor = cursor + len;
+        Ok(StreamAllocation {
+            offset,
+            count: len,
+        })
+    }
+
+    /// Returns the offset of the first element of a frame region in the buffer.
+    fn region_start(&self, frame_index: usize) -> usize {
+        frame_index * self.frame_capacity
+    }
+
+    /// Finishes the current frame region and moves on to the next one.
+    ///
+    /// With persistent mapping the finished region is fenced, and the call blocks
+    /// until the GPU has finished reading the next region. Without it, the buffer
+    /// is orphaned when the ring starts over.
+    pub fn next_frame(&mut self) -> Result<()> {
+        if self.mode == StreamMode::PersistentMapped {
+            let fence =
+                with_backend(|backend| backend.fence_sync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0));
+            self.fences[self.frame_index] = fence;
+        }
+
+        self.frame_index = (self.frame_index + 1) % self.frame_count;
+        self.cursor = 0;
+
+        match self.mode {
+            StreamMode::PersistentMapped => self.wait_for_region(self.frame_index),
+            StreamMode::Orphaning if self.frame_index == 0 => self.buffer.orphan(),
+            StreamMode::Orphaning => Ok(()),
+        }
+    }
+
+    /// Blocks until the fence of the given frame region has been signaled.
+    fn wait_for_region(&mut self, frame_index: usize) -> Result<()> {
+        let fence = std::mem::replace(&mut self.fences[frame_index], ptr::null());
+        if fence.is_null() {
+            return Ok(());
+        }
+
+        let result = (0..MAX_FENCE_WAITS)
+            .find_map(|_| {
+                // The fence was created by `next_frame` and is only deleted below
+                let wait_result = with_backend(|backend| unsafe {
+                    backend.client_wait_sync(fence, gl::SYNC_FLUSH_COMMANDS_BIT, FENCE_WAIT_TIMEOUT)
+                });
+                match wait_result {
+                    gl::ALREADY_SIGNALED | gl::CONDITION_SATISFIED => Some(Ok(())),
+                    gl::TIMEOUT_EXPIRED => None,
+                    _ => Some(Err(anyhow!(
+                        "Failed to wait for stream buffer region {}",
+                        frame_index
+                    ))),
+                }
+            })
+            .unwrap_or_else(|| {
+                Err(anyhow!(
+                    "The GPU didn't finish reading stream buffer region {} within {} seconds",
+                    frame_index,
+                    MAX_FENCE_WAITS as u64 * FENCE_WAIT_TIMEOUT / 1_000_000_000
+                ))
+            });
+
+        with_backend(|backend| unsafe { backend.delete_sync(fence) });
+        result
+    }
+}
+
+/// Returns the cursor at which `len` elements are placed in the frame region
+/// starting at `region_start`, so that their offset into the buffer is a multiple
+/// of `alignment`. Returns `None` if they don't fit behind `cursor` and the stream
+/// has to move on to the next region.
+fn place_allocation(
+    region_start: usize,
+    cursor: usize,
+    len: usize,
+    alignment: usize,
+    frame_capacity: usize,
+) -> Option<usize> {
+    let offset = (region_start + cursor).next_multiple_of(alignment.max(1));
+    let cursor = offset - region_start;
+    (cursor + len <= frame_capacity).then_some(cursor)
+}
+
+impl<T: Copy> Drop for StreamBuffer<T> {
+    fn drop(&mut self) {
+        with_backend(|backend| {
+            for fence in self.fences.iter().filter(|fence| !fence.is_null()) {
+                unsafe { backend.delete_sync(*fence) };
+            }
+            if !self.mapped_ptr.is_null() {
+                backend.bind_buffer(gl::COPY_WRITE_BUFFER, self.buffer.buffer_id());
+                backend.unmap_buffer(gl::COPY_WRITE_BUFFER);
+                backend.bind_buffer(gl::COPY_WRITE_BUFFER, 0);
+            }
+        });
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - Shared vertex stream -
+//////////////////////////////////////////////////////////////////////////////
+
+thread_local! {
+    static SHARED_VERTEX_STREAM: RefCell<Option<StreamBuffer<f32>>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` with the vertex stream which is shared by the dynamic drawables of
+/// the library (text, rectangles). The stream is created on first use and has to
+/// be used from the thread owning the OpenGL context.
+pub fn with_shared_vertex_stream<R>(
+    f: impl FnOnce(&mut StreamBuffer<f32>) -> Result<R>,
+) -> Result<R> {
+    SHARED_VERTEX_STREAM.with(|cell| {
+        let mut stream = cell.borrow_mut();
+        if stream.is_none() {
+            *stream = Some(StreamBuffer::new(
+                BufferType::ArrayBuffer,
+                SHARED_STREAM_FRAME_CAPACITY,
+                SHARED_STREAM_FRAME_COUNT,
+            )?);
+        }
+        match stream.as_mut() {
+            Some(stream) => f(stream),
+            None => Err(anyhow!("Shared vertex stream is not available")),
+        }
+    })
+}
+
+/// Deletes the shared vertex stream while the OpenGL context is still alive. It is
+/// created again on the next use. Called when the `SdlWindow` is dropped.
+pub fn release_shared_vertex_stream() {
+    SHARED_VERTEX_STREAM.with(|cell| cell.borrow_mut().take());
+}
+
+/// Moves the shared vertex stream on to the next frame, if it has been used.
+/// Called by `SdlWindow::swap` at the end of every frame.
+pub fn advance_shared_vertex_stream() -> Result<()> {
+    SHARED_VERTEX_STREAM.with(|cell| match cell.borrow_mut().as_mut() {
+        Some(stream) => stream.next_frame(),
+        None => Ok(()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opengl::gl_backend::{install_backend, RecordingGlBackend};
+
+    #[test]
+    fn test_place_allocation() {
+        assert_eq!(place_allocation(0, 0, 4, 1, 16), Some(0));
+        assert_eq!(place_allocation(0, 5, 4, 1, 16), Some(5));
+        // Aligned allocations skip to the next multiple of the alignment
+        assert_eq!(place_allocation(0, 5, 4, 4, 16), Some(8));
+        assert_eq!(place_allocation(0, 8, 4, 4, 16), Some(8));
+        assert_eq!(place_allocation(0, 5, 4, 0, 16), Some(5));
+        // Allocations which reach the end of the region still fit
+        assert_eq!(place_allocation(0, 12, 4, 4, 16), Some(12));
+    }
+
+    #[test]
+    fn test_place_allocation_wraps() {
+        assert_eq!(place_allocation(0, 13, 4, 1, 16), None);
+        // The padding for the alignment can be what doesn't fit anymore
+        assert_eq!(place_allocation(0, 11, 4, 8, 16), None);
+        assert_eq!(place_allocation(0, 16, 0, 1, 16), Some(16));
+    }
+
+    #[test]
+    fn test_place_allocation_in_unaligned_region() {
+        // The region starts at 10, the first multiple of 4 in it is 12
+        assert_eq!(place_allocation(10, 0, 4, 4, 10), Some(2));
+        assert_eq!(place_allocation(10, 3, 4, 4, 10), Some(6));
+        assert_eq!(place_allocation(10, 0, 8, 4, 10), Some(2));
+        // Even an empty region only holds 8 elements aligned to 4
+        assert_eq!(place_allocation(10, 0, 9, 4, 10), None);
+    }
+
+    #[test]
+    fn test_aligned_allocations_with_unaligned_frame_capacity() {
+        for mode in [StreamMode::PersistentMapped, StreamMode::Orphaning] {
+            let backend = RecordingGlBackend::new();
+            let _guard = install_backend(backend.clone());
+            // The second region starts at 10, which isn't a multiple of 4
+            let mut stream =
+                StreamBuffer::<f32>::with_mode(BufferType::ArrayBuffer, 10, 2, mode).unwrap();
+            assert_eq!(stream.allocate(&[1.0; 3]).unwrap().offset, 0);
+
+            // Wouldn't fit into the next region after aligning, so nothing is skipped
+            assert!(stream.allocate_aligned(&[9.0; 9], 4).is_err());
+            assert_eq!((stream.frame_index(), stream.remaining()), (0, 7));
+
+            assert_eq!(stream.allocate_aligned(&[2.0; 4], 4).unwrap().offset, 4);
+            let allocation = stream.allocate_aligned(&[3.0; 4], 4).unwrap();
+            assert_eq!((stream.frame_index(), allocation.offset), (1, 12));
+            assert_eq!(stream.remaining(), 4);
+            let contents = backend.buffer_contents(stream.buffer_id()).unwrap();
+            let written: Vec<f32> = contents[12 * 4..16 * 4]
+                .chunks_exact(4)
+                .map(|bytes| f32::from_ne_bytes(bytes.try_into().unwrap()))
+                .collect();
+            assert_eq!(written, [3.0; 4], "{:?}", mode);
+
+            // The first region is aligned again
+            let allocation = stream.allocate_aligned(&[4.0; 8], 4).unwrap();
+            assert_eq!((stream.frame_index(), allocation.offset), (0, 0));
+            assert!(check_gl_error().is_ok());
+        }
+    }
+}
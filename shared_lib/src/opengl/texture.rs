@@ -4,6 +4,8 @@ use anyhow::{anyhow, Context};
 use gl::types::{GLenum, GLint};
 use image::GenericImageView;
 use crate::gl_prelude::{check_gl_error, Deletable, TextureTarget};
+use crate::opengl::gl_backend::with_backend;
+use crate::opengl::resource_registry::{self, ResourceKind};
 
 const ERR_DELETE_NON_OWNER: &str = r#"Attempted to delete a Texture that is not owned.
 Only the owner should attempt to delete the texture to avoid
@@ -55,52 +57,11 @@ impl Texture {
             img.into_rgb8().into_raw()
         };
 
-        let mut texture_id = 0;
-        unsafe {
-            gl::GenTextures(1, &mut texture_id);
-            check_gl_error()
-                .with_context(|| format!("Failed to create texture object: {:?}", path.as_ref()))?;
-            gl::BindTexture(gl::TEXTURE_2D, texture_id);
-            check_gl_error().with_context(|| {
-                format!("Failed to bind to texture: {:?} (id: {})", path.as_ref(), texture_id)
-            })?;
-
-            // Set texture parameters here (e.g. GL_TEXTURE_WRAP_S, GL_TEXTURE_MIN_FILTER)
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as GLint);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as GLint);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
-
-            let format = if has_alpha {
-                gl::RGBA
-            } else {
-                gl::RGB
-            };
-
-            let gl_texture_type = texture_type.to_gl_enum();
-            gl::TexImage2D(
-                gl_texture_type,
-                0,
-                format as GLint,
-                width as GLint,
-                height as GLint,
-                0,
-                format,
-                gl::UNSIGNED_BYTE,
-                img_raw.as_ptr() as *const c_void,
-            );
-
-            gl::GenerateMipmap(gl_texture_type);
-            check_gl_error().with_context(|| {
-                format!("Failed to generate mipmap: {:?} (id: {})", path.as_ref(), texture_id)
-            })?;
-
-            // Unbind the texture
-            gl::BindTexture(gl_texture_type, 0);
-        }
+        let label = path.as_ref().to_string_lossy().to_string();
+        let texture_id = upload_pixels(&label, width, height, has_alpha, &img_raw, texture_type)?;
 
         #[rustfmt::skip]
-        println!("Loaded texture: {} (id: {}, {}x{})", path.as_ref().to_string_lossy(), texture_id, width, height);
+        println!("Loaded texture: {} (id: {}, {}x{})", label, texture_id, width, height);
 
         let uniform_name = if uniform_name.is_empty() {
             None
@@ -110,7 +71,7 @@ impl Texture {
 
         Ok(Texture {
             id: texture_id,
-            path: path.as_ref().to_string_lossy().to_string(),
+            path: label,
             alpha: has_alpha,
             flip: [flip_horizontal, flip_vertical],
             dimension: [width, height],
@@ -120,6 +81,48 @@ impl Texture {
         })
     }
 
+    /// Creates a 2D texture from raw RGBA pixel data kept in memory.
+    ///
+    /// This is used for textures that are generated at runtime instead of being
+    /// loaded from a file. The `label` takes the place of the file path and is
+    /// returned by `path()`.
+    ///
+    /// # Arguments
+    /// * `label` - A descriptive name for the texture.
+    /// * `width` - The width of the image in pixels.
+    /// * `height` - The height of the image in pixels.
+    /// * `pixels` - Tightly packed RGBA8 pixel data, `width * height * 4` bytes.
+    pub fn from_rgba_pixels(
+        label: &str,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> anyhow::Result<Self> {
+        let expected_len = (width * height * 4) as usize;
+        if pixels.len() != expected_len {
+            return Err(anyhow!(
+                "Invalid pixel data for texture {}: expected {} bytes, got {}",
+                label,
+                expected_len,
+                pixels.len()
+            ));
+        }
+
+        let texture_type = TextureTarget::Texture2D;
+        let texture_id = upload_pixels(label, width, height, true, pixels, texture_type)?;
+
+        Ok(Texture {
+            id: texture_id,
+            path: label.to_string(),
+            alpha: true,
+            flip: [false, false],
+            dimension: [width, height],
+            uniform_name: None,
+            texture_type,
+            cloneable: true,
+        })
+    }
+
     pub(crate) fn clone_as_non_owner(&self) -> anyhow::Result<Self> {
         if !self.cloneable {
             Err(anyhow!(ERR_CLONE_NON_CLONEABLE))
@@ -148,10 +151,10 @@ impl Texture {
 
     /// Binds the texture for use in rendering.
     pub fn bind(&self) {
-        unsafe {
-            gl::ActiveTexture(gl::TEXTURE0);
-            gl::BindTexture(gl::TEXTURE_2D, self.id);
-        }
+        with_backend(|backend| {
+            backend.active_texture(gl::TEXTURE0);
+            backend.bind_texture(gl::TEXTURE_2D, self.id);
+        });
     }
 
     /// Binds the texture to a specified texture unit.
@@ -170,10 +173,10 @@ impl Texture {
         if texture_unit > 31 {
             panic!("Texture unit must be between 0 and 31.");
         }
-        unsafe {
-            gl::ActiveTexture(gl::TEXTURE0 + texture_unit);
-            gl::BindTexture(gl::TEXTURE_2D, self.id);
-        }
+        with_backend(|backend| {
+            backend.active_texture(gl::TEXTURE0 + texture_unit);
+            backend.bind_texture(gl::TEXTURE_2D, self.id);
+        });
     }
 
     /// Binds the texture represented by `self` to a specific texture unit.
@@ -189,17 +192,15 @@ impl Texture {
         if !(gl::TEXTURE0..=gl::TEXTURE31).contains(&texture_unit) {
             panic!("Texture unit must be in range from GL_TEXTURE0 to GL_TEXTURE31.");
         }
-        unsafe {
-            gl::ActiveTexture(texture_unit);
-            gl::BindTexture(gl::TEXTURE_2D, self.id);
-        }
+        with_backend(|backend| {
+            backend.active_texture(texture_unit);
+            backend.bind_texture(gl::TEXTURE_2D, self.id);
+        });
     }
 
     /// Unbinds the texture.
     pub fn unbind(&self) {
-        unsafe {
-            gl::BindTexture(gl::TEXTURE_2D, 0);
-        }
+        with_backend(|backend| backend.bind_texture(gl::TEXTURE_2D, 0));
     }
 
     pub fn has_alpha(&self) -> bool {
@@ -233,9 +234,8 @@ impl Deletable for Texture {
             return Err(anyhow!(ERR_DELETE_NON_OWNER));
         }
         if self.id != 0 {
-            unsafe {
-                gl::DeleteTextures(1, &self.id);
-            }
+            resource_registry::unregister(ResourceKind::Texture, self.id)?;
+            with_backend(|backend| backend.delete_texture(self.id));
             self.id = 0;
         }
         Ok(())
@@ -252,3 +252,98 @@ impl Drop for Texture {
         }
     }
 }
+
+/// Creates a texture object, uploads the given pixels and generates mipmaps.
+/// Returns the id of the new texture object.
+fn upload_pixels(
+    label: &str,
+    width: u32,
+    height: u32,
+    has_alpha: bool,
+    pixels: &[u8],
+    texture_type: TextureTarget,
+) -> anyhow::Result<u32> {
+    let texture_id = with_backend(|backend| backend.gen_texture());
+    check_gl_error().with_context(|| format!("Failed to create texture object: {:?}", label))?;
+    with_backend(|backend| backend.bind_texture(gl::TEXTURE_2D, texture_id));
+    check_gl_error()
+        .with_context(|| format!("Failed to bind to texture: {:?} (id: {})", label, texture_id))?;
+
+    let format = if has_alpha {
+        gl::RGBA
+    } else {
+        gl::RGB
+    };
+    let gl_texture_type = texture_type.to_gl_enum();
+    with_backend(|backend| {
+        // Set texture parameters here (e.g. GL_TEXTURE_WRAP_S, GL_TEXTURE_MIN_FILTER)
+        backend.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as GLint);
+        backend.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as GLint);
+        backend.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+        backend.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+
+        unsafe {
+            backend.tex_image_2d(
+                gl_texture_type,
+                0,
+                format as GLint,
+                width as GLint,
+                height as GLint,
+                format,
+                gl::UNSIGNED_BYTE,
+                pixels.as_ptr() as *const c_void,
+            );
+        }
+        backend.generate_mipmap(gl_texture_type);
+    });
+    check_gl_error()
+        .with_context(|| format!("Failed to generate mipmap: {:?} (id: {})", label, texture_id))?;
+
+    // Unbind the texture
+    with_backend(|backend| backend.bind_texture(gl_texture_type, 0));
+    // The mipmap chain adds about a third to the base level
+    resource_registry::register(ResourceKind::Texture, texture_id, label, pixels.len() * 4 / 3);
+    Ok(texture_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opengl::gl_backend::{install_backend, GlCall, RecordingGlBackend};
+
+    #[test]
+    fn test_from_rgba_pixels_uploads_and_deletes() {
+        let backend = RecordingGlBackend::new();
+        let _guard = install_backend(backend.clone());
+
+        assert!(Texture::from_rgba_pixels("too short", 2, 2, &[0; 12]).is_err());
+        assert!(backend.calls().is_empty());
+
+        let texture = Texture::from_rgba_pixels("pixels", 2, 1, &[255; 8]).unwrap();
+        let id = texture.get_texture_id();
+        assert_eq!(backend.texture_size(id), Some((2, 1)));
+        assert!(backend.calls().contains(&GlCall::TexImage2D {
+            target: gl::TEXTURE_2D,
+            level: 0,
+            internal_format: gl::RGBA as GLint,
+            width: 2,
+            height: 1,
+            format: gl::RGBA,
+            data_type: gl::UNSIGNED_BYTE,
+        }));
+        assert!(backend.calls().contains(&GlCall::GenerateMipmap(gl::TEXTURE_2D)));
+        // The texture is unbound after the upload
+        assert_eq!(backend.bound_texture(gl::TEXTURE0, gl::TEXTURE_2D), 0);
+
+        texture.bind_as_unit(3);
+        assert_eq!(backend.bound_texture(gl::TEXTURE3, gl::TEXTURE_2D), id);
+
+        // Only the owner deletes the texture
+        drop(texture.clone_as_non_owner().unwrap());
+        assert_eq!(backend.texture_size(id), Some((2, 1)));
+        drop(texture);
+        assert_eq!(backend.texture_size(id), None);
+        assert_eq!(backend.bound_texture(gl::TEXTURE3, gl::TEXTURE_2D), 0);
+        assert_eq!(backend.live_objects(), 0);
+    }
+}
@@ -1,9 +1,21 @@
+use crate::opengl::debug_output::label_object;
+use crate::opengl::resource_registry::ResourceKind;
 use crate::opengl::texture::Texture;
 use crate::opengl::texture_builder::TextureBuilder;
+use crate::opengl::texture_manifest::{TextureManifest, TextureManifestError};
+use crate::opengl::texture_utils::create_checkerboard_pixels;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::Path;
 use thiserror::Error;
 
+/// Name under which the built-in checkerboard texture is created.
+const FALLBACK_TEXTURE_NAME: &str = "<fallback checkerboard>";
+const FALLBACK_TEXTURE_SIZE: u32 = 64;
+const FALLBACK_TEXTURE_CELL_SIZE: u32 = 8;
+const FALLBACK_COLOR_A: [u8; 4] = [255, 0, 255, 255];
+const FALLBACK_COLOR_B: [u8; 4] = [0, 0, 0, 255];
+
 //////////////////////////////////////////////////////////////////////////////
 // - TextureManager -
 //////////////////////////////////////////////////////////////////////////////
@@ -21,6 +33,11 @@ use thiserror::Error;
 /// * `texture_error`: A hashmap that logs any errors related to specific textures.
 /// * `texture_flags`: A hashmap that stores flags or properties affecting how textures
 ///   are rendered or processed.
+/// * `texture_descriptors`: A hashmap with the descriptors of textures registered
+///   through `add_descriptor` or a manifest.
+/// * `graceful_mode`: If enabled, textures which fail to load are replaced by a
+///   built-in checkerboard texture instead of returning an error.
+/// * `fallback_texture`: The lazily created checkerboard texture.
 ///
 /// # Usage
 /// The `TextureManager` is typically used in graphical applications where managing
@@ -38,6 +55,9 @@ pub struct TextureManager {
     texture_paths: HashMap<String, String>,
     texture_error: HashMap<String, TextureError>,
     texture_flags: HashMap<String, TextureFlags>,
+    texture_descriptors: HashMap<String, TextureDescriptor>,
+    graceful_mode: bool,
+    fallback_texture: Option<Texture>,
 }
 
 impl TextureManager {
@@ -70,7 +90,7 @@ impl TextureManager {
         let name = name.to_string();
 
         // Check if key is already present
-        if self.textures.contains_key(&name) {
+        if self.textures.contains_key(&name) || self.texture_paths.contains_key(&name) {
             return Err(TextureError::KeyExists { key_name: name });
         }
 
@@ -151,10 +171,112 @@ impl TextureManager {
         if path.exists() {
             None
         } else {
-            Some(TextureError::FileNotFound)
+            Some(TextureError::FileNotFound {
+                path: texture_path.to_string(),
+            })
+        }
+    }
+
+    /// Registers a texture with all options of the given `TextureDescriptor`.
+    ///
+    /// The descriptor's path is registered like with `add_path`, and its options
+    /// are stored as the texture's flags. The descriptor itself is kept so that it
+    /// can be queried later with `get_texture_descriptor`.
+    ///
+    /// # Arguments
+    /// * `name` - The name under which the texture is registered.
+    /// * `descriptor` - The path and loading options of the texture.
+    ///
+    /// # Returns
+    /// `Ok(())` if the texture was registered, otherwise the `TextureError` that
+    /// prevented the registration (e.g. the name is taken or the file is missing).
+    pub fn add_descriptor(
+        &mut self,
+        name: &str,
+        descriptor: TextureDescriptor,
+    ) -> Result<(), TextureError> {
+        self.add_path(name, &descriptor.path)?;
+        self.add_texture_flags(name, TextureFlags::from(&descriptor));
+        self.texture_descriptors.insert(name.to_string(), descriptor);
+        Ok(())
+    }
+
+    /// Returns the descriptor a texture was registered with, if it was registered
+    /// through `add_descriptor` or a manifest.
+    pub fn get_texture_descriptor(&self, name: &str) -> Option<&TextureDescriptor> {
+        self.texture_descriptors.get(name)
+    }
+
+    /// Registers every texture of a JSON manifest file.
+    ///
+    /// See `TextureManifest` for the file format. All entries are processed, even
+    /// when some of them fail; the failures are collected and returned together,
+    /// each one tagged with the name of the texture it belongs to. Missing files are
+    /// also recorded per texture and can be queried with `texture_error`.
+    ///
+    /// # Arguments
+    /// * `path` - The path to the manifest file.
+    ///
+    /// # Returns
+    /// The names of the registered textures, or a `TextureManifestError` if the
+    /// manifest could not be read or at least one entry failed.
+    ///
+    /// # Examples
+    /// ```no-run
+    /// let mut texture_manager = TextureManager::default();
+    /// if let Err(e) = texture_manager.load_manifest("assets/textures/manifest.json") {
+    ///     for (name, error) in e.entry_errors() {
+    ///         eprintln!("Texture {name} not registered: {error}");
+    ///     }
+    /// }
+    /// ```
+    pub fn load_manifest<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<Vec<String>, TextureManifestError> {
+        let manifest = TextureManifest::from_file(path)?;
+        self.add_manifest(manifest)
+    }
+
+    /// Registers every entry of an already parsed `TextureManifest`.
+    ///
+    /// Behaves like `load_manifest` but without reading a file.
+    pub fn add_manifest(
+        &mut self,
+        manifest: TextureManifest,
+    ) -> Result<Vec<String>, TextureManifestError> {
+        let mut registered = Vec::new();
+        let mut errors = Vec::new();
+
+        for entry in manifest.textures {
+            match self.add_descriptor(&entry.name, entry.descriptor) {
+                Ok(_) => registered.push(entry.name),
+                Err(error) => errors.push((entry.name, error)),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(registered)
+        } else {
+            Err(TextureManifestError::Entries(errors))
         }
     }
 
+    /// Returns the names of all registered textures in alphabetical order.
+    ///
+    /// A texture counts as registered once its path has been added, regardless of
+    /// whether it has been loaded yet.
+    pub fn get_texture_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.texture_paths.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Returns `true` if a texture path is registered under the given name.
+    pub fn contains_texture(&self, name: &str) -> bool {
+        self.texture_paths.contains_key(name)
+    }
+
     /// Retrieves the texture error associated with a specific key, if any.
     ///
     /// This method returns a reference to a `TextureError` if there is an error
@@ -204,6 +326,29 @@ impl TextureManager {
         self.texture_error.remove(name);
     }
 
+    /// Enables or disables the graceful mode.
+    ///
+    /// In graceful mode `get_texture` never fails because of a missing or broken
+    /// texture. Instead a built-in magenta/black checkerboard texture is returned,
+    /// which makes the problem visible on screen without stopping the application.
+    /// The original error is recorded and can be queried with `texture_error`.
+    /// A texture with a recorded error is not loaded again until its error has
+    /// been removed with `clear_error`.
+    pub fn set_graceful_mode(&mut self, enabled: bool) {
+        self.graceful_mode = enabled;
+    }
+
+    /// Returns true if the graceful mode is enabled.
+    pub fn graceful_mode(&self) -> bool {
+        self.graceful_mode
+    }
+
+    /// Returns true if the given texture would be served by the checkerboard
+    /// fallback, i.e. the graceful mode is enabled and an error is recorded.
+    pub fn is_fallback(&self, name: &str) -> bool {
+        self.graceful_mode && self.has_error(name)
+    }
+
     /// Adds or updates flags associated with a specific texture in the texture
     /// manager. This method allows for setting or modifying the properties and
     /// behavior of textures within the application.
@@ -307,6 +452,9 @@ impl TextureManager {
     /// It may also return `TextureError::CloneFailure` if the cloning process fails,
     /// or `TextureError::FindFailed` if the texture could not be retrieved post-insertion.
     ///
+    /// In graceful mode these errors are recorded instead and the checkerboard
+    /// fallback texture is returned (see `set_graceful_mode`).
+    ///
     /// # Examples
     /// ```no-run
     /// use shared_lib::gl_texture::TextureManager;
@@ -327,6 +475,29 @@ impl TextureManager {
     /// If insertion of a new texture succeeds but retrieval fails, it handles this
     /// edge case by returning a `FindFailed` error.
     pub fn get_texture(&mut self, name: &str) -> anyhow::Result<Texture, TextureError> {
+        if !self.graceful_mode {
+            return self.load_texture(name);
+        }
+
+        // Don't retry textures which failed already, serve the fallback instead
+        if let Some(texture_error) = self.texture_error.get(name) {
+            let texture_error = texture_error.clone();
+            return self.get_fallback_texture().map_err(|_| texture_error);
+        }
+
+        match self.load_texture(name) {
+            Ok(texture) => Ok(texture),
+            Err(texture_error) => {
+                eprintln!("Using fallback for texture {}: {}", name, texture_error);
+                self.texture_error.insert(name.to_string(), texture_error.clone());
+                self.get_fallback_texture().map_err(|_| texture_error)
+            }
+        }
+    }
+
+    /// Retrieves a texture from the cache or loads it from its registered path.
+    /// See `get_texture` for details.
+    fn load_texture(&mut self, name: &str) -> anyhow::Result<Texture, TextureError> {
         // Attempt for retrieve and clone an existing texture
         if let Some(texture_data) = self.textures.get(name) {
             return get_cloned_texture(texture_data);
@@ -341,7 +512,10 @@ impl TextureManager {
 
         // Create, insert, and directly clone the new texture
         let texture = self.create_texture(name)?;
-        self.textures.insert(name.to_string(), TextureData::new(texture));
+        label_object(ResourceKind::Texture, texture.get_texture_id(), name);
+        let mut texture_data = TextureData::new(texture);
+        texture_data.description = self.texture_descriptors.get(name).cloned();
+        self.textures.insert(name.to_string(), texture_data);
 
         // Assuming insertion is successful and the texture is now available
         return self
@@ -352,12 +526,39 @@ impl TextureManager {
 
         // Helper function to clone a texture
         fn get_cloned_texture(texture_data: &TextureData) -> anyhow::Result<Texture, TextureError> {
-            match texture_data.texture.clone_as_non_owner() {
-                Ok(cloned_texture) => Ok(cloned_texture),
-                Err(e) => Err(TextureError::CloneFailure {
+            clone_texture(&texture_data.texture)
+        }
+    }
+
+    /// Returns a non-owning clone of the checkerboard texture, creating it on
+    /// first use.
+    fn get_fallback_texture(&mut self) -> anyhow::Result<Texture, TextureError> {
+        if self.fallback_texture.is_none() {
+            let pixels = create_checkerboard_pixels(
+                FALLBACK_TEXTURE_SIZE,
+                FALLBACK_TEXTURE_CELL_SIZE,
+                FALLBACK_COLOR_A,
+                FALLBACK_COLOR_B,
+            );
+            let texture = Texture::from_rgba_pixels(
+                FALLBACK_TEXTURE_NAME,
+                FALLBACK_TEXTURE_SIZE,
+                FALLBACK_TEXTURE_SIZE,
+                &pixels,
+            )
+            .map_err(|e| {
+                eprintln!("Failed creating fallback texture: {:?}", e);
+                TextureError::CreateTextureFailure {
                     message: e.to_string(),
-                }),
-            }
+                }
+            })?;
+            label_object(ResourceKind::Texture, texture.get_texture_id(), FALLBACK_TEXTURE_NAME);
+            self.fallback_texture = Some(texture);
+        }
+
+        match &self.fallback_texture {
+            Some(texture) => clone_texture(texture),
+            None => Err(TextureError::FindFailed),
         }
     }
 
@@ -462,17 +663,22 @@ impl TextureManager {
                 Some(flags) => flags.clone(),
                 None => TextureFlags::default(),
             };
-            TextureBuilder::default()
+            let mut builder = TextureBuilder::default()
                 .path(texture_path)
                 .has_alpha(texture_flags.has_alpha)
                 .flip_vertical(texture_flags.flip_vertically)
-                .build()
-                .map_err(|e| {
-                    eprintln!("Failed creating texture: {:?}", e);
-                    TextureError::CreateTextureFailure {
-                        message: e.to_string(),
-                    }
-                })
+                .flip_horizontal(texture_flags.flip_horizontally);
+            if let Some(descriptor) = self.texture_descriptors.get(name) {
+                if let Some(uniform_name) = &descriptor.uniform_name {
+                    builder = builder.with_uniform_name(uniform_name);
+                }
+            }
+            builder.build().map_err(|e| {
+                eprintln!("Failed creating texture: {:?}", e);
+                TextureError::CreateTextureFailure {
+                    message: e.to_string(),
+                }
+            })
         } else {
             Err(TextureError::KeyNotExisting {
                 key_name: name.to_string(),
@@ -481,6 +687,14 @@ impl TextureManager {
     }
 }
 
+/// Clones a texture as non-owner, so that dropping the clone keeps the GPU
+/// texture alive.
+fn clone_texture(texture: &Texture) -> anyhow::Result<Texture, TextureError> {
+    texture.clone_as_non_owner().map_err(|e| TextureError::CloneFailure {
+        message: e.to_string(),
+    })
+}
+
 //////////////////////////////////////////////////////////////////////////////
 // - TextureData -
 //////////////////////////////////////////////////////////////////////////////
@@ -513,8 +727,10 @@ pub enum TextureError {
     KeyNotExisting {
         key_name: String,
     },
-    #[error("File has not been found")]
-    FileNotFound,
+    #[error("File has not been found: {path}")]
+    FileNotFound {
+        path: String,
+    },
     #[error("Failed to create texture: {message}")]
     CreateTextureFailure {
         message: String,
@@ -535,6 +751,17 @@ pub enum TextureError {
 pub struct TextureFlags {
     pub has_alpha: bool,
     pub flip_vertically: bool,
+    pub flip_horizontally: bool,
+}
+
+impl From<&TextureDescriptor> for TextureFlags {
+    fn from(descriptor: &TextureDescriptor) -> Self {
+        Self {
+            has_alpha: descriptor.has_alpha,
+            flip_vertically: descriptor.flip_vertically,
+            flip_horizontally: descriptor.flip_horizontally,
+        }
+    }
 }
 
 //////////////////////////////////////////////////////////////////////////////
@@ -591,18 +818,82 @@ impl TextureResult {
 // - TextureDescriptor -
 //////////////////////////////////////////////////////////////////////////////
 
-#[derive(Debug, Default, Clone, PartialEq, Eq)]
+/// Describes where a texture is loaded from and how it is processed while loading.
+///
+/// All fields except `path` are optional in serialized form and fall back to
+/// their default values.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TextureDescriptor {
     pub path: String,
+    #[serde(default)]
+    pub has_alpha: bool,
+    #[serde(default)]
     pub flip_vertically: bool,
+    #[serde(default)]
     pub flip_horizontally: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uniform_name: Option<String>,
 }
 
 impl TextureDescriptor {
-    fn new(path: String) -> Self {
+    pub fn new(path: String) -> Self {
         Self {
             path,
             ..Default::default()
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opengl::gl_backend::{install_backend, GlCall, RecordingGlBackend};
+
+    #[test]
+    fn test_textures_are_loaded_once() {
+        let backend = RecordingGlBackend::new();
+        let _guard = install_backend(backend.clone());
+
+        let path =
+            std::env::temp_dir().join(format!("texture_manager_test_{}.png", std::process::id()));
+        image::RgbaImage::new(4, 2).save(&path).unwrap();
+
+        let mut texture_manager = TextureManager::default();
+        texture_manager.add_path("image", path.to_str().unwrap()).unwrap();
+        let texture = texture_manager.get_texture("image").unwrap();
+        let cached = texture_manager.get_texture("image").unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(texture.get_texture_id(), cached.get_texture_id());
+        assert_eq!(backend.texture_size(texture.get_texture_id()), Some((4, 2)));
+        let calls = backend.calls();
+        let generated = calls.iter().filter(|call| matches!(call, GlCall::GenTexture(_)));
+        assert_eq!(generated.count(), 1);
+
+        // The clones don't own the texture, the manager does
+        drop(texture);
+        drop(cached);
+        assert_eq!(backend.live_objects(), 1);
+        drop(texture_manager);
+        assert_eq!(backend.live_objects(), 0);
+    }
+
+    #[test]
+    fn test_graceful_mode_uploads_fallback() {
+        let backend = RecordingGlBackend::new();
+        let _guard = install_backend(backend.clone());
+
+        let mut texture_manager = TextureManager::default();
+        assert!(texture_manager.get_texture("missing").is_err());
+        assert!(backend.calls().is_empty());
+
+        texture_manager.set_graceful_mode(true);
+        let texture = texture_manager.get_texture("missing").unwrap();
+        assert!(texture_manager.is_fallback("missing"));
+        assert_eq!(texture.path(), FALLBACK_TEXTURE_NAME);
+        assert_eq!(
+            backend.texture_size(texture.get_texture_id()),
+            Some((FALLBACK_TEXTURE_SIZE as i32, FALLBACK_TEXTURE_SIZE as i32))
+        );
+    }
+}
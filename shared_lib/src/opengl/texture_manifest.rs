@@ -0,0 +1,178 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::opengl::texture_manager::{TextureDescriptor, TextureError};
+
+//////////////////////////////////////////////////////////////////////////////
+// - TextureManifest -
+//////////////////////////////////////////////////////////////////////////////
+
+/// A list of named textures which can be registered with a `TextureManager`
+/// in one go.
+///
+/// The manifest is stored as JSON. Every entry carries the name under which
+/// the texture is registered plus all options of a `TextureDescriptor`:
+///
+/// ```json
+/// {
+///   "textures": [
+///     { "name": "CRATE8", "path": "crate8.jpg" },
+///     { "name": "AWESOMEFACE2", "path": "awesomeface2.png",
+///       "has_alpha": true, "flip_vertically": true }
+///   ]
+/// }
+/// ```
+///
+/// Relative texture paths are resolved against the directory containing the
+/// manifest file when the manifest is loaded with `from_file`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextureManifest {
+    #[serde(default)]
+    pub textures: Vec<TextureManifestEntry>,
+}
+
+impl TextureManifest {
+    /// Reads and parses a manifest file.
+    ///
+    /// Relative paths of the entries are rewritten so that they point to the
+    /// texture files as seen from the manifest's own directory.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, TextureManifestError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(|e| TextureManifestError::Read {
+            path: path.to_string_lossy().to_string(),
+            message: e.to_string(),
+        })?;
+
+        let mut manifest = Self::from_json(&content)?;
+        if let Some(base_dir) = path.parent() {
+            manifest.resolve_paths(base_dir);
+        }
+        Ok(manifest)
+    }
+
+    /// Parses a manifest from a JSON string. Paths are taken as they are.
+    pub fn from_json(json: &str) -> Result<Self, TextureManifestError> {
+        serde_json::from_str(json).map_err(|e| TextureManifestError::Parse {
+            message: e.to_string(),
+        })
+    }
+
+    /// Prefixes every relative texture path with `base_dir`.
+    pub fn resolve_paths<P: AsRef<Path>>(&mut self, base_dir: P) {
+        for entry in &mut self.textures {
+            let texture_path = Path::new(&entry.descriptor.path);
+            if texture_path.is_relative() {
+                let resolved: PathBuf = base_dir.as_ref().join(texture_path);
+                entry.descriptor.path = resolved.to_string_lossy().to_string();
+            }
+        }
+    }
+
+    /// Returns the names of all entries in manifest order.
+    pub fn names(&self) -> Vec<&str> {
+        self.textures.iter().map(|entry| entry.name.as_str()).collect()
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - TextureManifestEntry -
+//////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextureManifestEntry {
+    pub name: String,
+    #[serde(flatten)]
+    pub descriptor: TextureDescriptor,
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - TextureManifestError -
+//////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, Error)]
+pub enum TextureManifestError {
+    #[error("Failed to read texture manifest {path}: {message}")]
+    Read {
+        path: String,
+        message: String,
+    },
+    #[error("Failed to parse texture manifest: {message}")]
+    Parse {
+        message: String,
+    },
+    #[error("{}", format_entry_errors(.0))]
+    Entries(Vec<(String, TextureError)>),
+}
+
+impl TextureManifestError {
+    /// Returns the failed entries as `(texture name, error)` pairs. The list is
+    /// empty when the manifest itself could not be read or parsed.
+    pub fn entry_errors(&self) -> &[(String, TextureError)] {
+        match self {
+            TextureManifestError::Entries(errors) => errors,
+            _ => &[],
+        }
+    }
+}
+
+fn format_entry_errors(errors: &[(String, TextureError)]) -> String {
+    let details: Vec<String> =
+        errors.iter().map(|(name, error)| format!("{}: {}", name, error)).collect();
+    format!("{} texture(s) failed to register ({})", errors.len(), details.join("; "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifest_with_defaults() {
+        let json = r#"{
+            "textures": [
+                { "name": "CRATE", "path": "crate.jpg" },
+                { "name": "FACE", "path": "face.png", "has_alpha": true,
+                  "flip_vertically": true, "flip_horizontally": true,
+                  "uniform_name": "texture2" }
+            ]
+        }"#;
+
+        let manifest = TextureManifest::from_json(json).unwrap();
+        assert_eq!(manifest.names(), vec!["CRATE", "FACE"]);
+
+        let crate_descriptor = &manifest.textures[0].descriptor;
+        assert_eq!(crate_descriptor.path, "crate.jpg");
+        assert!(!crate_descriptor.has_alpha);
+        assert!(!crate_descriptor.flip_vertically);
+        assert!(!crate_descriptor.flip_horizontally);
+        assert_eq!(crate_descriptor.uniform_name, None);
+
+        let face_descriptor = &manifest.textures[1].descriptor;
+        assert!(face_descriptor.has_alpha);
+        assert!(face_descriptor.flip_vertically);
+        assert!(face_descriptor.flip_horizontally);
+        assert_eq!(face_descriptor.uniform_name.as_deref(), Some("texture2"));
+    }
+
+    #[test]
+    fn test_resolve_relative_paths() {
+        let json = r#"{ "textures": [
+            { "name": "A", "path": "a.png" },
+            { "name": "B", "path": "/absolute/b.png" }
+        ] }"#;
+
+        let mut manifest = TextureManifest::from_json(json).unwrap();
+        manifest.resolve_paths("assets/textures");
+        let expected = Path::new("assets/textures").join("a.png");
+        assert_eq!(manifest.textures[0].descriptor.path, expected.to_string_lossy());
+        assert_eq!(manifest.textures[1].descriptor.path, "/absolute/b.png");
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let result = TextureManifest::from_json("{ \"textures\": [ { \"name\": 1 } ] }");
+        assert!(matches!(result, Err(TextureManifestError::Parse { .. })));
+    }
+}
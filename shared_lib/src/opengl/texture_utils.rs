@@ -15,3 +15,51 @@ pub(crate) fn get_texture_from_gpu(texture_id: GLuint, width: i32, height: i32)
     }
     DynamicImage::ImageRgba8(ImageBuffer::from_raw(width as u32, height as u32, data).unwrap())
 }
+
+/// Generates the RGBA8 pixels of a square checkerboard image.
+///
+/// # Arguments
+/// * `size` - The width and height of the image in pixels.
+/// * `cell_size` - The width and height of a single checkerboard cell in pixels.
+/// * `color_a` - The color of the cell in the top-left corner.
+/// * `color_b` - The color of the alternating cells.
+pub(crate) fn create_checkerboard_pixels(
+    size: u32,
+    cell_size: u32,
+    color_a: [u8; 4],
+    color_b: [u8; 4],
+) -> Vec<u8> {
+    let cell_size = cell_size.max(1);
+    let mut pixels = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let is_even_cell = (x / cell_size + y / cell_size).is_multiple_of(2);
+            pixels.extend_from_slice(if is_even_cell {
+                &color_a
+            } else {
+                &color_b
+            });
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkerboard_pixels() {
+        let black = [0, 0, 0, 255];
+        let magenta = [255, 0, 255, 255];
+        let pixels = create_checkerboard_pixels(4, 2, magenta, black);
+        assert_eq!(pixels.len(), 4 * 4 * 4);
+
+        let pixel_at = |x: usize, y: usize| &pixels[(y * 4 + x) * 4..(y * 4 + x) * 4 + 4];
+        assert_eq!(pixel_at(0, 0), magenta);
+        assert_eq!(pixel_at(1, 1), magenta);
+        assert_eq!(pixel_at(2, 0), black);
+        assert_eq!(pixel_at(0, 2), black);
+        assert_eq!(pixel_at(3, 3), magenta);
+    }
+}
@@ -0,0 +1,508 @@
+use crate::gl_traits::{Bindable, Deletable};
+use crate::gl_types::PrimitiveType;
+use crate::gl_utils::{cached_capability, check_gl_error, gl_has_extension, gl_version_at_least};
+use crate::opengl::buffer_object::BufferObject;
+use crate::opengl::gl_backend::with_backend;
+use crate::opengl::query::{Query, QueryTarget};
+use crate::opengl::resource_registry::{self, ResourceKind};
+use anyhow::{anyhow, Context, Result};
+use gl::types::{GLenum, GLint, GLintptr, GLsizeiptr, GLuint};
+use std::cell::Cell;
+use std::mem::size_of;
+
+//////////////////////////////////////////////////////////////////////////////
+// - TransformFeedbackMode -
+//////////////////////////////////////////////////////////////////////////////
+
+/// How the captured varyings are written into the transform feedback buffers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransformFeedbackMode {
+    /// All varyings are written one after another into the buffer at index 0.
+    #[default]
+    Interleaved,
+    /// Every varying is written into its own buffer, at the index of the varying.
+    Separate,
+}
+
+impl TransformFeedbackMode {
+    pub fn to_gl_enum(&self) -> GLenum {
+        match self {
+            TransformFeedbackMode::Interleaved => gl::INTERLEAVED_ATTRIBS,
+            TransformFeedbackMode::Separate => gl::SEPARATE_ATTRIBS,
+        }
+    }
+}
+
+/// The varyings a `ShaderProgram` captures, see
+/// `ShaderProgram::set_transform_feedback_varyings`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransformFeedbackVaryings {
+    pub names: Vec<String>,
+    pub mode: TransformFeedbackMode,
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - TransformFeedbackPrimitive -
+//////////////////////////////////////////////////////////////////////////////
+
+/// The kind of primitives captured between `TransformFeedback::begin` and `end`.
+/// The primitives drawn meanwhile have to match, e.g. `Triangles` captures
+/// triangles, triangle strips and triangle fans.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransformFeedbackPrimitive {
+    Points,
+    Lines,
+    Triangles,
+}
+
+impl TransformFeedbackPrimitive {
+    pub fn to_gl_enum(&self) -> GLenum {
+        match self {
+            TransformFeedbackPrimitive::Points => gl::POINTS,
+            TransformFeedbackPrimitive::Lines => gl::LINES,
+            TransformFeedbackPrimitive::Triangles => gl::TRIANGLES,
+        }
+    }
+
+    /// Returns the number of vertices written per captured primitive.
+    pub fn vertex_count(&self) -> usize {
+        match self {
+            TransformFeedbackPrimitive::Points => 1,
+            TransformFeedbackPrimitive::Lines => 2,
+            TransformFeedbackPrimitive::Triangles => 3,
+        }
+    }
+
+    /// Returns the capture mode for drawn primitives of type `primitive_type`, or
+    /// `None` for adjacency primitives and patches, which are captured as the
+    /// output of a geometry or tessellation shader instead.
+    pub fn from_primitive_type(primitive_type: PrimitiveType) -> Option<Self> {
+        match primitive_type {
+            PrimitiveType::Points => Some(TransformFeedbackPrimitive::Points),
+            PrimitiveType::Lines | PrimitiveType::LineStrip | PrimitiveType::LineLoop => {
+                Some(TransformFeedbackPrimitive::Lines)
+            }
+            PrimitiveType::Triangles
+            | PrimitiveType::TriangleStrip
+            | PrimitiveType::TriangleFan => Some(TransformFeedbackPrimitive::Triangles),
+            PrimitiveType::LineStripAdjacency
+            | PrimitiveType::LinesAdjacency
+            | PrimitiveType::TriangleStripAdjacency
+            | PrimitiveType::TrianglesAdjacency
+            | PrimitiveType::Patches => None,
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - TransformFeedback -
+//////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CaptureState {
+    Inactive,
+    Active,
+    Paused,
+}
+
+/// Checks if the current context supports transform feedback objects, including
+/// pausing, resuming and `glDrawTransformFeedback` (OpenGL 4.0 or
+/// `GL_ARB_transform_feedback2`). Capturing itself is part of OpenGL 3.0.
+pub fn supports_transform_feedback_objects() -> bool {
+    cached_capability("transform_feedback_objects", || {
+        gl_version_at_least(4, 0) || gl_has_extension("GL_ARB_transform_feedback2")
+    })
+}
+
+/// A transform feedback object, which captures the output of the vertex (or
+/// geometry) shader into buffers. It needs OpenGL 4.0 or
+/// `GL_ARB_transform_feedback2`, see `supports_transform_feedback_objects`.
+///
+/// The captured varyings have to be declared on the `ShaderProgram` before it is
+/// compiled (see `ShaderProgram::set_transform_feedback_varyings`). Every capture
+/// counts the primitives which were generated and written; if fewer were written
+/// the buffers were too small.
+///
+/// # Example
+/// ```ignore
+/// // Particle update: read the state from one buffer, write it into the other
+/// let mut program = ShaderProgram::new();
+/// program.add_source(ShaderType::Vertex, PARTICLE_UPDATE_SHADER)?;
+/// program.set_transform_feedback_varyings(
+///     &["out_position", "out_velocity"],
+///     TransformFeedbackMode::Interleaved,
+/// )?;
+/// program.compile()?;
+///
+/// let feedback = TransformFeedback::new()?;
+/// feedback.bind_buffer(0, &particles[next])?;
+/// program.activate();
+/// set_rasterizer_discard(true);
+/// feedback.begin(TransformFeedbackPrimitive::Points)?;
+/// particle_vaos[current].draw(DrawCommand::arrays(PrimitiveType::Points, particle_count))?;
+/// feedback.end()?;
+/// set_rasterizer_discard(false);
+///
+/// // Later, without stalling
+/// if let Some(written) = feedback.try_primitives_written()? { .. }
+/// ```
+#[derive(Debug)]
+pub struct TransformFeedback {
+    id: GLuint,
+    state: Cell<CaptureState>,
+    primitives_generated: Query,
+    primitives_written: Query,
+}
+
+impl TransformFeedback {
+    /// Creates a transform feedback object.
+    ///
+    /// # Errors
+    /// Returns an error if the context doesn't support transform feedback objects.
+    pub fn new() -> Result<Self> {
+        if !supports_transform_feedback_objects() {
+            return Err(anyhow!(
+                "Transform feedback objects need OpenGL 4.0 or GL_ARB_transform_feedback2"
+            ));
+        }
+        let id = with_backend(|backend| backend.gen_transform_feedback());
+        check_gl_error().context("Failed to create a transform feedback object")?;
+        resource_registry::register(ResourceKind::TransformFeedback, id, "TransformFeedback", 0);
+        Ok(Self {
+            id,
+            state: Cell::new(CaptureState::Inactive),
+            primitives_generated: Query::new(QueryTarget::PrimitivesGenerated),
+            primitives_written: Query::new(QueryTarget::TransformFeedbackPrimitivesWritten),
+        })
+    }
+
+    pub fn transform_feedback_id(&self) -> GLuint {
+        self.id
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.state.get() != CaptureState::Inactive
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.get() == CaptureState::Paused
+    }
+
+    /// Binds the whole GPU storage of `buffer` to the binding `index`. With
+    /// `TransformFeedbackMode::Separate` the index is the index of the varying.
+    pub fn bind_buffer<T>(&self, index: u32, buffer: &BufferObject<T>) -> Result<()> {
+        self.bind()?;
+        with_backend(|backend| {
+            backend.bind_buffer_base(gl::TRANSFORM_FEEDBACK_BUFFER, index, buffer.buffer_id())
+        });
+        check_gl_error().with_context(|| {
+            format!(
+                "Failed to bind buffer {} to transform feedback index {}",
+                buffer.buffer_id(),
+                index
+            )
+        })
+    }
+
+    /// Binds `count` elements of `buffer`, starting at the element `offset`, to the
+    /// binding `index`.
+    pub fn bind_buffer_range<T>(
+        &self,
+        index: u32,
+        buffer: &BufferObject<T>,
+        offset: usize,
+        count: usize,
+    ) -> Result<()> {
+        self.bind()?;
+        with_backend(|backend| {
+            backend.bind_buffer_range(
+                gl::TRANSFORM_FEEDBACK_BUFFER,
+                index,
+                buffer.buffer_id(),
+                (offset * size_of::<T>()) as GLintptr,
+                (count * size_of::<T>()) as GLsizeiptr,
+            )
+        });
+        check_gl_error().with_context(|| {
+            format!(
+                "Failed to bind buffer {} to transform feedback index {}",
+                buffer.buffer_id(),
+                index
+            )
+        })
+    }
+
+    /// Starts capturing primitives of the given kind and counting them. The program
+    /// with the captured varyings has to be active.
+    ///
+    /// # Errors
+    /// Returns an error if a capture is already running, or if OpenGL reports one,
+    /// e.g. because no buffer is bound for a varying.
+    pub fn begin(&self, primitive: TransformFeedbackPrimitive) -> Result<()> {
+        if self.is_active() {
+            return Err(anyhow!("Transform feedback {} is already active", self.id));
+        }
+        self.bind()?;
+        self.primitives_generated.begin()?;
+        if let Err(err) = self.primitives_written.begin() {
+            self.primitives_generated.end()?;
+            return Err(err);
+        }
+        with_backend(|backend| backend.begin_transform_feedback(primitive.to_gl_enum()));
+        if let Err(err) = check_gl_error() {
+            // Don't leave the queries running, the capture didn't start
+            self.primitives_written.end()?;
+            self.primitives_generated.end()?;
+            return Err(err.context(format!("Failed to begin transform feedback {}", self.id)));
+        }
+        self.state.set(CaptureState::Active);
+        Ok(())
+    }
+
+    /// Suspends the capture, so that draw calls in between aren't captured. They
+    /// still count as generated primitives.
+    pub fn pause(&self) -> Result<()> {
+        if self.state.get() != CaptureState::Active {
+            return Err(anyhow!("Transform feedback {} isn't capturing", self.id));
+        }
+        with_backend(|backend| backend.pause_transform_feedback());
+        check_gl_error()
+            .with_context(|| format!("Failed to pause transform feedback {}", self.id))?;
+        self.state.set(CaptureState::Paused);
+        Ok(())
+    }
+
+    /// Continues a capture suspended with `pause`.
+    pub fn resume(&self) -> Result<()> {
+        if self.state.get() != CaptureState::Paused {
+            return Err(anyhow!("Transform feedback {} isn't paused", self.id));
+        }
+        self.bind()?;
+        with_backend(|backend| backend.resume_transform_feedback());
+        check_gl_error()
+            .with_context(|| format!("Failed to resume transform feedback {}", self.id))?;
+        self.state.set(CaptureState::Active);
+        Ok(())
+    }
+
+    /// Finishes the capture. The primitive counts become available later, see
+    /// `try_primitives_written`.
+    pub fn end(&self) -> Result<()> {
+        if !self.is_active() {
+            return Err(anyhow!("Transform feedback {} isn't active", self.id));
+        }
+        if self.is_paused() {
+            self.bind()?;
+        }
+        with_backend(|backend| backend.end_transform_feedback());
+        check_gl_error()
+            .with_context(|| format!("Failed to end transform feedback {}", self.id))?;
+        self.state.set(CaptureState::Inactive);
+        self.primitives_written.end()?;
+        self.primitives_generated.end()
+    }
+
+    /// Returns the number of primitives written by the last capture, if the GPU has
+    /// finished it.
+    pub fn try_primitives_written(&self) -> Result<Option<u64>> {
+        self.primitives_written.try_result()
+    }
+
+    /// Waits for the last capture and returns the number of primitives written.
+    pub fn primitives_written(&self) -> Result<u64> {
+        self.primitives_written.result()
+    }
+
+    /// Returns the number of primitives generated during the last capture, if the GPU
+    /// has finished it. More primitives than written means the buffers overflowed.
+    pub fn try_primitives_generated(&self) -> Result<Option<u64>> {
+        self.primitives_generated.try_result()
+    }
+
+    /// Waits for the last capture and returns the number of primitives generated.
+    pub fn primitives_generated(&self) -> Result<u64> {
+        self.primitives_generated.result()
+    }
+
+    /// Draws the vertices written by the last capture, without reading back their
+    /// count. The vertex array object reading the captured buffer has to be bound.
+    pub fn draw(&self, primitive_type: PrimitiveType) -> Result<()> {
+        with_backend(|backend| {
+            backend.draw_transform_feedback(primitive_type.to_gl_enum(), self.id)
+        });
+        check_gl_error().with_context(|| format!("Failed to draw transform feedback {}", self.id))
+    }
+}
+
+/// Enables or disables rasterization. Disabling it skips the fragment stage, which
+/// is what a capture that only updates buffers (e.g. particle state) wants.
+pub fn set_rasterizer_discard(discard: bool) {
+    with_backend(|backend| {
+        if discard {
+            backend.enable(gl::RASTERIZER_DISCARD);
+        } else {
+            backend.disable(gl::RASTERIZER_DISCARD);
+        }
+    });
+}
+
+impl Bindable for TransformFeedback {
+    fn bind(&self) -> Result<()> {
+        with_backend(|backend| backend.bind_transform_feedback(gl::TRANSFORM_FEEDBACK, self.id));
+        check_gl_error()
+    }
+
+    fn unbind(&self) -> Result<()> {
+        with_backend(|backend| backend.bind_transform_feedback(gl::TRANSFORM_FEEDBACK, 0));
+        check_gl_error()
+    }
+
+    fn is_bound(&self) -> Result<bool> {
+        let current_id =
+            with_backend(|backend| backend.get_integer(gl::TRANSFORM_FEEDBACK_BINDING));
+        check_gl_error()?;
+        Ok(current_id == self.id as GLint)
+    }
+}
+
+impl Deletable for TransformFeedback {
+    fn delete(&mut self) -> Result<()> {
+        if self.id != 0 {
+            if self.is_active() {
+                self.end()?;
+            }
+            resource_registry::unregister(ResourceKind::TransformFeedback, self.id)?;
+            with_backend(|backend| backend.delete_transform_feedback(self.id));
+            self.id = 0;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TransformFeedback {
+    fn drop(&mut self) {
+        if let Err(err) = self.delete() {
+            eprintln!("Error while dropping TransformFeedback: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opengl::gl_backend::{install_backend, Capture, GlCall, RecordingGlBackend};
+
+    #[test]
+    fn test_from_primitive_type() {
+        let capture = TransformFeedbackPrimitive::from_primitive_type;
+        assert_eq!(capture(PrimitiveType::Points), Some(TransformFeedbackPrimitive::Points));
+        assert_eq!(capture(PrimitiveType::LineLoop), Some(TransformFeedbackPrimitive::Lines));
+        assert_eq!(
+            capture(PrimitiveType::TriangleFan),
+            Some(TransformFeedbackPrimitive::Triangles)
+        );
+        assert_eq!(capture(PrimitiveType::TrianglesAdjacency), None);
+        assert_eq!(capture(PrimitiveType::Patches), None);
+        assert_eq!(TransformFeedbackPrimitive::Triangles.vertex_count(), 3);
+    }
+
+    #[test]
+    fn test_capture_state_machine() {
+        let backend = RecordingGlBackend::new();
+        let _backend_guard = install_backend(backend.clone());
+        let feedback = TransformFeedback::new().unwrap();
+        let capture = || backend.transform_feedback_capture(feedback.transform_feedback_id());
+        assert_eq!(capture(), Some(Capture::Inactive));
+
+        assert!(feedback.pause().is_err());
+        assert!(feedback.resume().is_err());
+        assert!(feedback.end().is_err());
+
+        feedback.begin(TransformFeedbackPrimitive::Points).unwrap();
+        assert!(feedback.is_active() && !feedback.is_paused());
+        assert_eq!(capture(), Some(Capture::Active));
+        assert!(feedback.is_bound().unwrap());
+        assert_eq!(
+            backend.active_query(gl::TRANSFORM_FEEDBACK_PRIMITIVES_WRITTEN),
+            feedback.primitives_written.query_id()
+        );
+        assert!(feedback.begin(TransformFeedbackPrimitive::Points).is_err());
+        assert!(feedback.resume().is_err());
+
+        feedback.pause().unwrap();
+        assert!(feedback.is_active() && feedback.is_paused());
+        assert_eq!(capture(), Some(Capture::Paused));
+        assert!(feedback.pause().is_err());
+
+        feedback.resume().unwrap();
+        assert_eq!(capture(), Some(Capture::Active));
+
+        // Ending while paused is allowed as well
+        feedback.pause().unwrap();
+        feedback.end().unwrap();
+        assert!(!feedback.is_active() && !feedback.is_paused());
+        assert_eq!(capture(), Some(Capture::Inactive));
+        assert_eq!(backend.active_query(gl::PRIMITIVES_GENERATED), 0);
+        assert_eq!(backend.active_query(gl::TRANSFORM_FEEDBACK_PRIMITIVES_WRITTEN), 0);
+
+        backend.set_query_result(feedback.primitives_generated.query_id(), Some(12));
+        backend.set_query_result(feedback.primitives_written.query_id(), None);
+        assert_eq!(feedback.try_primitives_written().unwrap(), None);
+        backend.set_query_result(feedback.primitives_written.query_id(), Some(10));
+        assert_eq!(feedback.try_primitives_written().unwrap(), Some(10));
+        assert_eq!(feedback.primitives_generated().unwrap(), 12);
+        assert!(check_gl_error().is_ok());
+    }
+
+    #[test]
+    fn test_failed_begin_ends_the_queries() {
+        let backend = RecordingGlBackend::new();
+        let _backend_guard = install_backend(backend.clone());
+        let feedback = TransformFeedback::new().unwrap();
+
+        backend.fail_next(
+            |call| matches!(call, GlCall::BeginTransformFeedback(_)),
+            gl::INVALID_OPERATION,
+        );
+        assert!(feedback.begin(TransformFeedbackPrimitive::Triangles).is_err());
+        assert!(!feedback.is_active());
+        assert_eq!(backend.active_query(gl::PRIMITIVES_GENERATED), 0);
+        assert_eq!(backend.active_query(gl::TRANSFORM_FEEDBACK_PRIMITIVES_WRITTEN), 0);
+
+        // The next capture starts normally
+        feedback.begin(TransformFeedbackPrimitive::Triangles).unwrap();
+        feedback.end().unwrap();
+
+        backend.fail_next(
+            |call| {
+                matches!(call, GlCall::BeginQuery { target, .. }
+                    if *target == gl::TRANSFORM_FEEDBACK_PRIMITIVES_WRITTEN)
+            },
+            gl::INVALID_OPERATION,
+        );
+        assert!(feedback.begin(TransformFeedbackPrimitive::Triangles).is_err());
+        assert!(!feedback.is_active());
+        assert_eq!(backend.active_query(gl::PRIMITIVES_GENERATED), 0);
+        assert_eq!(
+            backend.transform_feedback_capture(feedback.transform_feedback_id()),
+            Some(Capture::Inactive)
+        );
+        assert!(check_gl_error().is_ok());
+    }
+
+    #[test]
+    fn test_delete_ends_an_active_capture() {
+        let backend = RecordingGlBackend::new();
+        let _backend_guard = install_backend(backend.clone());
+        let feedback = TransformFeedback::new().unwrap();
+        let id = feedback.transform_feedback_id();
+        feedback.begin(TransformFeedbackPrimitive::Lines).unwrap();
+        feedback.pause().unwrap();
+
+        drop(feedback);
+        assert!(backend.calls().contains(&GlCall::EndTransformFeedback));
+        assert_eq!(backend.transform_feedback_capture(id), None);
+        assert_eq!(backend.live_objects(), 0);
+        assert!(check_gl_error().is_ok());
+    }
+}
@@ -1,14 +1,24 @@
+use crate::gl_draw::{multi_draw_indirect, DrawCommand, IndirectCommand};
 use crate::gl_prelude::VertexAttributeType;
 use crate::gl_traits::{Bindable, Deletable};
-use crate::gl_types::convert_attributes;
-use crate::opengl::vertex_attribute::VertexAttribute;
+use crate::gl_types::{
+    convert_attributes, BufferType, IndexValue, IndicesValueType, PrimitiveType,
+};
+use crate::opengl::buffer_object::BufferObject;
+use crate::opengl::gl_backend::with_backend;
+use crate::opengl::indirect_buffer::IndirectBuffer;
+use crate::opengl::resource_registry::{self, ResourceKind};
+use crate::opengl::shader_program::{ActiveAttribute, ShaderProgram};
+use crate::opengl::vertex_attribute::{AttributePointer, VertexAttribute};
+use crate::opengl::vertex_layout::VertexLayoutError;
+use crate::serialization::vertex_attribute::VertexLayout as SerializedVertexLayout;
 use crate::{RenderDataState, RenderPrepare};
-use anyhow::Result;
-use gl::types::{GLboolean, GLint, GLsizei, GLuint, GLvoid};
+use anyhow::{anyhow, Context, Result};
+use gl::types::{GLint, GLsizei, GLuint};
 use sdl2::filesystem::PrefPathError;
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
 use std::ffi::c_void;
+use std::path::Path;
 
 /// Represents a Vertex Array Object (VAO) in OpenGL, which stores the format
 /// of the vertex data as well as the buffers that provide the vertex data.
@@ -16,9 +26,18 @@ use std::ffi::c_void;
 /// # Fields
 /// * `id` - A unique identifier for the Vertex Array Object.
 /// * `layout` - An optional `RefCell` containing layout data associated with the VAO.
+/// * `element_buffer` - The attached element buffer and the type of its indices.
 pub struct VertexArrayObject {
     id: u32,
     layout: Option<RefCell<LayoutData>>,
+    element_buffer: Cell<Option<ElementBuffer>>,
+}
+
+/// An element buffer attached to a `VertexArrayObject`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ElementBuffer {
+    buffer_id: u32,
+    indices_type: IndicesValueType,
 }
 
 /// Implements the `Default` trait for the `VertexArrayObject` struct.
@@ -27,19 +46,19 @@ pub struct VertexArrayObject {
 /// initializes the `VertexArrayObject` with the generated VAO ID and no layout data.
 ///
 /// # Safety
-/// This function contains an unsafe block to call the OpenGL function `gl::GenVertexArrays`.
+/// This function calls the OpenGL function `gl::GenVertexArrays` through the current
+/// `GlBackend`.
 ///
 /// # Returns
 /// A new instance of `VertexArrayObject` with a generated VAO ID and `None` layout data.
 impl Default for VertexArrayObject {
     fn default() -> Self {
-        let mut id = 0;
-        unsafe {
-            gl::GenVertexArrays(1, &mut id);
-        }
+        let id = with_backend(|backend| backend.gen_vertex_array());
+        resource_registry::register(ResourceKind::VertexArray, id, "VertexArrayObject", 0);
         Self {
             id,
             layout: None,
+            element_buffer: Cell::new(None),
         }
     }
 }
@@ -109,7 +128,7 @@ impl VertexArrayObject {
     /// operations use this VAO.
     ///
     /// # Safety
-    /// This function contains an unsafe block to call the OpenGL function `gl::BindVertexArray`.
+    /// This function calls the OpenGL function `gl::BindVertexArray`.
     ///
     /// # Example
     /// ```ignore
@@ -117,9 +136,7 @@ impl VertexArrayObject {
     /// vao.bind();
     /// ```
     pub fn bind(&self) {
-        unsafe {
-            gl::BindVertexArray(self.id);
-        }
+        with_backend(|backend| backend.bind_vertex_array(self.id));
     }
 
     /// Unbinds the currently bound `VertexArrayObject` from the OpenGL context.
@@ -128,16 +145,14 @@ impl VertexArrayObject {
     /// that might be bound.
     ///
     /// # Safety
-    /// This function contains an unsafe block to call the OpenGL function `gl::BindVertexArray` with zero.
+    /// This function calls the OpenGL function `gl::BindVertexArray` with zero.
     ///
     /// # Example
     /// ```ignore
     /// VertexArrayObject::unbind();
     /// ```
     pub fn unbind() {
-        unsafe {
-            gl::BindVertexArray(0);
-        }
+        with_backend(|backend| backend.bind_vertex_array(0));
     }
 
     /// Checks if the `VertexArrayObject` is currently bound to the OpenGL context.
@@ -151,7 +166,7 @@ impl VertexArrayObject {
     /// This function does not currently produce any errors, so it always returns `Ok`.
     ///
     /// # Safety
-    /// This function contains an unsafe block to call the OpenGL function `gl::GetIntegerv`.
+    /// This function calls the OpenGL function `gl::GetIntegerv`.
     ///
     /// # Example
     /// ```ignore
@@ -160,10 +175,7 @@ impl VertexArrayObject {
     /// assert!(vao.is_bound().unwrap());
     /// ```
     fn is_bound(&self) -> Result<bool> {
-        let mut current_vao = 0;
-        unsafe {
-            gl::GetIntegerv(gl::VERTEX_ARRAY_BINDING, &mut current_vao);
-        }
+        let current_vao = with_backend(|backend| backend.get_integer(gl::VERTEX_ARRAY_BINDING));
         Ok(current_vao == self.id as GLint)
     }
 
@@ -227,14 +239,197 @@ impl VertexArrayObject {
             }
         };
 
+        let streams = match self.layout.take() {
+            Some(layout_data) => std::mem::take(&mut layout_data.borrow_mut().streams),
+            None => Vec::new(),
+        };
+
         let layout_data = LayoutData {
             layout: attributes.clone(),
+            streams,
             layout_data_state: new_state,
+            enabled_locations: 0,
         };
 
         self.layout = Some(RefCell::new(layout_data));
     }
 
+    /// Reads a vertex layout file and sets its attributes as layout, see
+    /// `serialization::vertex_attribute::VertexLayout` for the file format.
+    ///
+    /// # Errors
+    /// Returns an error if the file can't be read or parsed, or if an attribute is
+    /// invalid (`VertexLayoutError`). The current layout is kept in that case.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut vao = VertexArrayObject::default();
+    /// vao.load_layout_file("assets/meshes/terrain.layout.json")?;
+    /// ```
+    pub fn load_layout_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let attributes = SerializedVertexLayout::from_file(path)?
+            .to_attributes()
+            .with_context(|| format!("Invalid vertex layout {}", path.display()))?;
+        self.set_layout(attributes);
+        Ok(())
+    }
+
+    /// Resolves the locations of all named attributes from the active attributes of
+    /// `program`, instead of assigning them by their index in the layout.
+    ///
+    /// Attributes without a name keep their location or follow the previous attribute.
+    /// The locations are applied with the next upload of the layout, so this should be
+    /// called before the first draw.
+    ///
+    /// # Errors
+    /// - `VertexLayoutError::InvalidShaderId` if the program isn't compiled.
+    /// - `VertexLayoutError::InvalidAttributeName` if the program has no active
+    ///   attribute with the name of a layout attribute.
+    /// - `VertexLayoutError::ComponentMismatch` if the number of components differs
+    ///   from the GLSL type, e.g. 3 components for a `vec4`.
+    /// - `VertexLayoutError::TypeMismatch` if the data type can't be read by the GLSL
+    ///   type, e.g. float data for an `ivec4`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut vao = VertexArrayObject::new_with_attributes(MeshVertex::attributes());
+    /// vao.bind_attribute_locations(shader_manager.get_shader(shaders::MESH)?)?;
+    /// ```
+    pub fn bind_attribute_locations(
+        &mut self,
+        program: &ShaderProgram,
+    ) -> Result<(), VertexLayoutError> {
+        if program.program_id() == 0 {
+            return Err(VertexLayoutError::InvalidShaderId);
+        }
+        let Some(layout_data) = &self.layout else {
+            return Ok(());
+        };
+
+        let active_attributes = program
+            .get_active_attributes()
+            .map_err(|e| VertexLayoutError::OpenGL(e.to_string()))?;
+
+        let mut layout_data = layout_data.borrow_mut();
+        let LayoutData {
+            layout,
+            streams,
+            ..
+        } = &mut *layout_data;
+        let attributes = layout
+            .iter_mut()
+            .chain(streams.iter_mut().flat_map(|stream| stream.attributes.iter_mut()));
+        for attribute in attributes {
+            let Some(name) = &attribute.name else {
+                continue;
+            };
+            let active_attribute = active_attributes
+                .iter()
+                .find(|active_attribute| &active_attribute.name == name)
+                .ok_or_else(|| VertexLayoutError::InvalidAttributeName(name.clone()))?;
+            check_attribute(attribute, active_attribute)?;
+            attribute.location = Some(active_attribute.location);
+        }
+
+        if layout_data.layout_data_state == RenderDataState::Uploaded {
+            layout_data.layout_data_state = RenderDataState::NeedsUpdate;
+        }
+        Ok(())
+    }
+
+    /// Adds attributes which are read from their own buffer, e.g. to keep positions
+    /// apart from the remaining vertex data, or to pass bone indices as integers.
+    ///
+    /// The attributes of the stream are placed behind the per-vertex attributes of
+    /// the layout and behind previously added streams, unless they have an explicit
+    /// location. The stride and offsets of the attributes are calculated for the
+    /// buffer on its own. Integer attributes which aren't normalized are passed to the
+    /// shader as integers, double attributes as doubles (see
+    /// `VertexAttribute::pointer`).
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut vao = VertexArrayObject::new_with_attributes([
+    ///     VertexAttribute::new(3, VertexDataType::Float).name("aPos".to_string()),
+    /// ]);
+    /// let skinning = BufferObject::new(BufferType::ArrayBuffer, BufferUsage::StaticDraw, bones);
+    /// vao.add_stream(
+    ///     &skinning,
+    ///     [
+    ///         // `in uvec4 aBoneIndices;`
+    ///         VertexAttribute::new(4, VertexDataType::UnsignedByte),
+    ///         // `in vec4 aBoneWeights;`
+    ///         VertexAttribute::new(4, VertexDataType::UnsignedByte).normalized(true),
+    ///     ],
+    /// );
+    /// ```
+    pub fn add_stream<B, T: AsRef<[VertexAttribute]>>(
+        &mut self,
+        buffer: &BufferObject<B>,
+        attributes: T,
+    ) {
+        let stream = StreamLayout {
+            buffer_id: buffer.buffer_id(),
+            attributes: attributes.as_ref().to_vec(),
+        };
+
+        match &self.layout {
+            Some(layout_data) => {
+                let mut layout_data = layout_data.borrow_mut();
+                layout_data.streams.push(stream);
+                if layout_data.layout_data_state == RenderDataState::Uploaded {
+                    layout_data.layout_data_state = RenderDataState::NeedsUpdate;
+                }
+            }
+            None => {
+                let layout_data = LayoutData {
+                    layout: Vec::new(),
+                    streams: vec![stream],
+                    layout_data_state: RenderDataState::Provided,
+                    enabled_locations: 0,
+                };
+                self.layout = Some(RefCell::new(layout_data));
+            }
+        }
+    }
+
+    /// Adds per-instance attributes, which are read from the given buffer.
+    ///
+    /// This is a stream (see `add_stream`) whose attributes advance once per instance
+    /// if they don't have a divisor; matrix attributes are split across consecutive
+    /// locations (see `VertexAttribute::matrix`).
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut vao = VertexArrayObject::new_with_attributes(TexturedVertex::attributes());
+    /// let instance_buffer = BufferObject::new(
+    ///     BufferType::ArrayBuffer,
+    ///     BufferUsage::DynamicDraw,
+    ///     model_matrices,
+    /// );
+    /// vao.add_instance_layout(
+    ///     &instance_buffer,
+    ///     [VertexAttribute::matrix(4, 4, VertexDataType::Float)],
+    /// );
+    /// vao.render_instanced(vertex_count, model_matrices.len())?;
+    /// ```
+    pub fn add_instance_layout<B, T: AsRef<[VertexAttribute]>>(
+        &mut self,
+        buffer: &BufferObject<B>,
+        attributes: T,
+    ) {
+        let attributes: Vec<_> = attributes
+            .as_ref()
+            .iter()
+            .map(|attribute| {
+                let divisor = attribute.divisor.max(1);
+                attribute.clone().divisor(divisor)
+            })
+            .collect();
+        self.add_stream(buffer, attributes);
+    }
+
     /// Clears the layout data of the `VertexArrayObject`.
     ///
     /// This function binds the VAO, sets its layout data to `None`, and then unbinds the VAO.
@@ -269,28 +464,149 @@ impl VertexArrayObject {
         self.layout.is_some()
     }
 
-    /// Renders the object using the specified number of triangles or EBO entries.
+    /// Attaches an element buffer to the vertex array object.
     ///
-    /// If `use_ebo` is true, the method uses the EBO to render the specified number of elements.
-    /// Otherwise, it uses the VBO to render the specified number of vertices.
+    /// The vertex array object remembers the buffer together with the type of its
+    /// indices, which is used by every indexed draw. Attaching another buffer
+    /// replaces the previous one.
     ///
-    /// # Parameters
-    /// - `use_ebo`: A boolean indicating whether to use the EBO for rendering.
-    /// - `count`: The number of triangles or EBO entries to render.
-    pub fn render(&self, use_ebo: bool, count: usize) {
-        self.prepare_render();
+    /// # Errors
+    /// Returns an error if the buffer isn't an `ElementArrayBuffer`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let indices: Vec<u16> = vec![0, 1, 2, 2, 3, 0];
+    /// let ebo = BufferObject::new(BufferType::ElementArrayBuffer, BufferUsage::StaticDraw, indices);
+    /// vao.attach_element_buffer(&ebo)?;
+    /// vao.draw(DrawCommand::elements(PrimitiveType::Triangles, ebo.data_len()))?;
+    /// ```
+    pub fn attach_element_buffer<T: IndexValue>(&self, buffer: &BufferObject<T>) -> Result<()> {
+        if buffer.buffer_type() != BufferType::ElementArrayBuffer {
+            return Err(anyhow!(
+                "Buffer {} of type {:?} can't be attached as element buffer",
+                buffer.buffer_id(),
+                buffer.buffer_type()
+            ));
+        }
+
         self.bind();
-        let count = count as GLsizei;
-        if use_ebo {
-            unsafe {
-                gl::DrawElements(gl::TRIANGLES, count, gl::UNSIGNED_INT, std::ptr::null());
+        with_backend(|backend| backend.bind_buffer(gl::ELEMENT_ARRAY_BUFFER, buffer.buffer_id()));
+        Self::unbind();
+        self.remember_element_buffer(buffer.buffer_id(), T::INDICES_VALUE_TYPE);
+        Ok(())
+    }
+
+    /// Records an element buffer which was bound while the vertex array object was bound.
+    pub(crate) fn remember_element_buffer(&self, buffer_id: u32, indices_type: IndicesValueType) {
+        self.element_buffer.set(Some(ElementBuffer {
+            buffer_id,
+            indices_type,
+        }));
+    }
+
+    /// Returns the identifier of the attached element buffer.
+    pub fn element_buffer_id(&self) -> Option<u32> {
+        self.element_buffer.get().map(|element_buffer| element_buffer.buffer_id)
+    }
+
+    /// Returns the type of the indices of the attached element buffer.
+    pub fn indices_type(&self) -> Option<IndicesValueType> {
+        self.element_buffer.get().map(|element_buffer| element_buffer.indices_type)
+    }
+
+    /// Draws the object as described by `command`.
+    ///
+    /// Indexed commands always use the indices type of the attached element buffer,
+    /// regardless of the type set in the command.
+    ///
+    /// # Errors
+    /// Returns an error if the command is indexed but no element buffer is attached.
+    pub fn draw(&self, command: DrawCommand) -> Result<()> {
+        let command = match (command.is_indexed(), self.indices_type()) {
+            (false, _) => command,
+            (true, Some(indices_type)) => command.indices_type(indices_type),
+            (true, None) => {
+                return Err(anyhow!(
+                    "Vertex array {} has no element buffer attached for an indexed draw",
+                    self.id
+                ))
             }
-        } else {
-            unsafe {
-                gl::DrawArrays(gl::TRIANGLES, 0, count);
+        };
+
+        self.prepare_render();
+        self.bind();
+        command.draw();
+        Self::unbind();
+        Ok(())
+    }
+
+    /// Draws all commands of `commands` with a single multi-draw indirect call, which
+    /// lets many meshes sharing the buffers of this vertex array object be submitted
+    /// at once. Indexed commands use the indices type of the attached element buffer.
+    ///
+    /// # Errors
+    /// Returns an error if the commands are indexed but no element buffer is attached,
+    /// or if the indirect buffer can't be bound.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let commands = IndirectBuffer::with_commands(
+    ///     BufferUsage::StaticDraw,
+    ///     vec![
+    ///         DrawElementsIndirectCommand::new(36),
+    ///         DrawElementsIndirectCommand::new(6).first_index(36).base_vertex(24),
+    ///     ],
+    /// );
+    /// vao.multi_draw_indirect(PrimitiveType::Triangles, &commands)?;
+    /// ```
+    pub fn multi_draw_indirect<C: IndirectCommand>(
+        &self,
+        primitive_type: PrimitiveType,
+        commands: &IndirectBuffer<C>,
+    ) -> Result<()> {
+        let indices_type = match (C::INDEXED, self.indices_type()) {
+            (false, _) => None,
+            (true, Some(indices_type)) => Some(indices_type),
+            (true, None) => {
+                return Err(anyhow!(
+                    "Vertex array {} has no element buffer attached for an indexed draw",
+                    self.id
+                ))
             }
-        }
+        };
+
+        self.prepare_render();
+        self.bind();
+        let result = multi_draw_indirect(primitive_type, indices_type, commands);
         Self::unbind();
+        result
+    }
+
+    /// Renders the object as triangles using the specified number of vertices or EBO entries.
+    ///
+    /// If `use_ebo` is true, the method uses the attached element buffer to render the specified
+    /// number of elements. Otherwise, it uses the VBO to render the specified number of vertices.
+    /// Use `draw` for other primitive types and ranges.
+    ///
+    /// # Parameters
+    /// - `use_ebo`: A boolean indicating whether to use the EBO for rendering.
+    /// - `count`: The number of vertices or EBO entries to render.
+    pub fn render(&self, use_ebo: bool, count: usize) -> Result<()> {
+        self.draw(triangles_command(use_ebo, count))
+    }
+
+    /// Renders `instances` instances of the object with `count` vertices or EBO entries
+    /// each.
+    ///
+    /// The element buffer is used if one is attached to the vertex array object.
+    /// Per-instance data is provided through `add_instance_layout`.
+    ///
+    /// # Parameters
+    /// - `count`: The number of vertices or EBO entries per instance.
+    /// - `instances`: The number of instances to render.
+    pub fn render_instanced(&self, count: usize, instances: usize) -> Result<()> {
+        let use_ebo = self.element_buffer.get().is_some();
+        self.draw(triangles_command(use_ebo, count).instances(instances))
     }
 
     /// Uploads the layout data of the `VertexArrayObject` to the GPU.
@@ -322,13 +638,52 @@ impl VertexArrayObject {
     }
 }
 
+/// Compares a layout attribute with the active shader attribute it is bound to.
+fn check_attribute(
+    attribute: &VertexAttribute,
+    active_attribute: &ActiveAttribute,
+) -> Result<(), VertexLayoutError> {
+    let name = &active_attribute.name;
+    let Some((_, columns, rows)) = active_attribute.shape() else {
+        return Ok(());
+    };
+
+    let expected = columns as usize * rows as usize;
+    let found = attribute.location_count() * attribute.components as usize;
+    if expected != found {
+        return Err(VertexLayoutError::ComponentMismatch {
+            name: name.clone(),
+            expected,
+            found,
+        });
+    }
+
+    if !active_attribute.accepts_data_type(attribute.data_type, attribute.normalized) {
+        return Err(VertexLayoutError::TypeMismatch {
+            name: name.clone(),
+            data_type: attribute.data_type,
+            shader_type: active_attribute.type_name(),
+        });
+    }
+    Ok(())
+}
+
+/// Creates a command which draws triangles, from the element buffer if `use_ebo` is set.
+fn triangles_command(use_ebo: bool, count: usize) -> DrawCommand {
+    if use_ebo {
+        DrawCommand::elements(PrimitiveType::Triangles, count)
+    } else {
+        DrawCommand::arrays(PrimitiveType::Triangles, count)
+    }
+}
+
 impl Deletable for VertexArrayObject {
     fn delete(&mut self) -> Result<()> {
         if self.id != 0 {
-            unsafe {
-                gl::DeleteVertexArrays(1, &self.id);
-            }
+            resource_registry::unregister(ResourceKind::VertexArray, self.id)?;
+            with_backend(|backend| backend.delete_vertex_array(self.id));
             self.id = 0;
+            self.element_buffer.set(None);
         }
         Ok(())
     }
@@ -370,7 +725,16 @@ impl RenderPrepare for VertexArrayObject {
 /// ```
 struct LayoutData {
     layout: Vec<VertexAttribute>,
+    streams: Vec<StreamLayout>,
     layout_data_state: RenderDataState,
+    /// Number of attribute locations enabled by the last upload.
+    enabled_locations: usize,
+}
+
+/// Attributes together with the buffer they are read from.
+struct StreamLayout {
+    buffer_id: u32,
+    attributes: Vec<VertexAttribute>,
 }
 
 /// Implements the `Drop` trait for the `LayoutData` struct.
@@ -380,7 +744,7 @@ struct LayoutData {
 /// is greater than `RenderDataState::Provided`.
 ///
 /// # Safety
-/// This function contains an unsafe block to call the OpenGL function `gl::DisableVertexAttribArray`.
+/// This function calls the OpenGL function `gl::DisableVertexAttribArray`.
 ///
 /// # Example
 /// ```ignore
@@ -395,11 +759,11 @@ struct LayoutData {
 impl Drop for LayoutData {
     fn drop(&mut self) {
         if self.layout_data_state > RenderDataState::Provided {
-            for (i, _attr) in self.layout.iter().enumerate() {
-                unsafe {
-                    gl::DisableVertexAttribArray(i as GLuint);
+            with_backend(|backend| {
+                for location in 0..self.enabled_locations {
+                    backend.disable_vertex_attrib_array(location as GLuint);
                 }
-            }
+            });
         }
     }
 }
@@ -407,80 +771,211 @@ impl Drop for LayoutData {
 impl LayoutData {
     /// Uploads the vertex attribute layout data to the GPU.
     ///
-    /// This function calculates the stride and offset for each vertex attribute if they are not set,
-    /// and then uploads the attribute data to the GPU using OpenGL functions.
-    ///
-    /// # Details
-    /// - If an attribute's stride is zero, the stride is calculated as the sum of the sizes of all components.
-    /// - If an attribute's offset is not set, it is calculated as the sum of the sizes of the components of the preceding attributes.
-    /// - The attributes are then uploaded to the GPU using `gl::VertexAttribPointer` and enabled using `gl::EnableVertexAttribArray`.
+    /// The per-vertex attributes are set up for the currently bound array buffer and
+    /// take the first locations. Each stream follows on the next free locations, with
+    /// its own buffer bound while its attribute pointers are set. See
+    /// `upload_attributes` for how stride and offsets are calculated.
     ///
     /// # Safety
-    /// This function contains unsafe blocks to call OpenGL functions (`gl::VertexAttribPointer` and `gl::EnableVertexAttribArray`).
-    ///
-    /// # Example
-    /// ```ignore
-    /// let mut layout_data = LayoutData {
-    ///     layout: vec![VertexAttribute::new(...), VertexAttribute::new(...)],
-    ///     layout_data_state: RenderDataState::Initialized,
-    /// };
-    /// layout_data.upload_to_gpu();
-    /// ```
-    ///
-    /// # Panics
-    /// This function may panic if the calculated stride or offset maps are not found in the respective hash maps.
+    /// This function calls OpenGL functions (`gl::VertexAttrib*Pointer` and `gl::EnableVertexAttribArray`) through the current `GlBackend`.
     fn upload_to_gpu(&mut self) {
-        // Calculate the stride if it is 0
-        let mut stride_map = HashMap::<usize, i32>::new();
-        for (i, attr) in self.layout.iter().enumerate() {
-            if attr.stride == 0 {
-                stride_map.insert(
-                    i,
-                    self.layout
-                        .iter()
-                        .map(|a| a.components as i32 * a.data_type.size() as i32)
-                        .sum(),
-                );
+        // The per-vertex attributes are read from the currently bound buffer
+        let mut location = upload_attributes(&self.layout, 0);
+
+        if !self.streams.is_empty() {
+            let bound_buffer =
+                with_backend(|backend| backend.get_integer(gl::ARRAY_BUFFER_BINDING));
+            for stream in &self.streams {
+                with_backend(|backend| backend.bind_buffer(gl::ARRAY_BUFFER, stream.buffer_id));
+                location = upload_attributes(&stream.attributes, location);
             }
+            with_backend(|backend| backend.bind_buffer(gl::ARRAY_BUFFER, bound_buffer as GLuint));
         }
+        self.enabled_locations = self.enabled_locations.max(location);
+    }
+}
 
-        // Calculate the offset if it is not set
-        let mut offset_map = HashMap::<usize, u32>::new();
-        for (i, attr) in self.layout.iter().enumerate() {
-            if attr.offset.is_none() {
-                offset_map.insert(
-                    i,
-                    self.layout
-                        .iter()
-                        .take(i)
-                        .map(|a| a.components as u32 * a.data_type.size() as u32)
-                        .sum(),
-                );
-            }
+/// Sets up the attribute pointers for attributes which are read from the same
+/// buffer, starting at `first_location`. Returns the location following the
+/// highest location in use.
+///
+/// # Details
+/// - If an attribute's stride is zero, the stride is calculated as the sum of the sizes of all attributes.
+/// - If an attribute's offset is not set, it is calculated as the sum of the sizes of the preceding attributes.
+/// - Attributes with an explicit location are placed there, the following attributes continue
+///   behind it.
+/// - Matrix attributes are split into one attribute pointer per column on consecutive locations.
+/// - The pointer function is chosen by `VertexAttribute::pointer`, so integers which aren't
+///   normalized and doubles reach the shader unconverted.
+fn upload_attributes(attributes: &[VertexAttribute], first_location: usize) -> usize {
+    let layout_stride: i32 = attributes.iter().map(|a| a.calculate_size() as i32).sum();
+
+    let mut location = first_location;
+    let mut end_location = first_location;
+    let mut layout_offset = 0;
+    for attr in attributes {
+        if let Some(explicit_location) = attr.location {
+            location = explicit_location as usize;
         }
+        let stride = if attr.stride > 0 {
+            attr.stride
+        } else {
+            layout_stride
+        };
+        let offset = attr.offset.unwrap_or(layout_offset) as usize;
+        layout_offset += attr.calculate_size() as u32;
 
-        // Upload the attribute data to the GPU
-        for (i, attr) in self.layout.iter().enumerate() {
-            let normalized = attr.normalized;
-            let stride = if attr.stride > 0 {
-                attr.stride
-            } else {
-                stride_map[&i]
-            };
-            let offset = attr.offset.unwrap_or(offset_map[&i]) as usize;
-            let type_ = attr.data_type.to_gl_enum();
-
-            unsafe {
-                gl::VertexAttribPointer(
-                    i as GLuint,
-                    attr.components as GLint,
-                    type_,
-                    normalized as GLboolean,
+        // Every column of a matrix takes its own location
+        for column in 0..attr.location_count() {
+            let column_offset = offset + column * attr.column_size();
+            let index = location as GLuint;
+            let size = attr.components as GLint;
+            let r#type = attr.data_type.to_gl_enum();
+            with_backend(|backend| {
+                match attr.pointer() {
+                    AttributePointer::Float => backend.vertex_attrib_pointer(
+                        index,
+                        size,
+                        r#type,
+                        attr.normalized,
+                        stride,
+                        column_offset,
+                    ),
+                    AttributePointer::Integer => {
+                        backend.vertex_attrib_i_pointer(index, size, r#type, stride, column_offset)
+                    }
+                    AttributePointer::Double => {
+                        backend.vertex_attrib_l_pointer(index, size, r#type, stride, column_offset)
+                    }
+                }
+                backend.enable_vertex_attrib_array(index);
+                backend.vertex_attrib_divisor(index, attr.divisor);
+            });
+            location += 1;
+        }
+        end_location = end_location.max(location);
+    }
+    end_location
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gl_types::{BufferUsage, VertexDataType};
+    use crate::opengl::gl_backend::{install_backend, GlCall, RecordingGlBackend};
+
+    fn active_attribute(gl_type: gl::types::GLenum) -> ActiveAttribute {
+        ActiveAttribute {
+            name: "aValue".to_string(),
+            location: 2,
+            gl_type,
+            size: 1,
+        }
+    }
+
+    #[test]
+    fn test_check_attribute() {
+        let vec3 = VertexAttribute::new(3, VertexDataType::Float);
+        assert!(check_attribute(&vec3, &active_attribute(gl::FLOAT_VEC3)).is_ok());
+        assert!(matches!(
+            check_attribute(&vec3, &active_attribute(gl::FLOAT_VEC4)),
+            Err(VertexLayoutError::ComponentMismatch {
+                expected: 4,
+                found: 3,
+                ..
+            })
+        ));
+
+        let mat4 = VertexAttribute::matrix(4, 4, VertexDataType::Float);
+        assert!(check_attribute(&mat4, &active_attribute(gl::FLOAT_MAT4)).is_ok());
+
+        let bytes = VertexAttribute::new(4, VertexDataType::UnsignedByte);
+        assert!(check_attribute(&bytes, &active_attribute(gl::UNSIGNED_INT_VEC4)).is_ok());
+        assert!(matches!(
+            check_attribute(&bytes, &active_attribute(gl::FLOAT_VEC4)),
+            Err(VertexLayoutError::TypeMismatch { .. })
+        ));
+        let normalized_bytes = bytes.clone().normalized(true);
+        assert!(check_attribute(&normalized_bytes, &active_attribute(gl::FLOAT_VEC4)).is_ok());
+        assert!(
+            check_attribute(&normalized_bytes, &active_attribute(gl::UNSIGNED_INT_VEC4)).is_err()
+        );
+        assert!(matches!(
+            check_attribute(
+                &VertexAttribute::new(4, VertexDataType::Float),
+                &active_attribute(gl::INT_VEC4)
+            ),
+            Err(VertexLayoutError::TypeMismatch { shader_type, .. }) if shader_type == "ivec4"
+        ));
+    }
+    #[test]
+    fn test_prepare_render_sets_attribute_pointers() {
+        let backend = RecordingGlBackend::new();
+        let _guard = install_backend(backend.clone());
+
+        let vertices =
+            BufferObject::new(BufferType::ArrayBuffer, BufferUsage::StaticDraw, vec![0.0f32; 10]);
+        let instances =
+            BufferObject::new(BufferType::ArrayBuffer, BufferUsage::DynamicDraw, vec![0.0f32; 16]);
+        let mut vao = VertexArrayObject::new_with_attributes([
+            VertexAttribute::new(3, VertexDataType::Float),
+            VertexAttribute::new(2, VertexDataType::Float),
+        ]);
+        vao.add_instance_layout(&instances, [VertexAttribute::matrix(4, 4, VertexDataType::Float)]);
+        vertices.bind().unwrap();
+
+        backend.take_calls();
+        vao.prepare_render();
+
+        let float_pointer = |index, size, stride, offset| {
+            [
+                GlCall::VertexAttribPointer {
+                    index,
+                    size,
+                    data_type: gl::FLOAT,
+                    normalized: false,
                     stride,
-                    offset as *const GLvoid,
-                );
-                gl::EnableVertexAttribArray(i as GLuint);
-            }
+                    offset,
+                },
+                GlCall::EnableVertexAttribArray(index),
+                GlCall::VertexAttribDivisor {
+                    index,
+                    divisor: (index >= 2) as u32,
+                },
+            ]
+        };
+        let bind_array_buffer = |buffer| GlCall::BindBuffer {
+            target: gl::ARRAY_BUFFER,
+            buffer,
+        };
+        let mut expected = vec![GlCall::BindVertexArray(vao.array_id())];
+        expected.extend(float_pointer(0, 3, 20, 0));
+        expected.extend(float_pointer(1, 2, 20, 12));
+        expected.push(bind_array_buffer(instances.buffer_id()));
+        // One location per matrix column
+        for column in 0..4 {
+            expected.extend(float_pointer(2 + column, 4, 64, 16 * column as usize));
         }
+        expected.push(bind_array_buffer(vertices.buffer_id()));
+        expected.push(GlCall::BindVertexArray(0));
+        assert_eq!(backend.take_calls(), expected);
+
+        // The uploaded layout isn't set up again
+        vao.prepare_render();
+        assert!(backend.calls().is_empty());
+    }
+
+    #[test]
+    fn test_attribute_pointer() {
+        let pointer = |data_type, normalized| {
+            VertexAttribute::new(4, data_type).normalized(normalized).pointer()
+        };
+        assert_eq!(pointer(VertexDataType::Float, false), AttributePointer::Float);
+        assert_eq!(pointer(VertexDataType::HalfFloat, false), AttributePointer::Float);
+        assert_eq!(pointer(VertexDataType::UnsignedByte, true), AttributePointer::Float);
+        assert_eq!(pointer(VertexDataType::UnsignedByte, false), AttributePointer::Integer);
+        assert_eq!(pointer(VertexDataType::Int, false), AttributePointer::Integer);
+        assert_eq!(pointer(VertexDataType::Double, false), AttributePointer::Double);
+        assert_eq!(pointer(VertexDataType::Int_2_10_10_10_Rev, false), AttributePointer::Float);
     }
 }
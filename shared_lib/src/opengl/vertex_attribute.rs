@@ -1,5 +1,18 @@
 use crate::gl_prelude::{VertexAttributeType, VertexDataType};
 
+/// The `glVertexAttrib*Pointer` function an attribute is set up with, which decides
+/// how the shader reads the values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributePointer {
+    /// `glVertexAttribPointer`: The values are converted to floats, integers are
+    /// normalized if requested.
+    Float,
+    /// `glVertexAttribIPointer`: Integers are passed unchanged to `int`/`uint` inputs.
+    Integer,
+    /// `glVertexAttribLPointer`: Doubles are passed unchanged to `double`/`dvec` inputs.
+    Double,
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct VertexAttribute {
     /// Optional name of the attribute, useful when querying by name in shader programs.
@@ -9,6 +22,15 @@ pub struct VertexAttribute {
     pub normalized: bool,
     pub stride: i32,
     pub offset: Option<u32>,
+    /// Instancing divisor: 0 advances the attribute per vertex, N advances it once
+    /// every N instances.
+    pub divisor: u32,
+    /// Number of columns of a matrix attribute, each column occupies its own
+    /// attribute location. 0 and 1 both describe a plain vector attribute.
+    pub columns: u8,
+    /// Explicit attribute location. Without one the attribute takes the location
+    /// following the previous attribute.
+    pub location: Option<u32>,
 }
 
 impl VertexAttribute {
@@ -20,6 +42,18 @@ impl VertexAttribute {
         }
     }
 
+    /// Creates a matrix attribute with `columns` columns of `rows` components each,
+    /// e.g. `VertexAttribute::matrix(4, 4, VertexDataType::Float)` for a `mat4`.
+    /// The columns are assigned to consecutive attribute locations.
+    pub fn matrix(columns: u8, rows: u8, data_type: VertexDataType) -> Self {
+        Self {
+            components: rows,
+            data_type,
+            columns,
+            ..Self::default()
+        }
+    }
+
     /// Sets the name field of the instance, consuming and returning self for method chaining.
     pub fn name(mut self, name: impl Into<Option<String>>) -> Self {
         self.name = name.into();
@@ -54,9 +88,52 @@ impl VertexAttribute {
         self
     }
 
+    pub fn divisor(mut self, divisor: u32) -> Self {
+        self.divisor = divisor;
+        self
+    }
+
+    pub fn columns(mut self, columns: u8) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    pub fn location(mut self, location: u32) -> Self {
+        self.location = Some(location);
+        self
+    }
+
+    /// Returns the number of attribute locations the attribute occupies.
+    pub fn location_count(&self) -> usize {
+        self.columns.max(1) as usize
+    }
+
+    /// Returns the byte size of a single column (the whole attribute for vectors).
+    /// Packed data types hold all components in a single value.
+    pub fn column_size(&self) -> usize {
+        if self.data_type.is_packed() {
+            self.data_type.size()
+        } else {
+            self.data_type.size() * self.components as usize
+        }
+    }
+
+    /// Returns how the attribute is passed to the shader. Integers which aren't
+    /// normalized stay integers and doubles stay doubles, everything else is read
+    /// as floats.
+    pub fn pointer(&self) -> AttributePointer {
+        if self.data_type == VertexDataType::Double {
+            AttributePointer::Double
+        } else if self.data_type.is_integer() && !self.normalized {
+            AttributePointer::Integer
+        } else {
+            AttributePointer::Float
+        }
+    }
+
     /// Calculates the byte size of the attribute based on its specifications or its type.
     pub fn calculate_size(&self) -> usize {
-        self.data_type.size() * self.components as usize
+        self.column_size() * self.location_count()
     }
 }
 
@@ -1,6 +1,11 @@
+use crate::gl_types::VertexDataType;
 use crate::opengl::vertex_attribute::VertexAttribute;
 use thiserror::Error;
 
+/// Derives `VertexLayout` for a `#[repr(C)]` struct from the types of its fields,
+/// see the `rs_vertex_kit` crate for the supported types and field attributes.
+pub use rs_vertex_kit::VertexLayout;
+
 pub trait VertexLayout {
     fn attributes() -> Vec<VertexAttribute>;
     fn layout_size() -> usize {
@@ -24,6 +29,20 @@ pub enum VertexLayoutError {
     InvalidLayoutName(String),
     #[error("Datatype not present for attribute in vertex layout")]
     DataTypeNotPresent,
+    #[error("Unknown data type for attribute in vertex layout: {0}")]
+    UnknownDataType(String),
+    #[error("Attribute {name} has {found} components, but the shader expects {expected}")]
+    ComponentMismatch {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("Attribute {name} of type {data_type} can't be read by the shader type {shader_type}")]
+    TypeMismatch {
+        name: String,
+        data_type: VertexDataType,
+        shader_type: String,
+    },
     #[error("OpenGL error: {0}")]
     OpenGL(String),
 }
@@ -11,8 +11,12 @@ use std::collections::HashSet;
 use crate::color::Color;
 use crate::gl_traits::ToOpenGL;
 use crate::gl_types::RenderMask;
-use crate::gl_utils::check_gl_error;
+use crate::gl_utils::{check_gl_error, clear_capability_cache};
 use crate::input::mouse_adapter::{MouseAdapter, MouseButton};
+use crate::opengl::debug_output::{self, print_debug_message, DebugMessageFilter};
+use crate::opengl::resource_registry;
+use crate::opengl::stencil::default_framebuffer_stencil_bits;
+use crate::opengl::stream_buffer::{advance_shared_vertex_stream, release_shared_vertex_stream};
 use crate::rectangle::Rectangle;
 use crate::{RenderTarget, Size2D};
 
@@ -22,6 +26,68 @@ pub trait WindowTrait {
     fn get_title(&self) -> &str;
 }
 
+//////////////////////////////////////////////////////////////////////////////
+// - SdlWindowOptions -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Options for `SdlWindow::with_options`. The default values match the window
+/// created by `SdlWindow::new` with VSync enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SdlWindowOptions {
+    pub enable_vsync: bool,
+    /// Requested bits of the depth buffer.
+    pub depth_bits: u8,
+    /// Requested bits of the stencil buffer, 0 for no stencil buffer.
+    pub stencil_bits: u8,
+    /// Creates a debug context and prints its debug messages, see `debug_output`.
+    pub debug_context: bool,
+    /// Renders without a display, see `SdlWindowOptions::headless`.
+    pub headless: bool,
+}
+
+impl Default for SdlWindowOptions {
+    fn default() -> Self {
+        Self {
+            enable_vsync: true,
+            depth_bits: 16,
+            stencil_bits: 0,
+            debug_context: false,
+            headless: false,
+        }
+    }
+}
+
+impl SdlWindowOptions {
+    pub fn vsync(mut self, enable_vsync: bool) -> Self {
+        self.enable_vsync = enable_vsync;
+        self
+    }
+
+    pub fn depth_bits(mut self, depth_bits: u8) -> Self {
+        self.depth_bits = depth_bits;
+        self
+    }
+
+    pub fn stencil_bits(mut self, stencil_bits: u8) -> Self {
+        self.stencil_bits = stencil_bits;
+        self
+    }
+
+    pub fn debug_context(mut self, debug_context: bool) -> Self {
+        self.debug_context = debug_context;
+        self
+    }
+
+    /// Creates a hidden window with SDL's offscreen video driver, which renders
+    /// into an EGL surfaceless context and needs no display. With Mesa, setting
+    /// `LIBGL_ALWAYS_SOFTWARE=1` selects the llvmpipe software renderer, so this
+    /// also works on machines without a GPU. VSync is ignored.
+    pub fn headless(mut self, headless: bool) -> Self {
+        self.headless = headless;
+        self
+    }
+}
+
 //////////////////////////////////////////////////////////////////////////////
 // - SdlWindow -
 //////////////////////////////////////////////////////////////////////////////
@@ -32,6 +98,10 @@ pub struct SdlWindow {
     pub gl_context: GLContext,
     pub event_pump: EventPump,
     pub clear_color: Color,
+    /// Queried once after creating the context, see `stencil_bits`.
+    stencil_bits: u32,
+    /// Set if the window was created with `SdlWindowOptions::headless`.
+    headless: bool,
 }
 
 impl SdlWindow {
@@ -81,16 +151,44 @@ impl SdlWindow {
     /// }
     /// ```
     pub fn new(width: usize, height: usize, title: &str, enable_vsync: bool) -> Result<SdlWindow> {
+        Self::with_options(width, height, title, SdlWindowOptions::default().vsync(enable_vsync))
+    }
+
+    /// Creates a new `SdlWindow` like `new`, with the framebuffer and context
+    /// configured by `options`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// // Request a stencil buffer for outlines and clip masks
+    /// let options = SdlWindowOptions::default().depth_bits(24).stencil_bits(8);
+    /// let window = SdlWindow::with_options(800, 600, "Stencil", options)?;
+    /// assert!(window.stencil_bits() >= 8);
+    /// ```
+    pub fn with_options(
+        width: usize,
+        height: usize,
+        title: &str,
+        options: SdlWindowOptions,
+    ) -> Result<SdlWindow> {
+        if options.headless {
+            sdl2::hint::set("SDL_VIDEODRIVER", "offscreen");
+        }
         let sdl = sdl2::init().map_err(Error::msg)?;
         let video_subsystem = sdl.video().map_err(Error::msg)?;
         let gl_attr = video_subsystem.gl_attr();
         gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
         gl_attr.set_context_version(3, 3);
-        let window = video_subsystem
-            .window(title, width as u32, height as u32)
-            .opengl()
-            .build()
-            .map_err(Error::msg)?;
+        gl_attr.set_depth_size(options.depth_bits);
+        gl_attr.set_stencil_size(options.stencil_bits);
+        if options.debug_context {
+            gl_attr.set_context_flags().debug().set();
+        }
+        let mut window_builder = video_subsystem.window(title, width as u32, height as u32);
+        window_builder.opengl();
+        if options.headless {
+            window_builder.hidden();
+        }
+        let window = window_builder.build().map_err(Error::msg)?;
 
         let gl_context = window.gl_create_context().map_err(Error::msg)?;
 
@@ -98,18 +196,32 @@ impl SdlWindow {
         gl::load_with(|s| video_subsystem.gl_get_proc_address(s) as *const _);
         check_gl_error()?;
 
+        // Route driver messages to stderr, errors included
+        if options.debug_context {
+            let filter = DebugMessageFilter::default();
+            if let Err(err) = debug_output::install_debug_callback(filter, print_debug_message) {
+                eprintln!("Debug output isn't available: {}", err);
+            }
+        }
+
         // Set the OpenGL viewport
         unsafe {
             gl::Viewport(0, 0, width as i32, height as i32);
         }
 
-        if enable_vsync {
+        if options.enable_vsync && !options.headless {
             window
                 .subsystem()
                 .gl_set_swap_interval(SwapInterval::VSync)
                 .map_err(Error::msg)?;
         }
 
+        // Headless contexts may have no default framebuffer to query
+        let stencil_bits = default_framebuffer_stencil_bits().unwrap_or_else(|err| {
+            eprintln!("{:#}", err);
+            0
+        });
+
         let event_pump = sdl.event_pump().map_err(Error::msg)?;
 
         Ok(SdlWindow {
@@ -118,6 +230,8 @@ impl SdlWindow {
             gl_context,
             event_pump,
             clear_color: Color::BLACK,
+            stencil_bits,
+            headless: options.headless,
         })
     }
 }
@@ -177,10 +291,26 @@ impl SdlWindow {
         }
     }
 
+    /// Returns the number of stencil bits the window got, which can differ from the
+    /// requested `SdlWindowOptions::stencil_bits`.
+    pub fn stencil_bits(&self) -> u32 {
+        self.stencil_bits
+    }
+
+    /// Returns `true` if the window renders without a display. Headless windows
+    /// have no keyboard focus, so their keyboard state shouldn't be polled.
+    pub fn is_headless(&self) -> bool {
+        self.headless
+    }
+
     /// Swaps the front and back buffers of the window.
     /// This should be called after rendering to display the updated content.
+    /// It also moves the shared vertex stream on to the next frame.
     pub fn swap(&self) {
         self.window.gl_swap_window();
+        if let Err(e) = advance_shared_vertex_stream() {
+            eprintln!("Failed to advance shared vertex stream: {}", e);
+        }
     }
 
     /// Sets the title of the window.
@@ -334,6 +464,16 @@ impl SdlWindow {
     }
 }
 
+/// Reports the GPU resources which are still alive when the OpenGL context goes
+/// away. Resources owned by the library itself are released first.
+impl Drop for SdlWindow {
+    fn drop(&mut self) {
+        release_shared_vertex_stream();
+        resource_registry::print_leak_report();
+        clear_capability_cache();
+    }
+}
+
 impl MouseAdapter for SdlWindow {
     fn focused_window_id(&self) -> Option<u32> {
         self.sdl.mouse().focused_window_id()
@@ -504,3 +644,30 @@ impl Default for SdlKeyboardState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_options() {
+        let options = SdlWindowOptions::default();
+        assert!(options.enable_vsync);
+        assert_eq!(options.depth_bits, 16);
+        // Stencil buffers have to be requested
+        assert_eq!(options.stencil_bits, 0);
+        assert!(!options.debug_context && !options.headless);
+
+        let options = options.vsync(false).depth_bits(24).stencil_bits(8).headless(true);
+        assert_eq!(
+            options,
+            SdlWindowOptions {
+                enable_vsync: false,
+                depth_bits: 24,
+                stencil_bits: 8,
+                debug_context: false,
+                headless: true,
+            }
+        );
+    }
+}
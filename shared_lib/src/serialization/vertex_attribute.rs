@@ -1,21 +1,226 @@
-use anyhow::Result;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
-pub(crate) struct VertexAttribute {
-    name: Option<String>,
-    // Optional: might not be used
-    components: i32,
-    data_type: Option<String>,
-    // Optional: data type might not always be necessary
-    normalized: Option<bool>,
+use crate::gl_types::VertexDataType;
+use crate::opengl::vertex_attribute;
+use crate::opengl::vertex_layout::VertexLayoutError;
+
+//////////////////////////////////////////////////////////////////////////////
+// - VertexAttribute -
+//////////////////////////////////////////////////////////////////////////////
+
+/// The serialized form of an `opengl::vertex_attribute::VertexAttribute`.
+///
+/// The data type is stored by name, e.g. `"Float"` or `"UnsignedByte"` (see
+/// `VertexDataType::from_name`). All other fields except `components` are optional.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VertexAttribute {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub components: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_type: Option<String>,
     // Optional: normalization might not be relevant for all data types
-    stride: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normalized: Option<bool>,
     // Optional: stride might be uniform and not needed to be specified each time
-    offset: Option<i32>, // Optional: offset might not be needed if data is tightly packed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stride: Option<i32>,
+    // Optional: offset might not be needed if data is tightly packed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub divisor: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub columns: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<u32>,
+}
+
+impl TryFrom<&VertexAttribute> for vertex_attribute::VertexAttribute {
+    type Error = VertexLayoutError;
+
+    /// Validates the serialized attribute and converts it.
+    ///
+    /// # Errors
+    /// - `VertexLayoutError::InvalidNumberOfComponents` if `components` isn't between 1 and 4.
+    /// - `VertexLayoutError::DataTypeNotPresent` if the data type is missing.
+    /// - `VertexLayoutError::UnknownDataType` if the data type isn't a known type name.
+    fn try_from(value: &VertexAttribute) -> Result<Self, Self::Error> {
+        if !(1..=4).contains(&value.components) {
+            return Err(VertexLayoutError::InvalidNumberOfComponents);
+        }
+        let name = value.data_type.as_deref().ok_or(VertexLayoutError::DataTypeNotPresent)?;
+        let data_type = VertexDataType::from_name(name)
+            .ok_or_else(|| VertexLayoutError::UnknownDataType(name.to_string()))?;
+
+        let mut attribute =
+            vertex_attribute::VertexAttribute::new(value.components as u8, data_type)
+                .name(value.name.clone())
+                .normalized(value.normalized.unwrap_or(false))
+                .stride(value.stride.unwrap_or(0))
+                .offset(value.offset)
+                .divisor(value.divisor.unwrap_or(0))
+                .columns(value.columns.unwrap_or(0));
+        attribute.location = value.location;
+        Ok(attribute)
+    }
 }
 
-#[derive(Serialize, Deserialize)]
-pub(crate) struct VertexLayout {
-    attributes: Vec<VertexAttribute>,
+impl From<&vertex_attribute::VertexAttribute> for VertexAttribute {
+    fn from(value: &vertex_attribute::VertexAttribute) -> Self {
+        Self {
+            name: value.name.clone(),
+            components: value.components as i32,
+            data_type: Some(value.data_type.to_string()),
+            normalized: value.normalized.then_some(true),
+            stride: (value.stride != 0).then_some(value.stride),
+            offset: value.offset,
+            divisor: (value.divisor != 0).then_some(value.divisor),
+            columns: (value.columns > 1).then_some(value.columns),
+            location: value.location,
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - VertexLayout -
+//////////////////////////////////////////////////////////////////////////////
+
+/// A vertex layout stored as JSON, which lets mesh files declare their own layout:
+///
+/// ```json
+/// {
+///   "attributes": [
+///     { "name": "position", "components": 3, "data_type": "Float" },
+///     { "name": "tex_coords", "components": 2, "data_type": "Float" },
+///     { "name": "color", "components": 4, "data_type": "UnsignedByte", "normalized": true }
+///   ]
+/// }
+/// ```
+///
+/// The layout is applied to a vertex array object with
+/// `VertexArrayObject::load_layout_file`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VertexLayout {
+    pub attributes: Vec<VertexAttribute>,
+}
+
+impl VertexLayout {
+    /// Reads and parses a layout file. The attributes are validated when they are
+    /// converted with `to_attributes`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read vertex layout {}", path.display()))?;
+        Self::from_json(&content)
+            .with_context(|| format!("Failed to parse vertex layout {}", path.display()))
+    }
+
+    /// Parses a layout from a JSON string.
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Serializes the layout to pretty printed JSON.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Creates the serialized form of the given attributes.
+    pub fn from_attributes(attributes: &[vertex_attribute::VertexAttribute]) -> Self {
+        Self {
+            attributes: attributes.iter().map(VertexAttribute::from).collect(),
+        }
+    }
+
+    /// Validates and converts all attributes of the layout.
+    ///
+    /// # Errors
+    /// Returns the error of the first invalid attribute, see
+    /// `TryFrom<&VertexAttribute> for opengl::vertex_attribute::VertexAttribute`.
+    pub fn to_attributes(
+        &self,
+    ) -> Result<Vec<vertex_attribute::VertexAttribute>, VertexLayoutError> {
+        self.attributes
+            .iter()
+            .map(vertex_attribute::VertexAttribute::try_from)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let attributes = vec![
+            vertex_attribute::VertexAttribute::new(3, VertexDataType::Float)
+                .name("position".to_string()),
+            vertex_attribute::VertexAttribute::new(4, VertexDataType::UnsignedByte)
+                .name("color".to_string())
+                .normalized(true)
+                .offset(Some(12)),
+            vertex_attribute::VertexAttribute::matrix(4, 4, VertexDataType::Float)
+                .divisor(1)
+                .location(3),
+        ];
+
+        let json = VertexLayout::from_attributes(&attributes).to_json().unwrap();
+        let converted = VertexLayout::from_json(&json).unwrap().to_attributes().unwrap();
+
+        assert_eq!(converted.len(), attributes.len());
+        for (converted, original) in converted.iter().zip(&attributes) {
+            assert_eq!(converted.name, original.name);
+            assert_eq!(converted.components, original.components);
+            assert_eq!(converted.data_type, original.data_type);
+            assert_eq!(converted.normalized, original.normalized);
+            assert_eq!(converted.offset, original.offset);
+            assert_eq!(converted.divisor, original.divisor);
+            assert_eq!(converted.location_count(), original.location_count());
+            assert_eq!(converted.location, original.location);
+        }
+    }
+
+    #[test]
+    fn test_parse_data_type_names() {
+        let layout = VertexLayout::from_json(
+            r#"{ "attributes": [
+                { "components": 2, "data_type": "float" },
+                { "components": 4, "data_type": "Int_2_10_10_10_Rev", "normalized": true }
+            ] }"#,
+        )
+        .unwrap();
+
+        let attributes = layout.to_attributes().unwrap();
+        assert_eq!(attributes[0].data_type, VertexDataType::Float);
+        assert_eq!(attributes[1].data_type, VertexDataType::Int_2_10_10_10_Rev);
+        assert!(attributes[1].normalized);
+    }
+
+    #[test]
+    fn test_invalid_attributes() {
+        let layout = |json: &str| VertexLayout::from_json(json).unwrap().to_attributes();
+
+        assert!(matches!(
+            layout(r#"{ "attributes": [{ "components": 5, "data_type": "Float" }] }"#),
+            Err(VertexLayoutError::InvalidNumberOfComponents)
+        ));
+        assert!(matches!(
+            layout(r#"{ "attributes": [{ "components": 0, "data_type": "Float" }] }"#),
+            Err(VertexLayoutError::InvalidNumberOfComponents)
+        ));
+        assert!(matches!(
+            layout(r#"{ "attributes": [{ "components": 3, "data_type": "Vec3" }] }"#),
+            Err(VertexLayoutError::UnknownDataType(name)) if name == "Vec3"
+        ));
+        assert!(matches!(
+            layout(r#"{ "attributes": [{ "components": 3 }] }"#),
+            Err(VertexLayoutError::DataTypeNotPresent)
+        ));
+    }
 }
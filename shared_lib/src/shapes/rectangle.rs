@@ -1,8 +1,9 @@
-#[macro_use]
+use std::mem::size_of;
 use std::sync::Mutex;
 
 use anyhow::{anyhow, Result};
 use cgmath::Matrix4;
+use gl::types::GLsizei;
 use once_cell::sync::Lazy;
 
 use crate::color::Color;
@@ -10,9 +11,13 @@ use crate::gl_draw::{draw_arrays, draw_elements};
 use crate::gl_prelude::{BufferType, BufferUsage, PrimitiveType, ShaderType, VertexAttributeType};
 use crate::gl_traits::Bindable;
 use crate::gl_types::IndicesValueType;
+use crate::gl_utils::{as_c_void, check_gl_error};
 use crate::opengl::blend_guard::BlendGuard;
 use crate::opengl::buffer_object::BufferObject;
+use crate::opengl::pipeline_state::PolygonMode;
 use crate::opengl::shader_program::ShaderProgram;
+use crate::opengl::state_guard::{CullGuard, DepthGuard, PolygonModeGuard};
+use crate::opengl::stream_buffer::{with_shared_vertex_stream, StreamAllocation};
 use crate::opengl::vertex_array_object::VertexArrayObject;
 use crate::{Drawable, Position2D, Size2D};
 
@@ -183,18 +188,13 @@ impl Drawable for Rectangle {
 
 struct RectangleDraw {
     vao: VertexArrayObject,
-    vbo: BufferObject<f32>,
     ebo: BufferObject<u32>,
-    vertices: Vec<f32>,
     shader: Option<ShaderProgram>,
-    last_position: Option<Position2D>,
-    last_size: Option<Size2D<f32>>,
 }
 
 impl RectangleDraw {
     pub fn new() -> Self {
         let vao = VertexArrayObject::default();
-        let vbo = BufferObject::empty(BufferType::ArrayBuffer, BufferUsage::DynamicDraw);
         let ebo = BufferObject::new(
             BufferType::ElementArrayBuffer,
             BufferUsage::StaticDraw,
@@ -227,28 +227,27 @@ impl RectangleDraw {
 
         Self {
             vao,
-            vbo,
             ebo,
-            vertices: Vec::new(),
             shader: if load_shader_ok {
                 Some(shader_program)
             } else {
                 None
             },
-            last_position: None,
-            last_size: None,
         }
     }
 
     pub fn draw(&mut self, rect: &Rectangle) -> Result<()> {
         self.vao.bind();
         self.update_shader_uniforms(rect);
-        self.update_vertices(rect);
+        let allocation = self.update_vertices(rect)?;
 
         assert_eq!(self.ebo.data_len(), 6);
-        assert_eq!(self.vbo.data_len(), 12);
+        assert_eq!(allocation.count, 12);
 
         let _blend_guard = BlendGuard::default();
+        let _depth_guard = DepthGuard::disabled()?;
+        let _cull_guard = CullGuard::disabled()?;
+        let _polygon_mode_guard = PolygonModeGuard::new(PolygonMode::Fill)?;
 
         if rect.fill_color.is_some() {
             // draw_elements(
@@ -292,11 +291,10 @@ impl RectangleDraw {
         }
     }
 
-    fn update_vertices(&mut self, rect: &Rectangle) -> Result<()> {
-        if !self.position_changed(rect.position) && !self.size_changed(rect.size) {
-            return Ok(());
-        }
-
+    /// Writes the corners of the rectangle into the shared vertex stream and
+    /// points the position attribute at them. The vertices are written every
+    /// frame, as stream regions are reused once the GPU is done with them.
+    fn update_vertices(&mut self, rect: &Rectangle) -> Result<StreamAllocation> {
         let Position2D {
             x,
             y,
@@ -313,21 +311,24 @@ impl RectangleDraw {
             x + width,y + height, 0.0,  // Top-right
             x, y + height, 0.0,         // Top-left
         ];
-        let vec = vertices.to_vec();
-        self.vbo.update_data(vec, None);
-
-        self.last_position = Some(rect.position);
-        self.last_size = Some(rect.size);
 
-        Ok(())
-    }
-
-    fn position_changed(&self, position: Position2D) -> bool {
-        self.last_position.map_or(true, |last_pos| last_pos != position)
-    }
-
-    fn size_changed(&self, size: Size2D<f32>) -> bool {
-        self.last_size.map_or(true, |last_size| last_size != size)
+        with_shared_vertex_stream(|stream| {
+            let allocation = stream.allocate(&vertices)?;
+            stream.buffer().bind()?;
+            unsafe {
+                gl::VertexAttribPointer(
+                    0,
+                    3,
+                    gl::FLOAT,
+                    gl::FALSE,
+                    (3 * size_of::<f32>()) as GLsizei,
+                    as_c_void(allocation.byte_offset::<f32>()),
+                );
+                gl::EnableVertexAttribArray(0);
+            }
+            check_gl_error()?;
+            Ok(allocation)
+        })
     }
 }
 
@@ -6,6 +6,7 @@ use std::mem;
 
 use crate::check_gl_panic;
 use crate::gl_prelude::check_gl_error;
+use crate::opengl::resource_registry::{self, ResourceKind};
 use crate::opengl::texture_utils::get_texture_from_gpu;
 use anyhow::{Context, Result};
 use image::{DynamicImage, Rgba, RgbaImage};
@@ -128,6 +129,12 @@ impl FontAtlas {
             check_gl_panic!("Failed to call GenerateTextureMipmap for texture: {}", texture);
             texture
         };
+        resource_registry::register(
+            ResourceKind::Texture,
+            texture_id,
+            "font atlas",
+            texture_data.len(),
+        );
 
         FontAtlas {
             texture_id,
@@ -191,6 +198,20 @@ impl FontAtlas {
     }
 }
 
+impl Drop for FontAtlas {
+    fn drop(&mut self) {
+        if self.texture_id == 0 {
+            return;
+        }
+        match resource_registry::unregister(ResourceKind::Texture, self.texture_id) {
+            Ok(()) => unsafe {
+                gl::DeleteTextures(1, &self.texture_id);
+            },
+            Err(err) => eprintln!("Error while dropping FontAtlas: {}", err),
+        }
+    }
+}
+
 /// Saves a mapping of character glyphs to an XML file.
 ///
 /// This function takes a reference to a `HashMap` of character-to-`GlyphData` mappings
@@ -1,5 +1,3 @@
-use std::ptr;
-
 use anyhow::{Context, Result};
 use cgmath::ortho;
 use gl::types::{GLfloat, GLsizei};
@@ -7,13 +5,17 @@ use rusttype::Scale;
 use sha2::digest::typenum::op;
 
 use crate::color::Color;
-use crate::gl_prelude::{check_gl_error2, BufferType, BufferUsage, PrimitiveType, ShaderType};
+use crate::gl_prelude::{check_gl_error2, PrimitiveType, ShaderType};
 use crate::gl_types::ProjectionMatrix;
-use crate::gl_utils::check_gl_error;
+use crate::gl_traits::Bindable;
+use crate::gl_utils::{as_c_void, check_gl_error};
 use crate::opengl::blend_guard::BlendGuard;
-use crate::opengl::buffer_object::BufferObject;
+use crate::opengl::debug_output::DebugGroup;
 use crate::opengl::font::Font;
+use crate::opengl::pipeline_state::PolygonMode;
 use crate::opengl::shader_program::ShaderProgram;
+use crate::opengl::state_guard::{CullGuard, DepthGuard, PolygonModeGuard};
+use crate::opengl::stream_buffer::with_shared_vertex_stream;
 use crate::opengl::vertex_array_object::VertexArrayObject;
 use crate::text::font_atlas::FontAtlas;
 use crate::{check_gl_panic, gl_draw, Position2D};
@@ -28,7 +30,6 @@ pub struct SimpleTextRenderer<'a> {
     font_atlas: FontAtlas,
     shader_program: ShaderProgram,
     vao: VertexArrayObject,
-    vbo: BufferObject<f32>,
     options: Option<&'a TextRenderOptions>,
 }
 
@@ -44,12 +45,6 @@ impl<'a> SimpleTextRenderer<'a> {
         let vao = VertexArrayObject::default();
         check_gl_error2();
 
-        let vbo = BufferObject::empty(BufferType::ArrayBuffer, BufferUsage::StaticDraw);
-        check_gl_error2();
-
-        setup_vertex_layout()?;
-        check_gl_panic!("Failed to setup vertex layout");
-
         let shader_program = create_shader_program()?;
         check_gl_panic!("Failed to create shader program");
 
@@ -57,7 +52,6 @@ impl<'a> SimpleTextRenderer<'a> {
             font_atlas,
             shader_program,
             vao,
-            vbo,
             options: None,
         };
 
@@ -72,7 +66,7 @@ impl<'a> SimpleTextRenderer<'a> {
         self.font_atlas.scale.y
     }
 
-    pub fn texture_id(self) -> u32 {
+    pub fn texture_id(&self) -> u32 {
         self.font_atlas.texture_id
     }
 
@@ -123,8 +117,19 @@ impl<'a> SimpleTextRenderer<'a> {
         let vertices = create_vertices_for_text(&self.font_atlas, text, position.x, position.y);
         let triangle_count = (vertices.len() / 4) as u32;
 
-        // Update vertex data
-        self.vbo.update_data(vertices, None);
+        // Write vertex data into the shared stream and point the layout at it
+        with_shared_vertex_stream(|stream| {
+            let allocation = stream.allocate(&vertices)?;
+            stream.buffer().bind()?;
+            setup_vertex_layout(allocation.byte_offset::<f32>())
+        })?;
+
+        let _debug_group = DebugGroup::push("Text");
+
+        // Draw on top of the scene, whatever state the caller left behind
+        let _depth_guard = DepthGuard::disabled()?;
+        let _cull_guard = CullGuard::disabled()?;
+        let _polygon_mode_guard = PolygonModeGuard::new(PolygonMode::Fill)?;
 
         // Enable blend mode
         let mut blend_guard = BlendGuard::default();
@@ -166,7 +171,7 @@ fn create_shader_program() -> Result<ShaderProgram> {
     Ok(shader_program)
 }
 
-fn setup_vertex_layout() -> Result<()> {
+fn setup_vertex_layout(byte_offset: usize) -> Result<()> {
     unsafe {
         gl::VertexAttribPointer(
             0,
@@ -174,7 +179,7 @@ fn setup_vertex_layout() -> Result<()> {
             gl::FLOAT,
             gl::FALSE,
             4 * std::mem::size_of::<GLfloat>() as GLsizei,
-            ptr::null(),
+            as_c_void(byte_offset),
         );
         check_gl_error()?;
 
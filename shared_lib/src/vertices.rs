@@ -1,5 +1,6 @@
 use crate::color::Color;
 
+pub mod compression;
 pub mod textured_vertex;
 
 pub trait VertexTexCoords {
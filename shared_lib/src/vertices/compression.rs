@@ -0,0 +1,642 @@
+//! CPU encoders and decoders for compact vertex attribute formats.
+//!
+//! | Data          | Encoding                                   | Bytes          |
+//! |---------------|--------------------------------------------|----------------|
+//! | Position      | `u16` per axis, relative to a bounding box | 6 instead of 12 |
+//! | Normal        | Octahedral, two snorm `i16`                | 4 instead of 12 |
+//! | Tangent       | `Int_2_10_10_10_Rev` snorm, W = handedness | 4 instead of 16 |
+//! | Texture coord | Half floats                                | 4 instead of 8  |
+//!
+//! `CompactMesh::from_vertices` converts a float mesh into `CompactVertex`es, which
+//! take 20 instead of 48 bytes per vertex.
+
+use crate::gl_types::VertexDataType;
+use crate::opengl::vertex_attribute::VertexAttribute;
+use crate::opengl::vertex_layout::VertexLayout;
+use cgmath::{ElementWise, InnerSpace, Matrix4, Vector2, Vector3, Vector4, Zero};
+use std::mem::{offset_of, size_of};
+
+//////////////////////////////////////////////////////////////////////////////
+// - Half floats -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Converts a float into the bits of an IEEE 754 half float
+/// (`VertexDataType::HalfFloat`), rounding to the nearest even value.
+///
+/// Values beyond the half float range (±65504) become infinite, values below the
+/// smallest subnormal (about 6e-8) become zero. NaN stays NaN.
+pub fn encode_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x007f_ffff;
+
+    // Infinity and NaN
+    if exponent == 0xff {
+        let nan = if mantissa != 0 {
+            0x0200
+        } else {
+            0
+        };
+        return sign | 0x7c00 | nan;
+    }
+
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if half_exponent <= 0 {
+        // Subnormal half float, the implicit leading one becomes explicit
+        if half_exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x0080_0000;
+        let shift = (14 - half_exponent) as u32;
+        return sign | round_shifted(mantissa, shift) as u16;
+    }
+
+    // A carry of the rounding correctly moves into the exponent
+    let half = ((half_exponent as u32) << 10) | (mantissa >> 13);
+    let remainder = mantissa & 0x1fff;
+    let rounded = if remainder > 0x1000 || (remainder == 0x1000 && half & 1 == 1) {
+        half + 1
+    } else {
+        half
+    };
+    sign | rounded as u16
+}
+
+/// Shifts `value` right by `shift` bits, rounding to the nearest even value.
+fn round_shifted(value: u32, shift: u32) -> u32 {
+    let shifted = value >> shift;
+    let remainder = value & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    if remainder > halfway || (remainder == halfway && shifted & 1 == 1) {
+        shifted + 1
+    } else {
+        shifted
+    }
+}
+
+/// Converts the bits of a half float back into a float. The conversion is exact.
+pub fn decode_half(half: u16) -> f32 {
+    let sign = ((half as u32) & 0x8000) << 16;
+    let exponent = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x03ff) as u32;
+
+    match exponent {
+        0 => {
+            let value = mantissa as f32 * 2f32.powi(-24);
+            if sign != 0 {
+                -value
+            } else {
+                value
+            }
+        }
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exponent + 127 - 15) << 23) | (mantissa << 13)),
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - Octahedral normals -
+//////////////////////////////////////////////////////////////////////////////
+
+/// GLSL function which decodes a normal encoded with `encode_octahedral`, read as
+/// a normalized `vec2` attribute.
+pub const OCTAHEDRAL_DECODE_GLSL: &str = r#"
+vec3 decode_octahedral(vec2 e) {
+    vec3 n = vec3(e.xy, 1.0 - abs(e.x) - abs(e.y));
+    float t = max(-n.z, 0.0);
+    n.xy += vec2(n.x >= 0.0 ? -t : t, n.y >= 0.0 ? -t : t);
+    return normalize(n);
+}
+"#;
+
+/// Encodes a unit vector with the octahedral mapping into two snorm values, which
+/// are read as `VertexDataType::Short` with two components and normalization.
+///
+/// The vector doesn't have to be normalized, but must not be zero. The angular error
+/// after decoding stays below 0.005 degrees.
+pub fn encode_octahedral(normal: Vector3<f32>) -> [i16; 2] {
+    let n = normal / (normal.x.abs() + normal.y.abs() + normal.z.abs());
+    let (x, y) = if n.z >= 0.0 {
+        (n.x, n.y)
+    } else {
+        // Fold the lower hemisphere over the diagonals
+        ((1.0 - n.y.abs()) * sign_not_zero(n.x), (1.0 - n.x.abs()) * sign_not_zero(n.y))
+    };
+    [encode_snorm16(x), encode_snorm16(y)]
+}
+
+/// Decodes a normal encoded with `encode_octahedral`. The result is normalized.
+pub fn decode_octahedral(encoded: [i16; 2]) -> Vector3<f32> {
+    let x = decode_snorm(encoded[0] as i32, i16::MAX as f32);
+    let y = decode_snorm(encoded[1] as i32, i16::MAX as f32);
+    let mut n = Vector3::new(x, y, 1.0 - x.abs() - y.abs());
+    let t = (-n.z).max(0.0);
+    n.x += if n.x >= 0.0 {
+        -t
+    } else {
+        t
+    };
+    n.y += if n.y >= 0.0 {
+        -t
+    } else {
+        t
+    };
+    n.normalize()
+}
+
+fn sign_not_zero(value: f32) -> f32 {
+    if value >= 0.0 {
+        1.0
+    } else {
+        -1.0
+    }
+}
+
+fn encode_snorm16(value: f32) -> i16 {
+    (value.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16
+}
+
+/// Converts a signed normalized integer with the given maximum to a float, the way
+/// OpenGL 4.2 does: the smallest value is clamped, so that -max and -max - 1 both
+/// map to -1.
+fn decode_snorm(value: i32, max: f32) -> f32 {
+    (value as f32 / max).max(-1.0)
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - 10:10:10:2 tangents -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Packs a tangent into a `VertexDataType::Int_2_10_10_10_Rev` value with four
+/// normalized components: 10 bits for each of X, Y and Z and 2 bits for W.
+///
+/// W holds the handedness of the bitangent and is reduced to its sign. X, Y and Z
+/// are clamped to -1..1 and have an error of at most 1/1022 after decoding.
+///
+/// The errors assume the signed normalized conversion of OpenGL 4.2 and later,
+/// which `decode_tangent` implements. OpenGL 3.3 converts a value `c` with `b` bits
+/// to `(2c + 1) / (2^b - 1)` instead, which adds up to 1/511 to the error of X, Y
+/// and Z and never yields exactly 0. For W, negative handedness is stored as -2,
+/// which both conversions turn into -1, and positive handedness as 1. A W of 0
+/// arrives as 1/3 on OpenGL 3.3, so shaders should use `sign(tangent.w)`.
+pub fn encode_tangent(tangent: Vector4<f32>) -> u32 {
+    let x = (encode_snorm(tangent.x, 511.0) as u32) & 0x3ff;
+    let y = (encode_snorm(tangent.y, 511.0) as u32) & 0x3ff;
+    let z = (encode_snorm(tangent.z, 511.0) as u32) & 0x3ff;
+    let w: u32 = if tangent.w < 0.0 {
+        // -2, the smallest 2-bit value
+        0b10
+    } else if tangent.w > 0.0 {
+        1
+    } else {
+        0
+    };
+    x | (y << 10) | (z << 20) | (w << 30)
+}
+
+/// Unpacks a tangent packed with `encode_tangent`.
+pub fn decode_tangent(packed: u32) -> Vector4<f32> {
+    // Shifting the field to the top and back sign-extends it
+    let x = ((packed << 22) as i32) >> 22;
+    let y = ((packed << 12) as i32) >> 22;
+    let z = ((packed << 2) as i32) >> 22;
+    let w = (packed as i32) >> 30;
+    Vector4::new(
+        decode_snorm(x, 511.0),
+        decode_snorm(y, 511.0),
+        decode_snorm(z, 511.0),
+        decode_snorm(w, 1.0),
+    )
+}
+
+fn encode_snorm(value: f32, max: f32) -> i32 {
+    (value.clamp(-1.0, 1.0) * max).round() as i32
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - Quantized positions -
+//////////////////////////////////////////////////////////////////////////////
+
+/// A bounding box which positions are quantized to, 16 bits per axis.
+///
+/// The quantized positions are read as normalized `VertexDataType::UnsignedShort`,
+/// so the shader receives them in the range 0 to 1. `dequantize_matrix` maps them
+/// back into the box and can be multiplied into the model matrix:
+///
+/// ```ignore
+/// let model = model * mesh.bounds.dequantize_matrix();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuantizationBounds {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl QuantizationBounds {
+    const STEPS: f32 = u16::MAX as f32;
+
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Self {
+        Self {
+            min,
+            max,
+        }
+    }
+
+    /// Returns the bounding box of `positions`, or `None` if there are none.
+    pub fn from_positions(positions: impl IntoIterator<Item = Vector3<f32>>) -> Option<Self> {
+        positions.into_iter().fold(None, |bounds, position| {
+            Some(match bounds {
+                None => Self::new(position, position),
+                Some(bounds) => Self::new(
+                    Vector3::new(
+                        bounds.min.x.min(position.x),
+                        bounds.min.y.min(position.y),
+                        bounds.min.z.min(position.z),
+                    ),
+                    Vector3::new(
+                        bounds.max.x.max(position.x),
+                        bounds.max.y.max(position.y),
+                        bounds.max.z.max(position.z),
+                    ),
+                ),
+            })
+        })
+    }
+
+    pub fn extent(&self) -> Vector3<f32> {
+        self.max - self.min
+    }
+
+    /// Returns the largest error per axis of a position after dequantization, which
+    /// is half a quantization step.
+    pub fn max_error(&self) -> Vector3<f32> {
+        self.extent() / (2.0 * Self::STEPS)
+    }
+
+    /// Quantizes a position. Positions outside the box are clamped to it.
+    pub fn quantize(&self, position: Vector3<f32>) -> [u16; 3] {
+        let extent = self.extent();
+        let quantize_axis = |value: f32, min: f32, extent: f32| {
+            if extent > 0.0 {
+                ((value - min) / extent * Self::STEPS).round().clamp(0.0, Self::STEPS) as u16
+            } else {
+                0
+            }
+        };
+        [
+            quantize_axis(position.x, self.min.x, extent.x),
+            quantize_axis(position.y, self.min.y, extent.y),
+            quantize_axis(position.z, self.min.z, extent.z),
+        ]
+    }
+
+    pub fn dequantize(&self, quantized: [u16; 3]) -> Vector3<f32> {
+        let normalized = Vector3::new(
+            quantized[0] as f32 / Self::STEPS,
+            quantized[1] as f32 / Self::STEPS,
+            quantized[2] as f32 / Self::STEPS,
+        );
+        self.min + self.extent().mul_element_wise(normalized)
+    }
+
+    /// Returns the matrix which transforms the normalized quantized positions, as
+    /// the shader reads them, back into the box.
+    pub fn dequantize_matrix(&self) -> Matrix4<f32> {
+        let extent = self.extent();
+        Matrix4::from_translation(self.min)
+            * Matrix4::from_nonuniform_scale(extent.x, extent.y, extent.z)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - CompactVertex -
+//////////////////////////////////////////////////////////////////////////////
+
+/// A vertex with float attributes, the input of `CompactMesh::from_vertices`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeshVertex {
+    pub position: Vector3<f32>,
+    pub normal: Vector3<f32>,
+    /// Tangent in XYZ, handedness of the bitangent in W.
+    pub tangent: Vector4<f32>,
+    pub tex_coords: Vector2<f32>,
+}
+
+/// A vertex with compressed attributes, see the module documentation.
+///
+/// The shader reads the attributes as:
+/// - `vec3 position` in the range 0 to 1, see `QuantizationBounds::dequantize_matrix`.
+/// - `vec2 normal`, decoded with `OCTAHEDRAL_DECODE_GLSL`.
+/// - `vec2 tex_coords`.
+/// - `vec4 tangent`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompactVertex {
+    pub position: [u16; 3],
+    pub normal: [i16; 2],
+    pub tex_coords: [u16; 2],
+    /// Keeps `tangent` aligned without leaving uninitialized bytes in the buffer.
+    _padding: u16,
+    pub tangent: u32,
+}
+
+impl CompactVertex {
+    pub fn new(position: [u16; 3], normal: [i16; 2], tex_coords: [u16; 2], tangent: u32) -> Self {
+        Self {
+            position,
+            normal,
+            tex_coords,
+            _padding: 0,
+            tangent,
+        }
+    }
+}
+
+// The derive macro can't express half floats and packed types
+impl VertexLayout for CompactVertex {
+    fn attributes() -> Vec<VertexAttribute> {
+        let stride = size_of::<Self>() as i32;
+        let attribute = |name: &str, components, data_type, offset: usize| {
+            VertexAttribute::new(components, data_type)
+                .name(name.to_string())
+                .stride(stride)
+                .offset(Some(offset as u32))
+        };
+        vec![
+            attribute("position", 3, VertexDataType::UnsignedShort, offset_of!(Self, position))
+                .normalized(true),
+            attribute("normal", 2, VertexDataType::Short, offset_of!(Self, normal))
+                .normalized(true),
+            attribute("tex_coords", 2, VertexDataType::HalfFloat, offset_of!(Self, tex_coords)),
+            attribute("tangent", 4, VertexDataType::Int_2_10_10_10_Rev, offset_of!(Self, tangent))
+                .normalized(true),
+        ]
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// - CompactMesh -
+//////////////////////////////////////////////////////////////////////////////
+
+/// Compressed vertices together with the bounds their positions are quantized to.
+///
+/// # Example
+/// ```ignore
+/// let mesh = CompactMesh::from_vertices(&vertices);
+/// let vbo = BufferObject::new(BufferType::ArrayBuffer, BufferUsage::StaticDraw, mesh.vertices.clone());
+/// let vao = VertexArrayObject::new_with_attributes(CompactVertex::attributes());
+/// shader.set_uniform_matrix("model", false, &(model * mesh.bounds.dequantize_matrix()))?;
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactMesh {
+    pub vertices: Vec<CompactVertex>,
+    pub bounds: QuantizationBounds,
+}
+
+impl CompactMesh {
+    /// Compresses the vertices, quantizing the positions to their bounding box.
+    pub fn from_vertices(vertices: &[MeshVertex]) -> Self {
+        let bounds = QuantizationBounds::from_positions(vertices.iter().map(|v| v.position))
+            .unwrap_or_else(|| QuantizationBounds::new(Vector3::zero(), Vector3::zero()));
+        let vertices = vertices
+            .iter()
+            .map(|vertex| {
+                CompactVertex::new(
+                    bounds.quantize(vertex.position),
+                    encode_octahedral(vertex.normal),
+                    [encode_half(vertex.tex_coords.x), encode_half(vertex.tex_coords.y)],
+                    encode_tangent(vertex.tangent),
+                )
+            })
+            .collect();
+        Self {
+            vertices,
+            bounds,
+        }
+    }
+
+    /// Decodes the vertices, e.g. to compare them with the original mesh.
+    pub fn to_vertices(&self) -> Vec<MeshVertex> {
+        self.vertices
+            .iter()
+            .map(|vertex| MeshVertex {
+                position: self.bounds.dequantize(vertex.position),
+                normal: decode_octahedral(vertex.normal),
+                tangent: decode_tangent(vertex.tangent),
+                tex_coords: Vector2::new(
+                    decode_half(vertex.tex_coords[0]),
+                    decode_half(vertex.tex_coords[1]),
+                ),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Point3, Transform};
+
+    /// Directions spread over the whole sphere, including the axes and the
+    /// diagonals, where the octahedral folding happens.
+    fn directions() -> Vec<Vector3<f32>> {
+        let mut directions = vec![
+            Vector3::unit_x(),
+            -Vector3::unit_x(),
+            Vector3::unit_y(),
+            -Vector3::unit_y(),
+            Vector3::unit_z(),
+            -Vector3::unit_z(),
+            Vector3::new(1.0, 1.0, -1.0).normalize(),
+            Vector3::new(-1.0, -1.0, -1.0).normalize(),
+        ];
+        for i in 0..32 {
+            for j in 0..64 {
+                let theta = std::f32::consts::PI * (i as f32 + 0.5) / 32.0;
+                let phi = 2.0 * std::f32::consts::PI * j as f32 / 64.0;
+                directions.push(Vector3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                ));
+            }
+        }
+        directions
+    }
+
+    #[test]
+    fn test_half_round_trip() {
+        for value in [0.0, -0.0, 1.0, -2.5, 0.1, 12.345, 1000.0, 65504.0, -0.333, 1e-3] {
+            let decoded = decode_half(encode_half(value));
+            // 11 significant bits give a relative error of at most 2^-11
+            assert!(
+                (decoded - value).abs() <= value.abs() * 2f32.powi(-11),
+                "{} decoded as {}",
+                value,
+                decoded
+            );
+        }
+
+        // Every finite half float survives the way back exactly
+        for half in 0..=u16::MAX {
+            let value = decode_half(half);
+            if value.is_finite() {
+                assert_eq!(encode_half(value), half, "half {:#06x}", half);
+            }
+        }
+    }
+
+    #[test]
+    fn test_half_special_values() {
+        assert_eq!(encode_half(1.0), 0x3c00);
+        assert_eq!(encode_half(-2.0), 0xc000);
+        assert_eq!(encode_half(65520.0), 0x7c00);
+        assert_eq!(encode_half(f32::NEG_INFINITY), 0xfc00);
+        assert!(decode_half(encode_half(f32::NAN)).is_nan());
+        assert_eq!(encode_half(1e-9), 0);
+        // Smallest subnormal
+        assert_eq!(encode_half(2f32.powi(-24)), 0x0001);
+        assert_eq!(decode_half(0x0001), 2f32.powi(-24));
+        // Ties round to even
+        assert_eq!(encode_half(1.0 + 2f32.powi(-11)), 0x3c00);
+        assert_eq!(encode_half(1.0 + 3.0 * 2f32.powi(-11)), 0x3c02);
+    }
+
+    #[test]
+    fn test_octahedral_round_trip() {
+        let max_error = 0.005f32.to_radians();
+        for direction in directions() {
+            let decoded = decode_octahedral(encode_octahedral(direction));
+            let angle = direction.cross(decoded).magnitude().atan2(direction.dot(decoded));
+            assert!(angle <= max_error, "{:?} decoded as {:?}", direction, decoded);
+            assert!((decoded.magnitude() - 1.0).abs() < 1e-6);
+        }
+
+        // The input doesn't have to be normalized
+        let decoded = decode_octahedral(encode_octahedral(Vector3::new(0.0, 0.0, -5.0)));
+        assert!((decoded - -Vector3::unit_z()).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn test_tangent_round_trip() {
+        for direction in directions() {
+            for handedness in [-1.0, 1.0] {
+                let tangent = direction.extend(handedness);
+                let decoded = decode_tangent(encode_tangent(tangent));
+                for axis in 0..3 {
+                    assert!(
+                        (decoded[axis] - tangent[axis]).abs() <= 1.0 / 1022.0 + 1e-6,
+                        "{:?} decoded as {:?}",
+                        tangent,
+                        decoded
+                    );
+                }
+                assert_eq!(decoded.w, handedness);
+            }
+        }
+
+        assert_eq!(
+            decode_tangent(encode_tangent(Vector4::new(-1.0, 1.0, 0.0, 0.0))),
+            Vector4::new(-1.0, 1.0, 0.0, 0.0)
+        );
+        // The clamped smallest value decodes to -1 as well
+        assert_eq!(decode_tangent(0x200).x, -1.0);
+    }
+
+    #[test]
+    fn test_tangent_handedness_with_gl_3_3_decoding() {
+        // The signed normalized conversion of OpenGL 3.3, (2c + 1) / (2^b - 1)
+        let decode_w = |packed: u32| (2.0 * ((packed as i32) >> 30) as f32 + 1.0) / 3.0;
+
+        let direction = Vector3::unit_x();
+        assert_eq!(decode_w(encode_tangent(direction.extend(-1.0))), -1.0);
+        assert_eq!(decode_w(encode_tangent(direction.extend(1.0))), 1.0);
+        assert_eq!(decode_w(encode_tangent(direction.extend(-0.2))), -1.0);
+        // Only the sign of a W of 0 survives
+        assert!(decode_w(encode_tangent(direction.extend(0.0))) > 0.0);
+    }
+
+    #[test]
+    fn test_quantized_positions() {
+        let positions = vec![
+            Vector3::new(-3.0, 0.5, 10.0),
+            Vector3::new(7.0, 0.5, -2.0),
+            Vector3::new(1.234, 0.5, 4.567),
+            Vector3::new(0.001, 0.5, 9.999),
+        ];
+        let bounds = QuantizationBounds::from_positions(positions.iter().copied()).unwrap();
+        assert_eq!(bounds.min, Vector3::new(-3.0, 0.5, -2.0));
+        assert_eq!(bounds.max, Vector3::new(7.0, 0.5, 10.0));
+
+        let max_error = bounds.max_error() + Vector3::new(1e-5, 1e-5, 1e-5);
+        let matrix = bounds.dequantize_matrix();
+        for position in positions {
+            let quantized = bounds.quantize(position);
+            let decoded = bounds.dequantize(quantized);
+            let error = decoded - position;
+            assert!(error.x.abs() <= max_error.x && error.z.abs() <= max_error.z);
+            // The flat axis is exact
+            assert_eq!(decoded.y, 0.5);
+
+            // The shader path gives the same result
+            let normalized = Point3::new(
+                quantized[0] as f32 / u16::MAX as f32,
+                quantized[1] as f32 / u16::MAX as f32,
+                quantized[2] as f32 / u16::MAX as f32,
+            );
+            let transformed = matrix.transform_point(normalized);
+            assert!(
+                (transformed - Point3::new(decoded.x, decoded.y, decoded.z)).magnitude() < 1e-5
+            );
+        }
+
+        assert_eq!(bounds.quantize(Vector3::new(100.0, 0.5, -100.0)), [u16::MAX, 0, 0]);
+        assert!(QuantizationBounds::from_positions(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn test_compact_mesh() {
+        let vertices: Vec<MeshVertex> = directions()
+            .into_iter()
+            .enumerate()
+            .map(|(index, normal)| MeshVertex {
+                position: normal * (index % 7) as f32,
+                normal,
+                tangent: normal.cross(Vector3::unit_z()).extend(1.0),
+                tex_coords: Vector2::new(index as f32 / 100.0, 1.0 - index as f32 / 5000.0),
+            })
+            .collect();
+
+        let mesh = CompactMesh::from_vertices(&vertices);
+        let max_error = mesh.bounds.max_error().magnitude() + 1e-5;
+        for (decoded, original) in mesh.to_vertices().iter().zip(&vertices) {
+            assert!((decoded.position - original.position).magnitude() <= max_error);
+            assert!(decoded.normal.dot(original.normal) > 0.99999);
+            assert!((decoded.tangent - original.tangent).magnitude() <= 0.002);
+            assert!(
+                (decoded.tex_coords.x - original.tex_coords.x).abs()
+                    <= original.tex_coords.x * 2f32.powi(-11)
+            );
+            assert!((decoded.tex_coords.y - original.tex_coords.y).abs() <= 2f32.powi(-11));
+        }
+        assert_eq!(mesh.to_vertices().len(), vertices.len());
+    }
+
+    #[test]
+    fn test_compact_vertex_layout() {
+        assert_eq!(size_of::<CompactVertex>(), 20);
+        // The attributes take 18 bytes, the stride adds the padding before `tangent`
+        assert_eq!(CompactVertex::layout_size(), 18);
+        assert_eq!(CompactVertex::attributes()[0].stride as usize, size_of::<CompactVertex>());
+
+        let attributes = CompactVertex::attributes();
+        let offsets: Vec<_> = attributes.iter().map(|a| a.offset.unwrap()).collect();
+        assert_eq!(offsets, vec![0, 6, 10, 16]);
+        assert!(attributes.iter().all(|a| a.stride == 20));
+        assert_eq!(attributes[3].column_size(), 4);
+    }
+}
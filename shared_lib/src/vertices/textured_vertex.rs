@@ -1,7 +1,5 @@
 use crate::color::Color;
-use crate::gl_prelude::VertexAttributeType;
 use crate::opengl::vertex_array_object::VertexArrayObject;
-use crate::opengl::vertex_attribute::VertexAttribute;
 use crate::opengl::vertex_layout::VertexLayout;
 use crate::vertices::{VertexColor, VertexTexCoords};
 
@@ -10,7 +8,7 @@ use crate::vertices::{VertexColor, VertexTexCoords};
 //////////////////////////////////////////////////////////////////////////////
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, VertexLayout)]
 pub struct TexturedVertex {
     pub position: [f32; 3],   // XYZ coordinates
     pub tex_coords: [f32; 2], // UV texture coordinates
@@ -173,13 +171,3 @@ impl VertexColor for TexturedVertex {
         self
     }
 }
-
-impl VertexLayout for TexturedVertex {
-    fn attributes() -> Vec<VertexAttribute> {
-        vec![
-            VertexAttributeType::Position.into(),
-            VertexAttributeType::TexCoords.into(),
-            VertexAttributeType::Color.into(),
-        ]
-    }
-}
@@ -0,0 +1,132 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use anyhow::{Context, Result};
+use image::RgbaImage;
+
+use shared_lib::opengl::golden_image::capture_framebuffer;
+use shared_lib::sdl_window::{SdlWindow, SdlWindowOptions};
+
+use crate::render_context::RenderContext;
+use crate::resources::{shaders, textures};
+use crate::scene::Scene;
+use crate::{CLEAR_COLOR, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// Size of headless renderings, a quarter of the window size in each direction so
+/// the aspect ratio used by the scenes still matches.
+pub(crate) const HEADLESS_WIDTH: usize = SCREEN_WIDTH / 4;
+pub(crate) const HEADLESS_HEIGHT: usize = SCREEN_HEIGHT / 4;
+
+/// Delta time of every headless frame in seconds.
+pub(crate) const FIXED_TIME_STEP: f32 = 1.0 / 60.0;
+
+/// Creates a hidden window without display, see `SdlWindowOptions::headless`.
+///
+/// SDL can only be used from one thread at a time, so all headless renderings of a
+/// process should share this window.
+pub(crate) fn create_headless_window() -> Result<Rc<RefCell<SdlWindow>>> {
+    let options = SdlWindowOptions::default()
+        .depth_bits(24)
+        .stencil_bits(8)
+        .headless(true);
+    let mut window = SdlWindow::with_options(HEADLESS_WIDTH, HEADLESS_HEIGHT, "Headless", options)
+        .context("Failed to create a headless OpenGL context")?;
+    window.clear_color = CLEAR_COLOR;
+    Ok(Rc::new(RefCell::new(window)))
+}
+
+/// Runs a scene for `frames` frames with a fixed time step, like the main loop does,
+/// and returns the image of the last frame.
+///
+/// Every scene gets its own render context, so shaders and textures are loaded
+/// again and no state is carried over from previous scenes. Missing assets are
+/// errors instead of being replaced by fallbacks.
+pub(crate) fn render_scene(
+    window: &Rc<RefCell<SdlWindow>>,
+    scene: &mut dyn Scene<RenderContext>,
+    frames: usize,
+) -> Result<RgbaImage> {
+    let mut context = RenderContext::new(Rc::clone(window));
+    context.set_fixed_delta_time(Some(FIXED_TIME_STEP));
+    context.set_graceful_mode(false);
+    textures::add_textures(context.texture_manager());
+    shaders::add_shaders(context.shader_manager());
+
+    scene.activate(&mut context)?;
+    let mut image = None;
+    for frame in 0..frames.max(1) {
+        context.update(&window.borrow());
+        window.borrow().clear();
+
+        scene.update_tick(&mut context, FIXED_TIME_STEP, true)?;
+        scene.update(&mut context)?;
+        context.begin_post_processing()?;
+        scene.draw(&mut context)?;
+        context.end_post_processing()?;
+
+        // The back buffer is read before swapping, its contents are undefined after
+        if frame + 1 == frames.max(1) {
+            let (width, height) = window.borrow().get_drawable_size();
+            image = Some(capture_framebuffer(width, height)?);
+        }
+        window.borrow().swap();
+    }
+    scene.deactivate(&mut context, true)?;
+
+    image.context("No frame was rendered")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenes::tutorial_scenes;
+    use shared_lib::opengl::golden_image::{check_golden_image, ImageTolerance};
+    use std::path::Path;
+
+    /// Number of frames rendered before the image is compared, which lets animated
+    /// scenes move away from their initial state.
+    const GOLDEN_IMAGE_FRAMES: usize = 30;
+
+    /// Environment variable which makes the golden image test fail instead of being
+    /// skipped if no headless OpenGL context can be created.
+    const REQUIRE_GOLDEN_IMAGES_VAR: &str = "REQUIRE_GOLDEN_IMAGES";
+
+    /// Renders every tutorial scene and compares it with `tests/golden/<scene>.png`.
+    ///
+    /// Needs an OpenGL 3.3 driver with EGL for SDL's offscreen video driver, e.g.
+    /// Mesa's llvmpipe on GPU-less Linux machines:
+    /// `LIBGL_ALWAYS_SOFTWARE=1 cargo test test_scenes_match_golden_images`.
+    /// Without such a driver the test is skipped, unless `REQUIRE_GOLDEN_IMAGES` is
+    /// set. Add `UPDATE_GOLDEN_IMAGES=1` to write new golden images after intended
+    /// changes. The golden images were rendered by llvmpipe.
+    #[test]
+    fn test_scenes_match_golden_images() {
+        let window = match create_headless_window() {
+            Ok(window) => window,
+            Err(err) if std::env::var_os(REQUIRE_GOLDEN_IMAGES_VAR).is_none() => {
+                eprintln!("Skipping the golden image test: {:#}", err);
+                return;
+            }
+            Err(err) => panic!("{:#}", err),
+        };
+        let golden_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+
+        // All scenes are checked, so one run reports all broken scenes
+        let mut failures = Vec::new();
+        for (name, mut scene) in tutorial_scenes() {
+            let result =
+                render_scene(&window, scene.as_mut(), GOLDEN_IMAGE_FRAMES).and_then(|image| {
+                    let path = golden_dir.join(format!("{}.png", name));
+                    check_golden_image(path, &image, &ImageTolerance::default())
+                });
+            if let Err(err) = result {
+                failures.push(format!("{}: {:#}", name, err));
+            }
+        }
+        assert!(
+            failures.is_empty(),
+            "Scenes don't match:\n{}",
+            failures.join("\n")
+        );
+    }
+}
@@ -8,20 +8,16 @@ use anyhow::Result;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 
-use scenes::_01_01_first_triangle::FirstTriangle;
-use scenes::_01_02_indexed_quad::IndexedQuad;
-use scenes::_01_03_shader_triangle::ShaderTriangle;
-use scenes::_01_04_texture_triangle::TextureTriangle;
-use scenes::_01_05_transformation::Transformation;
-use scenes::_01_06_projection::Projection;
 use shared_lib::color::Color;
 use shared_lib::gl_prelude::gl_get_version;
-use shared_lib::sdl_window::SdlWindow;
+use shared_lib::opengl::debug_output::DebugGroup;
+use shared_lib::sdl_window::{SdlWindow, SdlWindowOptions};
 
 use crate::render_context::RenderContext;
 use crate::resources::{shaders, textures};
 use crate::scene::Scene;
 
+mod headless;
 mod render_context;
 mod resources;
 mod scene;
@@ -36,34 +32,37 @@ mod vertex_data_3d;
 const WINDOW_TITLE: &str = "RUST OPENGL 2024";
 pub(crate) const SCREEN_WIDTH: usize = 1440;
 pub(crate) const SCREEN_HEIGHT: usize = 1080;
+pub(crate) const CLEAR_COLOR: Color = Color {
+    r: 0.10,
+    g: 0.10,
+    b: 0.25,
+    a: 1.0,
+};
 
 pub(crate) const SHADER_SIMPLE_RED: &str = "shader_simple_red";
 
 fn main() -> Result<()> {
-    let window = Rc::new(RefCell::new(SdlWindow::new(
+    let window_options = SdlWindowOptions::default()
+        .depth_bits(24)
+        .stencil_bits(8)
+        .debug_context(cfg!(debug_assertions));
+    let window = Rc::new(RefCell::new(SdlWindow::with_options(
         SCREEN_WIDTH,
         SCREEN_HEIGHT,
         WINDOW_TITLE,
-        true,
+        window_options,
     )?));
-    window.borrow_mut().clear_color = Color::new(0.10, 0.10, 0.25, 1.0);
+    window.borrow_mut().clear_color = CLEAR_COLOR;
 
     let opengl_version = gl_get_version();
     println!("OpenGL version: {}", opengl_version);
 
     // Create scenes
-    let mut scenes: Vec<Box<dyn Scene<RenderContext>>> = vec![
-        Box::<FirstTriangle>::default(),
-        Box::<IndexedQuad>::default(),
-        Box::<ShaderTriangle>::new(ShaderTriangle::new(false)),
-        Box::<ShaderTriangle>::new(ShaderTriangle::new(true)),
-        Box::<TextureTriangle>::default(),
-        Box::<Transformation>::default(),
-        Box::<Projection>::default(),
-        // Box::<TextRendering>::new(TextRendering::new()?),
-        // //Box::<DrawRectangle>::default(),
-        // //Box::<LightCube>::default(),
-    ];
+    let mut scenes: Vec<Box<dyn Scene<RenderContext>>> =
+        scenes::tutorial_scenes().into_iter().map(|(_, scene)| scene).collect();
+    // scenes.push(Box::<TextRendering>::new(TextRendering::new()?));
+    // //scenes.push(Box::<DrawRectangle>::default());
+    // //scenes.push(Box::<LightCube>::default());
 
     // Set the initial drawable to the last one
     let mut current_index = scenes.len().saturating_sub(1);
@@ -74,6 +73,7 @@ fn main() -> Result<()> {
 
     // Create the render context object
     let mut render_context = RenderContext::new(Rc::clone(&window));
+    render_context.set_graceful_mode(true);
     textures::add_textures(render_context.texture_manager());
     shaders::add_shaders(render_context.shader_manager());
 
@@ -118,7 +118,8 @@ fn main() -> Result<()> {
                     scene.deactivate(&mut render_context, false)?;
                 }
             }
-            // Activate new scene
+            // Activate new scene, which enables post-processing again if it uses it
+            render_context.disable_post_processing();
             if let Some(scene) = scenes.get_mut(current_index) {
                 scene.activate(&mut render_context)?;
             }
@@ -148,8 +149,16 @@ fn main() -> Result<()> {
 
         // Render active scene
         if let Some(scene) = scenes.get_mut(current_index) {
-            // Render scene
+            // Render scene inside a debug group, which names it in graphics debuggers
+            let _debug_group = DebugGroup::push(&format!("Scene {}", current_index + 1));
+            let gpu_scope = render_context.gpu_timer().begin_scope("scene draw")?;
+            render_context.begin_post_processing()?;
             scene.draw(&mut render_context)?;
+            render_context.gpu_timer().end_scope(gpu_scope)?;
+
+            let gpu_scope = render_context.gpu_timer().begin_scope("post process")?;
+            render_context.end_post_processing()?;
+            render_context.gpu_timer().end_scope(gpu_scope)?;
         }
 
         // Swap display buffers
@@ -157,12 +166,15 @@ fn main() -> Result<()> {
 
         // Update window title with scene number and fps tracking
         let window_title = if show_fps {
+            let gpu_times: Vec<String> =
+                render_context.gpu_timings().iter().map(|timing| timing.to_string()).collect();
             format!(
-                "{} [{}/{}] (FPS: {})",
+                "{} [{}/{}] (FPS: {}, GPU {})",
                 WINDOW_TITLE,
                 current_index + 1,
                 scenes.len(),
-                render_context.frame_rate()
+                render_context.frame_rate(),
+                gpu_times.join(", ")
             )
         } else {
             format!("{} [{}/{}]", WINDOW_TITLE, current_index + 1, scenes.len())
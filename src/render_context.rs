@@ -2,6 +2,11 @@ use std::cell::{Ref, RefCell, RefMut};
 use std::rc::Rc;
 use std::time::{Duration, Instant};
 
+use anyhow::Result;
+
+use shared_lib::opengl::gpu_timer::{GpuTimer, GpuTiming};
+use shared_lib::opengl::pipeline_state::StateCache;
+use shared_lib::opengl::post_process::PostProcessChain;
 use shared_lib::opengl::shader_manager::ShaderManager;
 use shared_lib::opengl::texture_manager::TextureManager;
 use shared_lib::prelude::SdlWindow;
@@ -10,9 +15,16 @@ use shared_lib::sdl_window::SdlKeyboardState;
 pub(crate) struct RenderContext {
     window: Rc<RefCell<SdlWindow>>,
     delta_time: f32,
+    /// Replaces the measured delta time, see `set_fixed_delta_time`.
+    fixed_delta_time: Option<f32>,
+    elapsed_time: f64,
     frame_rate: u32,
     shader_manager: ShaderManager,
     texture_manager: TextureManager,
+    state_cache: StateCache,
+    gpu_timer: GpuTimer,
+    /// Set while the active scene uses post-processing.
+    post_process: Option<PostProcessChain>,
     keyboard_state: SdlKeyboardState,
 
     last_update_time: Instant,
@@ -26,9 +38,14 @@ impl RenderContext {
         Self {
             window,
             delta_time: 0.0,
+            fixed_delta_time: None,
+            elapsed_time: 0.0,
             frame_rate: 0,
             shader_manager: ShaderManager::default(),
             texture_manager: TextureManager::default(),
+            state_cache: StateCache::default(),
+            gpu_timer: GpuTimer::default(),
+            post_process: None,
             last_update_time: time_now,
             last_fps_time: time_now,
             frame_count: 0,
@@ -36,10 +53,16 @@ impl RenderContext {
         }
     }
 
+    /// Advances the frame timing and polls the keyboard. Headless windows keep an
+    /// empty keyboard state, as polling it aborts in debug builds of sdl2 0.36,
+    /// which transmutes the invalid scancodes reported without a display.
     pub(crate) fn update(&mut self, window: &SdlWindow) {
         self.update_delta_time();
         self.update_frame_rate();
-        self.keyboard_state.update(window);
+        self.gpu_timer.begin_frame();
+        if !window.is_headless() {
+            self.keyboard_state.update(window);
+        }
     }
 
     /// Calculates and updates the delta time in seconds since the last update,
@@ -47,7 +70,8 @@ impl RenderContext {
         let now = Instant::now();
         let delta = now.duration_since(self.last_update_time);
         self.last_update_time = Instant::now();
-        self.delta_time = delta.as_secs_f32();
+        self.delta_time = self.fixed_delta_time.unwrap_or(delta.as_secs_f32());
+        self.elapsed_time += self.delta_time as f64;
     }
 
     /// Calculates and updates the frame rate every second.
@@ -65,10 +89,29 @@ impl RenderContext {
         self.delta_time
     }
 
+    /// Uses a fixed time step as delta time instead of the measured frame time,
+    /// which makes animations reproducible, e.g. for golden image tests.
+    pub(crate) fn set_fixed_delta_time(&mut self, fixed_delta_time: Option<f32>) {
+        self.fixed_delta_time = fixed_delta_time;
+    }
+
+    /// Returns the sum of all delta times in seconds. Scenes should animate with
+    /// this instead of the wall clock, so a fixed delta time applies to them.
+    pub(crate) fn elapsed_time(&self) -> f64 {
+        self.elapsed_time
+    }
+
     pub(crate) fn frame_rate(&self) -> u32 {
         self.frame_rate
     }
 
+    /// Enables the graceful mode of the shader and texture manager, so that
+    /// missing or broken assets are replaced by built-in fallbacks.
+    pub(crate) fn set_graceful_mode(&mut self, enabled: bool) {
+        self.shader_manager.set_graceful_mode(enabled);
+        self.texture_manager.set_graceful_mode(enabled);
+    }
+
     pub(crate) fn shader_manager(&mut self) -> &mut ShaderManager {
         &mut self.shader_manager
     }
@@ -77,6 +120,83 @@ impl RenderContext {
         &mut self.texture_manager
     }
 
+    /// Returns the cache used to apply `PipelineState`s, which skips the GL calls for
+    /// state that is already set.
+    pub(crate) fn state_cache(&mut self) -> &mut StateCache {
+        &mut self.state_cache
+    }
+
+    /// Returns the timer measuring the GPU time of named scopes, e.g.
+    /// `"scene draw"`. Its frames start with `update`.
+    pub(crate) fn gpu_timer(&mut self) -> &mut GpuTimer {
+        &mut self.gpu_timer
+    }
+
+    /// Returns the GPU times of all measured scopes, averaged over the last frames.
+    pub(crate) fn gpu_timings(&self) -> Vec<GpuTiming> {
+        self.gpu_timer.timings()
+    }
+
+    /// Lets the active scene render through a `PostProcessChain` with all standard
+    /// passes, which start disabled. The chain is created on the first call and
+    /// kept until `disable_post_processing`, which happens when scenes are switched.
+    /// An HDR chain is replaced by an 8-bit one.
+    pub(crate) fn enable_post_processing(&mut self) -> Result<&mut PostProcessChain> {
+        self.enable_chain(false)
+    }
+
+    /// Like `enable_post_processing`, but the scene is rendered with floating point
+    /// colors and tone mapped, see `PostProcessChain::with_hdr_passes`.
+    pub(crate) fn enable_hdr_post_processing(&mut self) -> Result<&mut PostProcessChain> {
+        self.enable_chain(true)
+    }
+
+    fn enable_chain(&mut self, hdr: bool) -> Result<&mut PostProcessChain> {
+        let matches = self
+            .post_process
+            .as_ref()
+            .is_some_and(|chain| chain.is_hdr() == hdr);
+        if !matches {
+            let (width, height) = self.get_drawable_size();
+            let chain = if hdr {
+                PostProcessChain::with_hdr_passes(width, height)?
+            } else {
+                PostProcessChain::with_standard_passes(width, height)?
+            };
+            self.post_process = Some(chain);
+        }
+        Ok(self
+            .post_process
+            .as_mut()
+            .expect("post processing was just enabled"))
+    }
+
+    pub(crate) fn disable_post_processing(&mut self) {
+        self.post_process = None;
+    }
+
+    /// Returns the post process chain, if the active scene enabled it.
+    pub(crate) fn post_process(&mut self) -> Option<&mut PostProcessChain> {
+        self.post_process.as_mut()
+    }
+
+    /// Redirects the drawing of the scene to the post process chain, if enabled.
+    pub(crate) fn begin_post_processing(&mut self) -> Result<()> {
+        match &mut self.post_process {
+            Some(chain) => chain.begin_frame(&self.window.borrow()),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs the enabled post process passes and copies the result to the window.
+    pub(crate) fn end_post_processing(&mut self) -> Result<()> {
+        let time = self.elapsed_time as f32;
+        match &mut self.post_process {
+            Some(chain) => chain.end_frame(&self.window.borrow(), time),
+            None => Ok(()),
+        }
+    }
+
     pub(crate) fn keyboard_state(&self) -> &SdlKeyboardState {
         &self.keyboard_state
     }
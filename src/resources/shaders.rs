@@ -6,6 +6,7 @@ pub const SIMPLE_TRIANGLE: &str = "simple_triangle_shader";
 pub const SIMPLE_TEXTURED_TRIANGLE: &str = "simple_textured_triangle_shader";
 pub const SIMPLE_TRANSFORM: &str = "simple_transform_shader";
 pub const SIMPLE_PROJECTION: &str = "simple_projection_shader";
+pub const SIMPLE_PROJECTION_INSTANCED: &str = "simple_projection_instanced_shader";
 pub const LIGHT_CUBE: &str = "light_cube_shader";
 
 pub(crate) fn add_shaders(shader_manager: &mut ShaderManager) {
@@ -30,6 +31,10 @@ pub(crate) fn add_shaders(shader_manager: &mut ShaderManager) {
         "assets/shaders/simple/projection.vert",
         "assets/shaders/simple/projection.frag",
     ]);
+    shader_map.insert(SIMPLE_PROJECTION_INSTANCED, vec![
+        "assets/shaders/simple/projection_instanced.vert",
+        "assets/shaders/simple/projection.frag",
+    ]);
     shader_map.insert(LIGHT_CUBE, vec![
         "assets/shaders/light/light_cube.vert",
         "assets/shaders/light/light_cube.frag",
@@ -7,3 +7,26 @@ pub mod basic;
 pub mod experimental;
 pub mod light;
 pub mod _01_06_projection;
+
+use crate::render_context::RenderContext;
+use crate::scene::Scene;
+use _01_01_first_triangle::FirstTriangle;
+use _01_02_indexed_quad::IndexedQuad;
+use _01_03_shader_triangle::ShaderTriangle;
+use _01_04_texture_triangle::TextureTriangle;
+use _01_05_transformation::Transformation;
+use _01_06_projection::Projection;
+
+/// Creates the scenes of the "Getting started" chapter in their order, each with a
+/// name which identifies it, e.g. in golden image tests.
+pub(crate) fn tutorial_scenes() -> Vec<(&'static str, Box<dyn Scene<RenderContext>>)> {
+    vec![
+        ("01_01_first_triangle", Box::<FirstTriangle>::default()),
+        ("01_02_indexed_quad", Box::<IndexedQuad>::default()),
+        ("01_03_shader_triangle", Box::new(ShaderTriangle::new(false))),
+        ("01_03_shader_triangle_uniform", Box::new(ShaderTriangle::new(true))),
+        ("01_04_texture_triangle", Box::<TextureTriangle>::default()),
+        ("01_05_transformation", Box::<Transformation>::default()),
+        ("01_06_projection", Box::<Projection>::default()),
+    ]
+}
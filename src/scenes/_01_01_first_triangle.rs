@@ -42,7 +42,7 @@ impl Scene<RenderContext> for FirstTriangle {
     fn draw(&mut self, context: &mut RenderContext) -> SceneResult {
         if let Some(vao) = self.vao.as_mut() {
             context.shader_manager().activate_shader(shaders::SIMPLE_RED);
-            vao.render(false, 3);
+            vao.render(false, 3)?;
         }
         Ok(())
     }
@@ -53,7 +53,7 @@ impl Scene<RenderContext> for IndexedQuad {
             context
                 .shader_manager()
                 .activate_shader(shaders::SIMPLE_RED);
-            vao.render(true, 6);
+            vao.render(true, 6)?;
         }
         Ok(())
     }
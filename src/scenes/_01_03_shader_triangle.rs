@@ -1,12 +1,8 @@
-use std::time::Instant;
-
 use gl::types::GLfloat;
 
-use shared_lib::gl_prelude::VertexDataType;
 use shared_lib::opengl::buffer_object::BufferObject;
 use shared_lib::opengl::vertex_array_object::VertexArrayObject;
-use shared_lib::opengl::vertex_attribute::VertexAttribute;
-use shared_lib::gl_prelude::{BufferType, BufferUsage, VertexAttributeType};
+use shared_lib::gl_prelude::{BufferType, BufferUsage};
 use shared_lib::opengl::vertex_layout::VertexLayout;
 use crate::render_context::RenderContext;
 use crate::resources::shaders;
@@ -16,7 +12,8 @@ pub struct ShaderTriangle {
     use_uniform: bool,
     vao: Option<VertexArrayObject>,
     vbo: Option<BufferObject<ColorPointVertex>>,
-    start_time: Option<Instant>,
+    /// Elapsed time of the render context when the scene was activated.
+    start_time: Option<f64>,
 }
 
 impl ShaderTriangle {
@@ -29,14 +26,14 @@ impl ShaderTriangle {
         }
     }
 
-    fn get_current_time_in_seconds(&self) -> f64 {
+    fn get_current_time_in_seconds(&self, context: &RenderContext) -> f64 {
         self.start_time
-            .map_or(0.0, |start_time| start_time.elapsed().as_secs_f64())
+            .map_or(0.0, |start_time| context.elapsed_time() - start_time)
     }
 }
 
 impl Scene<RenderContext> for ShaderTriangle {
-    fn activate(&mut self, _context: &mut RenderContext) -> SceneResult {
+    fn activate(&mut self, context: &mut RenderContext) -> SceneResult {
         if self.vao.is_none() {
             let vertices = vec![
                 ColorPointVertex {
@@ -58,7 +55,7 @@ impl Scene<RenderContext> for ShaderTriangle {
 
             self.vao = Some(vao);
             self.vbo = Some(vbo);
-            self.start_time = Some(Instant::now());
+            self.start_time = Some(context.elapsed_time());
         }
         Ok(())
     }
@@ -67,20 +64,20 @@ impl Scene<RenderContext> for ShaderTriangle {
         if let Some(vao) = self.vao.as_ref() {
             vao.bind();
 
+            let current_time = if self.use_uniform {
+                self.get_current_time_in_seconds(context)
+            } else {
+                -1.0
+            };
+
             if let Ok(shader) = context
                 .shader_manager()
                 .get_shader_mut(shaders::SIMPLE_TRIANGLE)
             {
-                let current_time = if self.use_uniform {
-                    self.get_current_time_in_seconds()
-                } else {
-                    -1.0
-                };
-
                 if let Ok(time_location) = shader.get_uniform_location("time") {
                     shader.activate();
                     shader.set_uniform_value(time_location, current_time as GLfloat)?;
-                    vao.render(false,  3);
+                    vao.render(false,  3)?;
                 }
             }
         }
@@ -88,16 +85,9 @@ impl Scene<RenderContext> for ShaderTriangle {
     }
 }
 
+#[repr(C)]
+#[derive(VertexLayout)]
 struct ColorPointVertex {
     pub position: [f32; 3],
     pub color: [f32; 3],
 }
-
-impl VertexLayout for ColorPointVertex {
-    fn attributes() -> Vec<VertexAttribute> {
-        vec![
-            VertexAttributeType::Position.into(),
-            VertexAttribute::new(3, VertexDataType::Float),
-        ]
-    }
-}
@@ -147,7 +147,7 @@ impl Scene<RenderContext> for TextureTriangle {
                 }
             }
 
-            vao.render(true, self.vertex_count);
+            vao.render(true, self.vertex_count)?;
         }
 
         Ok(())
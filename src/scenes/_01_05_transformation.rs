@@ -1,5 +1,4 @@
 use std::fmt::{Display, Formatter};
-use std::time::Instant;
 
 use cgmath::{vec3, Deg, Matrix4, Rad, SquareMatrix};
 use sdl2::keyboard::Keycode;
@@ -35,21 +34,26 @@ pub struct Transformation {
     rotation_speed: i32,
     rotation_paused: bool,
     scale_time: f32,
-    last_speed_change: Option<Instant>,
+    /// Elapsed time of the render context when the speed was last changed.
+    last_speed_change: Option<f64>,
 }
 
 impl Transformation {
-    fn process_keyboard_input(&mut self, keyboard_state: &SdlKeyboardState) -> SceneResult {
+    fn process_keyboard_input(
+        &mut self,
+        keyboard_state: &SdlKeyboardState,
+        elapsed_time: f64,
+    ) -> SceneResult {
         if keyboard_state.is_key_pressed(Keycode::F3) {
             self.render_mode = self.render_mode.next();
             println!("Render mode: {}", self.render_mode);
         }
-        if keyboard_state.is_key_down(Keycode::Plus) && self.can_change_speed() {
+        if keyboard_state.is_key_down(Keycode::Plus) && self.can_change_speed(elapsed_time) {
             self.rotation_speed += ROTATION_SPEED_CHANGE;
             self.rotation_speed = self.rotation_speed.min(MAX_ROTATION_SPEED);
             self.print_rotation_speed();
         }
-        if keyboard_state.is_key_down(Keycode::Minus) && self.can_change_speed() {
+        if keyboard_state.is_key_down(Keycode::Minus) && self.can_change_speed(elapsed_time) {
             self.rotation_speed -= ROTATION_SPEED_CHANGE;
             self.rotation_speed = self.rotation_speed.max(-MAX_ROTATION_SPEED);
             self.print_rotation_speed();
@@ -71,19 +75,18 @@ impl Transformation {
         println!("Rotation speed: {}", self.rotation_speed);
     }
 
-    fn can_change_speed(&mut self) -> bool {
+    fn can_change_speed(&mut self, elapsed_time: f64) -> bool {
         match self.last_speed_change {
             Some(last_change) => {
-                let current_time = Instant::now();
-                if (current_time - last_change).as_millis() > SPEED_CHANGE_DELAY_MS as u128 {
-                    self.last_speed_change = Some(current_time);
+                if (elapsed_time - last_change) * 1000.0 > SPEED_CHANGE_DELAY_MS as f64 {
+                    self.last_speed_change = Some(elapsed_time);
                     true
                 } else {
                     false
                 }
             }
             None => {
-                self.last_speed_change = Some(Instant::now());
+                self.last_speed_change = Some(elapsed_time);
                 true
             }
         }
@@ -123,7 +126,8 @@ impl Scene<RenderContext> for Transformation {
     }
 
     fn update(&mut self, context: &mut RenderContext) -> SceneResult {
-        self.process_keyboard_input(context.keyboard_state())
+        let elapsed_time = context.elapsed_time();
+        self.process_keyboard_input(context.keyboard_state(), elapsed_time)
     }
 
     fn update_tick(
@@ -188,7 +192,7 @@ impl Scene<RenderContext> for Transformation {
                 shader.set_uniform_matrix("transform", false, &transform)?;
 
                 // Render the scene
-                vao.render(true, ibo.data_len());
+                vao.render(true, ibo.data_len())?;
 
                 // Update transformation
                 if render_cycle == 0 {
@@ -1,9 +1,10 @@
 use std::fmt::{Display, Formatter};
-use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use cgmath::{perspective, vec3, Deg, InnerSpace, Matrix4, Point3, Rad, Vector3};
 use chrono::{Local, Timelike};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use sdl2::keyboard::Keycode;
 
 use crate::render_context::RenderContext;
@@ -15,11 +16,14 @@ use shared_lib::camera::moveable_camera::MoveableCamera;
 use shared_lib::camera::{Camera, CameraMovement};
 use shared_lib::color::Color;
 use shared_lib::gl_prelude::Bindable;
-use shared_lib::gl_types::Capability;
+use shared_lib::gl_types::{BufferType, BufferUsage, VertexDataType};
 use shared_lib::opengl::buffer_object::BufferObject;
+use shared_lib::opengl::pipeline_state::PipelineState;
+use shared_lib::opengl::post_process::tonemap::{OutputEncoding, Tonemap};
 use shared_lib::opengl::shader_program::ShaderProgram;
 use shared_lib::opengl::texture::Texture;
 use shared_lib::opengl::vertex_array_object::VertexArrayObject;
+use shared_lib::opengl::vertex_attribute::VertexAttribute;
 use shared_lib::opengl::vertex_layout::VertexLayout;
 use shared_lib::sdl_window::SdlKeyboardState;
 use shared_lib::shapes::rectangle::Rectangle;
@@ -31,6 +35,10 @@ const MAX_MODEL_DISTANCE: f32 = -16.0;
 const MIN_MODEL_DISTANCE: f32 = -1.0;
 const MODEL_DISTANCE_SPEED: f32 = 0.05;
 const RADIUS: f32 = 10.0;
+/// Seconds between the changes of the cube rotation speeds.
+const ROTATION_UPDATE_INTERVAL: f64 = 3.0;
+/// Seed of the rotation speeds, so that the cubes rotate the same way on every run.
+const ROTATION_SEED: u64 = 0x01_06;
 
 #[derive(Default)]
 pub(crate) struct Projection {
@@ -42,26 +50,30 @@ pub(crate) struct Projection {
     model_distance: f32,
     model_strafe: f32,
     render_mode: RenderMode,
+    depth_test: bool,
     cube_positions: Vec<[f32; 3]>,
     cube_rotations: Vec<CubeRotation>,
-    last_update: Option<Instant>,
+    rotation_rng: RotationRng,
+    /// Elapsed time of the render context when the rotations were last updated.
+    last_update: Option<f64>,
     first_only: bool,
     camera_mode: CameraMode,
     camera_speed: f32,
-    start_time: Option<Instant>,
+    /// Elapsed time of the render context when the scene was activated.
+    start_time: Option<f64>,
     camera: MoveableCamera,
     rotation_paused: bool,
     mouse_capture: bool,
     rectangle: Option<Rectangle>,
     synchronized_rotation: bool,
     synchronized_rotation_prev: bool,
+    hdr: bool,
 }
 
 impl<'a> Projection {
-    fn check_rotation_update_required(&mut self) -> bool {
-        let now = Instant::now();
+    fn check_rotation_update_required(&mut self, now: f64) -> bool {
         match self.last_update {
-            Some(last_update) if now.duration_since(last_update) < Duration::from_secs(3) => false,
+            Some(last_update) if now - last_update < ROTATION_UPDATE_INTERVAL => false,
             _ => {
                 self.last_update = Some(now);
                 true
@@ -69,11 +81,11 @@ impl<'a> Projection {
         }
     }
 
-    fn update_rotations(&mut self, delta_time: f32) {
-        let now = Instant::now();
-        if self.check_rotation_update_required() {
+    fn update_rotations(&mut self, delta_time: f32, now: f64) {
+        if self.check_rotation_update_required(now) {
+            let rng = &mut self.rotation_rng.0;
             if self.synchronized_rotation {
-                let rotation_speed = CubeRotation::random_speed();
+                let rotation_speed = CubeRotation::random_speed(rng);
                 for rotation in &mut self.cube_rotations {
                     if !self.synchronized_rotation_prev {
                         rotation.reset_angle();
@@ -82,11 +94,10 @@ impl<'a> Projection {
                 }
             } else {
                 for rotation in &mut self.cube_rotations {
-                    rotation.update();
+                    rotation.update(rng);
                 }
             }
 
-            self.last_update = Some(now);
             self.synchronized_rotation_prev = self.synchronized_rotation;
 
             let time_now = Local::now();
@@ -116,6 +127,34 @@ impl<'a> Projection {
             .get_shader_mut(shaders::SIMPLE_PROJECTION)
     }
 
+    fn get_instanced_shader_mut(context: &'a mut RenderContext) -> Result<&'a mut ShaderProgram> {
+        context
+            .shader_manager()
+            .get_shader_mut(shaders::SIMPLE_PROJECTION_INSTANCED)
+    }
+
+    /// Calculates the model matrix of the cube with the given index.
+    fn cube_model(&self, index: usize) -> Matrix4<f32> {
+        let pos = self.cube_positions[index];
+        let pos_vector3 = Vector3::new(pos[0], pos[1], pos[2]);
+        let translation = Matrix4::from_translation(pos_vector3);
+        let rotation = if self.render_mode != RenderMode::MultipleCubesRotating {
+            let angle = Rad::from(Deg(20.0 * index as f32));
+            let axis = Vector3::new(1.0, 0.3, 0.5).normalize();
+            Matrix4::from_axis_angle(axis, angle)
+        } else {
+            let cube_rotation = &self.cube_rotations[index];
+            let rotation_x = Matrix4::from_angle_x(Deg(cube_rotation.angle.x));
+            let rotation_y = Matrix4::from_angle_y(Deg(cube_rotation.angle.y));
+            let rotation_z = Matrix4::from_angle_z(Deg(cube_rotation.angle.z));
+
+            // Combine rotations: Note the order of multiplication matters
+            rotation_x * rotation_y * rotation_z
+        };
+
+        translation * rotation
+    }
+
     fn activate_shader(context: &'a mut RenderContext) {
         context
             .shader_manager()
@@ -183,6 +222,97 @@ impl<'a> Projection {
         Ok(())
     }
 
+    /// Toggles the post process passes with the number keys, in the order of the
+    /// chain: 1 bloom, 2 FXAA, 3 chromatic aberration, 4 color filter, 5 vignette
+    /// and 6 film grain. H switches to HDR rendering, where 2 is the tonemap pass
+    /// and the others move one key up. With HDR, T cycles the tone mapping operator,
+    /// E toggles eye adaptation and Page Up and Page Down change the exposure.
+    fn process_post_process_keys(&mut self, context: &mut RenderContext) -> SceneResult {
+        if context.keyboard_state().is_key_pressed(Keycode::H) {
+            self.hdr = !self.hdr;
+            self.enable_post_processing(context)?;
+            println!(
+                "HDR rendering: {}",
+                if self.hdr { "activated" } else { "deactivated" }
+            );
+        }
+
+        let keys = [
+            Keycode::Num1,
+            Keycode::Num2,
+            Keycode::Num3,
+            Keycode::Num4,
+            Keycode::Num5,
+            Keycode::Num6,
+            Keycode::Num7,
+        ];
+        let pressed: Vec<usize> = keys
+            .iter()
+            .enumerate()
+            .filter(|(_, key)| context.keyboard_state().is_key_pressed(**key))
+            .map(|(index, _)| index)
+            .collect();
+        let keyboard_state = context.keyboard_state();
+        let next_operator = keyboard_state.is_key_pressed(Keycode::T);
+        let toggle_adaptation = keyboard_state.is_key_pressed(Keycode::E);
+        let exposure_step = if keyboard_state.is_key_pressed(Keycode::PageUp) {
+            0.5
+        } else if keyboard_state.is_key_pressed(Keycode::PageDown) {
+            -0.5
+        } else {
+            0.0
+        };
+
+        if let Some(chain) = context.post_process() {
+            let names = chain.pass_names();
+            for name in pressed.iter().filter_map(|&index| names.get(index)) {
+                let enabled = chain.toggle(name)?;
+                println!(
+                    "Post process pass {} {}",
+                    name,
+                    if enabled { "activated" } else { "deactivated" }
+                );
+            }
+            if let Some(tonemap) = chain.pass_mut::<Tonemap>() {
+                if next_operator {
+                    tonemap.operator = tonemap.operator.next();
+                    println!("Tone mapping operator: {}", tonemap.operator);
+                }
+                if toggle_adaptation {
+                    tonemap.auto_exposure = !tonemap.auto_exposure;
+                    println!(
+                        "Eye adaptation: {}",
+                        if tonemap.auto_exposure {
+                            "activated"
+                        } else {
+                            "deactivated"
+                        }
+                    );
+                }
+                if exposure_step != 0.0 {
+                    tonemap.exposure_ev += exposure_step;
+                    println!("Exposure: {:+.1} EV", tonemap.exposure_ev);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Enables the 8-bit or the HDR post process chain, depending on `hdr`.
+    fn enable_post_processing(&self, context: &mut RenderContext) -> SceneResult {
+        if self.hdr {
+            let chain = context.enable_hdr_post_processing()?;
+            // The textures are loaded as they are stored, display encoded, and shaded
+            // without conversion, so the tone mapped colors mustn't be encoded again
+            if let Some(tonemap) = chain.pass_mut::<Tonemap>() {
+                tonemap.encoding = OutputEncoding::Linear;
+            }
+        } else {
+            context.enable_post_processing()?;
+        }
+        Ok(())
+    }
+
     fn is_keyboard_camera_mode(&self) -> bool {
         matches!(
             self.camera_mode,
@@ -276,10 +406,7 @@ impl<'a> Projection {
         self.render_mode = self.render_mode.next();
 
         // Enable/Disable the depth testing capability
-        match self.render_mode {
-            RenderMode::CubeNoDepth => Capability::DepthTest.disable(),
-            _ => Capability::DepthTest.enable(),
-        }
+        self.depth_test = self.render_mode != RenderMode::CubeNoDepth;
 
         // Update vertex layout attributes (very important!) -- NOT *REALLY* IMPORTANT?
         // let model = match self.render_mode {
@@ -289,12 +416,10 @@ impl<'a> Projection {
     }
 
     fn toggle_depth_test(&mut self) {
-        let depth_test_enabled = Capability::DepthTest.check_enabled();
-        if !depth_test_enabled {
-            Capability::DepthTest.enable();
+        self.depth_test = !self.depth_test;
+        if self.depth_test {
             println!("Depth-Test enabled");
         } else {
-            Capability::DepthTest.disable();
             println!("Depth-Test disabled");
         }
     }
@@ -313,14 +438,16 @@ impl Scene<RenderContext> for Projection {
             self.rotation_speed = 16;
 
             // Set starting time for this scene
-            self.start_time = Some(Instant::now());
+            self.start_time = Some(context.elapsed_time());
 
             // Create models for rendering
             let rm1 = RenderModel::create_plane()?;
             let rm2 = RenderModel::create_cube()?;
+            let rm3 = RenderModel::create_instanced_cube()?;
 
             self.render_models.push(rm1);
             self.render_models.push(rm2);
+            self.render_models.push(rm3);
 
             // Load textures
             self.textures
@@ -328,8 +455,9 @@ impl Scene<RenderContext> for Projection {
             self.textures
                 .push(query_texture(context, textures::AWESOMEFACE2)?);
 
-            // Create shader program
+            // Create shader programs
             Self::get_shader_mut(context)?;
+            Self::get_instanced_shader_mut(context)?;
 
             // Created vector with positions for cubes
             self.cube_positions = vec![
@@ -346,8 +474,9 @@ impl Scene<RenderContext> for Projection {
             ];
 
             // Create vector for cube rotations
+            let rng = &mut self.rotation_rng.0;
             self.cube_rotations
-                .extend(std::iter::repeat_with(CubeRotation::new).take(10));
+                .extend(std::iter::repeat_with(|| CubeRotation::new(rng)).take(10));
 
             // Create rectangle in upper left corner
             let window_size = context.window().size();
@@ -364,7 +493,9 @@ impl Scene<RenderContext> for Projection {
             //rectangle.set_strength(3.0);
             //self.rectangle = Some(rectangle);
         }
-        Ok(())
+
+        // All passes start disabled, see `process_post_process_keys`
+        self.enable_post_processing(context)
     }
 
     fn update(&mut self, context: &mut RenderContext) -> SceneResult {
@@ -373,6 +504,7 @@ impl Scene<RenderContext> for Projection {
             //self.camera.update_direction(&*window);
         }
 
+        self.process_post_process_keys(context)?;
         self.process_keyboard_input(context.keyboard_state(), context.delta_time())
     }
 
@@ -391,6 +523,10 @@ impl Scene<RenderContext> for Projection {
     }
 
     fn draw(&mut self, context: &mut RenderContext) -> SceneResult {
+        // Apply the depth test, the state cache skips this if nothing changed
+        let pipeline_state = PipelineState::new().depth_test(self.depth_test);
+        context.state_cache().apply(&pipeline_state)?;
+
         // Activate shader
         Self::activate_shader(context);
 
@@ -398,6 +534,9 @@ impl Scene<RenderContext> for Projection {
         self.textures[0].bind_as_unit(0);
         self.textures[1].bind_as_unit(1);
 
+        // Read before the shader borrows the context
+        let elapsed_time = context.elapsed_time();
+
         // Set texture units once after shader is activated
         let shader = Self::get_shader_mut(context)?;
         shader.set_uniform("texture1", 0)?;
@@ -416,11 +555,10 @@ impl Scene<RenderContext> for Projection {
         if self.is_multiple_cubes() {
             match self.camera_mode {
                 CameraMode::Circle => {
-                    let time_elapsed = self
+                    let start_time = self
                         .start_time
-                        .expect("Start time hasn't been set in projection scene!")
-                        .elapsed()
-                        .as_secs_f32();
+                        .expect("Start time hasn't been set in projection scene!");
+                    let time_elapsed = (elapsed_time - start_time) as f32;
 
                     let cam_x = time_elapsed.sin() * RADIUS;
                     let cam_z = time_elapsed.cos() * RADIUS;
@@ -451,32 +589,25 @@ impl Scene<RenderContext> for Projection {
                 self.render_models[0].render()?;
             }
             RenderMode::MultipleCubes | RenderMode::MultipleCubesRotating => {
-                for (i, pos) in self.cube_positions.iter().enumerate() {
-                    let pos_vector3 = Vector3::new(pos[0], pos[1], pos[2]);
-                    let translation = Matrix4::from_translation(pos_vector3);
-                    let rotation = if self.render_mode != RenderMode::MultipleCubesRotating {
-                        let angle = Rad::from(Deg(20.0 * i as f32));
-                        let axis = Vector3::new(1.0, 0.3, 0.5).normalize();
-                        Matrix4::from_axis_angle(axis, angle)
-                    } else {
-                        let cube_rotation = &self.cube_rotations[i];
-                        let rotation_x = Matrix4::from_angle_x(Deg(cube_rotation.angle.x));
-                        let rotation_y = Matrix4::from_angle_y(Deg(cube_rotation.angle.y));
-                        let rotation_z = Matrix4::from_angle_z(Deg(cube_rotation.angle.z));
-
-                        // Combine rotations: Note the order of multiplication matters
-                        rotation_x * rotation_y * rotation_z
-                    };
-
-                    let model = translation * rotation;
-                    shader.set_uniform_matrix("model", false, &model)?;
-                    if i == 0 || !self.first_only {
-                        self.render_models[1].render()?;
-                    }
-                }
+                // All cubes are drawn with a single instanced draw call
+                let instance_count = if self.first_only {
+                    1
+                } else {
+                    self.cube_positions.len()
+                };
+                let models: Vec<Matrix4<f32>> =
+                    (0..instance_count).map(|i| self.cube_model(i)).collect();
+
+                let shader = Self::get_instanced_shader_mut(context)?;
+                shader.activate();
+                shader.set_uniform("texture1", 0)?;
+                shader.set_uniform("texture2", 1)?;
+                shader.set_uniform_matrix("view", false, &view)?;
+                shader.set_uniform_matrix("projection", false, &projection)?;
+                self.render_models[2].render_instanced(models)?;
 
                 if self.render_mode == RenderMode::MultipleCubesRotating && !self.rotation_paused {
-                    self.update_rotations(context.delta_time());
+                    self.update_rotations(context.delta_time(), context.elapsed_time());
                 }
             }
             _ => {
@@ -531,6 +662,7 @@ struct RenderModel {
     vao: Option<VertexArrayObject>,
     vbo: Option<BufferObject<TexturedVertex>>,
     ibo: Option<BufferObject<u32>>,
+    instance_buffer: Option<BufferObject<Matrix4<f32>>>,
 }
 
 impl RenderModel {
@@ -543,6 +675,7 @@ impl RenderModel {
             vao: Some(vao),
             vbo: Some(vbo),
             ibo: Some(ibo),
+            instance_buffer: None,
         })
     }
 
@@ -554,6 +687,26 @@ impl RenderModel {
             vao: Some(vao),
             vbo: Some(vbo),
             ibo: None,
+            instance_buffer: None,
+        })
+    }
+
+    /// Creates a cube which reads its model matrix per instance from a buffer.
+    pub fn create_instanced_cube() -> Result<RenderModel> {
+        let vertex_data = vertex_data_3d::create_cube_data();
+        let mut vao = VertexArrayObject::new_with_attributes(TexturedVertex::attributes());
+        let vbo = vertex_data.create_vbo(&vao);
+        let instance_buffer =
+            BufferObject::empty(BufferType::ArrayBuffer, BufferUsage::DynamicDraw);
+        vao.add_instance_layout(
+            &instance_buffer,
+            [VertexAttribute::matrix(4, 4, VertexDataType::Float)],
+        );
+        Ok(RenderModel {
+            vao: Some(vao),
+            vbo: Some(vbo),
+            ibo: None,
+            instance_buffer: Some(instance_buffer),
         })
     }
 
@@ -562,18 +715,33 @@ impl RenderModel {
             vao.bind();
             vbo.bind()?;
             ibo.bind()?;
-            vao.render(true, ibo.data_len());
+            vao.render(true, ibo.data_len())?;
             unbind_buffers!(vbo, ibo);
         } else if let (Some(vao), Some(vbo)) = (&self.vao, &self.vbo) {
             vao.bind();
             vbo.bind()?;
-            vao.render(false, vbo.data_len());
+            vao.render(false, vbo.data_len())?;
             VertexArrayObject::unbind();
             vbo.unbind()?;
         }
 
         Ok(())
     }
+
+    /// Renders one instance per model matrix.
+    pub fn render_instanced(&mut self, models: Vec<Matrix4<f32>>) -> Result<()> {
+        if let (Some(vao), Some(vbo), Some(instance_buffer)) =
+            (&self.vao, &self.vbo, &mut self.instance_buffer)
+        {
+            let instance_count = models.len();
+            instance_buffer.update_data(models, None);
+            vbo.bind()?;
+            vao.render_instanced(vbo.data_len(), instance_count)?;
+            vbo.unbind()?;
+        }
+
+        Ok(())
+    }
 }
 
 struct CubeRotation {
@@ -582,24 +750,23 @@ struct CubeRotation {
 }
 
 impl CubeRotation {
-    fn new() -> Self {
+    fn new(rng: &mut StdRng) -> Self {
         Self {
             angle: Vector3::new(0.0, 0.0, 0.0),
-            speed: Self::random_speed(),
+            speed: Self::random_speed(rng),
         }
     }
 
-    fn random_speed() -> Vector3<f32> {
-        let mut rng = rand::thread_rng();
+    fn random_speed(rng: &mut StdRng) -> Vector3<f32> {
         Vector3::new(
-            rand::Rng::gen_range(&mut rng, -90.0..90.0),
-            rand::Rng::gen_range(&mut rng, -90.0..90.0),
-            rand::Rng::gen_range(&mut rng, -90.0..90.0),
+            rng.gen_range(-90.0..90.0),
+            rng.gen_range(-90.0..90.0),
+            rng.gen_range(-90.0..90.0),
         )
     }
 
-    fn update(&mut self) {
-        self.speed = Self::random_speed();
+    fn update(&mut self, rng: &mut StdRng) {
+        self.speed = Self::random_speed(rng);
     }
 
     fn reset_angle(&mut self) {
@@ -607,6 +774,15 @@ impl CubeRotation {
     }
 }
 
+/// The random number generator of the rotation speeds, seeded with `ROTATION_SEED`.
+struct RotationRng(StdRng);
+
+impl Default for RotationRng {
+    fn default() -> Self {
+        Self(StdRng::seed_from_u64(ROTATION_SEED))
+    }
+}
+
 #[derive(Default, Copy, Clone, PartialEq)]
 enum CameraMode {
     #[default]